@@ -0,0 +1,25 @@
+// Capa de interfaz en espacio de pantalla: una textura del tamaño de la ventana
+// pintada en CPU (popups de notas, avisos) que se dibuja encima de todo.
+
+@group(0) @binding(0) var t_hud: texture_2d<f32>;
+@group(0) @binding(1) var s_hud: sampler;
+
+struct HudOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+}
+
+// Un único triángulo que cubre toda la pantalla (sin vertex buffer)
+@vertex
+fn vs_hud(@builtin(vertex_index) index: u32) -> HudOutput {
+    var out: HudOutput;
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    out.uv = uv;
+    out.clip_position = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+    return out;
+}
+
+@fragment
+fn fs_hud(in: HudOutput) -> @location(0) vec4<f32> {
+    return textureSample(t_hud, s_hud, in.uv);
+}
@@ -1,7 +1,11 @@
 struct CameraUniform {
     scale: vec2<f32>,
     translation: vec2<f32>,
-    ui_flags: vec2<f32>, // x = carousel_open
+    ui_flags: vec2<f32>, // x = carousel_open, y = presentación (sin UI)
+    screen: vec2<f32>,   // Tamaño de la ventana en píxeles
+    tool: vec4<f32>,     // x = casilla de la herramienta activa, y = casilla del color (-1 libre), z = paleta visible, w = grosor en px
+    tool_color: vec4<f32>,
+    palette: vec4<f32>,  // x = casillas de herramientas, y = de colores, z = total (PALETTE en ui/mod.rs)
 }
 
 @group(1) @binding(0) var<uniform> camera: CameraUniform;
@@ -34,6 +38,7 @@ fn vs_main(model: VertexInput) -> VertexOutput {
 @group(0) @binding(0) var t_diffuse: texture_2d<f32>;
 @group(0) @binding(1) var s_diffuse: sampler;
 @group(0) @binding(2) var t_overlay: texture_2d<f32>;
+@group(0) @binding(3) var t_strokes: texture_2d<f32>; // Tamaño de la ventana, alpha premultiplicado
 
 // Función auxiliar para dibujar círculos (Botones)
 fn sdf_circle(uv: vec2<f32>, center: vec2<f32>, radius: f32) -> f32 {
@@ -41,12 +46,150 @@ fn sdf_circle(uv: vec2<f32>, center: vec2<f32>, radius: f32) -> f32 {
     return 1.0 - smoothstep(radius, radius + 0.01, d);
 }
 
+fn sdf_segment(p: vec2<f32>, a: vec2<f32>, b: vec2<f32>) -> f32 {
+    let pa = p - a;
+    let ba = b - a;
+    let h = clamp(dot(pa, ba) / dot(ba, ba), 0.0, 1.0);
+    return length(pa - ba * h);
+}
+
+fn sdf_box(p: vec2<f32>, half_size: vec2<f32>) -> f32 {
+    let d = abs(p) - half_size;
+    return length(max(d, vec2<f32>(0.0))) + min(max(d.x, d.y), 0.0);
+}
+
+// Trazo blanco de grosor `w` sobre una distancia con signo
+fn ink(d: f32, w: f32) -> f32 {
+    return 1.0 - smoothstep(w, w + 1.0, d);
+}
+
+// Mismo orden que COLOR_PRESETS en ui/mod.rs
+fn preset_color(i: i32) -> vec3<f32> {
+    switch i {
+        case 0: { return vec3<f32>(0.08, 0.08, 0.08); }
+        case 1: { return vec3<f32>(0.86, 0.16, 0.16); }
+        case 2: { return vec3<f32>(0.16, 0.35, 0.86); }
+        case 3: { return vec3<f32>(0.16, 0.67, 0.27); }
+        case 4: { return vec3<f32>(1.0, 0.9, 0.0); }
+        default: { return vec3<f32>(1.0, 0.55, 0.0); }
+    }
+}
+
+// Icono de cada herramienta (mismo orden que PALETTE): cobertura 0..1 en píxeles locales
+fn tool_icon(slot: i32, p: vec2<f32>) -> f32 {
+    switch slot {
+        case 0: {                                                                                           // Selección (cursor)
+            let left = sdf_segment(p, vec2<f32>(-5.0, -8.0), vec2<f32>(-5.0, 5.0));
+            let top = sdf_segment(p, vec2<f32>(-5.0, -8.0), vec2<f32>(5.0, 2.0));
+            let base = sdf_segment(p, vec2<f32>(-5.0, 5.0), vec2<f32>(5.0, 2.0));
+            let tail = sdf_segment(p, vec2<f32>(0.0, 3.5), vec2<f32>(3.0, 9.0));
+            return ink(min(min(left, top), min(base, tail)), 0.8);
+        }
+        case 1: { return ink(sdf_segment(p, vec2<f32>(-7.0, 7.0), vec2<f32>(7.0, -7.0)), 1.0); }            // Lápiz
+        case 2: { return ink(sdf_segment(p, vec2<f32>(-7.0, 5.0), vec2<f32>(7.0, -5.0)), 3.5); }            // Subrayador
+        case 3: {                                                                                           // Resaltar texto ("T")
+            let bar = sdf_segment(p, vec2<f32>(-6.0, -7.0), vec2<f32>(6.0, -7.0));
+            let stem = sdf_segment(p, vec2<f32>(0.0, -7.0), vec2<f32>(0.0, 7.0));
+            return ink(min(bar, stem), 1.0);
+        }
+        case 4: {                                                                                           // Nota
+            let frame = abs(sdf_box(p, vec2<f32>(7.0, 8.0)));
+            let lines = min(sdf_segment(p, vec2<f32>(-3.0, -2.0), vec2<f32>(3.0, -2.0)), sdf_segment(p, vec2<f32>(-3.0, 2.0), vec2<f32>(3.0, 2.0)));
+            return ink(min(frame, lines), 0.8);
+        }
+        case 5: {                                                                                           // Texto libre ("A")
+            let left = sdf_segment(p, vec2<f32>(-6.0, 7.0), vec2<f32>(0.0, -7.0));
+            let right = sdf_segment(p, vec2<f32>(0.0, -7.0), vec2<f32>(6.0, 7.0));
+            let bar = sdf_segment(p, vec2<f32>(-3.0, 2.0), vec2<f32>(3.0, 2.0));
+            return ink(min(min(left, right), bar), 0.9);
+        }
+        case 6: { return ink(sdf_box(p, vec2<f32>(8.0, 5.0)), 0.0); }                                       // Goma
+        case 7: { return ink(abs(sdf_box(p, vec2<f32>(9.0, 7.0))), 0.8); }                                  // Rectángulo
+        case 8: { return ink(abs(length(p * vec2<f32>(1.0, 1.3)) - 9.0), 0.8); }                            // Elipse
+        case 9: { return ink(sdf_segment(p, vec2<f32>(-8.0, 8.0), vec2<f32>(8.0, -8.0)), 0.8); }            // Línea
+        default: {                                                                                          // Flecha
+            let shaft = sdf_segment(p, vec2<f32>(-8.0, 8.0), vec2<f32>(8.0, -8.0));
+            let wing_a = sdf_segment(p, vec2<f32>(8.0, -8.0), vec2<f32>(1.0, -7.0));
+            let wing_b = sdf_segment(p, vec2<f32>(8.0, -8.0), vec2<f32>(7.0, -1.0));
+            return ink(min(shaft, min(wing_a, wing_b)), 0.8);
+        }
+    }
+}
+
+fn hue_to_rgb(h: f32) -> vec3<f32> {
+    let k = vec3<f32>(0.0, 4.0, 2.0);
+    return clamp(abs(fract(h + k / 6.0) * 6.0 - 3.0) - 1.0, vec3<f32>(0.0), vec3<f32>(1.0));
+}
+
+// Ancho del carrusel en píxeles (UiState::side_panel_width)
+const SIDE_PANEL_WIDTH: f32 = 200.0;
+
+// Paleta de herramientas: fila de casillas de 44 px sobre la barra inferior (80 px)
+fn draw_palette(px: vec2<f32>, base: vec4<f32>) -> vec4<f32> {
+    // Casillas en el orden de PALETTE (ui/mod.rs): herramientas, colores, tono libre, más fino,
+    // vista previa, más grueso. Cuántas hay de cada grupo llega en la cámara
+    let tool_slots = i32(camera.palette.x);
+    let color_slots = i32(camera.palette.y);
+    let slots = camera.palette.z;
+    let slot_size = 44.0;
+    let top = camera.screen.y - 80.0 - 52.0;
+    if (px.y < top || px.y > camera.screen.y - 80.0) {
+        return base;
+    }
+
+    var color = mix(base, vec4<f32>(0.12, 0.12, 0.17, 1.0), 0.85);
+    let left = camera.screen.x * 0.5 - slots * slot_size * 0.5;
+    let slot_f = floor((px.x - left) / slot_size);
+    if (slot_f < 0.0 || slot_f >= slots) {
+        return color;
+    }
+    let slot = i32(slot_f);
+    let p = px - vec2<f32>(left + (slot_f + 0.5) * slot_size, top + 26.0); // Relativo al centro de la casilla
+    let selected = vec4<f32>(1.0, 0.6, 0.2, 1.0);
+
+    let extra = slot - tool_slots - color_slots; // Casillas tras los colores
+
+    if (slot < tool_slots) {
+        // Herramientas
+        let is_active = f32(slot) == camera.tool.x;
+        let disc = 1.0 - smoothstep(17.0, 18.0, length(p));
+        color = mix(color, select(vec4<f32>(0.25, 0.25, 0.3, 1.0), selected, is_active), disc);
+        color = mix(color, vec4<f32>(1.0), tool_icon(slot, p));
+    } else if (extra < 0) {
+        // Colores predefinidos; el activo lleva un anillo
+        let ring = ink(abs(length(p) - 16.0), 1.0) * f32(f32(slot) == camera.tool.y);
+        let disc = 1.0 - smoothstep(12.0, 13.0, length(p));
+        color = mix(color, vec4<f32>(preset_color(slot - tool_slots), 1.0), disc);
+        color = mix(color, vec4<f32>(1.0), ring);
+    } else if (extra == 0) {
+        // Tono libre
+        let strip = 1.0 - smoothstep(0.0, 1.0, sdf_box(p, vec2<f32>(20.0, 8.0)));
+        color = mix(color, vec4<f32>(hue_to_rgb((p.x + 22.0) / 44.0), 1.0), strip);
+    } else if (extra == 2) {
+        // Vista previa del grosor con el color actual
+        let r = clamp(camera.tool.w * 0.5, 1.0, 18.0);
+        let dot_mask = 1.0 - smoothstep(r, r + 1.0, length(p));
+        color = mix(color, vec4<f32>(camera.tool_color.rgb, 1.0), dot_mask * max(camera.tool_color.a, 0.4));
+    } else {
+        // Más fino (-) / más grueso (+)
+        var d = sdf_segment(p, vec2<f32>(-7.0, 0.0), vec2<f32>(7.0, 0.0));
+        if (extra == 3) {
+            d = min(d, sdf_segment(p, vec2<f32>(0.0, -7.0), vec2<f32>(0.0, 7.0)));
+        }
+        color = mix(color, vec4<f32>(1.0), ink(d, 1.0));
+    }
+    return color;
+}
+
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
     // 1. Renderizado PDF + Overlay
     let pdf = textureSample(t_diffuse, s_diffuse, in.tex_coords);
     let ovr = textureSample(t_overlay, s_diffuse, in.tex_coords);
     var color = mix(pdf, ovr * pdf, ovr.a);
+    // Trazos vectoriales (gpu/strokes.rs): multiplican igual que el overlay, un resaltado no tapa el texto
+    let strokes = textureLoad(t_strokes, vec2<i32>(in.clip_position.xy), 0);
+    color = vec4<f32>(color.rgb * (1.0 - strokes.a + strokes.rgb), color.a);
 
     // 2. UI - Coordenadas de pantalla crudas (0,0 a 1,1)
     // Para glassmorphism, necesitamos coordenadas absolutas de ventana, 
@@ -54,7 +197,7 @@ fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
     let uv = in.tex_coords; 
     
     // -- Barra Inferior --
-    if (uv.y > 0.88) {
+    if (uv.y > 0.88 && camera.ui_flags.y < 0.5) {
         // Fondo Glassmorphism
         let noise = fract(sin(dot(uv, vec2<f32>(12.9, 78.2))) * 43758.5);
         let glass = vec4<f32>(0.1, 0.1, 0.15, 0.85); // Oscuro moderno
@@ -76,16 +219,15 @@ fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
         color = mix(color, vec4<f32>(0.2, 0.6, 1.0, 1.0), btn_search); // Azul
     }
 
-    // -- Panel Lateral (Carrusel) --
-    if (camera.ui_flags.x > 0.5 && uv.x < 0.2) {
+    // -- Paleta de herramientas --
+    if (camera.tool.z > 0.5 && camera.ui_flags.y < 0.5) {
+        color = draw_palette(in.clip_position.xy, color);
+    }
+
+    // -- Panel Lateral (Carrusel): solo el fondo, las miniaturas las pinta el HUD --
+    if (camera.ui_flags.x > 0.5 && in.clip_position.x < SIDE_PANEL_WIDTH) {
         let glass_side = vec4<f32>(0.05, 0.05, 0.05, 0.95);
         color = mix(color, glass_side, 0.95);
-        
-        // Simular miniaturas (cajas grises)
-        let thumbnail_y = fract(uv.y * 5.0);
-        if (thumbnail_y > 0.1 && thumbnail_y < 0.9 && uv.x > 0.02 && uv.x < 0.18) {
-             color = mix(color, vec4<f32>(1.0, 1.0, 1.0, 0.1), 0.5);
-        }
     }
 
     return color;
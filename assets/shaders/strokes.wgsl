@@ -0,0 +1,32 @@
+// Anotaciones vectoriales (trazos, figuras, resaltados): triángulos en puntos de página
+// pintados con MSAA en una textura del tamaño de la ventana, que fs_main compone sobre el PDF.
+
+struct StrokeCamera {
+    scale: vec2<f32>,
+    translation: vec2<f32>,
+    page_size: vec2<f32>, // Puntos PDF
+    padding: vec2<f32>,
+}
+
+@group(0) @binding(0) var<uniform> camera: StrokeCamera;
+
+struct StrokeOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) color: vec4<f32>,
+}
+
+@vertex
+fn vs_stroke(@location(0) position: vec2<f32>, @location(1) color: vec4<f32>) -> StrokeOutput {
+    var out: StrokeOutput;
+    // Punto de página -> quad de la página (-1..1, Y hacia arriba) -> cámara, igual que en vs_main
+    let quad = position / camera.page_size * 2.0 - 1.0;
+    out.clip_position = vec4<f32>(quad * camera.scale + camera.translation, 0.0, 1.0);
+    out.color = color;
+    return out;
+}
+
+@fragment
+fn fs_stroke(in: StrokeOutput) -> @location(0) vec4<f32> {
+    // Alpha premultiplicado: así se mezclan bien los bordes del MSAA
+    return vec4<f32>(in.color.rgb * in.color.a, in.color.a);
+}
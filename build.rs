@@ -1,8 +1,4 @@
-use anyhow::Context;
 use std::env;
-use std::fs;
-use std::io::{Cursor, Read};
-use std::path::{Path, PathBuf};
 
 fn main() -> anyhow::Result<()> {
     // Solo ejecutamos esta lógica en Windows, ya que el target es .exe/.msi
@@ -20,6 +16,11 @@ fn main() -> anyhow::Result<()> {
 
 #[cfg(target_os = "windows")]
 fn setup_pdfium() -> anyhow::Result<()> {
+    use anyhow::Context;
+    use std::fs;
+    use std::io::Cursor;
+    use std::path::PathBuf;
+
     // Definimos la URL de la última versión estable para Windows x64
     // Usamos el .zip que es nativo para Windows (evitando dependencias de tar/gz extra)
     const PDFIUM_URL: &str = "https://github.com/bblanchon/pdfium-binaries/releases/latest/download/pdfium-win-x64.zip";
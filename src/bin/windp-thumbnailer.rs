@@ -0,0 +1,75 @@
+//! Miniaturas de PDF para escritorios Linux (especificación de thumbnailers de freedesktop).
+//! Uso: windp-thumbnailer -s TAMAÑO ENTRADA SALIDA.png
+//! ENTRADA puede ser una ruta (%i) o una URI file:// (%u).
+
+use windp::pdf::{PdfSystem, render::render_page_to_memory};
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let Err(e) = run(&args) {
+        eprintln!("windp-thumbnailer: {}", e);
+        std::process::exit(1);
+    }
+}
+
+fn run(args: &[String]) -> Result<(), String> {
+    let mut size: u32 = 256; // Tamaño "large" de la especificación si no se indica
+    let mut positional = Vec::new();
+    let mut iter = args.iter();
+
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "-s" | "--size" => {
+                size = iter.next()
+                    .and_then(|v| v.parse().ok())
+                    .filter(|&s| s > 0)
+                    .ok_or("Tamaño inválido")?;
+            },
+            _ => positional.push(arg.as_str()),
+        }
+    }
+
+    let [input, output] = positional[..] else {
+        return Err("Uso: windp-thumbnailer -s TAMAÑO ENTRADA SALIDA.png".to_string());
+    };
+    let input = uri_to_path(input);
+
+    let pdf_system = PdfSystem::new();
+    let document = pdf_system.open_file(&input).map_err(|e| format!("No se pudo abrir {}: {:?}", input, e))?;
+
+    // El lado mayor de la primera página mide `size` píxeles
+    let page_size = document.pages().page_size(0).map_err(|e| format!("{:?}", e))?;
+    let longest = page_size.width().value.max(page_size.height().value).max(1.0);
+    let bitmap = render_page_to_memory(&document, 0, size as f32 / longest).map_err(|e| format!("{:?}", e))?;
+
+    bitmap.to_rgba_image().save_with_format(output, image::ImageFormat::Png)
+        .map_err(|e| format!("No se pudo guardar {}: {}", output, e))
+}
+
+/// Convierte "file:///ruta%20con%20espacios.pdf" en "/ruta con espacios.pdf".
+/// Las rutas normales pasan tal cual.
+fn uri_to_path(input: &str) -> String {
+    let Some(rest) = input.strip_prefix("file://") else {
+        return input.to_string();
+    };
+    // Quitamos el host (normalmente vacío o "localhost")
+    let path = &rest[rest.find('/').unwrap_or(0)..];
+
+    let bytes = path.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes.get(i + 1..i + 3).and_then(|h| std::str::from_utf8(h).ok()).and_then(|h| u8::from_str_radix(h, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            },
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            },
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
@@ -0,0 +1,528 @@
+use std::path::{Path, PathBuf};
+
+use crate::sidecar::AnnotationStorage;
+
+pub const USAGE: &str = "\
+Uso: windp [OPCIONES] [ARCHIVO.pdf[#page=N&zoom=Z&search=T&nameddest=D]]...
+
+Opciones:
+  --page N              Abrir en la página N (la primera es 1)
+  --zoom Z              Zoom inicial en porcentaje (p.ej. 150 o 150%)
+  --fit width|page      Ajustar la página al ancho o entera en la ventana
+  --search TERMINO      Buscar y saltar a la primera coincidencia
+  --dest NOMBRE         Abrir en un destino con nombre del documento
+  --password CLAVE      Contraseña del documento
+  --annotations DONDE   Guardar las anotaciones en el PDF (pdf), en un .windp.json
+                        junto a él (sidecar) o en el directorio de datos (data)
+  --presentation        Pantalla completa sin interfaz
+  --config RUTA         Archivo de configuración (clave = valor)
+  --single-instance     Reutilizar la ventana abierta si ya existe
+  --remote[=ENDPOINT]   Abrir el socket de control remoto JSON
+  -h, --help            Mostrar esta ayuda
+  -V, --version         Mostrar la versión
+
+Con varios archivos, el primero se abre en esta ventana y el resto en ventanas nuevas.
+Las opciones del fragmento (#page=5&zoom=150) tienen prioridad sobre las globales.";
+
+/// Cómo encajar la página en la ventana al abrir.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ZoomMode {
+    /// Porcentaje del tamaño real (100 = tamaño físico de la página).
+    Percent(f32),
+    FitWidth,
+    FitPage,
+}
+
+/// Vista inicial de un documento: todo opcional, lo que falte usa el valor por defecto.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct InitialView {
+    pub page: Option<u16>, // Base 0
+    pub zoom: Option<ZoomMode>,
+    pub search: Option<String>,
+    pub named_dest: Option<String>,
+}
+
+impl InitialView {
+    /// Combina dos vistas: lo que fije `self` tiene prioridad sobre `fallback`.
+    pub fn or(self, fallback: &InitialView) -> InitialView {
+        InitialView {
+            page: self.page.or(fallback.page),
+            zoom: self.zoom.or(fallback.zoom),
+            search: self.search.or_else(|| fallback.search.clone()),
+            named_dest: self.named_dest.or_else(|| fallback.named_dest.clone()),
+        }
+    }
+}
+
+/// Un archivo de la línea de comandos, con la vista de su fragmento `#...` (si lo tenía).
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileArg {
+    pub path: String,
+    pub view: InitialView,
+    pub raw: String, // El argumento tal cual, para relanzarlo en otra ventana
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct CliArgs {
+    pub files: Vec<FileArg>,
+    pub view: InitialView,
+    pub password: Option<String>,
+    pub presentation: bool,
+    pub annotations: Option<AnnotationStorage>,
+    pub config: Option<PathBuf>,
+    pub single_instance: bool,
+    pub remote: Option<String>,
+}
+
+impl CliArgs {
+    /// Vista inicial efectiva para un archivo: su fragmento manda sobre las opciones globales.
+    pub fn view_for(&self, file: Option<&FileArg>) -> InitialView {
+        match file {
+            Some(file) => file.view.clone().or(&self.view),
+            None => self.view.clone(),
+        }
+    }
+
+    /// Argumentos para abrir `file` en un proceso (ventana) aparte con las mismas opciones globales.
+    /// No se reenvían --single-instance ni --remote: esas pertenecen a la primera ventana.
+    pub fn relaunch_args(&self, file: &FileArg) -> Vec<String> {
+        let mut args = vec![file.raw.clone()];
+        if let Some(page) = self.view.page {
+            args.extend(["--page".to_string(), (page + 1).to_string()]);
+        }
+        match self.view.zoom {
+            Some(ZoomMode::Percent(pct)) => args.extend(["--zoom".to_string(), pct.to_string()]),
+            Some(ZoomMode::FitWidth) => args.extend(["--fit".to_string(), "width".to_string()]),
+            Some(ZoomMode::FitPage) => args.extend(["--fit".to_string(), "page".to_string()]),
+            None => {},
+        }
+        if let Some(term) = &self.view.search {
+            args.extend(["--search".to_string(), term.clone()]);
+        }
+        if let Some(dest) = &self.view.named_dest {
+            args.extend(["--dest".to_string(), dest.clone()]);
+        }
+        if let Some(password) = &self.password {
+            args.extend(["--password".to_string(), password.clone()]);
+        }
+        if let Some(storage) = self.annotations {
+            args.extend(["--annotations".to_string(), storage.as_str().to_string()]);
+        }
+        if let Some(config) = &self.config {
+            args.extend(["--config".to_string(), config.to_string_lossy().into_owned()]);
+        }
+        if self.presentation {
+            args.push("--presentation".to_string());
+        }
+        args
+    }
+}
+
+pub enum CliAction {
+    Run(CliArgs),
+    Help,
+    Version,
+}
+
+/// Interpreta los argumentos (sin el ejecutable) y aplica el archivo de configuración si se indicó.
+pub fn parse(args: impl IntoIterator<Item = String>) -> Result<CliAction, String> {
+    let mut cli = CliArgs::default();
+    let mut args = args.into_iter();
+
+    while let Some(arg) = args.next() {
+        // Admitimos tanto "--zoom 150" como "--zoom=150"
+        let (flag, inline) = match arg.split_once('=') {
+            Some((flag, value)) if flag.starts_with("--") => (flag.to_string(), Some(value.to_string())),
+            _ => (arg.clone(), None),
+        };
+        let mut value = |name: &str| inline.clone().or_else(|| args.next()).ok_or(format!("Falta el valor de {}", name));
+
+        match flag.as_str() {
+            "-h" | "--help" => return Ok(CliAction::Help),
+            "-V" | "--version" => return Ok(CliAction::Version),
+            "--page" => cli.view.page = Some(parse_page(&value("--page")?)?),
+            "--zoom" => cli.view.zoom = Some(parse_zoom(&value("--zoom")?)?),
+            "--fit" => cli.view.zoom = Some(parse_fit(&value("--fit")?)?),
+            "--search" => cli.view.search = Some(value("--search")?),
+            "--dest" => cli.view.named_dest = Some(value("--dest")?),
+            "--password" => cli.password = Some(value("--password")?),
+            "--presentation" => cli.presentation = true,
+            "--annotations" => cli.annotations = Some(AnnotationStorage::parse(&value("--annotations")?)?),
+            "--config" => cli.config = Some(PathBuf::from(value("--config")?)),
+            "--single-instance" => cli.single_instance = true,
+            // El endpoint es opcional: "--remote" a secas usa el de por defecto, pero "--remote=" vacío
+            // es un error (fallaría más tarde, al abrir el socket, sin decir por qué)
+            "--remote" => cli.remote = match inline.clone() {
+                Some(endpoint) if endpoint.trim().is_empty() => return Err("Falta el endpoint de --remote=".to_string()),
+                endpoint => Some(endpoint.unwrap_or_else(crate::remote::default_endpoint)),
+            },
+            _ if arg.starts_with("--") => return Err(format!("Opción desconocida: {}", arg)),
+            _ => cli.files.push(parse_file_arg(&arg)),
+        }
+    }
+
+    if let Some(path) = cli.config.clone() {
+        apply_config(&mut cli, &path)?;
+    }
+
+    Ok(CliAction::Run(cli))
+}
+
+/// Separa "archivo.pdf#page=5&zoom=150" en ruta + vista inicial.
+/// Si existe un archivo con '#' en el nombre tal cual, o lo que va detrás no son parámetros de
+/// apertura válidos ("informe #3.pdf"), la almohadilla es parte del nombre.
+fn parse_file_arg(arg: &str) -> FileArg {
+    let literal = || FileArg { path: arg.to_string(), view: InitialView::default(), raw: arg.to_string() };
+    let Some((path, fragment)) = arg.rsplit_once('#') else {
+        return literal();
+    };
+    if Path::new(arg).exists() {
+        return literal();
+    }
+
+    match parse_fragment(fragment) {
+        Ok(view) => FileArg { path: path.to_string(), view, raw: arg.to_string() },
+        Err(_) => literal(),
+    }
+}
+
+/// Parámetros de apertura estilo Acrobat: page, zoom, search, nameddest y view (Fit/FitH/FitV).
+fn parse_fragment(fragment: &str) -> Result<InitialView, String> {
+    let mut view = InitialView::default();
+
+    for param in fragment.split('&').filter(|p| !p.is_empty()) {
+        // Todos los parámetros son "clave=valor": sin '=' no es un fragmento de apertura
+        let (key, value) = param.split_once('=').ok_or(format!("Parámetro de apertura inválido: {}", param))?;
+        match key.to_ascii_lowercase().as_str() {
+            "page" => view.page = Some(parse_page(value)?),
+            // Acrobat admite "zoom=150,x,y": el desplazamiento lo ignoramos
+            "zoom" => view.zoom = Some(parse_zoom(value.split(',').next().unwrap_or(value))?),
+            "search" => view.search = Some(value.trim_matches('"').replace("%20", " ")),
+            "nameddest" => view.named_dest = Some(value.to_string()),
+            "view" => {
+                view.zoom = match value.split(',').next().unwrap_or("").to_ascii_lowercase().as_str() {
+                    "fith" | "fitbh" => Some(ZoomMode::FitWidth),
+                    "fit" | "fitb" | "fitv" | "fitbv" => Some(ZoomMode::FitPage),
+                    _ => view.zoom,
+                }
+            },
+            _ => log::warn!("CLI: parámetro de apertura ignorado: {}", param),
+        }
+    }
+
+    Ok(view)
+}
+
+fn parse_page(value: &str) -> Result<u16, String> {
+    match value.trim().parse::<u16>() {
+        Ok(n) if n >= 1 => Ok(n - 1),
+        _ => Err(format!("Número de página inválido: {}", value)),
+    }
+}
+
+fn parse_zoom(value: &str) -> Result<ZoomMode, String> {
+    let trimmed = value.trim().trim_end_matches('%');
+    match trimmed.parse::<f32>() {
+        Ok(pct) if pct > 0.0 => Ok(ZoomMode::Percent(pct)),
+        _ => parse_fit(value).map_err(|_| format!("Zoom inválido: {}", value)),
+    }
+}
+
+fn parse_fit(value: &str) -> Result<ZoomMode, String> {
+    match value.trim().to_ascii_lowercase().as_str() {
+        "width" | "fit-width" | "fith" => Ok(ZoomMode::FitWidth),
+        "page" | "fit-page" | "fit" => Ok(ZoomMode::FitPage),
+        _ => Err(format!("Ajuste inválido (width|page): {}", value)),
+    }
+}
+
+/// Archivo de configuración "clave = valor" (líneas con # son comentarios).
+/// Solo rellena lo que no vino por línea de comandos: los flags siempre mandan.
+fn apply_config(cli: &mut CliArgs, path: &Path) -> Result<(), String> {
+    let content = std::fs::read_to_string(path)
+        .map_err(|e| format!("No se pudo leer la configuración {:?}: {}", path, e))?;
+
+    for (number, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let Some((key, value)) = line.split_once('=') else {
+            return Err(format!("{:?}:{}: se esperaba clave = valor", path, number + 1));
+        };
+        let (key, value) = (key.trim(), value.trim().trim_matches('"'));
+
+        match key {
+            "single_instance" => cli.single_instance |= parse_bool(value)?,
+            "presentation" => cli.presentation |= parse_bool(value)?,
+            "remote" => {
+                if cli.remote.is_none() && !value.is_empty() && value != "false" {
+                    cli.remote = Some(if value == "true" { crate::remote::default_endpoint() } else { value.to_string() });
+                }
+            },
+            "annotations" => {
+                if cli.annotations.is_none() {
+                    cli.annotations = Some(AnnotationStorage::parse(value)?);
+                }
+            },
+            "zoom" => {
+                if cli.view.zoom.is_none() {
+                    cli.view.zoom = Some(parse_zoom(value)?);
+                }
+            },
+            _ => log::warn!("Config: clave desconocida ignorada: {}", key),
+        }
+    }
+
+    Ok(())
+}
+
+fn parse_bool(value: &str) -> Result<bool, String> {
+    match value {
+        "true" | "yes" | "1" | "on" => Ok(true),
+        "false" | "no" | "0" | "off" => Ok(false),
+        _ => Err(format!("Valor booleano inválido: {}", value)),
+    }
+}
+
+/// Interpreta rangos de páginas estilo "1-3,5,7-" (base 1, extremos incluidos; "7-" llega al final).
+/// Devuelve los rangos en base 0, semiabiertos, en el orden escrito.
+pub fn parse_page_ranges(spec: &str, total_pages: u16) -> Result<Vec<std::ops::Range<u16>>, String> {
+    let mut ranges = Vec::new();
+
+    for part in spec.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+        let (start, end) = match part.split_once('-') {
+            Some((start, end)) => {
+                let start = if start.trim().is_empty() { 1 } else { parse_page(start)? + 1 };
+                let end = if end.trim().is_empty() { total_pages } else { parse_page(end)? + 1 };
+                (start, end)
+            },
+            None => {
+                let page = parse_page(part)? + 1;
+                (page, page)
+            },
+        };
+
+        if start > end || end > total_pages {
+            return Err(format!("Rango fuera del documento ({} páginas): {}", total_pages, part));
+        }
+        ranges.push(start - 1..end);
+    }
+
+    if ranges.is_empty() {
+        return Err(format!("Rango de páginas vacío: '{}'", spec));
+    }
+    Ok(ranges)
+}
+
+/// Interpreta un tamaño de papel: "a3", "a4", "a5", "letter" o "legal" (en vertical), o "ANCHOxALTO"
+/// en puntos PDF ("612x792") o en milímetros con el sufijo "mm" ("210x297mm"). Devuelve puntos.
+pub fn parse_paper_size(spec: &str) -> Result<(f32, f32), String> {
+    const MM: f32 = 72.0 / 25.4;
+    let spec = spec.trim().to_ascii_lowercase();
+    let named = match spec.as_str() {
+        "a3" => Some((297.0 * MM, 420.0 * MM)),
+        "a4" => Some((210.0 * MM, 297.0 * MM)),
+        "a5" => Some((148.0 * MM, 210.0 * MM)),
+        "letter" => Some((612.0, 792.0)),
+        "legal" => Some((612.0, 1008.0)),
+        _ => None,
+    };
+    if let Some(size) = named {
+        return Ok(size);
+    }
+
+    let (numbers, unit) = match spec.strip_suffix("mm") {
+        Some(numbers) => (numbers, MM),
+        None => (spec.as_str(), 1.0),
+    };
+    let (width, height) = numbers.split_once('x').ok_or_else(|| format!("Tamaño de papel inválido: {}", spec))?;
+    let parse = |value: &str| value.trim().parse::<f32>().ok().filter(|v| *v > 0.0).ok_or_else(|| format!("Tamaño de papel inválido: {}", spec));
+    Ok((parse(width)? * unit, parse(height)? * unit))
+}
+
+/// Tamaño de página al combinar imágenes: "auto" (o vacío) deja cada página del tamaño de su imagen;
+/// si no, lo mismo que `parse_paper_size`.
+pub fn parse_image_page(spec: &str) -> Result<Option<(f32, f32)>, String> {
+    match spec.trim() {
+        "" => Ok(None),
+        spec if spec.eq_ignore_ascii_case("auto") => Ok(None),
+        spec => parse_paper_size(spec).map(Some),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(args: &[&str]) -> Result<CliArgs, String> {
+        match parse(args.iter().map(|arg| arg.to_string()))? {
+            CliAction::Run(cli) => Ok(cli),
+            CliAction::Help => Err("help".to_string()),
+            CliAction::Version => Err("version".to_string()),
+        }
+    }
+
+    #[test]
+    fn parse_reads_flags_with_separate_and_inline_values() {
+        let cli = run(&["--page", "3", "--zoom=150%", "--search", "hola mundo", "--password=x", "--presentation", "a.pdf"]).unwrap();
+        assert_eq!(cli.view.page, Some(2));
+        assert_eq!(cli.view.zoom, Some(ZoomMode::Percent(150.0)));
+        assert_eq!(cli.view.search.as_deref(), Some("hola mundo"));
+        assert_eq!(cli.password.as_deref(), Some("x"));
+        assert!(cli.presentation);
+        assert_eq!(cli.files.len(), 1);
+        assert_eq!(cli.files[0].path, "a.pdf");
+    }
+
+    #[test]
+    fn parse_handles_help_version_and_errors() {
+        assert_eq!(run(&["-h"]).err().as_deref(), Some("help"));
+        assert_eq!(run(&["--version"]).err().as_deref(), Some("version"));
+        let cases: &[(&[&str], &str)] = &[
+            (&["--page"], "Falta el valor de --page"),
+            (&["--page", "0"], "Número de página inválido: 0"),
+            (&["--zoom", "-5"], "Zoom inválido: -5"),
+            (&["--fit", "height"], "Ajuste inválido (width|page): height"),
+            (&["--bogus"], "Opción desconocida: --bogus"),
+            (&["--remote="], "Falta el endpoint de --remote="),
+            (&["--remote= "], "Falta el endpoint de --remote="),
+        ];
+        for (args, error) in cases {
+            assert_eq!(run(args).err().as_deref(), Some(*error), "{:?}", args);
+        }
+    }
+
+    #[test]
+    fn parse_remote_endpoint_is_optional() {
+        assert_eq!(run(&["--remote"]).unwrap().remote, Some(crate::remote::default_endpoint()));
+        assert_eq!(run(&["--remote=/tmp/x.sock"]).unwrap().remote.as_deref(), Some("/tmp/x.sock"));
+    }
+
+    #[test]
+    fn file_fragments_override_global_options() {
+        let cli = run(&["--zoom", "50", "--page", "9", "doc.pdf#page=5&zoom=200,10,20", "otro.pdf"]).unwrap();
+        assert_eq!(cli.files[0].path, "doc.pdf");
+        assert_eq!(cli.files[0].raw, "doc.pdf#page=5&zoom=200,10,20");
+        let view = cli.view_for(cli.files.first());
+        assert_eq!((view.page, view.zoom), (Some(4), Some(ZoomMode::Percent(200.0))));
+        let view = cli.view_for(cli.files.get(1));
+        assert_eq!((view.page, view.zoom), (Some(8), Some(ZoomMode::Percent(50.0))));
+    }
+
+    #[test]
+    fn parse_fragment_table() {
+        let view = |page, zoom, search: Option<&str>, dest: Option<&str>| InitialView {
+            page, zoom, search: search.map(String::from), named_dest: dest.map(String::from),
+        };
+        let cases = [
+            ("", view(None, None, None, None)),
+            ("page=1", view(Some(0), None, None, None)),
+            ("PAGE=12&", view(Some(11), None, None, None)),
+            ("zoom=75", view(None, Some(ZoomMode::Percent(75.0)), None, None)),
+            ("view=FitH,100", view(None, Some(ZoomMode::FitWidth), None, None)),
+            ("view=Fit", view(None, Some(ZoomMode::FitPage), None, None)),
+            ("view=Unknown", view(None, None, None, None)),
+            ("search=\"dos%20palabras\"", view(None, None, Some("dos palabras"), None)),
+            ("nameddest=cap2&page=3", view(Some(2), None, None, Some("cap2"))),
+            ("toolbar=0", view(None, None, None, None)),
+        ];
+        for (fragment, expected) in cases {
+            assert_eq!(parse_fragment(fragment), Ok(expected), "{}", fragment);
+        }
+    }
+
+    #[test]
+    fn parse_fragment_rejects_bad_values() {
+        for fragment in ["page=0", "page=abc", "page=", "page=70000", "zoom=", "zoom=ancho", "3.pdf", "page=2&final"] {
+            assert!(parse_fragment(fragment).is_err(), "{}", fragment);
+        }
+    }
+
+    #[test]
+    fn file_arg_with_hash_in_an_existing_name_is_literal() {
+        let path = std::env::temp_dir().join(format!("windp-cli-{}#page=2", std::process::id()));
+        std::fs::write(&path, "").unwrap();
+        let arg = parse_file_arg(&path.to_string_lossy());
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(arg.path, path.to_string_lossy());
+        assert_eq!(arg.view, InitialView::default());
+    }
+
+    #[test]
+    fn file_arg_keeps_the_hash_when_the_fragment_does_not_parse() {
+        for name in ["informe #3.pdf", "doc.pdf#page=0", "notas#final.pdf"] {
+            let arg = parse_file_arg(name);
+            assert_eq!((arg.path.as_str(), arg.view), (name, InitialView::default()), "{}", name);
+        }
+        assert_eq!(parse_file_arg("doc.pdf#page=2").path, "doc.pdf");
+    }
+
+    fn config(name: &str, content: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("windp-cli-{}-{}.conf", std::process::id(), name));
+        std::fs::write(&path, content).unwrap();
+        path
+    }
+
+    #[test]
+    fn config_fills_only_what_the_flags_left_unset() {
+        let path = config("fill", "# comentario\n\nzoom = 120\nannotations = \"sidecar\"\nsingle_instance = yes\nremote = true\nnueva = 1\n");
+        let mut cli = run(&["--zoom", "80"]).unwrap();
+        let result = apply_config(&mut cli, &path);
+        std::fs::remove_file(&path).unwrap();
+        result.unwrap();
+        assert_eq!(cli.view.zoom, Some(ZoomMode::Percent(80.0)));
+        assert_eq!(cli.annotations, Some(AnnotationStorage::Sidecar));
+        assert!(cli.single_instance);
+        assert_eq!(cli.remote, Some(crate::remote::default_endpoint()));
+    }
+
+    #[test]
+    fn config_reports_bad_lines() {
+        for (name, content) in [("noeq", "zoom 120\n"), ("bool", "presentation = quizá\n"), ("zoom", "zoom = 0\n")] {
+            let path = config(name, content);
+            let result = apply_config(&mut CliArgs::default(), &path);
+            std::fs::remove_file(&path).unwrap();
+            assert!(result.is_err(), "{}", content);
+        }
+        assert!(apply_config(&mut CliArgs::default(), Path::new("/no/existe/windp.conf")).is_err());
+    }
+
+    #[test]
+    fn page_ranges_table() {
+        // Rangos esperados como (inicio, fin) en base 0, fin excluido
+        let cases = [
+            ("1-3,5", 10, &[(0, 3), (4, 5)][..]),
+            ("7-", 10, &[(6, 10)][..]),
+            ("-2", 10, &[(0, 2)][..]),
+            ("-", 4, &[(0, 4)][..]),
+            (" 2 , 4-4 ,", 5, &[(1, 2), (3, 4)][..]),
+            ("3,1", 3, &[(2, 3), (0, 1)][..]),
+        ];
+        for (spec, total, expected) in cases {
+            let expected: Vec<std::ops::Range<u16>> = expected.iter().map(|&(start, end)| start..end).collect();
+            assert_eq!(parse_page_ranges(spec, total), Ok(expected), "{}", spec);
+        }
+    }
+
+    #[test]
+    fn page_ranges_reject_out_of_range_and_malformed() {
+        for spec in ["0", "11", "5-11", "4-2", "", ",", "a-b", "1-2-3", "9-"] {
+            assert!(parse_page_ranges(spec, 8).is_err(), "{}", spec);
+        }
+    }
+
+    #[test]
+    fn parse_paper_size_table() {
+        let close = |(w, h): (f32, f32), (ew, eh): (f32, f32)| (w - ew).abs() < 0.01 && (h - eh).abs() < 0.01;
+        let a4 = (595.276, 841.89);
+        for (spec, expected) in [("210x297mm", a4), ("a4", a4), (" A4 ", a4), ("612x792", (612.0, 792.0)), ("letter", (612.0, 792.0)), ("100.5x50", (100.5, 50.0))] {
+            let size = parse_paper_size(spec).unwrap();
+            assert!(close(size, expected), "{}: {:?}", spec, size);
+        }
+        for spec in ["x", "0x5", "5x0", "-5x5", "210", "210x", "x297mm", "a6", "", "mm"] {
+            assert!(parse_paper_size(spec).is_err(), "{}", spec);
+        }
+    }
+
+}
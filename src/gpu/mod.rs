@@ -1,3 +1,6 @@
+pub mod texture;
+pub mod strokes;
+
 use winit::window::Window;
 
 pub struct GpuContext {
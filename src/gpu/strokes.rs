@@ -0,0 +1,262 @@
+//! Anotaciones vectoriales en GPU: trazos, figuras y resaltados de texto se teselan en triángulos
+//! en puntos de página y se pintan en su propia pasada sobre una textura del tamaño de la ventana.
+//! No dependen de la resolución del overlay raster (nítidas con cualquier zoom) y dibujar solo
+//! sube la geometría del trazo en curso, no la página entera.
+
+use std::ops::Range;
+
+use crate::ui::overlay::{page_polygons, Annotation};
+
+pub const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8Unorm;
+const STENCIL_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Stencil8;
+// Muestras por píxel del antialiasing (4 lo soporta cualquier adaptador)
+const SAMPLES: u32 = 4;
+// Cada anotación marca el stencil con su propio valor (1..=255): una anotación translúcida no se
+// pinta dos veces sobre sí misma (como la máscara de cobertura del raster). Con más, otra pasada.
+const STENCIL_VALUES: usize = 255;
+// Puntos de curva por punto de página al suavizar la tinta. Fijo: el zoom no obliga a reteselar
+const CURVE_DENSITY: f32 = 2.0;
+const INITIAL_CAPACITY: u64 = 64 * 1024;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct StrokeVertex {
+    position: [f32; 2], // Puntos de página
+    color: [u8; 4],
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct StrokeCamera {
+    scale: [f32; 2],
+    translation: [f32; 2],
+    page_size: [f32; 2],
+    padding: [f32; 2],
+}
+
+/// Geometría de varias anotaciones en un vertex buffer, con el tramo de vértices de cada una.
+struct Mesh {
+    label: &'static str,
+    buffer: wgpu::Buffer,
+    capacity: u64,
+    ranges: Vec<Range<u32>>,
+}
+
+impl Mesh {
+    fn new(device: &wgpu::Device, label: &'static str) -> Self {
+        Self { label, buffer: vertex_buffer(device, label, INITIAL_CAPACITY), capacity: INITIAL_CAPACITY, ranges: Vec::new() }
+    }
+
+    fn upload(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, annotations: &[&Annotation]) {
+        let mut vertices = Vec::new();
+        self.ranges.clear();
+        for annotation in annotations {
+            let start = vertices.len() as u32;
+            tessellate(annotation, &mut vertices);
+            if vertices.len() as u32 > start {
+                self.ranges.push(start..vertices.len() as u32);
+            }
+        }
+
+        let bytes: &[u8] = bytemuck::cast_slice(&vertices);
+        if bytes.len() as u64 > self.capacity {
+            self.capacity = (bytes.len() as u64).next_power_of_two();
+            self.buffer = vertex_buffer(device, self.label, self.capacity);
+        }
+        if !bytes.is_empty() {
+            queue.write_buffer(&self.buffer, 0, bytes);
+        }
+    }
+}
+
+fn vertex_buffer(device: &wgpu::Device, label: &str, size: u64) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some(label),
+        size,
+        usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
+
+/// Capa de anotaciones vectoriales de la página actual.
+pub struct StrokeLayer {
+    pipeline: wgpu::RenderPipeline,
+    camera_buffer: wgpu::Buffer,
+    camera_bind_group: wgpu::BindGroup,
+    page: Mesh,   // Anotaciones terminadas
+    active: Mesh, // La que se está dibujando: se vuelve a teselar con cada muestra
+    msaa_view: wgpu::TextureView,
+    stencil_view: wgpu::TextureView,
+    /// Resultado ya resuelto (sin MSAA), del tamaño de la ventana; lo lee fs_main.
+    pub view: wgpu::TextureView,
+}
+
+impl StrokeLayer {
+    pub fn new(device: &wgpu::Device, width: u32, height: u32) -> Self {
+        let camera_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Stroke Camera"),
+            size: std::mem::size_of::<StrokeCamera>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let camera_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX,
+                ty: wgpu::BindingType::Buffer { ty: wgpu::BufferBindingType::Uniform, has_dynamic_offset: false, min_binding_size: None },
+                count: None,
+            }],
+            label: Some("Stroke Camera Layout"),
+        });
+        let camera_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &camera_layout,
+            entries: &[wgpu::BindGroupEntry { binding: 0, resource: camera_buffer.as_entire_binding() }],
+            label: Some("Stroke Camera BG"),
+        });
+
+        let shader = device.create_shader_module(wgpu::include_wgsl!("../../assets/shaders/strokes.wgsl"));
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Stroke Pipeline Layout"),
+            bind_group_layouts: &[&camera_layout],
+            push_constant_ranges: &[],
+        });
+        // Solo pasa donde el stencil aún no tiene el valor de esta anotación, y lo deja puesto
+        let stencil_face = wgpu::StencilFaceState {
+            compare: wgpu::CompareFunction::NotEqual,
+            fail_op: wgpu::StencilOperation::Keep,
+            depth_fail_op: wgpu::StencilOperation::Keep,
+            pass_op: wgpu::StencilOperation::Replace,
+        };
+        let premultiplied = wgpu::BlendComponent { src_factor: wgpu::BlendFactor::One, dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha, operation: wgpu::BlendOperation::Add };
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Stroke Pipeline"),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_stroke",
+                buffers: &[wgpu::VertexBufferLayout {
+                    array_stride: std::mem::size_of::<StrokeVertex>() as wgpu::BufferAddress,
+                    step_mode: wgpu::VertexStepMode::Vertex,
+                    attributes: &[
+                        wgpu::VertexAttribute { offset: 0, shader_location: 0, format: wgpu::VertexFormat::Float32x2 },
+                        wgpu::VertexAttribute { offset: std::mem::size_of::<[f32; 2]>() as wgpu::BufferAddress, shader_location: 1, format: wgpu::VertexFormat::Unorm8x4 },
+                    ],
+                }],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_stroke",
+                targets: &[Some(wgpu::ColorTargetState { format: FORMAT, blend: Some(wgpu::BlendState { color: premultiplied, alpha: premultiplied }), write_mask: wgpu::ColorWrites::ALL })],
+            }),
+            primitive: wgpu::PrimitiveState::default(), // Sin descartar caras: los triángulos salen en cualquier sentido
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: STENCIL_FORMAT,
+                depth_write_enabled: false,
+                depth_compare: wgpu::CompareFunction::Always,
+                stencil: wgpu::StencilState { front: stencil_face, back: stencil_face, read_mask: 0xff, write_mask: 0xff },
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState { count: SAMPLES, mask: !0, alpha_to_coverage_enabled: false },
+            multiview: None,
+        });
+
+        let (msaa_view, stencil_view, view) = create_targets(device, width, height);
+        Self {
+            pipeline, camera_buffer, camera_bind_group,
+            page: Mesh::new(device, "Stroke Page Vertices"),
+            active: Mesh::new(device, "Stroke Active Vertices"),
+            msaa_view, stencil_view, view,
+        }
+    }
+
+    /// Las texturas miden lo mismo que la ventana. Hay que rehacer los bind groups que usan `view`.
+    pub fn resize(&mut self, device: &wgpu::Device, width: u32, height: u32) {
+        (self.msaa_view, self.stencil_view, self.view) = create_targets(device, width, height);
+    }
+
+    /// Misma cámara que la página (`scale` y `translation` de CameraUniform) y su tamaño en puntos.
+    pub fn set_camera(&self, queue: &wgpu::Queue, scale: [f32; 2], translation: [f32; 2], page_size: (f32, f32)) {
+        let camera = StrokeCamera { scale, translation, page_size: [page_size.0, page_size.1], padding: [0.0; 2] };
+        queue.write_buffer(&self.camera_buffer, 0, bytemuck::cast_slice(&[camera]));
+    }
+
+    /// Anotaciones terminadas de la página, en orden de pintado.
+    pub fn set_page(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, annotations: &[&Annotation]) {
+        self.page.upload(device, queue, annotations);
+    }
+
+    /// La anotación que se está dibujando (None al terminarla: pasa a `set_page`).
+    pub fn set_active(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, annotation: Option<&Annotation>) {
+        self.active.upload(device, queue, annotation.as_slice());
+    }
+
+    /// Pinta las anotaciones en `view`. Va antes de la pasada principal, que la lee.
+    pub fn render(&self, encoder: &mut wgpu::CommandEncoder) {
+        let draws: Vec<(&wgpu::Buffer, &Range<u32>)> = self.page.ranges.iter().map(|r| (&self.page.buffer, r))
+            .chain(self.active.ranges.iter().map(|r| (&self.active.buffer, r)))
+            .collect();
+        // Sin nada que pintar hay que limpiar igualmente lo del frame anterior
+        let batches: Vec<_> = if draws.is_empty() { vec![&draws[..]] } else { draws.chunks(STENCIL_VALUES).collect() };
+
+        for (n, batch) in batches.into_iter().enumerate() {
+            let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Stroke Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &self.msaa_view,
+                    resolve_target: Some(&self.view),
+                    ops: wgpu::Operations {
+                        load: if n == 0 { wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT) } else { wgpu::LoadOp::Load },
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: &self.stencil_view,
+                    depth_ops: None,
+                    stencil_ops: Some(wgpu::Operations { load: wgpu::LoadOp::Clear(0), store: wgpu::StoreOp::Discard }),
+                }),
+                occlusion_query_set: None,
+                timestamp_writes: None,
+            });
+            pass.set_pipeline(&self.pipeline);
+            pass.set_bind_group(0, &self.camera_bind_group, &[]);
+            for (i, (buffer, range)) in batch.iter().enumerate() {
+                pass.set_stencil_reference(i as u32 + 1);
+                pass.set_vertex_buffer(0, buffer.slice(..));
+                pass.draw((*range).clone(), 0..1);
+            }
+        }
+    }
+}
+
+// Color con MSAA, stencil con MSAA y el color resuelto que se muestrea
+fn create_targets(device: &wgpu::Device, width: u32, height: u32) -> (wgpu::TextureView, wgpu::TextureView, wgpu::TextureView) {
+    let texture = |label: &str, format: wgpu::TextureFormat, sample_count: u32, usage: wgpu::TextureUsages| {
+        device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size: wgpu::Extent3d { width: width.max(1), height: height.max(1), depth_or_array_layers: 1 },
+            mip_level_count: 1,
+            sample_count,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage,
+            view_formats: &[],
+        }).create_view(&wgpu::TextureViewDescriptor::default())
+    };
+    (
+        texture("Stroke MSAA", FORMAT, SAMPLES, wgpu::TextureUsages::RENDER_ATTACHMENT),
+        texture("Stroke Stencil", STENCIL_FORMAT, SAMPLES, wgpu::TextureUsages::RENDER_ATTACHMENT),
+        texture("Strokes", FORMAT, 1, wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING),
+    )
+}
+
+/// Triángulos de una anotación (trazo, figura o resaltado de texto) en puntos de página: un abanico
+/// por cada polígono convexo de `page_polygons`.
+fn tessellate(annotation: &Annotation, out: &mut Vec<StrokeVertex>) {
+    let color = annotation.color;
+    for polygon in page_polygons(annotation, CURVE_DENSITY) {
+        let Some((&first, rest)) = polygon.split_first() else { continue };
+        for pair in rest.windows(2) {
+            out.extend([first, pair[0], pair[1]].map(|p| StrokeVertex { position: [p.x, p.y], color }));
+        }
+    }
+}
@@ -1,5 +1,4 @@
 use anyhow::*;
-use image::GenericImageView;
 
 pub struct Texture {
     pub texture: wgpu::Texture,
@@ -62,14 +61,4 @@ impl Texture {
 
         Ok(Self { texture, view, sampler })
     }
-    
-    // Crea una textura vacía para la capa de UI/Subrayado
-    pub fn create_empty(device: &wgpu::Device, width: u32, height: u32) -> Self {
-        // Creamos un buffer transparente (todo ceros)
-        let size = (width * height * 4) as usize;
-        let data = vec![0u8; size]; 
-        // Usamos la cola interna (trampa temporal para simplificar firma)
-        // En prod esto se haría mejor, pero funcionará para init.
-        panic!("Use from_bytes con vector de ceros para inicializar");
-    }
 }
@@ -0,0 +1,273 @@
+use anyhow::{anyhow, bail, Context, Result};
+use pdfium_render::prelude::*;
+use std::path::{Path, PathBuf};
+
+use crate::cli::{parse_image_page, parse_page_ranges, parse_paper_size};
+use crate::pdf::{same_file, PdfSystem, images::{collect_images, is_image, Fit}, impose::Layout, organize::split_target, outline::read_outline, render::render_page_to_memory};
+
+/// Subcomandos que funcionan sin ventana ni GPU (aptos para scripts y CI).
+pub const SUBCOMMANDS: &[&str] = &["info", "text", "render", "outline", "merge", "split", "impose", "images"];
+
+pub const USAGE: &str = "\
+Uso sin ventana:
+  windp info    ARCHIVO.pdf                          Metadatos y tamaño de cada página
+  windp text    ARCHIVO.pdf [--pages R]              Texto de cada página (separadas por \\f)
+  windp render  ARCHIVO.pdf [--pages R] [--dpi N] [-o DIR]
+                                                     Rasteriza a PNG (por defecto 150 DPI)
+  windp outline ARCHIVO.pdf                          Marcadores en JSON
+  windp merge   A.pdf B.pdf... -o SALIDA.pdf         Une los documentos en uno, en ese orden
+  windp split   ARCHIVO.pdf [--ranges R] [-o DIR]    Un PDF por rango (por defecto, uno por página)
+                                                     llamados ARCHIVO-1-3.pdf, ARCHIVO-4-9.pdf...
+  windp impose  ARCHIVO.pdf [--layout L] [--sheet P] -o SALIDA.pdf
+                                                     Varias páginas por hoja para imprimir; L es 2up
+                                                     (por defecto), 4up o booklet (cuadernillo)
+  windp images  IMAGEN|CARPETA... [--page P] [--fit F] -o SALIDA.pdf
+                                                     Una página por imagen (PNG, JPEG o TIFF; de una
+                                                     carpeta, por orden de nombre). P es auto (por
+                                                     defecto, del tamaño de la imagen) o un papel; F
+                                                     es fit (entera, por defecto) o fill (sin márgenes)
+
+Opciones comunes: --password CLAVE. R son rangos como 1-3,5,7-. P es un papel (a4, letter...)
+o ANCHOxALTO en puntos (612x792) o en milímetros (210x297mm). Los demás subcomandos también
+aceptan una imagen en lugar del PDF, como un documento de una página";
+
+pub fn is_subcommand(arg: &str) -> bool {
+    SUBCOMMANDS.contains(&arg)
+}
+
+/// Opciones de los subcomandos; cada uno usa las que le corresponden.
+struct Options {
+    file: String,
+    more_files: Vec<String>, // Solo merge e images aceptan más de un archivo
+    pages: Option<String>,
+    ranges: Option<String>,
+    layout: Layout,
+    sheet: Option<(f32, f32)>,
+    page_size: Option<(f32, f32)>,
+    fit: Fit,
+    dpi: f32,
+    output: Option<PathBuf>,
+    password: Option<String>,
+}
+
+/// Ejecuta un subcomando. `args` empieza por el nombre del subcomando.
+/// No crea bucle de eventos ni dispositivo wgpu: solo PDFium.
+pub fn run(args: &[String]) -> Result<()> {
+    let (command, rest) = args.split_first().ok_or_else(|| anyhow!("Falta el subcomando"))?;
+    let options = parse_options(rest)?;
+    // Sin PDFium no hay nada que hacer: error y código de salida, no un pánico
+    let pdf_system = PdfSystem::try_new().map_err(|e| anyhow!("No se pudo cargar PDFium: {:?}", e))?;
+    if command == "merge" {
+        return merge_files(&options, &pdf_system);
+    }
+    if command == "images" {
+        return combine_images(&options, &pdf_system);
+    }
+    if let Some(extra) = options.more_files.first() {
+        bail!("Sobra el argumento: {}", extra);
+    }
+    // Una imagen se abre como un PDF de una página, convertido en el directorio temporal
+    let source = if is_image(Path::new(&options.file)) { pdf_system.image_as_pdf(Path::new(&options.file))? } else { options.file.clone() };
+    let document = pdf_system.open_file_with_password(&source, options.password.as_deref())
+        .map_err(|e| anyhow!("No se pudo abrir {}: {:?}", options.file, e))?;
+
+    match command.as_str() {
+        "info" => print_info(&options, &document),
+        "text" => print_text(&options, &document),
+        "render" => render_pages(&options, &document),
+        "outline" => print_outline(&document),
+        "split" => split_file(&options, &source, &document, &pdf_system),
+        "impose" => impose_file(&options, &source, &pdf_system),
+        _ => bail!("Subcomando desconocido: {}", command),
+    }
+}
+
+fn parse_options(args: &[String]) -> Result<Options> {
+    let mut file = None;
+    let mut options = Options { file: String::new(), more_files: Vec::new(), pages: None, ranges: None, layout: Layout::TwoUp, sheet: None, page_size: None, fit: Fit::Contain, dpi: 150.0, output: None, password: None };
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().cloned().ok_or_else(|| anyhow!("Falta el valor de {}", arg));
+        match arg.as_str() {
+            "--pages" => options.pages = Some(value()?),
+            "--ranges" => options.ranges = Some(value()?),
+            "--layout" => options.layout = Layout::parse(&value()?).map_err(|e| anyhow!(e))?,
+            "--sheet" => options.sheet = Some(parse_paper_size(&value()?).map_err(|e| anyhow!(e))?),
+            "--page" => options.page_size = parse_image_page(&value()?).map_err(|e| anyhow!(e))?,
+            "--fit" => options.fit = Fit::parse(&value()?).map_err(|e| anyhow!(e))?,
+            "--dpi" => options.dpi = value()?.parse().context("DPI inválido")?,
+            "-o" | "--output" => options.output = Some(PathBuf::from(value()?)),
+            "--password" => options.password = Some(value()?),
+            _ if arg.starts_with('-') => bail!("Opción desconocida: {}\n\n{}", arg, USAGE),
+            _ if file.is_none() => file = Some(arg.clone()),
+            _ => options.more_files.push(arg.clone()),
+        }
+    }
+
+    options.file = file.ok_or_else(|| anyhow!("Falta el archivo PDF\n\n{}", USAGE))?;
+    if options.dpi <= 0.0 {
+        bail!("El DPI debe ser positivo");
+    }
+    Ok(options)
+}
+
+/// Páginas seleccionadas con --pages (todas si no se indicó), en base 0.
+fn selected_pages(options: &Options, document: &PdfDocument) -> Result<Vec<u16>> {
+    let total = document.pages().len();
+    match &options.pages {
+        Some(spec) => Ok(parse_page_ranges(spec, total).map_err(|e| anyhow!(e))?.into_iter().flatten().collect()),
+        None => Ok((0..total).collect()),
+    }
+}
+
+fn print_info(options: &Options, document: &PdfDocument) -> Result<()> {
+    println!("Archivo:     {}", options.file);
+    println!("Versión PDF: {:?}", document.version());
+
+    let metadata = document.metadata();
+    let tags = [
+        ("Título", PdfDocumentMetadataTagType::Title),
+        ("Autor", PdfDocumentMetadataTagType::Author),
+        ("Asunto", PdfDocumentMetadataTagType::Subject),
+        ("Palabras", PdfDocumentMetadataTagType::Keywords),
+        ("Creador", PdfDocumentMetadataTagType::Creator),
+        ("Productor", PdfDocumentMetadataTagType::Producer),
+        ("Creado", PdfDocumentMetadataTagType::CreationDate),
+        ("Modificado", PdfDocumentMetadataTagType::ModificationDate),
+    ];
+    for (label, tag) in tags {
+        if let Some(value) = metadata.get(tag) {
+            println!("{:<12} {}", format!("{}:", label), value.value());
+        }
+    }
+
+    let sizes = document.pages().page_sizes().map_err(|e| anyhow!("{:?}", e))?;
+    println!("Páginas:     {}", sizes.len());
+    for (idx, size) in sizes.iter().enumerate() {
+        let (w, h) = (size.width().value, size.height().value);
+        println!("  {:>4}: {:.1} x {:.1} pt ({:.0} x {:.0} mm)", idx + 1, w, h, w / 72.0 * 25.4, h / 72.0 * 25.4);
+    }
+    Ok(())
+}
+
+fn print_text(options: &Options, document: &PdfDocument) -> Result<()> {
+    for (n, page_idx) in selected_pages(options, document)?.into_iter().enumerate() {
+        let page = document.pages().get(page_idx).map_err(|e| anyhow!("Página {}: {:?}", page_idx + 1, e))?;
+        let text = page.text().map_err(|e| anyhow!("Página {}: {:?}", page_idx + 1, e))?;
+        // Salto de página (form feed) entre páginas, como pdftotext
+        if n > 0 {
+            print!("\x0c");
+        }
+        println!("{}", text.all());
+    }
+    Ok(())
+}
+
+fn render_pages(options: &Options, document: &PdfDocument) -> Result<()> {
+    let output = options.output.clone().unwrap_or_else(|| PathBuf::from("."));
+    std::fs::create_dir_all(&output).with_context(|| format!("No se pudo crear {:?}", output))?;
+    let stem = Path::new(&options.file).file_stem().unwrap_or_default().to_string_lossy();
+    let scale = options.dpi / 72.0;
+
+    for page_idx in selected_pages(options, document)? {
+        let bitmap = render_page_to_memory(document, page_idx, scale)
+            .map_err(|e| anyhow!("Página {}: {:?}", page_idx + 1, e))?;
+        let path = output.join(format!("{}-{}.png", stem, page_idx + 1));
+        bitmap.to_rgba_image().save(&path).with_context(|| format!("No se pudo guardar {:?}", path))?;
+        println!("{}", path.display());
+    }
+    Ok(())
+}
+
+fn print_outline(document: &PdfDocument) -> Result<()> {
+    println!("{}", serde_json::to_string_pretty(&read_outline(document))?);
+    Ok(())
+}
+
+fn merge_files(options: &Options, pdf_system: &PdfSystem) -> Result<()> {
+    let output = options.output.as_ref().ok_or_else(|| anyhow!("Falta el archivo de salida (-o SALIDA.pdf)"))?;
+    let sources: Vec<&str> = std::iter::once(options.file.as_str()).chain(options.more_files.iter().map(String::as_str)).collect();
+    if sources.iter().any(|source| same_file(Path::new(source), output)) {
+        bail!("La salida no puede ser uno de los documentos que se unen: {:?}", output);
+    }
+    let pages = pdf_system.merge(&sources, options.password.as_deref(), output)?;
+    println!("{} ({} documentos, {} páginas)", output.display(), sources.len(), pages);
+    Ok(())
+}
+
+fn split_file(options: &Options, source: &str, document: &PdfDocument, pdf_system: &PdfSystem) -> Result<()> {
+    let total = document.pages().len();
+    let ranges = match &options.ranges {
+        Some(spec) => parse_page_ranges(spec, total).map_err(|e| anyhow!(e))?,
+        None => (0..total).map(|page| page..page + 1).collect(),
+    };
+    let output = options.output.clone().unwrap_or_else(|| PathBuf::from("."));
+    std::fs::create_dir_all(&output).with_context(|| format!("No se pudo crear {:?}", output))?;
+    let base = output.join(Path::new(&options.file).file_name().unwrap_or_default());
+    if ranges.iter().any(|range| same_file(&split_target(&base, range), Path::new(&options.file))) {
+        bail!("Una de las partes sustituiría al documento: elige otra carpeta con -o");
+    }
+
+    for path in pdf_system.split(source, options.password.as_deref(), &base, &ranges)? {
+        println!("{}", path.display());
+    }
+    Ok(())
+}
+
+fn impose_file(options: &Options, source: &str, pdf_system: &PdfSystem) -> Result<()> {
+    let output = options.output.as_ref().ok_or_else(|| anyhow!("Falta el archivo de salida (-o SALIDA.pdf)"))?;
+    if same_file(Path::new(&options.file), output) {
+        bail!("La salida no puede sustituir al documento: {:?}", output);
+    }
+    let sheets = pdf_system.save_imposed(source, options.password.as_deref(), output, options.layout, options.sheet)?;
+    println!("{} ({}, {} caras)", output.display(), options.layout.name(), sheets);
+    Ok(())
+}
+
+fn combine_images(options: &Options, pdf_system: &PdfSystem) -> Result<()> {
+    let output = options.output.as_ref().ok_or_else(|| anyhow!("Falta el archivo de salida (-o SALIDA.pdf)"))?;
+    let inputs: Vec<&str> = std::iter::once(options.file.as_str()).chain(options.more_files.iter().map(String::as_str)).collect();
+    let images = collect_images(&inputs)?;
+    let pages = pdf_system.images_to_pdf(&images, options.page_size, options.fit, output)?;
+    println!("{} ({} páginas)", output.display(), pages);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(args: &[&str]) -> Result<Options> {
+        parse_options(&args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn options_collect_files_and_values() {
+        let parsed = options(&["a.pdf", "--pages", "1-3", "--dpi", "300", "-o", "salida", "b.pdf", "--password", "x"]).unwrap();
+        assert_eq!(parsed.file, "a.pdf");
+        assert_eq!(parsed.more_files, ["b.pdf"]);
+        assert_eq!(parsed.pages.as_deref(), Some("1-3"));
+        assert_eq!(parsed.dpi, 300.0);
+        assert_eq!(parsed.output, Some(PathBuf::from("salida")));
+        assert_eq!(parsed.password.as_deref(), Some("x"));
+
+        let parsed = options(&["a.pdf"]).unwrap();
+        assert_eq!((parsed.dpi, parsed.pages, parsed.output), (150.0, None, None));
+    }
+
+    #[test]
+    fn options_reject_bad_input() {
+        let cases: &[(&[&str], &str)] = &[
+            (&[], "Falta el archivo PDF"),
+            (&["a.pdf", "--pages"], "Falta el valor de --pages"),
+            (&["a.pdf", "--dpi", "alto"], "DPI inválido"),
+            (&["a.pdf", "--dpi", "0"], "El DPI debe ser positivo"),
+            (&["a.pdf", "--zoom", "2"], "Opción desconocida: --zoom"),
+        ];
+        for (args, error) in cases {
+            let message = options(args).err().map(|e| e.to_string()).unwrap_or_default();
+            assert!(message.starts_with(error), "{:?}: {}", args, message);
+        }
+    }
+}
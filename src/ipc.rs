@@ -0,0 +1,321 @@
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use winit::event_loop::EventLoopProxy;
+
+use crate::remote::Command;
+
+#[cfg(unix)]
+pub(crate) use std::os::unix::net::{UnixListener as LocalListener, UnixStream as LocalStream};
+#[cfg(not(unix))]
+pub(crate) use std::net::{TcpListener as LocalListener, TcpStream as LocalStream};
+
+/// Tiempo máximo para leer o escribir una petición: un cliente atascado no bloquea a los demás.
+const IO_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Eventos que llegan al bucle de winit desde fuera de la ventana.
+#[derive(Debug, Clone)]
+pub enum AppEvent {
+    /// Otra instancia nos pide abrir un archivo (y opcionalmente saltar a una página, base 0).
+    OpenFile { path: String, page: Option<u16> },
+    /// Otra instancia se lanzó sin archivo: solo traemos la ventana al frente.
+    Activate,
+    /// Orden JSON recibida por el socket de control remoto.
+    Remote { client: u64, id: Option<serde_json::Value>, command: Command },
+}
+
+/// Directorio para los sockets locales.
+/// En Linux es XDG_RUNTIME_DIR (por usuario). Si no existe, una carpeta "windp-USUARIO" dentro del
+/// directorio temporal: este es compartido, y otro usuario podría crear antes que nosotros el socket.
+#[cfg(unix)]
+pub fn runtime_dir() -> PathBuf {
+    std::env::var_os("XDG_RUNTIME_DIR").map(PathBuf::from).unwrap_or_else(|| {
+        let user = std::env::var("USER").or_else(|_| std::env::var("LOGNAME")).unwrap_or_default();
+        std::env::temp_dir().join(format!("windp-{}", user))
+    })
+}
+
+/// En Windows el directorio temporal ya es del usuario.
+#[cfg(not(unix))]
+pub fn runtime_dir() -> PathBuf {
+    std::env::temp_dir()
+}
+
+/// Crea, si hace falta, la carpeta de `runtime_dir` con permisos 0700 y la devuelve. Si ya existía
+/// (o la creó otro), solo la aceptamos si nadie más puede entrar.
+#[cfg(unix)]
+pub(crate) fn private_runtime_dir() -> io::Result<PathBuf> {
+    let dir = runtime_dir();
+    if std::env::var_os("XDG_RUNTIME_DIR").is_none() {
+        ensure_private_dir(&dir)?;
+    }
+    Ok(dir)
+}
+
+#[cfg(unix)]
+fn ensure_private_dir(dir: &Path) -> io::Result<()> {
+    use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+
+    match std::fs::DirBuilder::new().mode(0o700).create(dir) {
+        Err(e) if e.kind() != io::ErrorKind::AlreadyExists => return Err(e),
+        _ => {}
+    }
+    // Una carpeta ajena con 0700 ni siquiera podríamos abrirla; basta con descartar enlaces y permisos
+    // para el grupo u otros
+    let metadata = std::fs::symlink_metadata(dir)?;
+    if !metadata.is_dir() || metadata.permissions().mode() & 0o077 != 0 {
+        return Err(io::Error::new(io::ErrorKind::PermissionDenied, format!("{} no es una carpeta privada", dir.display())));
+    }
+    Ok(())
+}
+
+/// Ruta del socket de la instancia única.
+#[cfg(unix)]
+fn socket_path() -> io::Result<PathBuf> {
+    Ok(private_runtime_dir()?.join("windp.sock"))
+}
+
+#[cfg(unix)]
+fn connect() -> io::Result<LocalStream> {
+    LocalStream::connect(socket_path()?)
+}
+
+/// Sin sockets Unix (Windows) el canal es un puerto de loopback cualquiera, que cualquier proceso
+/// local podría usar: la instancia anota el puerto y una clave aleatoria en este archivo del usuario,
+/// y solo atiende peticiones que empiecen por esa clave.
+#[cfg(not(unix))]
+fn endpoint_file() -> PathBuf {
+    runtime_dir().join("windp-instance")
+}
+
+#[cfg(not(unix))]
+fn connect() -> io::Result<LocalStream> {
+    let info = std::fs::read_to_string(endpoint_file())?;
+    let (port, token) = info.trim().split_once('\t').ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "windp-instance inválido"))?;
+    let port: u16 = port.parse().map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "windp-instance inválido"))?;
+    let mut stream = LocalStream::connect(("127.0.0.1", port))?;
+    writeln!(stream, "{}", token)?;
+    Ok(stream)
+}
+
+// El listener y la clave que deben traer las peticiones (None: el socket ya es solo del usuario)
+#[cfg(unix)]
+fn bind() -> io::Result<(LocalListener, Option<String>)> {
+    Ok((bind_endpoint(&socket_path()?.to_string_lossy())?, None))
+}
+
+#[cfg(not(unix))]
+fn bind() -> io::Result<(LocalListener, Option<String>)> {
+    let listener = LocalListener::bind(("127.0.0.1", 0))?;
+    let token = publish_endpoint(&endpoint_file(), &listener)?;
+    Ok((listener, Some(token)))
+}
+
+/// Anota en `file` el puerto de `listener` y una clave aleatoria nueva ("puerto\tclave"), y devuelve
+/// la clave: los clientes la leen de ahí y la mandan como primera línea.
+#[cfg(not(unix))]
+pub(crate) fn publish_endpoint(file: &Path, listener: &LocalListener) -> io::Result<String> {
+    let token = random_token();
+    std::fs::write(file, format!("{}\t{}", listener.local_addr()?.port(), token))?;
+    Ok(token)
+}
+
+// 128 bits de las claves aleatorias que std usa para HashMap (las saca del sistema operativo)
+#[cfg(not(unix))]
+fn random_token() -> String {
+    use std::hash::{BuildHasher, Hasher};
+    let half = || {
+        let mut hasher = std::collections::hash_map::RandomState::new().build_hasher();
+        hasher.write_u128(std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).map_or(0, |d| d.as_nanos()));
+        hasher.finish()
+    };
+    format!("{:016x}{:016x}", half(), half())
+}
+
+/// Abre un listener local en una ruta de socket (Unix) o en host:puerto (Windows).
+#[cfg(unix)]
+pub(crate) fn bind_endpoint(endpoint: &str) -> io::Result<LocalListener> {
+    use std::os::unix::fs::FileTypeExt;
+
+    let path = Path::new(endpoint);
+    if let Ok(metadata) = std::fs::symlink_metadata(path) {
+        // La ruta puede venir del usuario (--remote=): nunca borramos algo que no sea un socket
+        if !metadata.file_type().is_socket() {
+            return Err(io::Error::new(io::ErrorKind::AlreadyExists, format!("{} existe y no es un socket", endpoint)));
+        }
+        // Si alguien responde, el socket está vivo y no es nuestro
+        if LocalStream::connect(path).is_ok() {
+            return Err(io::Error::new(io::ErrorKind::AddrInUse, format!("{} ya está en uso", endpoint)));
+        }
+        // Nadie respondió: es un resto de una sesión que terminó mal.
+        std::fs::remove_file(path)?;
+    }
+    LocalListener::bind(path)
+}
+
+#[cfg(not(unix))]
+pub(crate) fn bind_endpoint(endpoint: &str) -> io::Result<LocalListener> {
+    LocalListener::bind(endpoint)
+}
+
+/// Intenta entregar el archivo a una instancia ya abierta.
+/// Retorna true si otra ventana lo aceptó (y este proceso debe terminar).
+pub fn forward_to_running(file_path: Option<&str>, page: Option<u16>) -> bool {
+    let Ok(mut stream) = connect() else {
+        return false;
+    };
+
+    // La otra instancia tiene otro directorio de trabajo: mandamos rutas absolutas.
+    let path = file_path.map(absolute_path).unwrap_or_default();
+    let page = page.map(|p| p.to_string()).unwrap_or_else(|| "-".to_string());
+
+    let _ = stream.set_read_timeout(Some(IO_TIMEOUT));
+    let _ = stream.set_write_timeout(Some(IO_TIMEOUT));
+    if writeln!(stream, "OPEN\t{}\t{}", page, path).is_err() {
+        return false;
+    }
+
+    // Esperamos el acuse para no salir antes de que la ventana reciba el mensaje
+    let mut reply = String::new();
+    BufReader::new(stream).read_line(&mut reply).is_ok() && reply.trim() == "OK"
+}
+
+/// Abre el socket de la instancia única y atiende peticiones en un hilo propio.
+/// Cada petición se reenvía al bucle de eventos a través del proxy.
+pub fn listen(proxy: EventLoopProxy<AppEvent>) -> io::Result<()> {
+    let (listener, token) = bind()?;
+
+    std::thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let _ = stream.set_read_timeout(Some(IO_TIMEOUT));
+            let _ = stream.set_write_timeout(Some(IO_TIMEOUT));
+            if let Some(event) = read_request(&stream, token.as_deref()) {
+                let accepted = proxy.send_event(event).is_ok();
+                let _ = writeln!(&stream, "{}", if accepted { "OK" } else { "ERR" });
+                if !accepted {
+                    // El bucle de eventos se ha cerrado: no queda ventana a la que reenviar nada
+                    break;
+                }
+            }
+        }
+    });
+
+    Ok(())
+}
+
+/// Elimina el socket al cerrar para que la siguiente ejecución no lo vea como vivo.
+pub fn cleanup() {
+    #[cfg(unix)]
+    {
+        if let Ok(path) = socket_path() {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = std::fs::remove_file(endpoint_file());
+    }
+}
+
+/// ¿Empieza la conexión con la clave esperada? Sin clave (sockets Unix) no se lee nada.
+pub(crate) fn token_matches(reader: &mut impl BufRead, token: Option<&str>) -> bool {
+    let Some(token) = token else { return true };
+    let mut line = String::new();
+    reader.read_line(&mut line).is_ok() && line.trim_end_matches(['\r', '\n']) == token
+}
+
+fn read_request(stream: &LocalStream, token: Option<&str>) -> Option<AppEvent> {
+    let mut reader = BufReader::new(stream);
+    if !token_matches(&mut reader, token) {
+        return None;
+    }
+    let mut line = String::new();
+    reader.read_line(&mut line).ok()?;
+
+    // Formato: OPEN \t página|- \t ruta
+    let mut parts = line.trim_end_matches(['\r', '\n']).splitn(3, '\t');
+    if parts.next()? != "OPEN" {
+        return None;
+    }
+    let page = parts.next()?.parse::<u16>().ok();
+    let path = parts.next()?.to_string();
+    if path.is_empty() {
+        return Some(AppEvent::Activate);
+    }
+
+    Some(AppEvent::OpenFile { path, page })
+}
+
+fn absolute_path(path: &str) -> String {
+    std::path::absolute(Path::new(path))
+        .map(|p| p.to_string_lossy().into_owned())
+        .unwrap_or_else(|_| path.to_string())
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("windp-ipc-{}-{}", std::process::id(), name))
+    }
+
+    #[test]
+    fn requests_must_start_with_the_token() {
+        let mut good = io::Cursor::new("clave\r\nOPEN\t-\t\n");
+        assert!(token_matches(&mut good, Some("clave")));
+        let mut rest = String::new();
+        good.read_line(&mut rest).unwrap();
+        assert_eq!(rest, "OPEN\t-\t\n");
+
+        assert!(!token_matches(&mut io::Cursor::new("POST / HTTP/1.1\r\n"), Some("clave")));
+        assert!(!token_matches(&mut io::Cursor::new(""), Some("clave")));
+        assert!(token_matches(&mut io::Cursor::new(""), None));
+    }
+
+    #[test]
+    fn private_dir_is_created_owner_only_and_shared_ones_are_refused() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = temp_path("private");
+        ensure_private_dir(&dir).unwrap();
+        assert_eq!(std::fs::metadata(&dir).unwrap().permissions().mode() & 0o777, 0o700);
+        ensure_private_dir(&dir).unwrap(); // Ya existía y es nuestra
+        std::fs::set_permissions(&dir, std::fs::Permissions::from_mode(0o777)).unwrap();
+        assert_eq!(ensure_private_dir(&dir).err().map(|e| e.kind()), Some(io::ErrorKind::PermissionDenied));
+        std::fs::remove_dir(&dir).unwrap();
+
+        let file = temp_path("not-a-dir");
+        std::fs::write(&file, "").unwrap();
+        assert!(ensure_private_dir(&file).is_err());
+        std::fs::remove_file(&file).unwrap();
+    }
+
+    #[test]
+    fn bind_endpoint_refuses_to_replace_a_regular_file() {
+        let path = temp_path("file");
+        std::fs::write(&path, "datos").unwrap();
+        let result = bind_endpoint(&path.to_string_lossy());
+        assert_eq!(result.err().map(|e| e.kind()), Some(io::ErrorKind::AlreadyExists));
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "datos");
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn bind_endpoint_replaces_a_stale_socket() {
+        let path = temp_path("stale");
+        drop(LocalListener::bind(&path).unwrap()); // Queda el archivo, sin nadie escuchando
+        let listener = bind_endpoint(&path.to_string_lossy());
+        assert!(listener.is_ok());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn bind_endpoint_refuses_a_live_socket() {
+        let path = temp_path("live");
+        let _live = LocalListener::bind(&path).unwrap();
+        let result = bind_endpoint(&path.to_string_lossy());
+        assert_eq!(result.err().map(|e| e.kind()), Some(io::ErrorKind::AddrInUse));
+        std::fs::remove_file(&path).unwrap();
+    }
+}
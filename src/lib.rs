@@ -0,0 +1,10 @@
+pub mod cli;
+pub mod gpu;
+pub mod headless;
+pub mod ipc;
+pub mod pdf;
+pub mod remote;
+pub mod sidecar;
+pub mod state;
+pub mod ui;
+pub mod xfdf;
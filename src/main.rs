@@ -1,78 +1,161 @@
 use windp::state::State;
+use windp::cli::{self, CliAction};
+use windp::headless;
+use windp::ipc::{self, AppEvent};
+use windp::remote::{self, RemoteServer};
 use windp::pdf::PdfSystem; // Necesitamos instanciarlo aquí para manejar lifetimes
 use winit::{
     event::*,
-    event_loop::{ControlFlow, EventLoop},
-    window::WindowBuilder,
+    event_loop::EventLoopBuilder,
+    window::{Fullscreen, WindowBuilder},
 };
 
 fn main() {
     env_logger::init();
 
-    // 1. Capturar argumentos de lanzamiento (Para "Abrir con...")
-    let args: Vec<String> = std::env::args().collect();
-    let file_path = if args.len() > 1 {
-        // args[0] es el ejecutable, args[1] es el archivo PDF
-        Some(args[1].clone())
-    } else {
-        None
+    let args: Vec<String> = std::env::args().skip(1).collect(); // args[0] es el ejecutable
+
+    // 0. Subcomandos sin ventana (info, text, render, outline): ni winit ni wgpu
+    if args.first().is_some_and(|arg| headless::is_subcommand(arg)) {
+        if let Err(e) = headless::run(&args) {
+            eprintln!("Error: {:#}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    // 1. Capturar argumentos de lanzamiento (Para "Abrir con..." y scripts)
+    let cli = match cli::parse(args) {
+        Ok(CliAction::Run(cli)) => cli,
+        Ok(CliAction::Help) => {
+            println!("{}\n\n{}", cli::USAGE, headless::USAGE);
+            return;
+        },
+        Ok(CliAction::Version) => {
+            println!("WindP {}", env!("CARGO_PKG_VERSION"));
+            return;
+        },
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, cli::USAGE);
+            std::process::exit(2);
+        },
     };
 
-    let event_loop = EventLoop::new().unwrap();
+    // 1.1 Varios archivos: el primero es para esta ventana, el resto abre ventanas propias
+    let first_file = cli.files.first();
+    for extra in cli.files.iter().skip(1) {
+        let spawned = std::env::current_exe().and_then(|exe| std::process::Command::new(exe).args(cli.relaunch_args(extra)).spawn());
+        if let Err(e) = spawned {
+            eprintln!("No se pudo abrir {} en otra ventana: {:?}", extra.path, e);
+        }
+    }
+    let view = cli.view_for(first_file);
+    let file_path = first_file.map(|f| f.path.clone());
+
+    // 1.2 Modo instancia única: si ya hay una ventana abierta le pasamos el archivo y salimos
+    if cli.single_instance && ipc::forward_to_running(file_path.as_deref(), view.page) {
+        println!("WindP ya estaba abierto: archivo enviado a la instancia existente");
+        return;
+    }
+
+    let event_loop = EventLoopBuilder::<AppEvent>::with_user_event().build().unwrap();
     let window = WindowBuilder::new()
         .with_title("WindP")
         .with_inner_size(winit::dpi::PhysicalSize::new(1200, 800))
         .with_transparent(true)
         .with_decorations(true)
+        .with_fullscreen(cli.presentation.then_some(Fullscreen::Borderless(None)))
         .build(&event_loop)
         .unwrap();
+    // Texto de notas y comentarios: con IME llega compuesto (acentos, CJK...) por WindowEvent::Ime
+    window.set_ime_allowed(true);
 
     // 2. Inicializar sistema PDF en el hilo principal
     // Lo creamos aquí para que viva tanto como la ventana
     let pdf_system = PdfSystem::new();
 
-    // 3. Pasamos el sistema y la ruta (si existe) al Estado
-    let mut state = pollster::block_on(State::new(&window, &pdf_system, file_path));
+    // 3. Pasamos el sistema y la ruta (si existe) al Estado, y aplicamos la vista inicial
+    let mut state = pollster::block_on(State::new(&window, &pdf_system, file_path, cli.password.as_deref(), cli.annotations.unwrap_or_default()));
+    state.set_presentation(cli.presentation);
+    state.apply_initial_view(&view);
+
+    // 4. Somos la instancia principal: escuchamos a los siguientes lanzamientos
+    if cli.single_instance {
+        if let Err(e) = ipc::listen(event_loop.create_proxy()) {
+            eprintln!("No se pudo abrir el socket de instancia única: {:?}", e);
+        }
+    }
+
+    // 5. Control remoto opcional (automatización de tests, integración con editores)
+    let mut remote = cli.remote.clone().and_then(|endpoint| {
+        RemoteServer::start(&endpoint, event_loop.create_proxy())
+            .map_err(|e| eprintln!("No se pudo abrir el control remoto en {}: {:?}", endpoint, e))
+            .ok()
+    });
 
     let _ = event_loop.run(move |event, elwt| {
         match event {
             Event::WindowEvent {
                 ref event,
                 window_id,
-            } if window_id == window.id() => {
-                if !state.input(event) {
-                    match event {
-                        WindowEvent::CloseRequested
-                        | WindowEvent::KeyboardInput {
-                            event:
-                                KeyEvent {
-                                    state: ElementState::Pressed,
-                                    physical_key: winit::keyboard::PhysicalKey::Code(winit::keyboard::KeyCode::Escape),
-                                    ..
-                                },
-                            ..
-                        } => elwt.exit(),
-                        
-                        WindowEvent::Resized(physical_size) => {
-                            state.resize(*physical_size);
-                        }
-                        
-                        WindowEvent::RedrawRequested => {
-                            state.update();
-                            match state.render() {
-                                Ok(_) => {}
-                                Err(wgpu::SurfaceError::Lost) => state.resize(state.size()),
-                                Err(wgpu::SurfaceError::OutOfMemory) => elwt.exit(),
-                                Err(e) => eprintln!("{:?}", e),
-                            }
+            } if window_id == window.id() && !state.input(event) => {
+                match event {
+                    WindowEvent::CloseRequested
+                    | WindowEvent::KeyboardInput {
+                        event:
+                            KeyEvent {
+                                state: ElementState::Pressed,
+                                physical_key: winit::keyboard::PhysicalKey::Code(winit::keyboard::KeyCode::Escape),
+                                ..
+                            },
+                        ..
+                    } => elwt.exit(),
+                    
+                    WindowEvent::Resized(physical_size) => {
+                        state.resize(*physical_size);
+                    }
+                    
+                    WindowEvent::RedrawRequested => {
+                        state.update();
+                        match state.render() {
+                            Ok(_) => {}
+                            Err(wgpu::SurfaceError::Lost) => state.resize(state.size()),
+                            Err(wgpu::SurfaceError::OutOfMemory) => elwt.exit(),
+                            Err(e) => eprintln!("{:?}", e),
                         }
-                        _ => {}
                     }
+                    _ => {}
+                }
+            }
+            Event::UserEvent(AppEvent::OpenFile { path, page }) => {
+                if let Err(e) = state.open_document(&path, page, None) {
+                    state.notify(format!("No se pudo abrir {}: {:#}", path, e));
+                }
+                window.focus_window();
+            }
+            Event::UserEvent(AppEvent::Activate) => {
+                window.focus_window();
+            }
+            Event::UserEvent(AppEvent::Remote { client, id, command }) => {
+                if let Some(remote) = &remote {
+                    let result = remote::execute(&mut state, command);
+                    remote.reply(client, id, result);
                 }
             }
             Event::AboutToWait => {
+                if let Some(remote) = &mut remote {
+                    remote.publish(state.snapshot());
+                }
                 window.request_redraw();
             }
+            Event::LoopExiting => {
+                if cli.single_instance {
+                    ipc::cleanup();
+                }
+                if let Some(remote) = &remote {
+                    remote.shutdown();
+                }
+            }
             _ => {}
         }
     });
@@ -0,0 +1,925 @@
+use anyhow::{anyhow, bail, Context, Result};
+use pdfium_render::prelude::*;
+use std::io::Write;
+use std::os::raw::{c_int, c_ulong, c_void};
+use std::path::Path;
+
+use crate::ui::overlay::{filled_rects, new_name, Annotation, AnnotationKind, AnnotationStore, Color, InkSample, PagePoint, Shape, HIGHLIGHT_YELLOW, INK_BLACK, NOTE_YELLOW};
+
+// Subtipos y constantes de fpdf_annot.h (pdfium-render no los reexporta)
+const FPDF_ANNOT_TEXT: c_int = 1;
+const FPDF_ANNOT_FREETEXT: c_int = 3;
+const FPDF_ANNOT_LINE: c_int = 4;
+const FPDF_ANNOT_SQUARE: c_int = 5;
+const FPDF_ANNOT_CIRCLE: c_int = 6;
+const FPDF_ANNOT_HIGHLIGHT: c_int = 9;
+const FPDF_ANNOT_INK: c_int = 15;
+const FPDF_ANNOT_POPUP: c_int = 16;
+const FPDF_ANNOT_FLAG_HIDDEN: c_int = 2;
+const FPDF_ANNOT_FLAG_PRINT: c_int = 4;
+const COLORTYPE_COLOR: FPDFANNOT_COLORTYPE = 0;
+const AP_NORMAL: FPDF_ANNOT_APPEARANCEMODE = 0;
+const AP_ROLLOVER: FPDF_ANNOT_APPEARANCEMODE = 1;
+const AP_DOWN: FPDF_ANNOT_APPEARANCEMODE = 2;
+const COLORTYPE_INTERIOR: FPDFANNOT_COLORTYPE = 1;
+
+/// Prefijo del /NM de las anotaciones que escribe WindP ("windp-<id>"),
+/// para reconocerlas al volver a abrir el documento.
+pub const NAME_PREFIX: &str = "windp-";
+
+/// Lo que queda en el archivo tras escribir las anotaciones: cuántas se escribieron (nuevas o
+/// cambiadas) y dónde está cada una del modelo: (id, página, índice de su /Annot).
+pub struct WrittenAnnotations {
+    pub written: usize,
+    pub placed: Vec<(u64, u16, usize)>,
+}
+
+/// Anotaciones editables de una página, cada una con el índice del /Annot del que salió (un Ink de
+/// varios trazos da varias con el mismo). Con él se ocultan en el render de PDFium (las pinta el
+/// overlay) y, al guardar, se modifica ese mismo /Annot.
+pub struct PageAnnotations {
+    pub page: u16,
+    pub annotations: Vec<(usize, Annotation)>,
+}
+
+/// Lee las anotaciones que WindP sabe editar: Highlight, Ink, Square/Circle sin relleno,
+/// Text y FreeText. El resto (enlaces, formularios, sellos, popups...) se queda en el PDF
+/// tal cual: PDFium las sigue pintando y al guardar no se tocan.
+pub fn read_annotations(bindings: &dyn PdfiumLibraryBindings, source: &str, password: Option<&str>) -> Result<Vec<PageAnnotations>> {
+    let document = bindings.FPDF_LoadDocument(source, password);
+    if document.is_null() {
+        bail!("PDFium no pudo abrir {} (error {})", source, bindings.FPDF_GetLastError());
+    }
+
+    let mut pages = Vec::new();
+    for page_idx in 0..bindings.FPDF_GetPageCount(document) {
+        let page = bindings.FPDF_LoadPage(document, page_idx);
+        if page.is_null() {
+            continue;
+        }
+        let mut imported = PageAnnotations { page: page_idx as u16, annotations: Vec::new() };
+        for index in 0..bindings.FPDFPage_GetAnnotCount(page) {
+            let annot = bindings.FPDFPage_GetAnnot(page, index);
+            if annot.is_null() {
+                continue;
+            }
+            let annotations = read_annotation(bindings, annot);
+            imported.annotations.extend(annotations.into_iter().map(|annotation| (index as usize, annotation)));
+            bindings.FPDFPage_CloseAnnot(annot);
+        }
+        bindings.FPDF_ClosePage(page);
+        if !imported.annotations.is_empty() {
+            pages.push(imported);
+        }
+    }
+
+    bindings.FPDF_CloseDocument(document);
+    Ok(pages)
+}
+
+/// Oculta en el documento abierto (solo en memoria) los /Annot que están en el modelo,
+/// (página, índice), para que PDFium no los pinte debajo de las anotaciones del overlay.
+pub fn hide_annotations(document: &PdfDocument, annots: &[(u16, usize)]) {
+    for &(page_idx, index) in annots {
+        let Ok(page) = document.pages().get(page_idx) else { continue };
+        if let Ok(mut annotation) = page.annotations().get(index) {
+            let _ = annotation.set_is_hidden(true);
+        }
+    }
+}
+
+// ¿La edita WindP? (las demás se quedan en el PDF tal cual)
+fn is_editable(bindings: &dyn PdfiumLibraryBindings, annot: FPDF_ANNOTATION) -> bool {
+    if bindings.FPDFAnnot_GetFlags(annot) & FPDF_ANNOT_FLAG_HIDDEN != 0 {
+        return false;
+    }
+    match bindings.FPDFAnnot_GetSubtype(annot) {
+        FPDF_ANNOT_HIGHLIGHT | FPDF_ANNOT_INK | FPDF_ANNOT_LINE | FPDF_ANNOT_TEXT | FPDF_ANNOT_FREETEXT => true,
+        // Las figuras rellenas no caben en el modelo: se respetan tal cual
+        FPDF_ANNOT_SQUARE | FPDF_ANNOT_CIRCLE => {
+            let (mut r, mut g, mut b, mut a) = (0, 0, 0, 0);
+            !bindings.is_true(bindings.FPDFAnnot_GetColor(annot, COLORTYPE_INTERIOR, &mut r, &mut g, &mut b, &mut a))
+        },
+        _ => false,
+    }
+}
+
+// Una anotación del PDF en el modelo. Un Ink con varios trazos da una anotación por trazo.
+fn read_annotation(bindings: &dyn PdfiumLibraryBindings, annot: FPDF_ANNOTATION) -> Vec<Annotation> {
+    if !is_editable(bindings, annot) {
+        return Vec::new();
+    }
+    let subtype = bindings.FPDFAnnot_GetSubtype(annot);
+    let mut rect = FS_RECTF { left: 0.0, top: 0.0, right: 0.0, bottom: 0.0 };
+    if !bindings.is_true(bindings.FPDFAnnot_GetRect(annot, &mut rect)) {
+        return Vec::new();
+    }
+    let (left, right) = (rect.left.min(rect.right), rect.left.max(rect.right));
+    let (bottom, top) = (rect.bottom.min(rect.top), rect.bottom.max(rect.top));
+    let contents = string_value(bindings, annot, "Contents").unwrap_or_default();
+    let name = string_value(bindings, annot, "NM").unwrap_or_default();
+    let width = border_width(bindings, annot);
+
+    let kinds = match subtype {
+        FPDF_ANNOT_HIGHLIGHT => {
+            let rects: Vec<[f32; 4]> = (0..bindings.FPDFAnnot_CountAttachmentPoints(annot)).filter_map(|i| {
+                let mut q = FS_QUADPOINTSF { x1: 0.0, y1: 0.0, x2: 0.0, y2: 0.0, x3: 0.0, y3: 0.0, x4: 0.0, y4: 0.0 };
+                bindings.is_true(bindings.FPDFAnnot_GetAttachmentPoints(annot, i, &mut q)).then(|| {
+                    let (xs, ys) = ([q.x1, q.x2, q.x3, q.x4], [q.y1, q.y2, q.y3, q.y4]);
+                    let min = |v: [f32; 4]| v.into_iter().fold(f32::MAX, f32::min);
+                    let max = |v: [f32; 4]| v.into_iter().fold(f32::MIN, f32::max);
+                    [min(xs), min(ys), max(xs), max(ys)]
+                })
+            }).collect();
+            let rects = if rects.is_empty() { vec![[left, bottom, right, top]] } else { rects };
+            vec![AnnotationKind::TextHighlight { rects, text: contents }]
+        },
+        FPDF_ANNOT_INK => {
+            let strokes = ink_paths(bindings, annot);
+            // Líneas y flechas nuestras: el primer trazo es el cuerpo (ver `new_name`)
+            let shape = if name.starts_with(&format!("{}line-", NAME_PREFIX)) {
+                Some(Shape::Line)
+            } else if name.starts_with(&format!("{}arrow-", NAME_PREFIX)) {
+                Some(Shape::Arrow)
+            } else {
+                None
+            };
+            match (shape, strokes.first()) {
+                (Some(shape), Some(body)) if body.len() >= 2 => {
+                    vec![AnnotationKind::Shape { shape, from: body[0], to: body[body.len() - 1], width }]
+                },
+                _ => strokes.into_iter().filter(|s| !s.is_empty()).map(|stroke| AnnotationKind::Ink {
+                    samples: stroke.into_iter().map(|pos| InkSample { pos, pressure: 1.0 }).collect(),
+                    width,
+                }).collect(),
+            }
+        },
+        FPDF_ANNOT_LINE => {
+            // Las puntas (/LE) no se leen: solo las flechas nuestras vuelven a serlo
+            let (mut start, mut end) = (FS_POINTF { x: 0.0, y: 0.0 }, FS_POINTF { x: 0.0, y: 0.0 });
+            if !bindings.is_true(bindings.FPDFAnnot_GetLine(annot, &mut start, &mut end)) {
+                return Vec::new();
+            }
+            let shape = if name.starts_with(&format!("{}arrow-", NAME_PREFIX)) { Shape::Arrow } else { Shape::Line };
+            let (from, to) = (PagePoint { x: start.x, y: start.y }, PagePoint { x: end.x, y: end.y });
+            vec![AnnotationKind::Shape { shape, from, to, width }]
+        },
+        FPDF_ANNOT_SQUARE | FPDF_ANNOT_CIRCLE => {
+            // El borde va por dentro de /Rect: el contorno pasa a medio grosor del borde
+            let half = width * 0.5;
+            let shape = if subtype == FPDF_ANNOT_SQUARE { Shape::Rectangle } else { Shape::Ellipse };
+            let from = PagePoint { x: left + half, y: bottom + half };
+            let to = PagePoint { x: right - half, y: top - half };
+            vec![AnnotationKind::Shape { shape, from, to, width }]
+        },
+        FPDF_ANNOT_TEXT => vec![AnnotationKind::Note { at: PagePoint { x: left, y: top }, text: contents }],
+        FPDF_ANNOT_FREETEXT => {
+            let (font_size, _) = parse_default_appearance(&string_value(bindings, annot, "DA").unwrap_or_default());
+            vec![AnnotationKind::FreeText { at: PagePoint { x: left, y: top }, text: contents, size: font_size * 1.2 }]
+        },
+        _ => Vec::new(),
+    };
+
+    // En el texto libre /C es el fondo de la caja: el color del texto viene en /DA
+    let color = if subtype == FPDF_ANNOT_FREETEXT {
+        parse_default_appearance(&string_value(bindings, annot, "DA").unwrap_or_default()).1
+    } else {
+        let (mut r, mut g, mut b, mut a) = (0, 0, 0, 255);
+        let found = bindings.is_true(bindings.FPDFAnnot_GetColor(annot, COLORTYPE_COLOR, &mut r, &mut g, &mut b, &mut a));
+        let fallback = if subtype == FPDF_ANNOT_HIGHLIGHT { HIGHLIGHT_YELLOW } else if subtype == FPDF_ANNOT_TEXT { NOTE_YELLOW } else { INK_BLACK };
+        if found { [r as u8, g as u8, b as u8, a as u8] } else { fallback }
+    };
+    let author = string_value(bindings, annot, "T");
+    let modified = string_value(bindings, annot, "M");
+
+    // El /NM es del /Annot entero: se lo queda el primer trazo (los demás reciben uno propio)
+    let mut name = Some(name).filter(|name| !name.is_empty());
+    kinds.into_iter()
+        .map(|kind| Annotation { id: 0, kind, color, author: author.clone(), modified: modified.clone(), name: name.take() })
+        .collect()
+}
+
+fn ink_paths(bindings: &dyn PdfiumLibraryBindings, annot: FPDF_ANNOTATION) -> Vec<Vec<PagePoint>> {
+    (0..bindings.FPDFAnnot_GetInkListCount(annot)).map(|path| {
+        let len = bindings.FPDFAnnot_GetInkListPath(annot, path, std::ptr::null_mut(), 0);
+        let mut points = vec![FS_POINTF { x: 0.0, y: 0.0 }; len as usize];
+        bindings.FPDFAnnot_GetInkListPath(annot, path, points.as_mut_ptr(), len);
+        points.into_iter().map(|p| PagePoint { x: p.x, y: p.y }).collect()
+    }).collect()
+}
+
+fn border_width(bindings: &dyn PdfiumLibraryBindings, annot: FPDF_ANNOTATION) -> f32 {
+    let (mut rx, mut ry, mut width) = (0.0, 0.0, 1.0);
+    if bindings.is_true(bindings.FPDFAnnot_GetBorder(annot, &mut rx, &mut ry, &mut width)) && width > 0.0 {
+        width
+    } else {
+        1.0
+    }
+}
+
+// Valor de texto de una clave del diccionario (PDFium lo da en UTF-16LE con terminador)
+fn string_value(bindings: &dyn PdfiumLibraryBindings, annot: FPDF_ANNOTATION, key: &str) -> Option<String> {
+    let len = bindings.FPDFAnnot_GetStringValue(annot, key, std::ptr::null_mut(), 0);
+    if len <= 2 {
+        return None;
+    }
+    let mut buffer: Vec<FPDF_WCHAR> = vec![0; len as usize / 2];
+    bindings.FPDFAnnot_GetStringValue(annot, key, buffer.as_mut_ptr(), len);
+    Some(String::from_utf16_lossy(&buffer[..buffer.len() - 1]))
+}
+
+/// Tamaño de letra y color de un /DA ("/Helv 12 Tf 0 0 1 rg"). Sin datos: 12 pt y negro.
+pub fn parse_default_appearance(da: &str) -> (f32, Color) {
+    let tokens: Vec<&str> = da.split_whitespace().collect();
+    let number = |i: usize| tokens.get(i).and_then(|t| t.parse::<f32>().ok());
+    let channel = |v: f32| (v.clamp(0.0, 1.0) * 255.0).round() as u8;
+    let (mut size, mut color) = (12.0, INK_BLACK);
+
+    for (i, token) in tokens.iter().enumerate() {
+        match *token {
+            "Tf" if i >= 1 => size = number(i - 1).filter(|s| *s > 0.0).unwrap_or(size),
+            "g" if i >= 1 => if let Some(gray) = number(i - 1) {
+                color = [channel(gray), channel(gray), channel(gray), 255];
+            },
+            "rg" if i >= 3 => if let (Some(r), Some(g), Some(b)) = (number(i - 3), number(i - 2), number(i - 1)) {
+                color = [channel(r), channel(g), channel(b), 255];
+            },
+            _ => {},
+        }
+    }
+    (size, color)
+}
+
+/// Copia `source` en `target` con las anotaciones del modelo como objetos PDF estándar:
+/// Highlight (resaltado de texto), Ink (trazos), Square/Circle (rectángulo y elipse), Text (notas)
+/// y FreeText (texto libre), cada una con su apariencia (/AP). PDFium no sabe crear anotaciones
+/// Line: las líneas y flechas se escriben como Ink, con la punta como trazos y su /NM
+/// ("windp-line-", "windp-arrow-") para volver a leerlas como figuras.
+///
+/// Solo se tocan los /Annot que el modelo cambió (ver `Origin`): los que leímos y siguen igual se
+/// quedan como estaban, los borrados se quitan (con su popup), los modificados se cambian en sitio
+/// y las anotaciones nuevas se añaden. Lo que el modelo no leyó no se toca nunca.
+///
+/// Como en `PdfSystem::resolve_named_destination`, trabajamos con los bindings crudos
+/// sobre un handle propio: pdfium-render no expone InkList, bordes ni círculos.
+pub fn write_annotated_copy(bindings: &dyn PdfiumLibraryBindings, source: &str, password: Option<&str>, target: &Path, store: &AnnotationStore) -> Result<WrittenAnnotations> {
+    let document = bindings.FPDF_LoadDocument(source, password);
+    if document.is_null() {
+        bail!("PDFium no pudo abrir {} (error {})", source, bindings.FPDF_GetLastError());
+    }
+
+    let result = write_annotations(bindings, document, store).and_then(|written| {
+        save_copy(bindings, document, target)?;
+        Ok(written)
+    });
+    bindings.FPDF_CloseDocument(document);
+    result
+}
+
+fn write_annotations(bindings: &dyn PdfiumLibraryBindings, document: FPDF_DOCUMENT, store: &AnnotationStore) -> Result<WrittenAnnotations> {
+    let page_count = bindings.FPDF_GetPageCount(document);
+    let mut pages: Vec<u16> = store.pages().map(|(page, _)| page).chain(store.origin_pages()).collect();
+    pages.sort_unstable();
+    pages.dedup();
+
+    let mut result = WrittenAnnotations { written: 0, placed: Vec::new() };
+    for page_idx in pages {
+        if page_idx as c_int >= page_count {
+            continue;
+        }
+        let page = bindings.FPDF_LoadPage(document, page_idx as c_int);
+        if page.is_null() {
+            bail!("No se pudo cargar la página {}", page_idx + 1);
+        }
+        result.written += write_page(bindings, page, page_idx, store, &mut result.placed);
+        bindings.FPDF_ClosePage(page);
+    }
+    Ok(result)
+}
+
+// Lleva al /Annots de una página los cambios del modelo. Primero se modifica en sitio (con los
+// índices aún como al leerlos), luego se borra y al final se añade lo nuevo. En `placed` deja
+// dónde acaba cada anotación del modelo.
+fn write_page(bindings: &dyn PdfiumLibraryBindings, page: FPDF_PAGE, page_idx: u16, store: &AnnotationStore, placed: &mut Vec<(u64, u16, usize)>) -> usize {
+    let annotations = store.page(page_idx);
+    let mut fresh: Vec<&Annotation> = annotations.iter().filter(|a| store.origin(a.id).is_none()).collect();
+    let mut doomed = Vec::new();
+    let mut kept: Vec<(usize, Vec<u64>)> = Vec::new();
+    let mut written = 0;
+
+    for (index, ids) in store.origins_on(page_idx) {
+        let parts: Vec<&Annotation> = annotations.iter().filter(|a| ids.contains(&a.id)).collect();
+        let saved: Vec<&Annotation> = ids.iter().filter_map(|id| store.origin(*id)).map(|origin| &origin.saved).collect();
+        let unchanged = parts.len() == saved.len() && parts.iter().all(|a| store.origin(a.id).is_some_and(|origin| origin.saved == **a));
+        if unchanged {
+            kept.push((index, ids));
+            continue;
+        }
+        if parts.is_empty() {
+            doomed.push(index);
+            continue;
+        }
+
+        // Un trazo recoloreado o de otro grosor ya no cabe en el Ink de sus hermanos: va aparte
+        let (same, other): (Vec<&Annotation>, Vec<&Annotation>) = parts.iter().partition(|a| same_style(a, parts[0]));
+        let annot = bindings.FPDFPage_GetAnnot(page, index as c_int);
+        let updated = !annot.is_null() && update_annotation(bindings, annot, &same, &saved);
+        if !annot.is_null() {
+            bindings.FPDFPage_CloseAnnot(annot);
+        }
+        if updated {
+            written += same.len();
+            kept.push((index, same.iter().map(|a| a.id).collect()));
+            fresh.extend(other);
+        } else {
+            // Lo que no se puede cambiar en sitio se sustituye entero
+            doomed.push(index);
+            fresh.extend(parts);
+        }
+    }
+
+    // Al quitar /Annot, los que quedan detrás bajan de índice
+    let removed = remove_annotations(bindings, page, &doomed);
+    for (index, ids) in kept {
+        let index = index - removed.iter().filter(|&&r| r < index).count();
+        placed.extend(ids.into_iter().map(|id| (id, page_idx, index)));
+    }
+    for annotation in fresh {
+        if let Some(index) = add_annotation(bindings, page, annotation) {
+            placed.push((annotation.id, page_idx, index));
+            written += 1;
+        } else {
+            log::warn!("Anotación {} de la página {} no guardada", annotation.id, page_idx + 1);
+        }
+    }
+    written
+}
+
+// ¿Pueden ir en el mismo /Annot? (solo los trazos de un Ink se reparten entre varias anotaciones)
+fn same_style(a: &Annotation, b: &Annotation) -> bool {
+    std::mem::discriminant(&a.kind) == std::mem::discriminant(&b.kind) && a.color == b.color && a.kind.width() == b.kind.width()
+}
+
+/// Quita de la página los /Annot de `indices` y los popups que cuelgan de ellos.
+/// Se borran de atrás adelante, para no mover los índices que faltan.
+/// Devuelve los índices quitados (popups incluidos), en orden.
+pub(crate) fn remove_annotations(bindings: &dyn PdfiumLibraryBindings, page: FPDF_PAGE, indices: &[usize]) -> Vec<usize> {
+    if indices.is_empty() {
+        return Vec::new();
+    }
+    let mut doomed = indices.to_vec();
+    for index in 0..bindings.FPDFPage_GetAnnotCount(page) {
+        let annot = bindings.FPDFPage_GetAnnot(page, index);
+        if annot.is_null() {
+            continue;
+        }
+        if bindings.FPDFAnnot_GetSubtype(annot) == FPDF_ANNOT_POPUP {
+            let parent = bindings.FPDFAnnot_GetLinkedAnnot(annot, "Parent");
+            if !parent.is_null() {
+                let parent_index = bindings.FPDFPage_GetAnnotIndex(page, parent);
+                bindings.FPDFPage_CloseAnnot(parent);
+                if parent_index >= 0 && indices.contains(&(parent_index as usize)) {
+                    doomed.push(index as usize);
+                }
+            }
+        }
+        bindings.FPDFPage_CloseAnnot(annot);
+    }
+    doomed.sort_unstable();
+    doomed.dedup();
+    for index in doomed.iter().rev() {
+        bindings.FPDFPage_RemoveAnnot(page, *index as c_int);
+    }
+    doomed
+}
+
+// Cambia en sitio un /Annot leído del PDF para que muestre `parts` (sus anotaciones en el modelo;
+// `saved`, cómo eran al leerlas). Solo se escriben las claves que el modelo representa: geometría,
+// color, grosor, texto, autor y fecha, y la apariencia nueva. /NM, /Popup, /IRT, /Subj,
+// /CreationDate, /RD y lo demás se quedan como estaban. False si no se puede (el llamador lo
+// sustituye entero).
+fn update_annotation(bindings: &dyn PdfiumLibraryBindings, annot: FPDF_ANNOTATION, parts: &[&Annotation], saved: &[&Annotation]) -> bool {
+    let is_true = |result: FPDF_BOOL| bindings.is_true(result);
+    let set_string = |key: &str, value: &str| is_true(bindings.FPDFAnnot_SetStringValue_str(annot, key, value));
+    let (Some(first), Some(fallback)) = (parts.first(), saved.first()) else { return false };
+    let before = saved.iter().find(|a| a.id == first.id).unwrap_or(fallback);
+    let mut rect = FS_RECTF { left: 0.0, top: 0.0, right: 0.0, bottom: 0.0 };
+    if !is_true(bindings.FPDFAnnot_GetRect(annot, &mut rect)) {
+        return false;
+    }
+    let old_rect = [rect.left.min(rect.right), rect.bottom.min(rect.top), rect.left.max(rect.right), rect.bottom.max(rect.top)];
+
+    let mut ok = true;
+    // Sin /NM no se puede referir desde fuera (XFDF, respuestas): ya que se reescribe, que lo tenga
+    if let Some(name) = first.name.as_ref().filter(|_| !is_true(bindings.FPDFAnnot_HasKey(annot, "NM"))) {
+        ok &= set_string("NM", name);
+    }
+    if first.author != before.author {
+        ok &= set_string("T", first.author.as_deref().unwrap_or_default());
+    }
+    if let Some(modified) = first.modified.as_ref().filter(|_| first.modified != before.modified) {
+        ok &= set_string("M", modified);
+    }
+    if first.color != before.color && !matches!(first.kind, AnnotationKind::FreeText { .. }) {
+        let [r, g, b, a] = first.color.map(|c| c as u32);
+        ok &= is_true(bindings.FPDFAnnot_SetColor(annot, COLORTYPE_COLOR, r, g, b, a));
+    }
+    if first.kind.width() != before.kind.width() {
+        ok &= is_true(bindings.FPDFAnnot_SetBorder(annot, 0.0, 0.0, first.kind.width()));
+    }
+    // Texto cambiado: /Contents y, si lo tenía, también la versión con formato
+    let set_text = |text: &str, old: &str| {
+        text == old || (set_string("Contents", text) && (!is_true(bindings.FPDFAnnot_HasKey(annot, "RC")) || set_string("RC", &rich_text(text))))
+    };
+
+    match (&first.kind, &before.kind) {
+        (AnnotationKind::Shape { shape: Shape::Line | Shape::Arrow, .. }, _) if bindings.FPDFAnnot_GetSubtype(annot) == FPDF_ANNOT_LINE => {
+            ok &= parts.len() == 1 && set_rect(bindings, annot, first.kind.bounds());
+        },
+        (AnnotationKind::Ink { .. } | AnnotationKind::Shape { shape: Shape::Line | Shape::Arrow, .. }, _) => {
+            // Todos los trazos de nuevo, en el orden del modelo
+            ok &= is_true(bindings.FPDFAnnot_RemoveInkList(annot));
+            for stroke in parts.iter().flat_map(|part| part.kind.outline()) {
+                let points: Vec<FS_POINTF> = stroke.iter().map(|p| FS_POINTF { x: p.x, y: p.y }).collect();
+                ok &= bindings.FPDFAnnot_AddInkStroke(annot, points.as_ptr(), points.len()) >= 0;
+            }
+            let bounds = parts.iter().map(|part| part.kind.bounds()).collect::<Vec<_>>();
+            ok &= set_rect(bindings, annot, union_rects(&bounds));
+        },
+        (AnnotationKind::TextHighlight { rects, text }, AnnotationKind::TextHighlight { rects: old_rects, text: old_text }) => {
+            // Cada cuadrilátero sigue a su línea: así no se pierden los girados ni los inclinados
+            let quads = bindings.FPDFAnnot_CountAttachmentPoints(annot);
+            if rects != old_rects && quads > 0 {
+                if quads != old_rects.len() || rects.len() != old_rects.len() {
+                    return false;
+                }
+                for (i, (old, new)) in old_rects.iter().zip(rects).enumerate() {
+                    let mut q = FS_QUADPOINTSF { x1: 0.0, y1: 0.0, x2: 0.0, y2: 0.0, x3: 0.0, y3: 0.0, x4: 0.0, y4: 0.0 };
+                    ok &= is_true(bindings.FPDFAnnot_GetAttachmentPoints(annot, i, &mut q));
+                    let [(x1, y1), (x2, y2), (x3, y3), (x4, y4)] = [(q.x1, q.y1), (q.x2, q.y2), (q.x3, q.y3), (q.x4, q.y4)].map(|(x, y)| map_point(x, y, *old, *new));
+                    let quad = FS_QUADPOINTSF { x1, y1, x2, y2, x3, y3, x4, y4 };
+                    ok &= is_true(bindings.FPDFAnnot_SetAttachmentPoints(annot, i, &quad));
+                }
+            }
+            ok &= set_rect(bindings, annot, map_rect(old_rect, union_rects(old_rects), union_rects(rects)));
+            ok &= set_text(text, old_text);
+        },
+        (AnnotationKind::Note { text, .. }, AnnotationKind::Note { text: old_text, .. }) => {
+            ok &= set_rect(bindings, annot, map_rect(old_rect, before.kind.bounds(), first.kind.bounds()));
+            ok &= set_text(text, old_text);
+        },
+        (AnnotationKind::FreeText { text, size, .. }, AnnotationKind::FreeText { text: old_text, size: old_size, .. }) => {
+            // La caja sigue al texto; si crece, que quepa lo escrito
+            let moved = map_rect(old_rect, before.kind.bounds(), first.kind.bounds());
+            let rect = if text == old_text && size == old_size { moved } else { union_rects(&[moved, first.kind.bounds()]) };
+            ok &= set_rect(bindings, annot, rect) && set_text(text, old_text);
+            if first.color != before.color || size != old_size {
+                let da = string_value(bindings, annot, "DA").unwrap_or_default();
+                ok &= set_string("DA", &update_default_appearance(&da, first.color, size / 1.2));
+            }
+        },
+        (AnnotationKind::Shape { .. }, AnnotationKind::Shape { .. }) => {
+            ok &= set_rect(bindings, annot, map_rect(old_rect, before.kind.bounds(), first.kind.bounds()));
+        },
+        _ => return false,
+    }
+
+    ok && set_appearance(bindings, annot, parts)
+}
+
+// Lleva un punto del marco `from` al marco `to` ([left, bottom, right, top]) con la misma posición
+// relativa. En un eje sin anchura (una línea horizontal) solo se desplaza.
+fn map_point(x: f32, y: f32, from: [f32; 4], to: [f32; 4]) -> (f32, f32) {
+    let axis = |v: f32, lo: f32, hi: f32, new_lo: f32, new_hi: f32| {
+        if hi - lo > 1e-3 { new_lo + (v - lo) * (new_hi - new_lo) / (hi - lo) } else { v + new_lo - lo }
+    };
+    (axis(x, from[0], from[2], to[0], to[2]), axis(y, from[1], from[3], to[1], to[3]))
+}
+
+fn map_rect(rect: [f32; 4], from: [f32; 4], to: [f32; 4]) -> [f32; 4] {
+    let (left, bottom) = map_point(rect[0], rect[1], from, to);
+    let (right, top) = map_point(rect[2], rect[3], from, to);
+    [left.min(right), bottom.min(top), left.max(right), bottom.max(top)]
+}
+
+/// /RC (texto con formato, XHTML) de un texto plano: un párrafo por línea.
+fn rich_text(text: &str) -> String {
+    let escaped = text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;");
+    let paragraphs: String = escaped.split('\n').map(|line| format!("<p>{}</p>", line)).collect();
+    format!("<?xml version=\"1.0\"?><body xmlns=\"http://www.w3.org/1999/xhtml\">{}</body>", paragraphs)
+}
+
+// Crea la anotación PDF equivalente y devuelve su índice en la página. None si PDFium la rechaza.
+fn add_annotation(bindings: &dyn PdfiumLibraryBindings, page: FPDF_PAGE, annotation: &Annotation) -> Option<usize> {
+    let subtype = match &annotation.kind {
+        AnnotationKind::Ink { .. } | AnnotationKind::Shape { shape: Shape::Line | Shape::Arrow, .. } => FPDF_ANNOT_INK,
+        AnnotationKind::Shape { shape: Shape::Rectangle, .. } => FPDF_ANNOT_SQUARE,
+        AnnotationKind::Shape { shape: Shape::Ellipse, .. } => FPDF_ANNOT_CIRCLE,
+        AnnotationKind::TextHighlight { .. } => FPDF_ANNOT_HIGHLIGHT,
+        AnnotationKind::Note { .. } => FPDF_ANNOT_TEXT,
+        AnnotationKind::FreeText { .. } => FPDF_ANNOT_FREETEXT,
+    };
+    let annot = bindings.FPDFPage_CreateAnnot(page, subtype);
+    if annot.is_null() {
+        return None;
+    }
+
+    let ok = fill_annotation(bindings, annot, annotation) && set_appearance(bindings, annot, &[annotation]);
+    let index = bindings.FPDFPage_GetAnnotIndex(page, annot);
+    bindings.FPDFPage_CloseAnnot(annot);
+    usize::try_from(index).ok().filter(|_| ok)
+}
+
+// Apariencia normal nueva del /Annot: la de antes (y las de pasar o pulsar) ya no es la suya
+fn set_appearance(bindings: &dyn PdfiumLibraryBindings, annot: FPDF_ANNOTATION, parts: &[&Annotation]) -> bool {
+    for mode in [AP_ROLLOVER, AP_DOWN] {
+        bindings.FPDFAnnot_SetAP(annot, mode, std::ptr::null());
+    }
+    // Con /CA < 1, PDFium le pone al stream un ExtGState /GS con esa opacidad
+    let mut opacity = 1.0;
+    let translucent = bindings.is_true(bindings.FPDFAnnot_GetNumberValue(annot, "CA", &mut opacity)) && opacity < 1.0;
+    bindings.is_true(bindings.FPDFAnnot_SetAP_str(annot, AP_NORMAL, &appearance_stream(parts, translucent)))
+}
+
+/// Contenido de la apariencia (/AP /N) de unas anotaciones: lo mismo que pinta el overlay, con
+/// trazos vectoriales y rectángulos rellenos. PDFium le da al stream /BBox = /Rect y matriz
+/// identidad, así que se escribe en coordenadas de página.
+fn appearance_stream(parts: &[&Annotation], translucent: bool) -> String {
+    let mut ops = String::from("q\n");
+    if translucent {
+        ops.push_str("/GS gs\n");
+    }
+    for part in parts {
+        let [r, g, b, _] = part.color.map(|c| c as f32 / 255.0);
+        if matches!(part.kind, AnnotationKind::Ink { .. } | AnnotationKind::Shape { .. }) {
+            ops.push_str(&format!("{:.3} {:.3} {:.3} RG {:.2} w 1 J 1 j\n", r, g, b, part.kind.width()));
+            for stroke in part.kind.outline() {
+                for (i, p) in stroke.iter().enumerate() {
+                    ops.push_str(&format!("{:.2} {:.2} {}\n", p.x, p.y, if i == 0 { "m" } else { "l" }));
+                }
+                // Un punto suelto: con extremo redondo, un segmento nulo pinta un círculo
+                if let [p] = stroke.as_slice() {
+                    ops.push_str(&format!("{:.2} {:.2} l\n", p.x, p.y));
+                }
+                if !stroke.is_empty() {
+                    ops.push_str("S\n");
+                }
+            }
+        } else {
+            let rects = filled_rects(&part.kind);
+            if rects.is_empty() {
+                continue;
+            }
+            ops.push_str(&format!("{:.3} {:.3} {:.3} rg\n", r, g, b));
+            for [left, bottom, right, top] in rects {
+                ops.push_str(&format!("{:.2} {:.2} {:.2} {:.2} re\n", left, bottom, right - left, top - bottom));
+            }
+            ops.push_str("f\n");
+        }
+    }
+    ops.push_str("Q\n");
+    ops
+}
+
+fn fill_annotation(bindings: &dyn PdfiumLibraryBindings, annot: FPDF_ANNOTATION, annotation: &Annotation) -> bool {
+    let is_true = |result: FPDF_BOOL| bindings.is_true(result);
+    let set_string = |key: &str, value: &str| is_true(bindings.FPDFAnnot_SetStringValue_str(annot, key, value));
+    let name = annotation.name.clone().unwrap_or_else(|| new_name(&annotation.kind));
+    let mut ok = is_true(bindings.FPDFAnnot_SetFlags(annot, FPDF_ANNOT_FLAG_PRINT)) && set_string("NM", &name);
+    if let Some(author) = &annotation.author {
+        ok &= set_string("T", author);
+    }
+    if let Some(modified) = &annotation.modified {
+        ok &= set_string("M", modified);
+    }
+    // En el texto libre /C sería el fondo de la caja: su color va en /DA
+    if !matches!(annotation.kind, AnnotationKind::FreeText { .. }) {
+        let [r, g, b, a] = annotation.color.map(|c| c as u32);
+        ok &= is_true(bindings.FPDFAnnot_SetColor(annot, COLORTYPE_COLOR, r, g, b, a));
+    }
+
+    match &annotation.kind {
+        AnnotationKind::Ink { .. } | AnnotationKind::Shape { shape: Shape::Line | Shape::Arrow, .. } => {
+            // El grosor es el nominal: el formato no guarda la presión de cada punto
+            let width = annotation.kind.width();
+            let strokes = annotation.kind.outline();
+            for stroke in &strokes {
+                let points: Vec<FS_POINTF> = stroke.iter().map(|p| FS_POINTF { x: p.x, y: p.y }).collect();
+                ok &= bindings.FPDFAnnot_AddInkStroke(annot, points.as_ptr(), points.len()) >= 0;
+            }
+            ok &= set_rect(bindings, annot, annotation.kind.bounds())
+                && is_true(bindings.FPDFAnnot_SetBorder(annot, 0.0, 0.0, width));
+        },
+        AnnotationKind::Shape { from, to, width, .. } => {
+            // En Square/Circle el borde se pinta por dentro de /Rect: lo ampliamos medio grosor
+            let rect = [from.x.min(to.x), from.y.min(to.y), from.x.max(to.x), from.y.max(to.y)];
+            let half = width * 0.5;
+            ok &= set_rect(bindings, annot, [rect[0] - half, rect[1] - half, rect[2] + half, rect[3] + half])
+                && is_true(bindings.FPDFAnnot_SetBorder(annot, 0.0, 0.0, *width));
+        },
+        AnnotationKind::TextHighlight { rects, text } => {
+            // QuadPoints en el orden de Acrobat: arriba-izquierda, arriba-derecha, abajo-izquierda, abajo-derecha
+            for &[left, bottom, right, top] in rects {
+                let quad = FS_QUADPOINTSF { x1: left, y1: top, x2: right, y2: top, x3: left, y3: bottom, x4: right, y4: bottom };
+                ok &= is_true(bindings.FPDFAnnot_AppendAttachmentPoints(annot, &quad));
+            }
+            ok &= set_rect(bindings, annot, union_rects(rects)) && set_string("Contents", text);
+        },
+        AnnotationKind::Note { text, .. } | AnnotationKind::FreeText { text, .. } => {
+            ok &= set_rect(bindings, annot, union_rects(&annotation.kind.areas())) && set_string("Contents", text);
+            if let AnnotationKind::FreeText { size, .. } = &annotation.kind {
+                // Sin recuadro, y con la fuente estándar Helvetica: nuestro alto de línea deja
+                // un 20% de interlineado, así que el cuerpo de letra es algo menor
+                ok &= set_string("DA", &default_appearance(annotation.color, size / 1.2))
+                    && is_true(bindings.FPDFAnnot_SetBorder(annot, 0.0, 0.0, 0.0));
+            }
+        },
+    }
+
+    ok
+}
+
+/// El mismo /DA con otro tamaño y color: se conserva la fuente (y lo que no sea tamaño ni color).
+pub fn update_default_appearance(da: &str, color: Color, font_size: f32) -> String {
+    let tokens: Vec<&str> = da.split_whitespace().collect();
+    let mut kept: Vec<String> = Vec::new();
+    let mut has_font = false;
+    for token in &tokens {
+        // Los operadores de color se llevan sus operandos (1 gris, 3 RGB, 4 CMYK)
+        let operands = match *token {
+            "g" | "G" => 1,
+            "rg" | "RG" => 3,
+            "k" | "K" => 4,
+            "Tf" => {
+                has_font = true;
+                if let Some(size) = kept.last_mut() {
+                    *size = format!("{:.2}", font_size);
+                }
+                kept.push(token.to_string());
+                continue;
+            },
+            _ => {
+                kept.push(token.to_string());
+                continue;
+            },
+        };
+        kept.truncate(kept.len().saturating_sub(operands));
+    }
+    let [r, g, b, _] = color.map(|c| c as f32 / 255.0);
+    let mut da = if has_font { kept.join(" ") } else { format!("/Helv {:.2} Tf {}", font_size, kept.join(" ")).trim_end().to_string() };
+    da.push_str(&format!(" {:.3} {:.3} {:.3} rg", r, g, b));
+    da
+}
+
+/// /DA de un texto libre: fuente, tamaño y color de relleno (RGB 0..1).
+pub fn default_appearance(color: Color, font_size: f32) -> String {
+    let [r, g, b, _] = color.map(|c| c as f32 / 255.0);
+    format!("/Helv {:.2} Tf {:.3} {:.3} {:.3} rg", font_size, r, g, b)
+}
+
+fn set_rect(bindings: &dyn PdfiumLibraryBindings, annot: FPDF_ANNOTATION, [left, bottom, right, top]: [f32; 4]) -> bool {
+    let rect = FS_RECTF { left, top, right, bottom };
+    bindings.is_true(bindings.FPDFAnnot_SetRect(annot, &rect))
+}
+
+fn union_rects(rects: &[[f32; 4]]) -> [f32; 4] {
+    rects.iter().fold([f32::MAX, f32::MAX, f32::MIN, f32::MIN], |acc, r| {
+        [acc[0].min(r[0]), acc[1].min(r[1]), acc[2].max(r[2]), acc[3].max(r[3])]
+    })
+}
+
+// FPDF_FILEWRITE con el archivo de destino detrás: PDFium llama a WriteBlock por trozos
+#[repr(C)]
+struct FileWriter {
+    base: FPDF_FILEWRITE,
+    file: std::io::BufWriter<std::fs::File>,
+}
+
+unsafe extern "C" fn write_block(this: *mut FPDF_FILEWRITE, data: *const c_void, size: c_ulong) -> c_int {
+    // `base` es el primer campo de un struct repr(C): el puntero es el del FileWriter entero
+    let writer = &mut *(this as *mut FileWriter);
+    let bytes = std::slice::from_raw_parts(data as *const u8, size as usize);
+    writer.file.write_all(bytes).is_ok() as c_int
+}
+
+pub(crate) fn save_copy(bindings: &dyn PdfiumLibraryBindings, document: FPDF_DOCUMENT, target: &Path) -> Result<()> {
+    let file = std::fs::File::create(target).with_context(|| format!("No se pudo crear {:?}", target))?;
+    let mut writer = FileWriter {
+        base: FPDF_FILEWRITE { version: 1, WriteBlock: Some(write_block) },
+        file: std::io::BufWriter::new(file),
+    };
+
+    let this = &mut writer as *mut FileWriter as *mut FPDF_FILEWRITE;
+    if !bindings.is_true(bindings.FPDF_SaveAsCopy(document, this, 0)) {
+        return Err(anyhow!("PDFium no pudo guardar {:?}", target));
+    }
+    writer.file.flush().with_context(|| format!("No se pudo escribir {:?}", target))?;
+    writer.file.get_ref().sync_all().with_context(|| format!("No se pudo escribir {:?}", target))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pdf::{fixture_pdf, test_path, with_test_system, PdfSystem};
+
+    #[test]
+    fn default_appearance_keeps_the_font() {
+        let red = [255, 0, 0, 255];
+        let cases = [
+            ("/Helv 12 Tf 0 g", "/Helv 9.00 Tf 1.000 0.000 0.000 rg"),
+            ("/Cour 10 Tf 0 0 1 rg 2 Tz", "/Cour 9.00 Tf 2 Tz 1.000 0.000 0.000 rg"),
+            ("0 0 0 1 k", "/Helv 9.00 Tf 1.000 0.000 0.000 rg"),
+            ("", "/Helv 9.00 Tf 1.000 0.000 0.000 rg"),
+        ];
+        for (da, expected) in cases {
+            assert_eq!(update_default_appearance(da, red, 9.0), expected, "{:?}", da);
+            assert_eq!(parse_default_appearance(expected), (9.0, red));
+        }
+    }
+
+    #[test]
+    fn map_rect_follows_the_frame() {
+        let from = [10.0, 10.0, 110.0, 40.0];
+        // Desplazado
+        assert_eq!(map_rect([10.0, 30.0, 110.0, 40.0], from, [15.0, 10.0, 115.0, 40.0]), [15.0, 30.0, 115.0, 40.0]);
+        // Al doble de ancho, desde la izquierda
+        assert_eq!(map_rect([60.0, 10.0, 110.0, 20.0], from, [10.0, 10.0, 210.0, 40.0]), [110.0, 10.0, 210.0, 20.0]);
+        // Un eje sin anchura solo se desplaza
+        assert_eq!(map_point(5.0, 20.0, [5.0, 0.0, 5.0, 40.0], [8.0, 0.0, 8.0, 80.0]), (8.0, 40.0));
+    }
+
+    #[test]
+    fn rich_text_escapes_markup() {
+        assert_eq!(
+            rich_text("a<b>&c\nd"),
+            "<?xml version=\"1.0\"?><body xmlns=\"http://www.w3.org/1999/xhtml\"><p>a&lt;b&gt;&amp;c</p><p>d</p></body>"
+        );
+    }
+
+    // Resaltado de dos líneas con popup y claves que el modelo no lee, un Ink de dos trazos,
+    // un cuadrado y un enlace (que no se edita)
+    const FIXTURE: [&str; 8] = [
+        "<< /Type /Catalog /Pages 2 0 R >>",
+        "<< /Type /Pages /Kids [3 0 R] /Count 1 >>",
+        "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 200 200] /Annots [4 0 R 5 0 R 6 0 R 7 0 R 8 0 R] >>",
+        "<< /Type /Annot /Subtype /Highlight /Rect [10 10 110 40] /QuadPoints [10 40 110 40 10 30 110 30 10 20 60 20 10 10 60 10] /C [1 1 0] /NM (ajeno-1) /Subj (Revisar) /CreationDate (D:20230101120000Z) /Contents (hola) /Popup 5 0 R /F 4 >>",
+        "<< /Type /Annot /Subtype /Popup /Parent 4 0 R /Rect [120 10 190 60] >>",
+        "<< /Type /Annot /Subtype /Ink /Rect [0 100 100 200] /InkList [[10 110 50 150] [60 160 90 190]] /C [0 0 1] /Border [0 0 2] /NM (trazos) /F 4 >>",
+        "<< /Type /Annot /Subtype /Square /Rect [120 120 180 180] /C [1 0 0] /NM (cuadro) /F 4 >>",
+        "<< /Type /Annot /Subtype /Link /Rect [0 0 5 5] /Border [0 0 0] >>",
+    ];
+
+    fn load(bindings: &dyn PdfiumLibraryBindings, source: &str) -> AnnotationStore {
+        let mut store = AnnotationStore::new();
+        for page in read_annotations(bindings, source, None).unwrap() {
+            for (index, annotation) in page.annotations {
+                store.import_at(page.page, annotation, index);
+            }
+        }
+        store
+    }
+
+    fn subtypes(bindings: &dyn PdfiumLibraryBindings, page: FPDF_PAGE) -> Vec<c_int> {
+        (0..bindings.FPDFPage_GetAnnotCount(page)).map(|i| {
+            let annot = bindings.FPDFPage_GetAnnot(page, i);
+            let subtype = bindings.FPDFAnnot_GetSubtype(annot);
+            bindings.FPDFPage_CloseAnnot(annot);
+            subtype
+        }).collect()
+    }
+
+    #[test]
+    #[ignore = "needs pdfium"]
+    fn saving_changes_only_what_the_model_changed() {
+        with_test_system(saving_changes_only_what_the_model_changed_with);
+    }
+
+    fn saving_changes_only_what_the_model_changed_with(system: &PdfSystem) {
+        let bindings = system.library.bindings();
+        let (source, first, second) = (test_path("annots.pdf"), test_path("annots-1.pdf"), test_path("annots-2.pdf"));
+        std::fs::write(&source, fixture_pdf(&FIXTURE)).unwrap();
+        let source = source.to_str().unwrap();
+
+        let mut store = load(bindings, source);
+        let ids: Vec<u64> = store.page(0).iter().map(|a| a.id).collect();
+        assert_eq!(store.origins_on(0).into_iter().map(|(index, ids)| (index, ids.len())).collect::<Vec<_>>(), vec![(0, 1), (2, 2), (3, 1)]);
+        // Sin cambios no se escribe nada
+        assert_eq!(write_annotated_copy(bindings, source, None, &first, &store).unwrap().written, 0);
+
+        store.update(0, &ids[..1], |a| a.kind.translate(5.0, 0.0));
+        assert!(store.remove(0, ids[1]));
+        let result = write_annotated_copy(bindings, source, None, &first, &store).unwrap();
+        assert_eq!(result.written, 2);
+        // El segundo trazo se queda en su /Annot; el resaltado y el cuadrado, en los suyos
+        let mut placed = result.placed;
+        placed.sort_unstable();
+        assert_eq!(placed, vec![(ids[0], 0, 0), (ids[2], 0, 2), (ids[3], 0, 3)]);
+
+        let document = bindings.FPDF_LoadDocument(first.to_str().unwrap(), None);
+        let page = bindings.FPDF_LoadPage(document, 0);
+        assert_eq!(subtypes(bindings, page), vec![FPDF_ANNOT_HIGHLIGHT, FPDF_ANNOT_POPUP, FPDF_ANNOT_INK, FPDF_ANNOT_SQUARE, 2]);
+
+        let highlight = bindings.FPDFPage_GetAnnot(page, 0);
+        assert_eq!(string_value(bindings, highlight, "NM").as_deref(), Some("ajeno-1"));
+        assert_eq!(string_value(bindings, highlight, "Subj").as_deref(), Some("Revisar"));
+        assert_eq!(string_value(bindings, highlight, "CreationDate").as_deref(), Some("D:20230101120000Z"));
+        let popup = bindings.FPDFAnnot_GetLinkedAnnot(highlight, "Popup");
+        assert!(!popup.is_null());
+        bindings.FPDFPage_CloseAnnot(popup);
+        // Los cuadriláteros siguen siendo dos, desplazados, no la caja entera
+        assert_eq!(bindings.FPDFAnnot_CountAttachmentPoints(highlight), 2);
+        let mut q = FS_QUADPOINTSF { x1: 0.0, y1: 0.0, x2: 0.0, y2: 0.0, x3: 0.0, y3: 0.0, x4: 0.0, y4: 0.0 };
+        assert!(bindings.is_true(bindings.FPDFAnnot_GetAttachmentPoints(highlight, 1, &mut q)));
+        assert_eq!((q.x1, q.x2, q.y1), (15.0, 65.0, 20.0));
+        bindings.FPDFPage_CloseAnnot(highlight);
+
+        let ink = bindings.FPDFPage_GetAnnot(page, 2);
+        assert_eq!(string_value(bindings, ink, "NM").as_deref(), Some("trazos"));
+        assert_eq!(ink_paths(bindings, ink), vec![vec![PagePoint { x: 60.0, y: 160.0 }, PagePoint { x: 90.0, y: 190.0 }]]);
+        bindings.FPDFPage_CloseAnnot(ink);
+        bindings.FPDF_ClosePage(page);
+        bindings.FPDF_CloseDocument(document);
+
+        // Borrar el resaltado quita también su popup; lo demás se queda
+        let first_path = first.to_str().unwrap();
+        let mut store = load(bindings, first_path);
+        let highlight = store.origins_on(0)[&0][0];
+        assert!(store.remove(0, highlight));
+        let result = write_annotated_copy(bindings, first_path, None, &second, &store).unwrap();
+        assert_eq!(result.written, 0);
+        // Sin el resaltado ni su popup, el resto sube dos puestos
+        let mut placed = result.placed;
+        placed.sort_unstable();
+        assert_eq!(placed.iter().map(|&(_, page, index)| (page, index)).collect::<Vec<_>>(), vec![(0, 0), (0, 1)]);
+        let document = bindings.FPDF_LoadDocument(second.to_str().unwrap(), None);
+        let page = bindings.FPDF_LoadPage(document, 0);
+        assert_eq!(subtypes(bindings, page), vec![FPDF_ANNOT_INK, FPDF_ANNOT_SQUARE, 2]);
+        bindings.FPDF_ClosePage(page);
+        bindings.FPDF_CloseDocument(document);
+
+        for path in [source, first_path, second.to_str().unwrap()] {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+
+    #[test]
+    fn appearance_streams_draw_what_the_overlay_draws() {
+        let annotation = |kind, color| Annotation { id: 1, kind, color, author: None, modified: None, name: None };
+        let samples = [(10.0, 20.0), (30.0, 40.0)].map(|(x, y)| InkSample { pos: PagePoint { x, y }, pressure: 1.0 }).to_vec();
+        let ink = annotation(AnnotationKind::Ink { samples, width: 2.5 }, [255, 0, 0, 255]);
+        assert_eq!(appearance_stream(&[&ink], false), "q\n1.000 0.000 0.000 RG 2.50 w 1 J 1 j\n10.00 20.00 m\n30.00 40.00 l\nS\nQ\n");
+
+        let dot = annotation(AnnotationKind::Ink { samples: vec![InkSample { pos: PagePoint { x: 5.0, y: 5.0 }, pressure: 1.0 }], width: 1.0 }, INK_BLACK);
+        assert!(appearance_stream(&[&dot], false).contains("5.00 5.00 m\n5.00 5.00 l\nS\n"));
+
+        let highlight = annotation(AnnotationKind::TextHighlight { rects: vec![[10.0, 10.0, 50.0, 20.0]], text: String::new() }, HIGHLIGHT_YELLOW);
+        assert_eq!(appearance_stream(&[&highlight], true), "q\n/GS gs\n1.000 1.000 0.000 rg\n10.00 10.00 40.00 10.00 re\nf\nQ\n");
+
+        // Una nota es un icono de varios rectángulos; un texto libre vacío no pinta nada
+        let note = annotation(AnnotationKind::Note { at: PagePoint { x: 0.0, y: 30.0 }, text: "x".into() }, NOTE_YELLOW);
+        assert_eq!(appearance_stream(&[&note], false).matches(" re\n").count(), filled_rects(&note.kind).len());
+        let empty = annotation(AnnotationKind::FreeText { at: PagePoint { x: 0.0, y: 0.0 }, text: String::new(), size: 12.0 }, INK_BLACK);
+        assert_eq!(appearance_stream(&[&empty], false), "q\nQ\n");
+    }
+
+    #[test]
+    #[ignore = "needs pdfium"]
+    fn lines_are_saved_as_ink_with_their_appearance() {
+        with_test_system(lines_are_saved_as_ink_with_their_appearance_with);
+    }
+
+    fn lines_are_saved_as_ink_with_their_appearance_with(system: &PdfSystem) {
+        let bindings = system.library.bindings();
+        let (source, target) = (test_path("lines.pdf"), test_path("lines-1.pdf"));
+        let blank = [FIXTURE[0], FIXTURE[1], "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 200 200] >>"];
+        std::fs::write(&source, fixture_pdf(&blank)).unwrap();
+        let mut store = AnnotationStore::new();
+        let shape = |shape, from: (f32, f32), to: (f32, f32)| AnnotationKind::Shape { shape, from: PagePoint { x: from.0, y: from.1 }, to: PagePoint { x: to.0, y: to.1 }, width: 2.0 };
+        let line = store.add(0, shape(Shape::Line, (10.0, 10.0), (90.0, 50.0)), INK_BLACK);
+        let arrow = store.add(0, shape(Shape::Arrow, (20.0, 150.0), (120.0, 150.0)), [0, 0, 255, 255]);
+        let result = write_annotated_copy(bindings, source.to_str().unwrap(), None, &target, &store).unwrap();
+        assert_eq!(result.written, 2);
+        assert_eq!(result.placed, vec![(line, 0, 0), (arrow, 0, 1)]);
+
+        let document = bindings.FPDF_LoadDocument(target.to_str().unwrap(), None);
+        let page = bindings.FPDF_LoadPage(document, 0);
+        assert_eq!(subtypes(bindings, page), vec![FPDF_ANNOT_INK, FPDF_ANNOT_INK]);
+        let annot = bindings.FPDFPage_GetAnnot(page, 1);
+        // El cuerpo y la punta, como trazos del InkList y en la apariencia
+        assert_eq!(bindings.FPDFAnnot_GetInkListCount(annot), 2);
+        assert!(bindings.FPDFAnnot_GetAP(annot, AP_NORMAL, std::ptr::null_mut(), 0) > 2);
+        bindings.FPDFPage_CloseAnnot(annot);
+        bindings.FPDF_ClosePage(page);
+        bindings.FPDF_CloseDocument(document);
+        // Nada queda a medio convertir en el archivo
+        let raw = String::from_utf8_lossy(&std::fs::read(&target).unwrap()).into_owned();
+        assert!(!raw.contains("/Subtype /Line") && !raw.contains("/Subtype/Line"));
+
+        let read = load(bindings, target.to_str().unwrap());
+        let kinds: Vec<&AnnotationKind> = read.page(0).iter().map(|a| &a.kind).collect();
+        assert_eq!(kinds, store.page(0).iter().map(|a| &a.kind).collect::<Vec<_>>());
+        for path in [source, target] {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
@@ -0,0 +1,216 @@
+use anyhow::{bail, Result};
+use pdfium_render::prelude::*;
+use std::os::raw::c_int;
+use std::path::Path;
+
+use super::annotations::{remove_annotations, save_copy};
+use crate::ui::overlay::{page_polygons, rasterize_annotation, Annotation, AnnotationStore, PixelRect, RasterTarget};
+
+// Constantes de fpdfview.h / fpdf_edit.h / fpdf_flatten.h (pdfium-render no las reexporta)
+const FPDF_BITMAP_BGRA: c_int = 4;
+const FPDF_FILLMODE_WINDING: c_int = 2;
+const FLAT_PRINT: c_int = 1;
+const FLATTEN_FAIL: c_int = 0;
+
+/// Escribe en `target` una copia de `source` sin anotaciones que se puedan editar: las del modelo
+/// quedan pintadas en el contenido de cada página y las demás del PDF (sellos, formularios...) se
+/// aplanan con PDFium.
+///
+/// Los trazos, las figuras y los resaltados entran como trayectos vectoriales (los mismos polígonos
+/// que tesela la GPU, rellenos con la regla del número de vueltas para que una anotación translúcida
+/// no se oscurezca donde se cruza consigo misma); las notas y el texto libre, como una imagen con
+/// alpha recortada a lo que ocupan. Todo con modo de fusión Multiply: el mismo
+/// `mix(pdf, capa * pdf, alpha)` que hace el shader, así que se ve igual que en pantalla y el texto
+/// original sigue siendo texto. `scale` son los píxeles por punto de esa imagen y los puntos de curva
+/// por punto de página de los trazos. Devuelve cuántas anotaciones se aplanaron.
+pub fn write_flattened_copy(bindings: &dyn PdfiumLibraryBindings, source: &str, password: Option<&str>, target: &Path, store: &AnnotationStore, scale: f32) -> Result<usize> {
+    let document = bindings.FPDF_LoadDocument(source, password);
+    if document.is_null() {
+        bail!("PDFium no pudo abrir {} (error {})", source, bindings.FPDF_GetLastError());
+    }
+
+    let result = flatten_pages(bindings, document, store, scale).and_then(|flattened| {
+        save_copy(bindings, document, target)?;
+        Ok(flattened)
+    });
+    bindings.FPDF_CloseDocument(document);
+    result
+}
+
+fn flatten_pages(bindings: &dyn PdfiumLibraryBindings, document: FPDF_DOCUMENT, store: &AnnotationStore, scale: f32) -> Result<usize> {
+    let mut flattened = 0;
+    for page_idx in 0..bindings.FPDF_GetPageCount(document) {
+        let mut page = bindings.FPDF_LoadPage(document, page_idx);
+        if page.is_null() {
+            bail!("No se pudo cargar la página {}", page_idx + 1);
+        }
+        // Las que el modelo leyó del PDF se pintan con él (y las que borró no se pintan)
+        let origins: Vec<usize> = store.origins_on(page_idx as u16).into_keys().collect();
+        remove_annotations(bindings, page, &origins);
+        let result = bindings.FPDFPage_Flatten(page, FLAT_PRINT);
+        bindings.FPDF_ClosePage(page);
+        if result == FLATTEN_FAIL {
+            bail!("PDFium no pudo aplanar la página {}", page_idx + 1);
+        }
+
+        let annotations = store.page(page_idx as u16);
+        if annotations.is_empty() {
+            continue;
+        }
+        // Tras FPDFPage_Flatten hay que volver a cargar la página
+        page = bindings.FPDF_LoadPage(document, page_idx);
+        if page.is_null() {
+            bail!("No se pudo cargar la página {}", page_idx + 1);
+        }
+        let (page_w, page_h) = (bindings.FPDF_GetPageWidthF(page), bindings.FPDF_GetPageHeightF(page));
+        let width = ((page_w * scale) as u32).max(1);
+        let height = ((page_h * scale) as u32).max(1);
+
+        // Notas y texto libre: la capa raster del overlay
+        let mut layer = vec![0u8; (width * height * 4) as usize];
+        let mut target = RasterTarget { data: &mut layer, width, height, scale, page_height: page_h };
+        for annotation in annotations.iter().filter(|a| !a.kind.is_stroke()) {
+            rasterize_annotation(&mut target, annotation);
+        }
+        let rect = opaque_rect(&layer, width, height);
+        let mut ok = rect.is_empty() || insert_layer(bindings, document, &mut page, &layer, width, rect, scale, page_h);
+        for annotation in annotations.iter().filter(|a| a.kind.is_stroke()) {
+            ok &= insert_vector(bindings, page, annotation, scale);
+        }
+        ok &= bindings.is_true(bindings.FPDFPage_GenerateContent(page));
+        bindings.FPDF_ClosePage(page);
+        if !ok {
+            bail!("No se pudieron pintar las anotaciones de la página {}", page_idx + 1);
+        }
+        flattened += annotations.len();
+    }
+    Ok(flattened)
+}
+
+// Añade `rect` de la capa como imagen encima del contenido de la página, fundida con Multiply
+#[allow(clippy::too_many_arguments)]
+fn insert_layer(bindings: &dyn PdfiumLibraryBindings, document: FPDF_DOCUMENT, page: &mut FPDF_PAGE, layer: &[u8], width: u32, rect: PixelRect, scale: f32, page_h: f32) -> bool {
+    let (crop_w, crop_h) = ((rect.x1 - rect.x0) as usize, (rect.y1 - rect.y0) as usize);
+    let mut pixels = Vec::with_capacity(crop_w * crop_h * 4);
+    for y in rect.y0 as usize..rect.y1 as usize {
+        let row = (y * width as usize + rect.x0 as usize) * 4;
+        pixels.extend_from_slice(&layer[row..row + crop_w * 4]);
+    }
+
+    let bitmap = bindings.FPDFBitmap_CreateEx(crop_w as c_int, crop_h as c_int, FPDF_BITMAP_BGRA, pixels.as_mut_ptr().cast(), (crop_w * 4) as c_int);
+    if bitmap.is_null() {
+        return false;
+    }
+    let image = bindings.FPDFPageObj_NewImageObj(document);
+    // La imagen ocupa el cuadrado unidad: la matriz la lleva a su sitio en puntos (Y hacia arriba)
+    let (w, h) = (crop_w as f32 / scale, crop_h as f32 / scale);
+    let matrix = FS_MATRIX { a: w, b: 0.0, c: 0.0, d: h, e: rect.x0 as f32 / scale, f: page_h - rect.y0 as f32 / scale - h };
+    let ok = !image.is_null()
+        && bindings.is_true(bindings.FPDFImageObj_SetBitmap(page, 1, image, bitmap))
+        && bindings.is_true(bindings.FPDFPageObj_SetMatrix(image, &matrix));
+    bindings.FPDFBitmap_Destroy(bitmap);
+    if !ok {
+        if !image.is_null() {
+            bindings.FPDFPageObj_Destroy(image);
+        }
+        return false;
+    }
+    bindings.FPDFPageObj_SetBlendMode(image, "Multiply");
+    bindings.FPDFPage_InsertObject(*page, image);
+    true
+}
+
+// Añade la anotación como un trayecto relleno encima del contenido de la página, fundido con Multiply.
+// Cada polígono es un subtrayecto; como todos giran en el mismo sentido, se pinta su unión
+fn insert_vector(bindings: &dyn PdfiumLibraryBindings, page: FPDF_PAGE, annotation: &Annotation, density: f32) -> bool {
+    let polygons: Vec<_> = page_polygons(annotation, density).into_iter().filter(|polygon| polygon.len() >= 3).collect();
+    let Some(start) = polygons.first().map(|polygon| polygon[0]) else { return true };
+    // El trayecto nace con el primer punto ya puesto: el resto de polígonos empieza con MoveTo
+    let path = bindings.FPDFPageObj_CreateNewPath(start.x, start.y);
+    if path.is_null() {
+        return false;
+    }
+    let mut ok = true;
+    for (i, polygon) in polygons.iter().enumerate() {
+        if i > 0 {
+            ok &= bindings.is_true(bindings.FPDFPath_MoveTo(path, polygon[0].x, polygon[0].y));
+        }
+        for point in &polygon[1..] {
+            ok &= bindings.is_true(bindings.FPDFPath_LineTo(path, point.x, point.y));
+        }
+        ok &= bindings.is_true(bindings.FPDFPath_Close(path));
+    }
+    let [r, g, b, a] = annotation.color.map(u32::from);
+    ok = ok
+        && bindings.is_true(bindings.FPDFPath_SetDrawMode(path, FPDF_FILLMODE_WINDING, 0))
+        && bindings.is_true(bindings.FPDFPageObj_SetFillColor(path, r, g, b, a));
+    if !ok {
+        bindings.FPDFPageObj_Destroy(path);
+        return false;
+    }
+    bindings.FPDFPageObj_SetBlendMode(path, "Multiply");
+    bindings.FPDFPage_InsertObject(page, path);
+    true
+}
+
+/// Rectángulo mínimo con píxeles visibles (alpha > 0) de un buffer BGRA.
+fn opaque_rect(data: &[u8], width: u32, height: u32) -> PixelRect {
+    let mut rect = PixelRect { x0: i32::MAX, y0: i32::MAX, x1: i32::MIN, y1: i32::MIN };
+    for y in 0..height as i32 {
+        let row = &data[(y as usize * width as usize) * 4..(y as usize + 1) * width as usize * 4];
+        let visible = |px: &[u8]| px[3] != 0;
+        if let (Some(first), Some(last)) = (row.chunks_exact(4).position(visible), row.chunks_exact(4).rposition(visible)) {
+            rect = PixelRect { x0: rect.x0.min(first as i32), y0: rect.y0.min(y), x1: rect.x1.max(last as i32 + 1), y1: y + 1 };
+        }
+    }
+    rect
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pdf::{fixture_pdf, test_path, with_test_system, PdfSystem};
+    use crate::ui::overlay::{AnnotationKind, InkSample, PagePoint, INK_BLACK, NOTE_YELLOW};
+
+    // Constantes de fpdf_edit.h
+    const FPDF_PAGEOBJ_PATH: c_int = 2;
+    const FPDF_PAGEOBJ_IMAGE: c_int = 3;
+
+    #[test]
+    #[ignore = "needs pdfium"]
+    fn ink_is_flattened_as_vector_paths() {
+        with_test_system(ink_is_flattened_as_vector_paths_with);
+    }
+
+    fn ink_is_flattened_as_vector_paths_with(system: &PdfSystem) {
+        let bindings = system.library.bindings();
+        let (source, target) = (test_path("flatten.pdf"), test_path("flatten-1.pdf"));
+        let blank = [
+            "<< /Type /Catalog /Pages 2 0 R >>",
+            "<< /Type /Pages /Kids [3 0 R] /Count 1 >>",
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 200 200] >>",
+        ];
+        std::fs::write(&source, fixture_pdf(&blank)).unwrap();
+        let mut store = AnnotationStore::new();
+        let samples = (0..20).map(|i| InkSample { pos: PagePoint { x: 10.0 + i as f32 * 5.0, y: 50.0 + (i % 3) as f32 * 4.0 }, pressure: 0.5 }).collect();
+        store.add(0, AnnotationKind::Ink { samples, width: 3.0 }, INK_BLACK);
+        store.add(0, AnnotationKind::Note { at: PagePoint { x: 150.0, y: 180.0 }, text: "nota".into() }, NOTE_YELLOW);
+        let flattened = write_flattened_copy(bindings, source.to_str().unwrap(), None, &target, &store, 2.0).unwrap();
+        assert_eq!(flattened, 2);
+
+        let document = bindings.FPDF_LoadDocument(target.to_str().unwrap(), None);
+        let page = bindings.FPDF_LoadPage(document, 0);
+        let types: Vec<c_int> = (0..bindings.FPDFPage_CountObjects(page))
+            .map(|i| bindings.FPDFPageObj_GetType(bindings.FPDFPage_GetObject(page, i)))
+            .collect();
+        // La tinta, un trayecto; la nota, la única imagen
+        assert_eq!(types.iter().filter(|&&t| t == FPDF_PAGEOBJ_PATH).count(), 1, "{:?}", types);
+        assert_eq!(types.iter().filter(|&&t| t == FPDF_PAGEOBJ_IMAGE).count(), 1, "{:?}", types);
+        assert_eq!(bindings.FPDFPage_GetAnnotCount(page), 0);
+        bindings.FPDF_ClosePage(page);
+        bindings.FPDF_CloseDocument(document);
+        for path in [source, target] {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
@@ -0,0 +1,372 @@
+use anyhow::{anyhow, bail, Context, Result};
+use image::ImageFormat;
+use pdfium_render::prelude::*;
+use std::os::raw::{c_int, c_uchar, c_ulong, c_void};
+use std::path::{Path, PathBuf};
+
+use super::annotations::save_copy;
+
+// Constantes de fpdfview.h (pdfium-render no las reexporta)
+const FPDF_BITMAP_BGR: c_int = 2;
+const FPDF_BITMAP_BGRA: c_int = 4;
+// Sin papel, la página mide lo que la imagen a 96 DPI, como se vería en pantalla
+const POINTS_PER_PIXEL: f32 = 72.0 / 96.0;
+// Etiqueta EXIF/TIFF con la orientación de la imagen
+const TAG_ORIENTATION: u16 = 0x0112;
+const EXTENSIONS: &[&str] = &["png", "jpg", "jpeg", "tif", "tiff"];
+
+/// Cómo entra cada imagen en una página de otro tamaño.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Fit {
+    /// Entera, lo más grande posible y centrada (puede sobrar margen).
+    Contain,
+    /// Cubre toda la página, centrada (puede cortarse por los lados).
+    Cover,
+}
+
+impl Fit {
+    pub fn parse(name: &str) -> Result<Self, String> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "fit" | "contain" => Ok(Fit::Contain),
+            "fill" | "cover" => Ok(Fit::Cover),
+            other => Err(format!("Ajuste desconocido: {} (fit o fill)", other)),
+        }
+    }
+}
+
+/// ¿Es una imagen que se puede abrir como documento (PNG, JPEG o TIFF)?
+pub fn is_image(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| EXTENSIONS.iter().any(|known| ext.eq_ignore_ascii_case(known)))
+}
+
+/// Expande la lista de entradas: una carpeta aporta sus imágenes (no las de subcarpetas) por orden
+/// de nombre, como salen del escáner; un archivo tiene que ser una imagen.
+pub fn collect_images(inputs: &[impl AsRef<Path>]) -> Result<Vec<PathBuf>> {
+    let mut images = Vec::new();
+    for input in inputs {
+        let input = input.as_ref();
+        if input.is_dir() {
+            let mut found: Vec<PathBuf> = std::fs::read_dir(input).with_context(|| format!("No se pudo leer la carpeta {:?}", input))?
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|path| path.is_file() && is_image(path))
+                .collect();
+            found.sort();
+            images.extend(found);
+        } else if is_image(input) {
+            images.push(input.to_path_buf());
+        } else {
+            bail!("No es una imagen PNG, JPEG o TIFF: {:?}", input);
+        }
+    }
+    if images.is_empty() {
+        bail!("No hay ninguna imagen que combinar");
+    }
+    Ok(images)
+}
+
+/// Escribe en `target` un PDF con una página por imagen. Con `paper` (puntos), cada página tiene ese
+/// tamaño, girado si la imagen es apaisada, y la imagen entra según `fit`; sin él, la página mide lo
+/// que la imagen. El papel y el ajuste son los mismos para todas: por imagen solo cambia hacia dónde
+/// se gira el papel. Las imágenes se ven derechas según su orientación EXIF. Devuelve cuántas
+/// páginas tiene.
+///
+/// Los JPEG entran tal cual (DCTDecode), sin volver a comprimirlos; las demás se descomprimen y
+/// PDFium las guarda con Flate, sin pérdida.
+pub fn write_images_pdf(bindings: &dyn PdfiumLibraryBindings, images: &[PathBuf], paper: Option<(f32, f32)>, fit: Fit, target: &Path) -> Result<usize> {
+    let document = bindings.FPDF_CreateNewDocument();
+    if document.is_null() {
+        bail!("PDFium no pudo crear el documento");
+    }
+    let result = images.iter().enumerate()
+        .try_for_each(|(index, path)| add_image_page(bindings, document, index as c_int, path, paper, fit))
+        .and_then(|()| save_copy(bindings, document, target));
+    bindings.FPDF_CloseDocument(document);
+    result.map(|()| images.len())
+}
+
+fn add_image_page(bindings: &dyn PdfiumLibraryBindings, document: FPDF_DOCUMENT, index: c_int, path: &Path, paper: Option<(f32, f32)>, fit: Fit) -> Result<()> {
+    let data = std::fs::read(path).with_context(|| format!("No se pudo leer la imagen {:?}", path))?;
+    let format = image::guess_format(&data).with_context(|| format!("No se reconoce el formato de la imagen {:?}", path))?;
+    let (image_w, image_h) = image::io::Reader::with_format(std::io::Cursor::new(&data), format)
+        .into_dimensions()
+        .with_context(|| format!("No se pudo leer la imagen {:?}", path))?;
+    // Del 5 al 8 la imagen va tumbada: se ve con el ancho y el alto cambiados
+    let orientation = exif_orientation(&data);
+    let (iw, ih) = if orientation >= 5 { (image_h as f32, image_w as f32) } else { (image_w as f32, image_h as f32) };
+    let (page_w, page_h, scale) = match paper {
+        None => (iw * POINTS_PER_PIXEL, ih * POINTS_PER_PIXEL, POINTS_PER_PIXEL),
+        Some((w, h)) => {
+            let (w, h) = if (iw > ih) == (w > h) { (w, h) } else { (h, w) };
+            let scale = match fit {
+                Fit::Contain => (w / iw).min(h / ih),
+                Fit::Cover => (w / iw).max(h / ih),
+            };
+            (w, h, scale)
+        },
+    };
+
+    let mut page = bindings.FPDFPage_New(document, index, page_w as f64, page_h as f64);
+    if page.is_null() {
+        bail!("No se pudo crear la página de {:?}", path);
+    }
+    let object = bindings.FPDFPageObj_NewImageObj(document);
+    let (w, h) = (iw * scale, ih * scale);
+    let matrix = oriented_matrix(orientation, w, h, (page_w - w) / 2.0, (page_h - h) / 2.0);
+    let loaded = if object.is_null() {
+        Ok(false)
+    } else if format == ImageFormat::Jpeg && load_jpeg(bindings, &mut page, object, &data) {
+        Ok(true)
+    } else {
+        set_pixels(bindings, &mut page, object, &data)
+    };
+    let ok = matches!(loaded, Ok(true)) && bindings.is_true(bindings.FPDFPageObj_SetMatrix(object, &matrix));
+    if ok {
+        bindings.FPDFPage_InsertObject(page, object);
+    } else if !object.is_null() {
+        bindings.FPDFPageObj_Destroy(object);
+    }
+    let ok = ok && bindings.is_true(bindings.FPDFPage_GenerateContent(page));
+    bindings.FPDF_ClosePage(page);
+    loaded.with_context(|| format!("No se pudo leer la imagen {:?}", path))?;
+    if !ok {
+        return Err(anyhow!("No se pudo poner la imagen {:?} en su página", path));
+    }
+    Ok(())
+}
+
+// El JPEG entra en el PDF sin descomprimirlo. PDFium lo lee entero durante la llamada (Inline), así
+// que `data` solo tiene que vivir hasta que vuelva
+fn load_jpeg(bindings: &dyn PdfiumLibraryBindings, page: &mut FPDF_PAGE, object: FPDF_PAGEOBJECT, data: &[u8]) -> bool {
+    unsafe extern "C" fn read_block(param: *mut c_void, position: c_ulong, buffer: *mut c_uchar, size: c_ulong) -> c_int {
+        // SAFETY: `param` apunta a los bytes del JPEG y PDFium nunca pide nada fuera de m_FileLen
+        unsafe { std::ptr::copy_nonoverlapping((param as *const u8).add(position as usize), buffer, size as usize) };
+        1
+    }
+    let mut access = FPDF_FILEACCESS { m_FileLen: data.len() as c_ulong, m_GetBlock: Some(read_block), m_Param: data.as_ptr() as *mut c_void };
+    bindings.is_true(bindings.FPDFImageObj_LoadJpegFileInline(page, 1, object, &mut access))
+}
+
+// Las demás (o un JPEG que PDFium no acepta) se descomprimen y entran como mapa de bits
+fn set_pixels(bindings: &dyn PdfiumLibraryBindings, page: &mut FPDF_PAGE, object: FPDF_PAGEOBJECT, data: &[u8]) -> Result<bool> {
+    let image = image::load_from_memory(data)?;
+    let (image_w, image_h) = (image.width(), image.height());
+    // PDFium quiere BGR(A); sin transparencia, mejor sin canal alpha (no hace falta máscara)
+    let (format, channels, mut pixels) = if image.color().has_alpha() {
+        (FPDF_BITMAP_BGRA, 4, image.to_rgba8().into_raw())
+    } else {
+        (FPDF_BITMAP_BGR, 3, image.to_rgb8().into_raw())
+    };
+    for px in pixels.chunks_exact_mut(channels) {
+        px.swap(0, 2);
+    }
+    let bitmap = bindings.FPDFBitmap_CreateEx(image_w as c_int, image_h as c_int, format, pixels.as_mut_ptr().cast(), (image_w as usize * channels) as c_int);
+    if bitmap.is_null() {
+        return Ok(false);
+    }
+    let ok = bindings.is_true(bindings.FPDFImageObj_SetBitmap(page, 1, object, bitmap));
+    bindings.FPDFBitmap_Destroy(bitmap);
+    Ok(ok)
+}
+
+// La imagen ocupa el cuadrado unidad (su primera fila arriba): la matriz la endereza según la
+// orientación EXIF y la lleva a la caja de `w` x `h` puntos con la esquina inferior izquierda en (x, y)
+fn oriented_matrix(orientation: u16, w: f32, h: f32, x: f32, y: f32) -> FS_MATRIX {
+    // Dónde acaba (u, v) del cuadrado unidad dentro de la caja, también unidad:
+    // x' = ux * u + vx * v + tx, y' = uy * u + vy * v + ty
+    let (ux, uy, vx, vy, tx, ty) = match orientation {
+        2 => (-1.0, 0.0, 0.0, 1.0, 1.0, 0.0),  // Espejo horizontal
+        3 => (-1.0, 0.0, 0.0, -1.0, 1.0, 1.0), // Media vuelta
+        4 => (1.0, 0.0, 0.0, -1.0, 0.0, 1.0),  // Espejo vertical
+        5 => (0.0, -1.0, -1.0, 0.0, 1.0, 1.0), // Traspuesta
+        6 => (0.0, -1.0, 1.0, 0.0, 0.0, 1.0),  // Un cuarto de vuelta horario
+        7 => (0.0, 1.0, 1.0, 0.0, 0.0, 0.0),   // Traspuesta por la otra diagonal
+        8 => (0.0, 1.0, -1.0, 0.0, 1.0, 0.0),  // Un cuarto de vuelta antihorario
+        _ => (1.0, 0.0, 0.0, 1.0, 0.0, 0.0),
+    };
+    FS_MATRIX { a: ux * w, b: uy * h, c: vx * w, d: vy * h, e: x + tx * w, f: y + ty * h }
+}
+
+// Orientación EXIF (1 a 8) de una imagen: en JPEG va en el segmento APP1 "Exif", en TIFF en su
+// primer IFD y en PNG en el bloque eXIf. 1 (derecha) si no la trae o no se entiende
+fn exif_orientation(data: &[u8]) -> u16 {
+    exif_tiff(data).and_then(tiff_orientation).filter(|orientation| (1..=8).contains(orientation)).unwrap_or(1)
+}
+
+// La estructura TIFF con los datos EXIF, esté donde esté según el formato
+fn exif_tiff(data: &[u8]) -> Option<&[u8]> {
+    if data.starts_with(b"II*\0") || data.starts_with(b"MM\0*") {
+        return Some(data);
+    }
+    if data.starts_with(&[0xFF, 0xD8]) {
+        // Segmentos FF xx con su largo (que incluye los dos bytes del largo) hasta el comienzo de la imagen
+        let mut at = 2;
+        while data.get(at) == Some(&0xFF) {
+            let marker = *data.get(at + 1)?;
+            let len = u16::from_be_bytes([*data.get(at + 2)?, *data.get(at + 3)?]) as usize;
+            let segment = data.get(at + 4..at + 2 + len.max(2))?;
+            if marker == 0xE1 && segment.starts_with(b"Exif\0\0") {
+                return Some(&segment[6..]);
+            }
+            if marker == 0xDA {
+                return None;
+            }
+            at += 2 + len;
+        }
+        return None;
+    }
+    if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+        // Bloques: largo, tipo, datos y CRC
+        let mut at = 8;
+        while let Some(header) = data.get(at..at + 8) {
+            let len = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as usize;
+            match &header[4..] {
+                b"eXIf" => return data.get(at + 8..at + 8 + len),
+                b"IEND" => return None,
+                _ => at += 12 + len,
+            }
+        }
+    }
+    None
+}
+
+fn tiff_orientation(tiff: &[u8]) -> Option<u16> {
+    let big_endian = match tiff.get(..2)? {
+        b"II" => false,
+        b"MM" => true,
+        _ => return None,
+    };
+    let u16_at = |at: usize| {
+        let bytes = [*tiff.get(at)?, *tiff.get(at + 1)?];
+        Some(if big_endian { u16::from_be_bytes(bytes) } else { u16::from_le_bytes(bytes) })
+    };
+    let u32_at = |at: usize| {
+        let bytes = [*tiff.get(at)?, *tiff.get(at + 1)?, *tiff.get(at + 2)?, *tiff.get(at + 3)?];
+        Some(if big_endian { u32::from_be_bytes(bytes) } else { u32::from_le_bytes(bytes) })
+    };
+    // Cada entrada del IFD: etiqueta, tipo, cuántos y el valor (un SHORT va en los dos primeros bytes)
+    let ifd = u32_at(4)? as usize;
+    (0..u16_at(ifd)? as usize)
+        .map(|entry| ifd + 2 + entry * 12)
+        .find(|&entry| u16_at(entry) == Some(TAG_ORIENTATION))
+        .and_then(|entry| u16_at(entry + 8))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pdf::{test_path, with_test_system, PdfSystem};
+
+    // Cabecera TIFF con un IFD de una sola entrada: la orientación
+    fn tiff(big_endian: bool, orientation: u16) -> Vec<u8> {
+        let u16_bytes = |value: u16| if big_endian { value.to_be_bytes() } else { value.to_le_bytes() };
+        let u32_bytes = |value: u32| if big_endian { value.to_be_bytes() } else { value.to_le_bytes() };
+        let mut tiff = if big_endian { b"MM".to_vec() } else { b"II".to_vec() };
+        tiff.extend(u16_bytes(42));
+        tiff.extend(u32_bytes(8));
+        tiff.extend(u16_bytes(1));
+        tiff.extend(u16_bytes(TAG_ORIENTATION));
+        tiff.extend(u16_bytes(3)); // SHORT
+        tiff.extend(u32_bytes(1));
+        tiff.extend(u16_bytes(orientation));
+        tiff.extend([0, 0]);
+        tiff.extend(u32_bytes(0));
+        tiff
+    }
+
+    // Un JPEG con el segmento EXIF justo detrás del SOI
+    fn with_exif(jpeg: &[u8], orientation: u16) -> Vec<u8> {
+        let mut exif = b"Exif\0\0".to_vec();
+        exif.extend(tiff(false, orientation));
+        let mut out = jpeg[..2].to_vec();
+        out.extend([0xFF, 0xE1]);
+        out.extend((exif.len() as u16 + 2).to_be_bytes());
+        out.extend(exif);
+        out.extend(&jpeg[2..]);
+        out
+    }
+
+    fn png_chunk(kind: &[u8], body: &[u8]) -> Vec<u8> {
+        let mut chunk = (body.len() as u32).to_be_bytes().to_vec();
+        chunk.extend(kind);
+        chunk.extend(body);
+        chunk.extend([0; 4]); // CRC: no se comprueba
+        chunk
+    }
+
+    #[test]
+    fn exif_orientation_is_found_in_every_format() {
+        let jpeg_start = [0xFF, 0xD8, 0xFF, 0xDA, 0, 2];
+        assert_eq!(exif_orientation(&with_exif(&jpeg_start, 6)), 6);
+        assert_eq!(exif_orientation(&jpeg_start), 1);
+        assert_eq!(exif_orientation(&tiff(true, 8)), 8);
+        assert_eq!(exif_orientation(&tiff(false, 3)), 3);
+
+        let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+        png.extend(png_chunk(b"IHDR", &[0; 13]));
+        png.extend(png_chunk(b"eXIf", &tiff(true, 5)));
+        png.extend(png_chunk(b"IEND", &[]));
+        assert_eq!(exif_orientation(&png), 5);
+
+        // Valores fuera de rango, datos cortados o ningún formato conocido: derecha
+        assert_eq!(exif_orientation(&tiff(false, 9)), 1);
+        let cut = with_exif(&jpeg_start, 6);
+        assert_eq!(exif_orientation(&cut[..cut.len() - 12]), 1);
+        assert_eq!(exif_orientation(b"GIF89a"), 1);
+    }
+
+    #[test]
+    fn oriented_matrix_puts_the_top_left_pixel_where_it_is_seen() {
+        let apply = |m: &FS_MATRIX, u: f32, v: f32| (m.a * u + m.c * v + m.e, m.b * u + m.d * v + m.f);
+        // La esquina superior izquierda de la imagen guardada es (0, 1) en el cuadrado unidad
+        let expected = [(0.0, 10.0), (20.0, 10.0), (20.0, 0.0), (0.0, 0.0), (0.0, 10.0), (20.0, 10.0), (20.0, 0.0), (0.0, 0.0)];
+        for (orientation, corner) in (1..=8).zip(expected) {
+            let m = oriented_matrix(orientation, 20.0, 10.0, 0.0, 0.0);
+            assert_eq!(apply(&m, 0.0, 1.0), corner, "orientación {}", orientation);
+            // Y el cuadrado entero cae dentro de la caja
+            for (u, v) in [(0.0, 0.0), (1.0, 0.0), (0.0, 1.0), (1.0, 1.0)] {
+                let (x, y) = apply(&m, u, v);
+                assert!((0.0..=20.0).contains(&x) && (0.0..=10.0).contains(&y), "orientación {}: {:?}", orientation, (x, y));
+            }
+        }
+        let m = oriented_matrix(1, 20.0, 10.0, 5.0, 7.0);
+        assert_eq!(apply(&m, 1.0, 1.0), (25.0, 17.0));
+    }
+
+    #[test]
+    #[ignore = "needs pdfium"]
+    fn jpegs_are_embedded_and_turned_upright() {
+        with_test_system(jpegs_are_embedded_and_turned_upright_with);
+    }
+
+    fn jpegs_are_embedded_and_turned_upright_with(system: &PdfSystem) {
+        let bindings = system.library.bindings();
+        let (jpeg_path, png_path, target) = (test_path("foto.jpg"), test_path("captura.png"), test_path("imagenes.pdf"));
+        let mut jpeg = Vec::new();
+        image::codecs::jpeg::JpegEncoder::new(&mut jpeg).encode(&[128; 40 * 20 * 3], 40, 20, image::ColorType::Rgb8).unwrap();
+        std::fs::write(&jpeg_path, with_exif(&jpeg, 6)).unwrap();
+        image::RgbImage::from_pixel(64, 64, image::Rgb([0, 128, 255])).save(&png_path).unwrap();
+
+        let images = [jpeg_path.clone(), png_path.clone()];
+        assert_eq!(write_images_pdf(bindings, &images, None, Fit::Contain, &target).unwrap(), 2);
+        let document = bindings.FPDF_LoadDocument(target.to_str().unwrap(), None);
+        let sizes: Vec<(f32, f32)> = (0..bindings.FPDF_GetPageCount(document)).map(|index| {
+            let mut size = FS_SIZEF { width: 0.0, height: 0.0 };
+            bindings.FPDF_GetPageSizeByIndexF(document, index, &mut size);
+            (size.width, size.height)
+        }).collect();
+        bindings.FPDF_CloseDocument(document);
+        // La foto, de 40x20 píxeles, va de pie por su EXIF
+        assert_eq!(sizes, vec![(15.0, 30.0), (48.0, 48.0)]);
+
+        // El JPEG entra sin recomprimir y la captura, comprimida sin pérdida
+        let bytes: Vec<u8> = std::fs::read(&target).unwrap().into_iter().filter(|b| !b.is_ascii_whitespace()).collect();
+        let count = |needle: &[u8]| bytes.windows(needle.len()).filter(|window| *window == needle).count();
+        assert_eq!(count(b"/Subtype/Image"), 2);
+        assert_eq!(count(b"/DCTDecode"), 1);
+        assert!(count(b"/FlateDecode") >= 1);
+        assert!(bytes.len() < 64 * 64 * 3);
+        for path in [jpeg_path, png_path, target] {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
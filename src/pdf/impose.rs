@@ -0,0 +1,167 @@
+use anyhow::{anyhow, bail, Result};
+use pdfium_render::prelude::*;
+use std::os::raw::c_int;
+use std::path::Path;
+
+use super::annotations::save_copy;
+use super::organize::import_pages;
+
+// Constante de fpdf_flatten.h (pdfium-render no la reexporta)
+const FLAT_PRINT: c_int = 1;
+
+/// Cómo se reparten las páginas en cada cara de hoja.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Layout {
+    /// Dos páginas por cara, en orden.
+    TwoUp,
+    /// Cuatro páginas por cara (2x2), en orden de lectura.
+    FourUp,
+    /// Cuadernillo grapado por el centro: dos páginas por cara, ordenadas para que al imprimir
+    /// a doble cara (volteo por el lado corto) y doblar las hojas juntas queden seguidas.
+    Booklet,
+}
+
+impl Layout {
+    pub fn parse(name: &str) -> Result<Self, String> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "2up" | "2-up" => Ok(Layout::TwoUp),
+            "4up" | "4-up" => Ok(Layout::FourUp),
+            "booklet" => Ok(Layout::Booklet),
+            other => Err(format!("Imposición desconocida: {} (2up, 4up o booklet)", other)),
+        }
+    }
+
+    /// Nombre corto, el mismo que acepta `parse`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Layout::TwoUp => "2up",
+            Layout::FourUp => "4up",
+            Layout::Booklet => "booklet",
+        }
+    }
+
+    // Columnas y filas de páginas en cada cara. En 2-up, las páginas apaisadas van una encima de otra;
+    // el cuadernillo siempre lleva el lomo en el centro
+    fn grid(self, (page_w, page_h): (f32, f32)) -> (usize, usize) {
+        match self {
+            Layout::FourUp => (2, 2),
+            Layout::TwoUp if page_w > page_h => (1, 2),
+            Layout::TwoUp | Layout::Booklet => (2, 1),
+        }
+    }
+}
+
+/// Orden de las páginas (base 0) en las caras de un cuadernillo de `count` páginas: de dos en dos,
+/// izquierda y derecha, anverso y reverso de cada hoja. Se completa hasta múltiplo de 4 con
+/// páginas en blanco (None) al final.
+pub fn booklet_order(count: usize) -> Vec<Option<u16>> {
+    let total = count.div_ceil(4) * 4;
+    let mut order = Vec::with_capacity(total);
+    for sheet in 0..total / 4 {
+        let (outer, inner) = (total - 1 - 2 * sheet, 2 * sheet);
+        order.extend([outer, inner, inner + 1, outer - 1]);
+    }
+    order.into_iter().map(|page| (page < count).then_some(page as u16)).collect()
+}
+
+/// Escribe en `target` las páginas de `source` impuestas en hojas de `sheet` puntos (por defecto,
+/// del tamaño de la primera página), girada la hoja para que las páginas ocupen lo máximo.
+/// Cada página entra como XObject con FPDF_ImportNPagesToOne, escalada a su casilla y centrada.
+/// Devuelve cuántas caras de hoja tiene el resultado.
+pub fn write_imposed(bindings: &dyn PdfiumLibraryBindings, source: &str, password: Option<&str>, target: &Path, layout: Layout, sheet: Option<(f32, f32)>) -> Result<usize> {
+    let document = bindings.FPDF_LoadDocument(source, password);
+    if document.is_null() {
+        bail!("PDFium no pudo abrir {} (error {})", source, bindings.FPDF_GetLastError());
+    }
+    let result = impose(bindings, document, target, layout, sheet);
+    bindings.FPDF_CloseDocument(document);
+    result
+}
+
+fn impose(bindings: &dyn PdfiumLibraryBindings, document: FPDF_DOCUMENT, target: &Path, layout: Layout, sheet: Option<(f32, f32)>) -> Result<usize> {
+    let count = bindings.FPDF_GetPageCount(document).max(0) as usize;
+    let mut first = FS_SIZEF { width: 0.0, height: 0.0 };
+    if count == 0 || !bindings.is_true(bindings.FPDF_GetPageSizeByIndexF(document, 0, &mut first)) {
+        bail!("El documento no tiene páginas");
+    }
+    let page = (first.width, first.height);
+    let order = match layout {
+        Layout::Booklet => booklet_order(count),
+        Layout::TwoUp | Layout::FourUp => (0..count as u16).map(Some).collect(),
+    };
+
+    // 1. Documento intermedio en el orden de las caras, con lo que falte en blanco y las anotaciones
+    //    aplanadas: FPDF_ImportNPagesToOne solo se lleva el contenido de cada página
+    let ordered = bindings.FPDF_CreateNewDocument();
+    if ordered.is_null() {
+        bail!("PDFium no pudo crear el documento intermedio");
+    }
+    let result = fill_ordered(bindings, document, ordered, &order, page).and_then(|()| {
+        // 2. Las hojas: la orientación que mejor aprovecha la rejilla
+        let (cols, rows) = layout.grid(page);
+        let (sheet_w, sheet_h) = sheet.unwrap_or(page);
+        let landscape = cols as f32 * page.0 >= rows as f32 * page.1;
+        let (sheet_w, sheet_h) = if landscape == (sheet_w >= sheet_h) { (sheet_w, sheet_h) } else { (sheet_h, sheet_w) };
+        let imposed = bindings.FPDF_ImportNPagesToOne(ordered, sheet_w, sheet_h, cols, rows);
+        if imposed.is_null() {
+            bail!("PDFium no pudo imponer las páginas ({})", layout.name());
+        }
+        let sheets = bindings.FPDF_GetPageCount(imposed).max(0) as usize;
+        let saved = save_copy(bindings, imposed, target);
+        bindings.FPDF_CloseDocument(imposed);
+        saved.map(|()| sheets)
+    });
+    bindings.FPDF_CloseDocument(ordered);
+    result
+}
+
+fn fill_ordered(bindings: &dyn PdfiumLibraryBindings, document: FPDF_DOCUMENT, ordered: FPDF_DOCUMENT, order: &[Option<u16>], (page_w, page_h): (f32, f32)) -> Result<()> {
+    // Las páginas seguidas se copian de una vez (comparten los recursos); las en blanco, una a una
+    let mut index = 0;
+    for run in order.chunk_by(|a, b| a.is_some() && b.is_some()) {
+        let at = index as c_int;
+        let ok = match run[0] {
+            Some(_) => import_pages(bindings, ordered, document, run.iter().flatten().copied(), at),
+            None => {
+                let blank = bindings.FPDFPage_New(ordered, at, page_w as f64, page_h as f64);
+                if !blank.is_null() {
+                    bindings.FPDF_ClosePage(blank);
+                }
+                !blank.is_null()
+            },
+        };
+        if !ok {
+            let pages: Vec<String> = run.iter().flatten().map(|page| (page + 1).to_string()).collect();
+            return Err(anyhow!("No se pudieron preparar las páginas {} para imponer", if pages.is_empty() { "en blanco".to_string() } else { pages.join(", ") }));
+        }
+        index += run.len();
+    }
+
+    for index in 0..order.len() as c_int {
+        let page = bindings.FPDF_LoadPage(ordered, index);
+        if page.is_null() {
+            bail!("No se pudo cargar la página {}", index + 1);
+        }
+        bindings.FPDFPage_Flatten(page, FLAT_PRINT);
+        bindings.FPDF_ClosePage(page);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // El orden en páginas base 1 (0 es una en blanco), de dos en dos: izquierda y derecha de cada cara
+    fn booklet_pairs(count: usize) -> Vec<[u16; 2]> {
+        booklet_order(count).chunks(2).map(|pair| [0, 1].map(|i| pair[i].map_or(0, |page| page + 1))).collect()
+    }
+
+    #[test]
+    fn booklet_order_table() {
+        assert_eq!(booklet_pairs(1), vec![[0, 1], [0, 0]]);
+        assert_eq!(booklet_pairs(4), vec![[4, 1], [2, 3]]);
+        assert_eq!(booklet_pairs(5), vec![[0, 1], [2, 0], [0, 3], [4, 5]]);
+        assert_eq!(booklet_pairs(8), vec![[8, 1], [2, 7], [6, 3], [4, 5]]);
+    }
+}
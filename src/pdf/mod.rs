@@ -1,34 +1,226 @@
+pub mod annotations;
+pub mod flatten;
+pub mod images;
+pub mod impose;
+pub mod organize;
+pub mod outline;
+pub mod redact;
 pub mod render;
+pub mod search;
+pub mod text;
 
 use pdfium_render::prelude::*;
-use std::sync::{Arc, Mutex};
+use std::path::Path;
+use std::rc::Rc;
 
-/// Estructura thread-safe que mantiene viva la instancia de PDFium.
-/// Usamos Arc para poder compartir referencias entre hilos si decidimos
-/// renderizar en background más adelante.
+use crate::ui::overlay::AnnotationStore;
+
+/// Estructura que mantiene viva la instancia de PDFium.
+/// Pdfium no es Send ni Sync, así que las copias comparten la instancia con Rc
+/// dentro del hilo que la creó.
 #[derive(Clone)]
 pub struct PdfSystem {
-    library: Arc<Pdfium>,
+    library: Rc<Pdfium>,
+}
+
+impl Default for PdfSystem {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl PdfSystem {
     pub fn new() -> Self {
+        Self::try_new().expect("CRITICAL: No se pudo cargar pdfium.dll. Asegúrate de que build.rs se ejecutó correctamente.")
+    }
+
+    /// Como `new`, sin abortar si no se encuentra la biblioteca de PDFium.
+    pub fn try_new() -> Result<Self, PdfiumError> {
         // Enlazamos dinámicamente con la DLL que descargó build.rs
         // Intentamos cargar localmente primero, luego en sistema.
         let bindings = Pdfium::bind_to_library(Pdfium::pdfium_platform_library_name_at_path("./"))
-            .or_else(|_| Pdfium::bind_to_system_library())
-            .expect("CRITICAL: No se pudo cargar pdfium.dll. Asegúrate de que build.rs se ejecutó correctamente.");
+            .or_else(|_| Pdfium::bind_to_system_library())?;
 
         let pdfium = Pdfium::new(bindings);
 
-        Self {
-            library: Arc::new(pdfium),
-        }
+        Ok(Self {
+            library: Rc::new(pdfium),
+        })
     }
 
     /// Abre un archivo PDF desde el disco.
     /// Retorna un documento gestionado que limpia su memoria al cerrarse.
-    pub fn open_file(&self, path: &str) -> Result<PdfDocument, PdfiumError> {
+    pub fn open_file(&self, path: &str) -> Result<PdfDocument<'_>, PdfiumError> {
         self.library.load_pdf_from_file(path, None)
     }
+
+    /// Igual que open_file, para documentos protegidos con contraseña.
+    pub fn open_file_with_password<'a>(&'a self, path: &str, password: Option<&'a str>) -> Result<PdfDocument<'a>, PdfiumError> {
+        self.library.load_pdf_from_file(path, password)
+    }
+
+    /// Resuelve un destino con nombre (/Dests o árbol /Names) a un índice de página.
+    /// pdfium-render no expone esta consulta, así que usamos los bindings crudos
+    /// con un handle propio que cerramos al terminar.
+    pub fn resolve_named_destination(&self, path: &str, password: Option<&str>, name: &str) -> Option<u16> {
+        let bindings = self.library.bindings();
+        let document = bindings.FPDF_LoadDocument(path, password);
+        if document.is_null() {
+            return None;
+        }
+
+        let dest = bindings.FPDF_GetNamedDestByName(document, name);
+        let page = if dest.is_null() { -1 } else { bindings.FPDFDest_GetDestPageIndex(document, dest) };
+        bindings.FPDF_CloseDocument(document);
+
+        u16::try_from(page).ok()
+    }
+
+    /// Lee las anotaciones del PDF que el overlay sabe editar (ver `annotations::read_annotations`).
+    pub fn read_annotations(&self, path: &str, password: Option<&str>) -> anyhow::Result<Vec<annotations::PageAnnotations>> {
+        annotations::read_annotations(self.library.bindings(), path, password)
+    }
+
+    /// Escribe en `target` una copia de `source` con las anotaciones como objetos PDF.
+    /// `source` no se toca: quien guarda decide cuándo sustituirlo. De las que ya tenía, solo cambian
+    /// las que el modelo leyó de él y se editaron o borraron después.
+    pub fn save_with_annotations(&self, source: &str, password: Option<&str>, target: &Path, store: &AnnotationStore) -> anyhow::Result<annotations::WrittenAnnotations> {
+        annotations::write_annotated_copy(self.library.bindings(), source, password, target, store)
+    }
+
+    /// Escribe en `target` una copia de `source` con las anotaciones pintadas en las páginas
+    /// (ver `flatten::write_flattened_copy`). `scale`: píxeles por punto de lo que se pinta.
+    pub fn save_flattened(&self, source: &str, password: Option<&str>, target: &Path, store: &AnnotationStore, scale: f32) -> anyhow::Result<usize> {
+        flatten::write_flattened_copy(self.library.bindings(), source, password, target, store, scale)
+    }
+
+    /// Escribe en `target` una copia de `source` sin nada de lo que había bajo las marcas
+    /// (ver `redact::write_redacted_copy`). `source` no se toca.
+    pub fn save_redacted(&self, source: &str, password: Option<&str>, target: &Path, marks: &[redact::RedactionMark]) -> anyhow::Result<redact::RedactedCopy> {
+        redact::write_redacted_copy(self.library.bindings(), source, password, target, marks)
+    }
+
+    /// Escribe en `target` un documento nuevo con las páginas de `plan` (ver `organize::write_organized_copy`).
+    pub fn save_organized(&self, source: &str, password: Option<&str>, target: &Path, plan: &[organize::PagePlan]) -> anyhow::Result<usize> {
+        organize::write_organized_copy(self.library.bindings(), source, password, target, plan)
+    }
+
+    /// Escribe en `target` las páginas de todos los `sources`, en orden (ver `organize::write_merged`).
+    pub fn merge(&self, sources: &[&str], password: Option<&str>, target: &Path) -> anyhow::Result<usize> {
+        organize::write_merged(self.library.bindings(), sources, password, target)
+    }
+
+    /// Divide `source` en un PDF por rango de páginas (ver `organize::write_split`).
+    pub fn split(&self, source: &str, password: Option<&str>, base: &Path, ranges: &[std::ops::Range<u16>]) -> anyhow::Result<Vec<std::path::PathBuf>> {
+        organize::write_split(self.library.bindings(), source, password, base, ranges)
+    }
+
+    /// Escribe en `target` las páginas de `source` impuestas en hojas para imprimir (ver `impose::write_imposed`).
+    pub fn save_imposed(&self, source: &str, password: Option<&str>, target: &Path, layout: impose::Layout, sheet: Option<(f32, f32)>) -> anyhow::Result<usize> {
+        impose::write_imposed(self.library.bindings(), source, password, target, layout, sheet)
+    }
+
+    /// Escribe en `target` un PDF con una página por imagen de `images`, en ese orden, sobre papel de
+    /// `paper` puntos o, sin él, del tamaño de cada imagen (ver `images::write_images_pdf`).
+    /// Devuelve cuántas páginas tiene.
+    pub fn images_to_pdf(&self, images: &[std::path::PathBuf], paper: Option<(f32, f32)>, fit: images::Fit, target: &Path) -> anyhow::Result<usize> {
+        images::write_images_pdf(self.library.bindings(), images, paper, fit, target)
+    }
+
+    /// Convierte una imagen en un PDF de una página, en el directorio temporal, para abrirla como
+    /// cualquier otro documento. Devuelve la ruta del PDF.
+    pub fn image_as_pdf(&self, image: &Path) -> anyhow::Result<String> {
+        use std::hash::{Hash, Hasher};
+        let dir = std::env::temp_dir().join("windp-images");
+        std::fs::create_dir_all(&dir)?;
+        // Dos imágenes con el mismo nombre en carpetas distintas no se pisan
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        std::fs::canonicalize(image).unwrap_or_else(|_| image.to_path_buf()).hash(&mut hasher);
+        let stem = image.file_stem().unwrap_or_default().to_string_lossy();
+        let target = dir.join(format!("{}-{:08x}.pdf", stem, hasher.finish() as u32));
+        self.images_to_pdf(&[image.to_path_buf()], None, images::Fit::Contain, &target)?;
+        Ok(target.to_string_lossy().into_owned())
+    }
+}
+
+/// ¿Nombran las dos rutas el mismo archivo? Se comparan canónicas ("./doc.pdf", enlaces...); la que
+/// aún no existe, con su carpeta canónica. Antes de escribir una copia hay que descartar que el
+/// destino sea uno de los PDF que se están leyendo: crearlo los truncaría.
+pub fn same_file(a: &Path, b: &Path) -> bool {
+    let canonical = |path: &Path| std::fs::canonicalize(path).ok().or_else(|| {
+        let dir = path.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new("."));
+        Some(std::fs::canonicalize(dir).ok()?.join(path.file_name()?))
+    });
+    match (canonical(a), canonical(b)) {
+        (Some(a), Some(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+/// Ejecuta `test` con PDFium, cargado una vez por proceso y de una prueba en una (no es
+/// reentrante). Las pruebas que lo usan llevan `#[ignore = "needs pdfium"]` y se pasan con
+/// `cargo test -- --ignored` donde esté la biblioteca: sin ella fallan, no se dan por buenas.
+#[cfg(test)]
+pub(crate) fn with_test_system(test: impl FnOnce(&PdfSystem)) {
+    struct Shared(Result<PdfSystem, String>);
+    // Solo se usa con LOCK tomado: nunca desde dos hilos a la vez
+    unsafe impl Send for Shared {}
+    unsafe impl Sync for Shared {}
+    static LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
+    static SYSTEM: std::sync::OnceLock<Shared> = std::sync::OnceLock::new();
+
+    let _guard = LOCK.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    let shared = SYSTEM.get_or_init(|| Shared(PdfSystem::try_new().map_err(|e| format!("{:?}", e))));
+    match &shared.0 {
+        Ok(system) => test(system),
+        Err(e) => panic!("PDFium no disponible: {}", e),
+    }
+}
+
+/// PDF mínimo escrito a mano para las pruebas: `objects[i]` es el cuerpo del objeto i + 1 (el 1 debe
+/// ser el catálogo). Calcula la tabla xref y el trailer.
+#[cfg(test)]
+pub(crate) fn fixture_pdf(objects: &[&str]) -> Vec<u8> {
+    let mut pdf = b"%PDF-1.7\n".to_vec();
+    let mut offsets = Vec::new();
+    for (i, object) in objects.iter().enumerate() {
+        offsets.push(pdf.len());
+        pdf.extend_from_slice(format!("{} 0 obj\n{}\nendobj\n", i + 1, object).as_bytes());
+    }
+    let xref = pdf.len();
+    pdf.extend_from_slice(format!("xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1).as_bytes());
+    for offset in offsets {
+        pdf.extend_from_slice(format!("{:010} 00000 n \n", offset).as_bytes());
+    }
+    pdf.extend_from_slice(format!("trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{}\n%%EOF\n", objects.len() + 1, xref).as_bytes());
+    pdf
+}
+
+/// Ruta única en el directorio temporal para los archivos de una prueba.
+#[cfg(test)]
+pub(crate) fn test_path(name: &str) -> std::path::PathBuf {
+    std::env::temp_dir().join(format!("windp-test-{}-{}", std::process::id(), name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn same_file_compares_canonical_paths() {
+        let dir = std::env::temp_dir().join(format!("windp-same-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("sub")).unwrap();
+        let image = dir.join("foto.png");
+        std::fs::write(&image, "").unwrap();
+        let result = std::panic::catch_unwind(|| {
+            assert!(same_file(&image, &dir.join("sub/../foto.png")));
+            assert!(same_file(&dir.join("./foto.png"), &image));
+            assert!(!same_file(&image, &dir.join("foto.pdf")));
+            // Un destino que aún no existe también se reconoce
+            assert!(same_file(&dir.join("nueva.pdf"), &dir.join("sub/../nueva.pdf")));
+            assert!(!same_file(&dir.join("nueva.pdf"), &dir.join("sub/nueva.pdf")));
+        });
+        let _ = std::fs::remove_dir_all(&dir);
+        result.unwrap();
+    }
 }
@@ -0,0 +1,248 @@
+use anyhow::{anyhow, bail, Result};
+use pdfium_render::prelude::*;
+use std::os::raw::c_int;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use super::annotations::save_copy;
+
+/// De dónde sale una página del documento reorganizado.
+#[derive(Clone, Debug, PartialEq)]
+pub enum PageSource {
+    /// Página (base 0) del documento que se organiza.
+    Original(u16),
+    /// Página (base 0) de otro PDF (sin contraseña o con la del documento que se organiza).
+    Imported { path: String, page: u16 },
+    /// Página en blanco de ese tamaño, en puntos.
+    Blank { width: f32, height: f32 },
+}
+
+/// Una página del documento nuevo: su origen y cuántos cuartos de vuelta (horarios) se le suman
+/// al giro que ya tuviera.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PagePlan {
+    pub source: PageSource,
+    pub rotation: u8,
+}
+
+/// Escribe en `target` un documento nuevo con las páginas de `plan`, en ese orden.
+/// Las páginas se copian enteras con FPDF_ImportPagesByIndex (contenido, anotaciones, enlaces),
+/// así que se puede repetir una, saltarse otras o mezclar varios PDF. `password` se prueba también
+/// con los otros PDF. Devuelve cuántas páginas tiene.
+pub fn write_organized_copy(bindings: &dyn PdfiumLibraryBindings, source: &str, password: Option<&str>, target: &Path, plan: &[PagePlan]) -> Result<usize> {
+    if plan.is_empty() {
+        bail!("El documento organizado no tiene ninguna página");
+    }
+    let document = bindings.FPDF_LoadDocument(source, password);
+    if document.is_null() {
+        bail!("PDFium no pudo abrir {} (error {})", source, bindings.FPDF_GetLastError());
+    }
+    let output = bindings.FPDF_CreateNewDocument();
+    if output.is_null() {
+        bindings.FPDF_CloseDocument(document);
+        bail!("PDFium no pudo crear el documento organizado");
+    }
+
+    // Los otros PDF se abren una sola vez, aunque aporten varias páginas
+    let mut imported: Vec<(&str, FPDF_DOCUMENT)> = Vec::new();
+    let result = build_pages(bindings, document, password, output, plan, &mut imported).and_then(|()| save_copy(bindings, output, target));
+    for (_, other) in imported {
+        bindings.FPDF_CloseDocument(other);
+    }
+    bindings.FPDF_CloseDocument(output);
+    bindings.FPDF_CloseDocument(document);
+    result.map(|()| plan.len())
+}
+
+fn build_pages<'p>(bindings: &dyn PdfiumLibraryBindings, document: FPDF_DOCUMENT, password: Option<&str>, output: FPDF_DOCUMENT, plan: &'p [PagePlan], imported: &mut Vec<(&'p str, FPDF_DOCUMENT)>) -> Result<()> {
+    // Las páginas seguidas del mismo PDF se copian de una vez: PDFium comparte entonces los recursos
+    // (fuentes, imágenes) que tengan en común en lugar de duplicarlos por página
+    let mut index = 0;
+    for run in plan.chunk_by(|a, b| same_document(&a.source, &b.source)) {
+        let at = index as c_int;
+        let pages = || run.iter().filter_map(|page| match page.source {
+            PageSource::Original(page_idx) | PageSource::Imported { page: page_idx, .. } => Some(page_idx),
+            PageSource::Blank { .. } => None,
+        });
+        let ok = match &run[0].source {
+            PageSource::Original(_) => import_pages(bindings, output, document, pages(), at),
+            PageSource::Imported { path, .. } => {
+                let other = match imported.iter().find(|(p, _)| p == path) {
+                    Some(&(_, other)) => other,
+                    None => {
+                        let other = bindings.FPDF_LoadDocument(path, password);
+                        if other.is_null() {
+                            bail!("PDFium no pudo abrir {} (error {})", path, bindings.FPDF_GetLastError());
+                        }
+                        imported.push((path, other));
+                        other
+                    },
+                };
+                import_pages(bindings, output, other, pages(), at)
+            },
+            PageSource::Blank { width, height } => {
+                let blank = bindings.FPDFPage_New(output, at, *width as f64, *height as f64);
+                if !blank.is_null() {
+                    bindings.FPDF_ClosePage(blank);
+                }
+                !blank.is_null()
+            },
+        };
+        if !ok {
+            return Err(match run.len() {
+                1 => anyhow!("No se pudo copiar la página {} ({:?})", index + 1, run[0].source),
+                n => anyhow!("No se pudieron copiar las páginas {}-{} ({:?}...)", index + 1, index + n, run[0].source),
+            });
+        }
+        index += run.len();
+    }
+
+    for (index, page) in plan.iter().enumerate().filter(|(_, page)| page.rotation % 4 != 0) {
+        let loaded = bindings.FPDF_LoadPage(output, index as c_int);
+        if loaded.is_null() {
+            bail!("No se pudo cargar la página {}", index + 1);
+        }
+        let rotation = (bindings.FPDFPage_GetRotation(loaded) + page.rotation as c_int) % 4;
+        bindings.FPDFPage_SetRotation(loaded, rotation);
+        bindings.FPDF_ClosePage(loaded);
+    }
+    Ok(())
+}
+
+// ¿Salen las dos páginas del mismo PDF? Las en blanco no se agrupan: cada una se crea aparte
+fn same_document(a: &PageSource, b: &PageSource) -> bool {
+    match (a, b) {
+        (PageSource::Original(_), PageSource::Original(_)) => true,
+        (PageSource::Imported { path: a, .. }, PageSource::Imported { path: b, .. }) => a == b,
+        _ => false,
+    }
+}
+
+/// Escribe en `target` las páginas de todos los `sources`, uno detrás de otro. `password` se prueba
+/// con todos (a los que no la piden no les afecta). Devuelve cuántas páginas tiene el resultado.
+pub fn write_merged(bindings: &dyn PdfiumLibraryBindings, sources: &[&str], password: Option<&str>, target: &Path) -> Result<usize> {
+    let output = bindings.FPDF_CreateNewDocument();
+    if output.is_null() {
+        bail!("PDFium no pudo crear el documento combinado");
+    }
+    let result = append_documents(bindings, output, sources, password).and_then(|()| save_copy(bindings, output, target));
+    let count = bindings.FPDF_GetPageCount(output) as usize;
+    bindings.FPDF_CloseDocument(output);
+    result.map(|()| count)
+}
+
+fn append_documents(bindings: &dyn PdfiumLibraryBindings, output: FPDF_DOCUMENT, sources: &[&str], password: Option<&str>) -> Result<()> {
+    for source in sources {
+        let document = bindings.FPDF_LoadDocument(source, password);
+        if document.is_null() {
+            bail!("PDFium no pudo abrir {} (error {})", source, bindings.FPDF_GetLastError());
+        }
+        let pages = 0..bindings.FPDF_GetPageCount(document) as u16;
+        let ok = import_pages(bindings, output, document, pages, bindings.FPDF_GetPageCount(output));
+        bindings.FPDF_CloseDocument(document);
+        if !ok {
+            bail!("No se pudieron copiar las páginas de {}", source);
+        }
+    }
+    Ok(())
+}
+
+/// Divide `source` en un archivo por rango (base 0, semiabiertos, como los da `cli::parse_page_ranges`),
+/// con los nombres de `split_target(base, rango)`. Devuelve los archivos escritos.
+pub fn write_split(bindings: &dyn PdfiumLibraryBindings, source: &str, password: Option<&str>, base: &Path, ranges: &[Range<u16>]) -> Result<Vec<PathBuf>> {
+    let document = bindings.FPDF_LoadDocument(source, password);
+    if document.is_null() {
+        bail!("PDFium no pudo abrir {} (error {})", source, bindings.FPDF_GetLastError());
+    }
+    let result = ranges.iter().map(|range| {
+        let target = split_target(base, range);
+        let output = bindings.FPDF_CreateNewDocument();
+        if output.is_null() {
+            bail!("PDFium no pudo crear {:?}", target);
+        }
+        let written = if import_pages(bindings, output, document, range.clone(), 0) {
+            save_copy(bindings, output, &target)
+        } else {
+            Err(anyhow!("No se pudieron copiar las páginas {}-{}", range.start + 1, range.end))
+        };
+        bindings.FPDF_CloseDocument(output);
+        written.map(|()| target)
+    }).collect();
+    bindings.FPDF_CloseDocument(document);
+    result
+}
+
+/// Nombre de la parte de una división: "dir/nombre.pdf" con las páginas 1-3 da "dir/nombre-1-3.pdf"
+/// (y "dir/nombre-5.pdf" si es una sola).
+pub fn split_target(base: &Path, range: &Range<u16>) -> PathBuf {
+    let stem = base.file_stem().unwrap_or_default().to_string_lossy();
+    let pages = if range.len() == 1 { format!("{}", range.start + 1) } else { format!("{}-{}", range.start + 1, range.end) };
+    base.with_file_name(format!("{}-{}.pdf", stem, pages))
+}
+
+// Copia las páginas `pages` (base 0) de `from` a `output`, a partir de la posición `at`
+pub(crate) fn import_pages(bindings: &dyn PdfiumLibraryBindings, output: FPDF_DOCUMENT, from: FPDF_DOCUMENT, pages: impl IntoIterator<Item = u16>, at: c_int) -> bool {
+    let indices: Vec<c_int> = pages.into_iter().map(c_int::from).collect();
+    !indices.is_empty() && bindings.is_true(bindings.FPDF_ImportPagesByIndex_vec(output, from, indices, at))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pdf::{fixture_pdf, test_path, with_test_system, PdfSystem};
+
+    #[test]
+    fn runs_of_the_same_document_are_grouped() {
+        let original = |page| PageSource::Original(page);
+        let other = |path: &str| PageSource::Imported { path: path.into(), page: 0 };
+        let blank = PageSource::Blank { width: 10.0, height: 10.0 };
+        assert!(same_document(&original(0), &original(5)));
+        assert!(same_document(&other("a.pdf"), &other("a.pdf")));
+        assert!(!same_document(&other("a.pdf"), &other("b.pdf")));
+        assert!(!same_document(&original(0), &other("a.pdf")));
+        assert!(!same_document(&blank, &blank));
+    }
+
+    #[test]
+    #[ignore = "needs pdfium"]
+    fn organized_pages_keep_order_and_rotation() {
+        with_test_system(organized_pages_keep_order_and_rotation_with);
+    }
+
+    fn organized_pages_keep_order_and_rotation_with(system: &PdfSystem) {
+        let bindings = system.library.bindings();
+        let (source, target) = (test_path("organize.pdf"), test_path("organize-1.pdf"));
+        // Cada página se reconoce por su ancho
+        let objects = [
+            "<< /Type /Catalog /Pages 2 0 R >>",
+            "<< /Type /Pages /Kids [3 0 R 4 0 R 5 0 R] /Count 3 >>",
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 100 300] >>",
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 110 300] >>",
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 120 300] >>",
+        ];
+        std::fs::write(&source, fixture_pdf(&objects)).unwrap();
+        let page = |source, rotation| PagePlan { source, rotation };
+        let plan = [
+            page(PageSource::Original(2), 0),
+            page(PageSource::Original(0), 1),
+            page(PageSource::Blank { width: 50.0, height: 50.0 }, 0),
+            page(PageSource::Original(1), 2),
+            page(PageSource::Original(1), 0),
+        ];
+        assert_eq!(write_organized_copy(bindings, source.to_str().unwrap(), None, &target, &plan).unwrap(), 5);
+
+        let document = bindings.FPDF_LoadDocument(target.to_str().unwrap(), None);
+        let pages: Vec<(f32, c_int)> = (0..bindings.FPDF_GetPageCount(document)).map(|index| {
+            let page = bindings.FPDF_LoadPage(document, index);
+            let found = (bindings.FPDF_GetPageWidthF(page), bindings.FPDFPage_GetRotation(page));
+            bindings.FPDF_ClosePage(page);
+            found
+        }).collect();
+        bindings.FPDF_CloseDocument(document);
+        // Con el giro, el ancho que da PDFium es el de la página ya girada
+        assert_eq!(pages, vec![(120.0, 0), (300.0, 1), (50.0, 0), (110.0, 2), (110.0, 0)]);
+        for path in [source, target] {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
@@ -0,0 +1,35 @@
+use pdfium_render::prelude::*;
+
+/// Un marcador del índice del documento, con sus hijos.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct OutlineEntry {
+    pub title: String,
+    pub page: Option<u16>, // Base 1; None si el marcador no apunta a una página (p.ej. una URL)
+    pub children: Vec<OutlineEntry>,
+}
+
+/// Extrae el árbol de marcadores completo.
+pub fn read_outline(document: &PdfDocument) -> Vec<OutlineEntry> {
+    collect_siblings(document.bookmarks().root())
+}
+
+// Recorremos a mano first_child/next_sibling: es el orden exacto del /Outlines del PDF
+fn collect_siblings(first: Option<PdfBookmark>) -> Vec<OutlineEntry> {
+    let mut entries = Vec::new();
+    let mut current = first;
+
+    while let Some(bookmark) = current {
+        let page = bookmark.destination()
+            .and_then(|dest| dest.page_index().ok())
+            .map(|idx| idx + 1);
+
+        entries.push(OutlineEntry {
+            title: bookmark.title().unwrap_or_default(),
+            page,
+            children: collect_siblings(bookmark.first_child()),
+        });
+        current = bookmark.next_sibling();
+    }
+
+    entries
+}
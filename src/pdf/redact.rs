@@ -0,0 +1,400 @@
+use anyhow::{anyhow, bail, Result};
+use pdfium_render::prelude::*;
+use std::collections::HashMap;
+use std::os::raw::{c_double, c_float, c_int, c_uint};
+use std::path::Path;
+
+use super::annotations::save_copy;
+
+// Constantes de fpdf_edit.h / fpdfview.h (pdfium-render no las reexporta)
+const FPDF_PAGEOBJ_TEXT: c_int = 1;
+const FPDF_PAGEOBJ_PATH: c_int = 2;
+const FPDF_PAGEOBJ_IMAGE: c_int = 3;
+const FPDF_PAGEOBJ_FORM: c_int = 5;
+const FPDF_FILLMODE_WINDING: c_int = 2;
+const FPDF_BITMAP_GRAY: c_int = 1;
+const FPDF_BITMAP_BGR: c_int = 2;
+const FPDF_BITMAP_BGRX: c_int = 3;
+const FPDF_BITMAP_BGRA: c_int = 4;
+
+/// Zona marcada para redactar: página (base 0) y caja [left, bottom, right, top] en puntos PDF.
+pub type RedactionMark = (u16, [f32; 4]);
+
+/// Un carácter de la capa de texto: a qué objeto pertenece se sabe por la clave del mapa.
+struct PageChar {
+    unicode: c_uint,
+    origin: (c_double, c_double),
+    covered: bool, // Cae bajo alguna marca
+}
+
+/// Lo que cambió al redactar: cuántos objetos de página se quitaron o cambiaron y la página (base 0)
+/// de cada formulario que se quitó entero por tocar una marca, para avisar de ello.
+pub struct RedactedCopy {
+    pub changed: usize,
+    pub whole_forms: Vec<u16>,
+}
+
+/// Escribe en `target` una copia de `source` en la que lo que había bajo las marcas ya no existe:
+/// - los caracteres tapados se borran del contenido; el resto del mismo objeto de texto se vuelve a
+///   escribir carácter a carácter con su fuente, tamaño y color, en su mismo lugar del orden de
+///   pintado, para no perder la línea entera,
+/// - las imágenes se pintan de negro en esa zona (o se quitan enteras si están giradas),
+/// - los trazados que caben dentro de una marca, los XObject de formulario que la tocan (enteros:
+///   se avisa en `RedactedCopy::whole_forms`) y las anotaciones que la pisan se quitan,
+/// - encima de cada marca queda una caja negra.
+///
+/// Al regenerar el contenido, PDFium deja en los recursos de la página solo lo que se sigue usando,
+/// así que las imágenes y formularios quitados no viajan a la copia.
+pub fn write_redacted_copy(bindings: &dyn PdfiumLibraryBindings, source: &str, password: Option<&str>, target: &Path, marks: &[RedactionMark]) -> Result<RedactedCopy> {
+    let document = bindings.FPDF_LoadDocument(source, password);
+    if document.is_null() {
+        bail!("PDFium no pudo abrir {} (error {})", source, bindings.FPDF_GetLastError());
+    }
+
+    let mut pages: HashMap<u16, Vec<[f32; 4]>> = HashMap::new();
+    for &(page, rect) in marks {
+        pages.entry(page).or_default().push(rect);
+    }
+    let result = pages.iter()
+        .try_fold(RedactedCopy { changed: 0, whole_forms: Vec::new() }, |mut total, (&page, rects)| {
+            let (changed, forms) = redact_page(bindings, document, page, rects)?;
+            total.changed += changed;
+            total.whole_forms.extend(std::iter::repeat_n(page, forms));
+            Ok::<_, anyhow::Error>(total)
+        })
+        .and_then(|mut redacted| {
+            save_pages_only(bindings, document, target)?;
+            redacted.whole_forms.sort_unstable();
+            Ok(redacted)
+        });
+    bindings.FPDF_CloseDocument(document);
+    result
+}
+
+// FPDF_SaveAsCopy vuelve a escribir los objetos del archivo original aunque ya nada los use: el
+// contenido viejo de la página seguiría dentro. Copiando las páginas a un documento nuevo solo viaja
+// lo que cuelga de ellas (se pierden el índice y los metadatos, que aquí no importan).
+fn save_pages_only(bindings: &dyn PdfiumLibraryBindings, document: FPDF_DOCUMENT, target: &Path) -> Result<()> {
+    let fresh = bindings.FPDF_CreateNewDocument();
+    if fresh.is_null() {
+        bail!("PDFium no pudo crear el documento redactado");
+    }
+    let range = format!("1-{}", bindings.FPDF_GetPageCount(document));
+    let result = if bindings.is_true(bindings.FPDF_ImportPages(fresh, document, &range, 0)) {
+        save_copy(bindings, fresh, target)
+    } else {
+        Err(anyhow!("PDFium no pudo copiar las páginas redactadas"))
+    };
+    bindings.FPDF_CloseDocument(fresh);
+    result
+}
+
+// Devuelve cuántos objetos se quitaron o cambiaron y, de ellos, cuántos eran formularios enteros
+fn redact_page(bindings: &dyn PdfiumLibraryBindings, document: FPDF_DOCUMENT, page_idx: u16, rects: &[[f32; 4]]) -> Result<(usize, usize)> {
+    let mut page = bindings.FPDF_LoadPage(document, page_idx as c_int);
+    if page.is_null() {
+        bail!("No se pudo cargar la página {}", page_idx + 1);
+    }
+    let covered = |bounds: &[f32; 4]| rects.iter().any(|mark| overlaps(mark, bounds));
+
+    // 1. Qué caracteres quedan bajo las marcas, agrupados por su objeto de texto
+    let mut chars: HashMap<FPDF_PAGEOBJECT, Vec<PageChar>> = HashMap::new();
+    let text_page = bindings.FPDFText_LoadPage(page);
+    if !text_page.is_null() {
+        for index in 0..bindings.FPDFText_CountChars(text_page) {
+            let object = bindings.FPDFText_GetTextObject(text_page, index);
+            let (mut left, mut right, mut bottom, mut top) = (0.0, 0.0, 0.0, 0.0);
+            if object.is_null() || !bindings.is_true(bindings.FPDFText_GetCharBox(text_page, index, &mut left, &mut right, &mut bottom, &mut top)) {
+                continue;
+            }
+            let mut origin = (0.0, 0.0);
+            bindings.FPDFText_GetCharOrigin(text_page, index, &mut origin.0, &mut origin.1);
+            chars.entry(object).or_default().push(PageChar {
+                unicode: bindings.FPDFText_GetUnicode(text_page, index),
+                origin,
+                covered: covered(&[left as f32, bottom as f32, right as f32, top as f32]),
+            });
+        }
+        bindings.FPDFText_ClosePage(text_page);
+    }
+
+    // 2. Qué objetos se van (con lo que los sustituye, en su mismo sitio) y qué imágenes se tapan
+    let mut doomed: Vec<(usize, FPDF_PAGEOBJECT, Vec<FPDF_PAGEOBJECT>)> = Vec::new();
+    let mut changed = 0;
+    let mut forms = 0;
+    for index in 0..bindings.FPDFPage_CountObjects(page) {
+        let object = bindings.FPDFPage_GetObject(page, index);
+        let Some(bounds) = object_bounds(bindings, object) else { continue };
+        if !covered(&bounds) {
+            continue;
+        }
+        let index = index as usize;
+        match bindings.FPDFPageObj_GetType(object) {
+            FPDF_PAGEOBJ_TEXT => match chars.get(&object) {
+                Some(list) if !list.iter().any(|c| c.covered) => {},
+                Some(list) => doomed.push((index, object, surviving_chars(bindings, document, object, list))),
+                // Texto sin capa de texto (p.ej. fuentes sin Unicode): no sabemos qué hay, fuera entero
+                None => doomed.push((index, object, Vec::new())),
+            },
+            FPDF_PAGEOBJ_IMAGE => {
+                if black_out_image(bindings, &mut page, object, rects) {
+                    changed += 1;
+                } else {
+                    doomed.push((index, object, Vec::new()));
+                }
+            },
+            FPDF_PAGEOBJ_PATH if rects.iter().any(|mark| contains(mark, &bounds)) => doomed.push((index, object, Vec::new())),
+            // Lo de dentro de un formulario no se puede tocar por separado: se va entero y se avisa
+            FPDF_PAGEOBJ_FORM => {
+                forms += 1;
+                doomed.push((index, object, Vec::new()));
+            },
+            _ => {},
+        }
+    }
+
+    // Desde el primer objeto que cambia, todo sale de la página y vuelve a entrar en el mismo orden,
+    // con los sustitutos en el lugar del quitado: lo que tenía encima sigue encima. (pdfium-render no
+    // expone FPDFPage_InsertObjectAtIndex para la versión de la API que enlazamos)
+    changed += doomed.len();
+    let mut removed = true;
+    if let Some(&(first, _, _)) = doomed.first() {
+        let tail: Vec<FPDF_PAGEOBJECT> = (first..bindings.FPDFPage_CountObjects(page) as usize)
+            .map(|index| bindings.FPDFPage_GetObject(page, index as c_int))
+            .collect();
+        let mut replaced: HashMap<FPDF_PAGEOBJECT, Vec<FPDF_PAGEOBJECT>> = doomed.into_iter().map(|(_, object, replacements)| (object, replacements)).collect();
+        for object in tail {
+            if !bindings.is_true(bindings.FPDFPage_RemoveObject(page, object)) {
+                removed = false;
+                break;
+            }
+            match replaced.remove(&object) {
+                Some(replacements) => {
+                    bindings.FPDFPageObj_Destroy(object);
+                    replacements.into_iter().for_each(|glyph| bindings.FPDFPage_InsertObject(page, glyph));
+                },
+                None => bindings.FPDFPage_InsertObject(page, object),
+            }
+        }
+        replaced.into_values().flatten().for_each(|glyph| bindings.FPDFPageObj_Destroy(glyph));
+    }
+    changed += remove_annotations(bindings, page, rects);
+
+    // 3. Las cajas negras, encima de todo
+    for &[left, bottom, right, top] in rects {
+        let bar = bindings.FPDFPageObj_CreateNewRect(left, bottom, right - left, top - bottom);
+        if bar.is_null() {
+            continue;
+        }
+        bindings.FPDFPageObj_SetFillColor(bar, 0, 0, 0, 255);
+        bindings.FPDFPath_SetDrawMode(bar, FPDF_FILLMODE_WINDING, bindings.FALSE());
+        bindings.FPDFPage_InsertObject(page, bar);
+    }
+
+    let generated = removed && bindings.is_true(bindings.FPDFPage_GenerateContent(page));
+    bindings.FPDF_ClosePage(page);
+    if !generated {
+        bail!("PDFium no pudo reescribir el contenido de la página {}", page_idx + 1);
+    }
+    Ok((changed, forms))
+}
+
+// Un objeto de texto por cada carácter que sobrevive, en su mismo sitio. Si algo falla se pierde
+// ese carácter: redactar de más es aceptable, de menos no.
+fn surviving_chars(bindings: &dyn PdfiumLibraryBindings, document: FPDF_DOCUMENT, object: FPDF_PAGEOBJECT, chars: &[PageChar]) -> Vec<FPDF_PAGEOBJECT> {
+    let font = bindings.FPDFTextObj_GetFont(object);
+    let mut size: c_float = 0.0;
+    let mut matrix = FS_MATRIX { a: 1.0, b: 0.0, c: 0.0, d: 1.0, e: 0.0, f: 0.0 };
+    if font.is_null() || !bindings.is_true(bindings.FPDFTextObj_GetFontSize(object, &mut size)) || !bindings.is_true(bindings.FPDFPageObj_GetMatrix(object, &mut matrix)) {
+        return Vec::new();
+    }
+    let (mut r, mut g, mut b, mut a) = (0, 0, 0, 255);
+    let filled = bindings.is_true(bindings.FPDFPageObj_GetFillColor(object, &mut r, &mut g, &mut b, &mut a));
+    let mode = bindings.FPDFTextObj_GetTextRenderMode(object);
+
+    chars.iter().filter(|c| !c.covered).filter_map(|c| {
+        let ch = char::from_u32(c.unicode).filter(|ch| !ch.is_whitespace())?;
+        let glyph = bindings.FPDFPageObj_CreateTextObj(document, font, size);
+        if glyph.is_null() {
+            return None;
+        }
+        // Misma matriz que el original (escala, giro), trasladada al origen del carácter
+        let placed = FS_MATRIX { e: c.origin.0 as f32, f: c.origin.1 as f32, ..matrix };
+        if !bindings.is_true(bindings.FPDFText_SetText_str(glyph, &ch.to_string())) || !bindings.is_true(bindings.FPDFPageObj_SetMatrix(glyph, &placed)) {
+            bindings.FPDFPageObj_Destroy(glyph);
+            return None;
+        }
+        if filled {
+            bindings.FPDFPageObj_SetFillColor(glyph, r, g, b, a);
+        }
+        bindings.FPDFTextObj_SetTextRenderMode(glyph, mode);
+        Some(glyph)
+    }).collect()
+}
+
+// Pinta de negro los píxeles de la imagen que caen bajo las marcas. False si no se puede (imagen
+// girada o en un formato que no conocemos): entonces se quita entera.
+fn black_out_image(bindings: &dyn PdfiumLibraryBindings, page: &mut FPDF_PAGE, image: FPDF_PAGEOBJECT, rects: &[[f32; 4]]) -> bool {
+    let mut m = FS_MATRIX { a: 1.0, b: 0.0, c: 0.0, d: 1.0, e: 0.0, f: 0.0 };
+    if !bindings.is_true(bindings.FPDFPageObj_GetMatrix(image, &mut m)) || m.b != 0.0 || m.c != 0.0 || m.a == 0.0 || m.d == 0.0 {
+        return false;
+    }
+    let bitmap = bindings.FPDFImageObj_GetBitmap(image);
+    if bitmap.is_null() {
+        return false;
+    }
+    let (width, height) = (bindings.FPDFBitmap_GetWidth(bitmap), bindings.FPDFBitmap_GetHeight(bitmap));
+    let stride = bindings.FPDFBitmap_GetStride(bitmap) as usize;
+    let black: &[u8] = match bindings.FPDFBitmap_GetFormat(bitmap) {
+        FPDF_BITMAP_GRAY => &[0],
+        FPDF_BITMAP_BGR => &[0, 0, 0],
+        FPDF_BITMAP_BGRX | FPDF_BITMAP_BGRA => &[0, 0, 0, 255],
+        _ => {
+            bindings.FPDFBitmap_Destroy(bitmap);
+            return false;
+        },
+    };
+    // SAFETY: el buffer es del bitmap, que vive hasta el Destroy de abajo, y ocupa stride * alto bytes
+    let pixels = unsafe { std::slice::from_raw_parts_mut(bindings.FPDFBitmap_GetBuffer(bitmap) as *mut u8, stride * height as usize) };
+
+    for &[left, bottom, right, top] in rects {
+        // La imagen es el cuadrado unidad llevado a la página por la matriz; su fila 0 es la de arriba
+        let column = |x: f32| (x - m.e) / m.a * width as f32;
+        let row = |y: f32| (1.0 - (y - m.f) / m.d) * height as f32;
+        let span = |a: f32, b: f32, max: c_int| (a.min(b).floor().clamp(0.0, max as f32) as usize, a.max(b).ceil().clamp(0.0, max as f32) as usize);
+        let (x0, x1) = span(column(left), column(right), width);
+        let (y0, y1) = span(row(bottom), row(top), height);
+        for y in y0..y1 {
+            for x in x0..x1 {
+                let at = y * stride + x * black.len();
+                pixels[at..at + black.len()].copy_from_slice(black);
+            }
+        }
+    }
+
+    let ok = bindings.is_true(bindings.FPDFImageObj_SetBitmap(page, 1, image, bitmap));
+    bindings.FPDFBitmap_Destroy(bitmap);
+    ok
+}
+
+// Quita las anotaciones que pisan alguna marca (una nota encima también enseñaría el texto)
+fn remove_annotations(bindings: &dyn PdfiumLibraryBindings, page: FPDF_PAGE, rects: &[[f32; 4]]) -> usize {
+    let mut doomed = Vec::new();
+    for index in 0..bindings.FPDFPage_GetAnnotCount(page) {
+        let annot = bindings.FPDFPage_GetAnnot(page, index);
+        if annot.is_null() {
+            continue;
+        }
+        let mut rect = FS_RECTF { left: 0.0, top: 0.0, right: 0.0, bottom: 0.0 };
+        if bindings.is_true(bindings.FPDFAnnot_GetRect(annot, &mut rect)) {
+            let bounds = [rect.left.min(rect.right), rect.bottom.min(rect.top), rect.left.max(rect.right), rect.bottom.max(rect.top)];
+            if rects.iter().any(|mark| overlaps(mark, &bounds)) {
+                doomed.push(index);
+            }
+        }
+        bindings.FPDFPage_CloseAnnot(annot);
+    }
+    // De atrás adelante, para no mover los índices pendientes
+    for &index in doomed.iter().rev() {
+        bindings.FPDFPage_RemoveAnnot(page, index);
+    }
+    doomed.len()
+}
+
+fn object_bounds(bindings: &dyn PdfiumLibraryBindings, object: FPDF_PAGEOBJECT) -> Option<[f32; 4]> {
+    let (mut left, mut bottom, mut right, mut top) = (0.0, 0.0, 0.0, 0.0);
+    (!object.is_null() && bindings.is_true(bindings.FPDFPageObj_GetBounds(object, &mut left, &mut bottom, &mut right, &mut top)))
+        .then_some([left, bottom, right, top])
+}
+
+// Se tocan con área (rozarse en un borde no cuenta)
+fn overlaps(a: &[f32; 4], b: &[f32; 4]) -> bool {
+    a[0] < b[2] && b[0] < a[2] && a[1] < b[3] && b[1] < a[3]
+}
+
+fn contains(outer: &[f32; 4], inner: &[f32; 4]) -> bool {
+    outer[0] <= inner[0] && outer[1] <= inner[1] && inner[2] <= outer[2] && inner[3] <= outer[3]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pdf::{fixture_pdf, test_path, with_test_system, PdfSystem};
+
+    fn stream(dict: &str, data: &str) -> String {
+        format!("<< {} /Length {} >>\nstream\n{}\nendstream", dict, data.len(), data)
+    }
+
+    #[test]
+    #[ignore = "needs pdfium"]
+    fn covered_text_and_objects_do_not_survive() {
+        with_test_system(covered_text_and_objects_do_not_survive_with);
+    }
+
+    fn covered_text_and_objects_do_not_survive_with(system: &PdfSystem) {
+        let bindings = system.library.bindings();
+        let (source, target) = (test_path("redact.pdf"), test_path("redact-1.pdf"));
+        // Una imagen lejos de la marca, otra girada y un formulario que la tocan, la línea de texto
+        // y, encima de ella, una caja verde que asoma fuera de la marca
+        let content = "q 30 0 0 30 120 20 cm /Im1 Do Q\n\
+            q 0 30 -30 0 60 150 cm /Im2 Do Q\n\
+            q 1 0 0 1 20 100 cm /Fm1 Do Q\n\
+            BT /F1 12 Tf 20 150 Td (SECRET PUBLIC) Tj ET\n\
+            0 1 0 rg 60 148 30 10 re f";
+        let image = stream("/Type /XObject /Subtype /Image /Width 2 /Height 2 /ColorSpace /DeviceRGB /BitsPerComponent 8 /Filter /ASCIIHexDecode", "FF0000FF0000FF0000FF0000>");
+        let objects = [
+            "<< /Type /Catalog /Pages 2 0 R >>".to_string(),
+            "<< /Type /Pages /Kids [3 0 R] /Count 1 >>".to_string(),
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 200 200] /Contents 4 0 R /Resources << /Font << /F1 5 0 R >> /XObject << /Im1 6 0 R /Im2 7 0 R /Fm1 8 0 R >> >> >>".to_string(),
+            stream("", content),
+            "<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica >>".to_string(),
+            image.clone(),
+            image,
+            stream("/Type /XObject /Subtype /Form /BBox [0 0 40 50]", "0 0 1 rg 0 0 40 50 re f"),
+        ];
+        std::fs::write(&source, fixture_pdf(&objects.iter().map(String::as_str).collect::<Vec<_>>())).unwrap();
+        let mark = [15.0, 145.0, 70.0, 165.0];
+        let redacted = write_redacted_copy(bindings, source.to_str().unwrap(), None, &target, &[(0, mark)]).unwrap();
+        assert_eq!(redacted.whole_forms, vec![0]);
+
+        let document = bindings.FPDF_LoadDocument(target.to_str().unwrap(), None);
+        let page = bindings.FPDF_LoadPage(document, 0);
+        // Ningún carácter bajo la marca; lo demás de la línea sigue siendo texto
+        let text_page = bindings.FPDFText_LoadPage(page);
+        let mut text = String::new();
+        for index in 0..bindings.FPDFText_CountChars(text_page) {
+            let (mut left, mut right, mut bottom, mut top) = (0.0, 0.0, 0.0, 0.0);
+            bindings.FPDFText_GetCharBox(text_page, index, &mut left, &mut right, &mut bottom, &mut top);
+            let ch = char::from_u32(bindings.FPDFText_GetUnicode(text_page, index)).unwrap_or('?');
+            if !ch.is_whitespace() {
+                assert!(!overlaps(&mark, &[left as f32, bottom as f32, right as f32, top as f32]), "{:?} sigue bajo la marca", ch);
+                text.push(ch);
+            }
+        }
+        bindings.FPDFText_ClosePage(text_page);
+        assert_eq!(text, "PUBLIC");
+
+        // La caja verde sigue encima del texto que se volvió a escribir
+        let objects: Vec<FPDF_PAGEOBJECT> = (0..bindings.FPDFPage_CountObjects(page)).map(|i| bindings.FPDFPage_GetObject(page, i)).collect();
+        let kinds: Vec<c_int> = objects.iter().map(|&object| bindings.FPDFPageObj_GetType(object)).collect();
+        let green = objects.iter().zip(&kinds).position(|(&object, &kind)| {
+            let (mut r, mut g, mut b, mut a) = (0, 0, 0, 0);
+            bindings.FPDFPageObj_GetFillColor(object, &mut r, &mut g, &mut b, &mut a);
+            kind == FPDF_PAGEOBJ_PATH && (r, g, b) == (0, 255, 0)
+        }).expect("la caja verde se ha perdido");
+        assert!(kinds.iter().rposition(|&kind| kind == FPDF_PAGEOBJ_TEXT).unwrap() < green, "{:?}", kinds);
+        assert_eq!(kinds.iter().filter(|&&kind| kind == FPDF_PAGEOBJ_IMAGE).count(), 1);
+        assert!(!kinds.contains(&FPDF_PAGEOBJ_FORM));
+        bindings.FPDF_ClosePage(page);
+        bindings.FPDF_CloseDocument(document);
+
+        // En el archivo no queda ningún XObject sin usar: ni la imagen girada ni el formulario
+        let bytes: Vec<u8> = std::fs::read(&target).unwrap().into_iter().filter(|b| !b.is_ascii_whitespace()).collect();
+        let count = |needle: &[u8]| bytes.windows(needle.len()).filter(|window| *window == needle).count();
+        assert_eq!(count(b"/Subtype/Image"), 1);
+        assert_eq!(count(b"/Subtype/Form"), 0);
+        for path in [source, target] {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
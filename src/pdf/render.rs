@@ -7,6 +7,17 @@ pub struct PageBitmap {
     pub data: Vec<u8>, // Bytes crudos BGRA/RGBA listos para la GPU
 }
 
+impl PageBitmap {
+    /// Convierte el bitmap BGRA de PDFium a una imagen RGBA para guardarla con el crate image.
+    pub fn to_rgba_image(&self) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+        let mut rgba = self.data.clone();
+        for px in rgba.chunks_exact_mut(4) {
+            px.swap(0, 2);
+        }
+        ImageBuffer::from_raw(self.width, self.height, rgba).expect("Tamaño de bitmap inconsistente")
+    }
+}
+
 /// Renderiza una página específica a una escala dada.
 /// scale_factor: 1.0 = tamaño original (72 DPI), 2.0 = HiDPI/Retina.
 pub fn render_page_to_memory(
@@ -29,14 +40,14 @@ pub fn render_page_to_memory(
         .set_target_width(width)
         .set_target_height(height)
         .set_format(PdfBitmapFormat::BGRA) 
-        .rotate_if_landscape(PdfBitmapRotation::Degrees0, true); // Auto-rotar si es necesario
+        .rotate_if_landscape(PdfPageRenderRotation::None, true); // Auto-rotar si es necesario
 
     // 4. Rasterizar (Operación pesada para la CPU)
     let bitmap = page.render_with_config(&render_config)?;
 
     // 5. Extraer bytes
-    // as_bytes() nos da el buffer crudo sin copias innecesarias.
-    let data = bitmap.as_bytes().to_vec();
+    // as_raw_bytes() nos da una copia del buffer crudo del bitmap.
+    let data = bitmap.as_raw_bytes();
 
     Ok(PageBitmap {
         width: width as u32,
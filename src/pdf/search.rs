@@ -0,0 +1,35 @@
+use pdfium_render::prelude::*;
+
+/// Una coincidencia de búsqueda dentro del documento.
+pub struct SearchHit {
+    pub page: u16,
+    pub text: String,
+    /// Cajas de la coincidencia en puntos PDF: [left, bottom, right, top].
+    /// Una coincidencia que cruza de línea ocupa varias cajas.
+    pub rects: Vec<[f32; 4]>,
+}
+
+/// Busca un término en todas las páginas (sin distinguir mayúsculas).
+pub fn search_document(document: &PdfDocument, term: &str) -> Result<Vec<SearchHit>, PdfiumError> {
+    let options = PdfSearchOptions::new();
+    let mut hits = Vec::new();
+
+    for (page_idx, page) in document.pages().iter().enumerate() {
+        let text = page.text()?;
+        let search = text.search(term, &options)?;
+
+        for segments in search.iter(PdfSearchDirection::SearchForward) {
+            let rects = segments.iter()
+                .map(|segment| {
+                    let b = segment.bounds();
+                    [b.left().value, b.bottom().value, b.right().value, b.top().value]
+                })
+                .collect();
+            let text = segments.iter().map(|segment| segment.text()).collect();
+
+            hits.push(SearchHit { page: page_idx as u16, text, rects });
+        }
+    }
+
+    Ok(hits)
+}
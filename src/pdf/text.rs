@@ -0,0 +1,80 @@
+use pdfium_render::prelude::*;
+use std::ops::RangeInclusive;
+
+/// Un carácter de la capa de texto con su caja en puntos PDF: [left, bottom, right, top].
+/// Los caracteres que PDFium genera (espacios y saltos implícitos) no tienen caja.
+#[derive(Clone, Debug)]
+pub struct PageChar {
+    pub ch: char,
+    pub bounds: Option<[f32; 4]>,
+}
+
+/// Lee los caracteres de una página en orden de lectura.
+pub fn page_chars(document: &PdfDocument, page_idx: u16) -> Result<Vec<PageChar>, PdfiumError> {
+    let page = document.pages().get(page_idx)?;
+    let text = page.text()?;
+
+    let chars = text.chars().iter().map(|c| {
+        let ch = c.unicode_char().unwrap_or('\u{FFFD}');
+        // Caja "suelta": ocupa el alto de la línea, que es lo que queremos resaltar
+        let bounds = c.loose_bounds().ok()
+            .map(|b| [b.left().value, b.bottom().value, b.right().value, b.top().value])
+            .filter(|b| !ch.is_whitespace() && b[2] > b[0] && b[3] > b[1]);
+        PageChar { ch, bounds }
+    }).collect();
+
+    Ok(chars)
+}
+
+/// Carácter más cercano a un punto de la página (en puntos PDF), si está a menos de `max_distance`.
+pub fn char_near(chars: &[PageChar], x: f32, y: f32, max_distance: f32) -> Option<usize> {
+    chars.iter().enumerate()
+        .filter_map(|(i, c)| {
+            let [left, bottom, right, top] = c.bounds?;
+            let dx = (left - x).max(x - right).max(0.0);
+            let dy = (bottom - y).max(y - top).max(0.0);
+            Some((i, (dx * dx + dy * dy).sqrt()))
+        })
+        .filter(|(_, d)| *d <= max_distance)
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(i, _)| i)
+}
+
+/// Rango entre dos caracteres (en cualquier orden) ampliado a palabras completas.
+pub fn word_range(chars: &[PageChar], a: usize, b: usize) -> RangeInclusive<usize> {
+    let in_word = |i: usize| chars.get(i).is_some_and(|c| !c.ch.is_whitespace());
+    let (mut start, mut end) = (a.min(b), a.max(b));
+    while start > 0 && in_word(start - 1) && in_word(start) {
+        start -= 1;
+    }
+    while end + 1 < chars.len() && in_word(end + 1) && in_word(end) {
+        end += 1;
+    }
+    start..=end
+}
+
+/// Cajas de un rango de caracteres: una por línea, uniendo las de sus caracteres.
+/// Una línea nueva empieza cuando el carácter no cae a la altura de la línea actual
+/// o vuelve hacia la izquierda (salto de columna o de línea sin carácter de salto).
+pub fn line_rects(chars: &[PageChar], range: RangeInclusive<usize>) -> Vec<[f32; 4]> {
+    let mut rects: Vec<[f32; 4]> = Vec::new();
+
+    for c in &chars[range] {
+        let Some(b) = c.bounds else { continue };
+        let center_y = (b[1] + b[3]) * 0.5;
+        match rects.last_mut() {
+            Some(line) if center_y >= line[1] && center_y <= line[3] && b[0] >= line[2] - (line[3] - line[1]) => {
+                *line = [line[0].min(b[0]), line[1].min(b[1]), line[2].max(b[2]), line[3].max(b[3])];
+            },
+            _ => rects.push(b),
+        }
+    }
+
+    rects
+}
+
+/// Texto de un rango; los saltos de línea de PDFium se normalizan a espacios.
+pub fn range_text(chars: &[PageChar], range: RangeInclusive<usize>) -> String {
+    let text: String = chars[range].iter().map(|c| if c.ch == '\r' || c.ch == '\n' { ' ' } else { c.ch }).collect();
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
@@ -0,0 +1,276 @@
+use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use serde::Deserialize;
+use serde_json::{json, Value};
+use winit::event_loop::EventLoopProxy;
+
+use crate::ipc::{self, AppEvent, LocalStream};
+use crate::pdf::impose::Layout;
+use crate::state::{State, ViewSnapshot};
+
+/// Órdenes aceptadas por el socket de control remoto.
+/// Protocolo: un objeto JSON por línea, p.ej. `{"id": 1, "cmd": "goto", "page": 3}`.
+/// Las páginas siempre van en base 1, como las ve el usuario. En Windows el canal es un puerto de
+/// loopback: su número y una clave están en `%TEMP%\windp-remote` ("puerto\tclave"), y la primera
+/// línea del cliente debe ser esa clave.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "cmd", rename_all = "snake_case")]
+pub enum Command {
+    Open { path: String, page: Option<u16> },
+    Goto { page: u16 },
+    Zoom { value: f32 },
+    Search { term: String },
+    ExportAnnotations { path: Option<String> },
+    ExportXfdf { path: String },
+    ImportXfdf { path: String },
+    ExportMarkdown { path: String },
+    ExportFlattened { path: String, pages: Option<String> },
+    MarkRedactions { term: String },
+    ApplyRedactions { path: String },
+    Merge { inputs: Vec<String>, path: String },
+    Split { path: String, ranges: Option<String> },
+    Impose { path: String, layout: String, sheet: Option<String> },
+    Images { inputs: Vec<String>, path: String, page: Option<String>, fit: Option<String> },
+    State,
+}
+
+#[derive(Deserialize)]
+struct Request {
+    id: Option<Value>,
+    #[serde(flatten)]
+    command: Command,
+}
+
+/// Endpoint por defecto: socket en el directorio de runtime (Unix) o un puerto libre de loopback
+/// (Windows).
+pub fn default_endpoint() -> String {
+    #[cfg(unix)]
+    {
+        ipc::runtime_dir().join("windp-remote.sock").to_string_lossy().into_owned()
+    }
+    #[cfg(not(unix))]
+    {
+        "127.0.0.1:0".to_string()
+    }
+}
+
+/// Sin sockets Unix cualquier proceso local, o una página web con un POST, llega al puerto: como en
+/// la instancia única, aquí se anotan el puerto y la clave que deben mandar los clientes.
+#[cfg(not(unix))]
+fn endpoint_file() -> std::path::PathBuf {
+    ipc::runtime_dir().join("windp-remote")
+}
+
+/// Servidor de control remoto. Los clientes se atienden en hilos propios;
+/// las órdenes se ejecutan en el hilo principal (donde vive State) vía el proxy de winit.
+pub struct RemoteServer {
+    // Lo que se borra al salir: el socket (Unix) o el archivo del puerto y la clave (Windows)
+    leftover: std::path::PathBuf,
+    clients: Arc<Mutex<HashMap<u64, LocalStream>>>,
+    last_snapshot: Option<ViewSnapshot>,
+}
+
+impl RemoteServer {
+    pub fn start(endpoint: &str, proxy: EventLoopProxy<AppEvent>) -> io::Result<Self> {
+        // El endpoint por defecto vive en la carpeta privada de runtime, que quizá aún no exista
+        #[cfg(unix)]
+        if Path::new(endpoint).parent() == Some(ipc::runtime_dir().as_path()) {
+            ipc::private_runtime_dir()?;
+        }
+        let listener = ipc::bind_endpoint(endpoint)?;
+        #[cfg(unix)]
+        let (token, leftover, shown) = (None::<String>, std::path::PathBuf::from(endpoint), endpoint.to_string());
+        #[cfg(not(unix))]
+        let (token, leftover, shown) = (
+            Some(ipc::publish_endpoint(&endpoint_file(), &listener)?),
+            endpoint_file(),
+            format!("{} (clave en {})", listener.local_addr()?, endpoint_file().display()),
+        );
+        let clients: Arc<Mutex<HashMap<u64, LocalStream>>> = Arc::new(Mutex::new(HashMap::new()));
+
+        let accept_clients = clients.clone();
+        std::thread::spawn(move || {
+            let next_id = AtomicU64::new(1);
+            for stream in listener.incoming().flatten() {
+                let client = next_id.fetch_add(1, Ordering::Relaxed);
+                // Un cliente que no lee no debe congelar la ventana al escribirle
+                let _ = stream.set_write_timeout(Some(Duration::from_millis(500)));
+                let Ok(writer) = stream.try_clone() else { continue };
+                accept_clients.lock().unwrap().insert(client, writer);
+
+                let proxy = proxy.clone();
+                let clients = accept_clients.clone();
+                let token = token.clone();
+                std::thread::spawn(move || serve_client(client, stream, token.as_deref(), proxy, clients));
+            }
+        });
+
+        log::info!("Control remoto escuchando en {}", shown);
+        Ok(Self { leftover, clients, last_snapshot: None })
+    }
+
+    /// Envía la respuesta a una orden al cliente que la hizo.
+    pub fn reply(&self, client: u64, id: Option<Value>, result: Result<Value, String>) {
+        let message = match result {
+            Ok(result) => json!({ "id": id, "ok": true, "result": result }),
+            Err(error) => json!({ "id": id, "ok": false, "error": error }),
+        };
+        send_to(&self.clients, client, &message);
+    }
+
+    /// Compara el estado visible con el último publicado y emite eventos de cambio.
+    /// Se llama una vez por vuelta del bucle de eventos: es barato si nada cambió.
+    pub fn publish(&mut self, snapshot: ViewSnapshot) {
+        let previous = self.last_snapshot.replace(snapshot.clone());
+        let Some(previous) = previous else { return };
+        if previous == snapshot {
+            return;
+        }
+
+        if previous.file != snapshot.file {
+            self.broadcast(&json!({ "event": "document_opened", "state": snapshot }));
+        } else if previous.page != snapshot.page {
+            self.broadcast(&json!({ "event": "page_changed", "page": snapshot.page, "total_pages": snapshot.total_pages }));
+        }
+        if previous.zoom != snapshot.zoom {
+            self.broadcast(&json!({ "event": "zoom_changed", "zoom": snapshot.zoom }));
+        }
+    }
+
+    fn broadcast(&self, message: &Value) {
+        let ids: Vec<u64> = self.clients.lock().unwrap().keys().copied().collect();
+        for client in ids {
+            send_to(&self.clients, client, message);
+        }
+    }
+
+    /// En Unix el socket es un archivo, y en Windows queda el del puerto y la clave: los borramos al
+    /// salir para no dejar restos.
+    pub fn shutdown(&self) {
+        let _ = std::fs::remove_file(&self.leftover);
+    }
+}
+
+/// Ejecuta una orden sobre el estado de la app y devuelve el resultado en JSON.
+pub fn execute(state: &mut State, command: Command) -> Result<Value, String> {
+    match command {
+        Command::Open { path, page } => {
+            state.open_document(&path, page.map(|p| p.saturating_sub(1)), None)
+                .map_err(|e| format!("No se pudo abrir {}: {:#}", path, e))?;
+            Ok(json!(state.snapshot()))
+        },
+        Command::Goto { page } => {
+            let total = state.snapshot().total_pages;
+            if page == 0 || page > total {
+                return Err(format!("Página fuera de rango: {} (total {})", page, total));
+            }
+            state.go_to_page(page - 1);
+            Ok(json!(state.snapshot()))
+        },
+        Command::Zoom { value } => {
+            state.set_zoom(value);
+            Ok(json!(state.snapshot()))
+        },
+        Command::Search { term } => {
+            let hits = state.search(&term).map_err(|e| format!("Error en la búsqueda: {:?}", e))?;
+            let hits: Vec<Value> = hits.iter()
+                .map(|hit| json!({ "page": hit.page + 1, "text": hit.text, "rects": hit.rects }))
+                .collect();
+            Ok(json!({ "count": hits.len(), "hits": hits }))
+        },
+        // Sin ruta, las anotaciones van en la propia respuesta
+        Command::ExportAnnotations { path: Some(path) } => {
+            let count = state.export_annotations(Path::new(&path)).map_err(|e| format!("{:#}", e))?;
+            Ok(json!({ "path": path, "count": count }))
+        },
+        Command::ExportAnnotations { path: None } => {
+            state.annotations_json().ok_or_else(|| "No hay ningún documento abierto".to_string())
+        },
+        Command::ExportXfdf { path } => {
+            let count = state.export_xfdf(Path::new(&path)).map_err(|e| format!("{:#}", e))?;
+            Ok(json!({ "path": path, "count": count }))
+        },
+        Command::ImportXfdf { path } => {
+            let count = state.import_xfdf(Path::new(&path)).map_err(|e| format!("{:#}", e))?;
+            Ok(json!({ "path": path, "count": count }))
+        },
+        Command::ExportMarkdown { path } => {
+            let count = state.export_markdown(Path::new(&path)).map_err(|e| format!("{:#}", e))?;
+            Ok(json!({ "path": path, "count": count }))
+        },
+        Command::ExportFlattened { path, pages } => {
+            let count = state.export_flattened(Path::new(&path), pages.as_deref()).map_err(|e| format!("{:#}", e))?;
+            Ok(json!({ "path": path, "count": count }))
+        },
+        Command::MarkRedactions { term } => {
+            let count = state.mark_redactions(&term).map_err(|e| format!("{:#}", e))?;
+            Ok(json!({ "term": term, "count": count }))
+        },
+        Command::ApplyRedactions { path } => {
+            let count = state.apply_redactions(Path::new(&path)).map_err(|e| format!("{:#}", e))?;
+            Ok(json!({ "path": path, "count": count }))
+        },
+        Command::Merge { inputs, path } => {
+            let count = state.merge_documents(&inputs, Path::new(&path)).map_err(|e| format!("{:#}", e))?;
+            Ok(json!({ "path": path, "count": count }))
+        },
+        Command::Split { path, ranges } => {
+            let count = state.split_document(Path::new(&path), ranges.as_deref()).map_err(|e| format!("{:#}", e))?;
+            Ok(json!({ "path": path, "count": count }))
+        },
+        Command::Impose { path, layout, sheet } => {
+            let layout = Layout::parse(&layout)?;
+            let count = state.export_imposed(Path::new(&path), layout, sheet.as_deref()).map_err(|e| format!("{:#}", e))?;
+            Ok(json!({ "path": path, "count": count }))
+        },
+        Command::Images { inputs, path, page, fit } => {
+            let count = state.combine_images(&inputs, Path::new(&path), page.as_deref(), fit.as_deref()).map_err(|e| format!("{:#}", e))?;
+            Ok(json!({ "path": path, "count": count }))
+        },
+        Command::State => Ok(json!(state.snapshot())),
+    }
+}
+
+fn serve_client(client: u64, stream: LocalStream, token: Option<&str>, proxy: EventLoopProxy<AppEvent>, clients: Arc<Mutex<HashMap<u64, LocalStream>>>) {
+    let mut reader = BufReader::new(stream);
+    // Sin la clave no se atiende nada, ni siquiera para responder que la orden es inválida
+    if !ipc::token_matches(&mut reader, token) {
+        clients.lock().unwrap().remove(&client);
+        return;
+    }
+    for line in reader.lines() {
+        let Ok(line) = line else { break };
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        match serde_json::from_str::<Request>(&line) {
+            Ok(Request { id, command }) => {
+                if proxy.send_event(AppEvent::Remote { client, id, command }).is_err() {
+                    break; // La ventana se cerró
+                }
+            },
+            Err(e) => {
+                // El id puede venir aunque la orden sea inválida; lo devolvemos si se puede leer
+                let id = serde_json::from_str::<Value>(&line).ok().and_then(|v| v.get("id").cloned());
+                send_to(&clients, client, &json!({ "id": id, "ok": false, "error": format!("Orden inválida: {}", e) }));
+            },
+        }
+    }
+
+    clients.lock().unwrap().remove(&client);
+}
+
+fn send_to(clients: &Mutex<HashMap<u64, LocalStream>>, client: u64, message: &Value) {
+    let mut clients = clients.lock().unwrap();
+    let Some(stream) = clients.get_mut(&client) else { return };
+    if writeln!(stream, "{}", message).is_err() {
+        // Cliente desconectado o bloqueado: lo damos de baja
+        clients.remove(&client);
+    }
+}
@@ -0,0 +1,254 @@
+//! Archivos de anotaciones aparte del PDF ("sidecar"), para documentos en carpetas de solo lectura.
+//!
+//! Formato (JSON, UTF-8):
+//!
+//! ```json
+//! {
+//!   "format": "windp-annotations",
+//!   "version": 1,
+//!   "document": "informe.pdf",
+//!   "pages": {
+//!     "1": [
+//!       { "type": "ink", "samples": [{ "pos": { "x": 72.0, "y": 700.5 }, "pressure": 1.0 }], "width": 1.5, "color": [20, 20, 20, 255] },
+//!       { "type": "shape", "shape": "rectangle", "from": { "x": 72.0, "y": 600.0 }, "to": { "x": 200.0, "y": 650.0 }, "width": 2.0, "color": [220, 40, 40, 255] }
+//!     ],
+//!     "3": [
+//!       { "type": "text_highlight", "rects": [[72.0, 500.0, 300.0, 512.0]], "text": "texto resaltado", "color": [255, 255, 0, 100] },
+//!       { "type": "note", "at": { "x": 40.0, "y": 780.0 }, "text": "Revisar", "color": [245, 190, 30, 255], "author": "Ana", "modified": "D:20240105120000" },
+//!       { "type": "free_text", "at": { "x": 100.0, "y": 300.0 }, "text": "Aprobado", "size": 12.0, "color": [20, 20, 20, 255] }
+//!     ]
+//!   }
+//! }
+//! ```
+//!
+//! - Las claves de `pages` son números de página en base 1, como los ve el usuario.
+//! - Las coordenadas son puntos PDF (1/72") con el origen abajo-izquierda, igual que en el propio PDF:
+//!   no dependen del zoom ni de la resolución con que se pinte la página.
+//! - `color` es RGBA 0-255; el alpha es la opacidad de la anotación entera.
+//! - `type` es `ink`, `shape` (`rectangle`, `ellipse`, `line` o `arrow`), `text_highlight`
+//!   (cajas `[left, bottom, right, top]`), `note` o `free_text` (`size` = alto de línea en puntos).
+//! - `author`, `modified` y `name` (/T, /M y /NM del PDF) son opcionales. Sin `name`, se le da
+//!   uno nuevo al cargarla.
+//! - Dentro de una página, el orden es el de pintado (las últimas quedan encima).
+
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::ui::overlay::{Annotation, AnnotationStore};
+
+pub const FORMAT: &str = "windp-annotations";
+pub const VERSION: u32 = 1;
+
+/// Dónde viven las anotaciones de un documento.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum AnnotationStorage {
+    /// Dentro del propio PDF, al guardar con Ctrl+S.
+    #[default]
+    Pdf,
+    /// En "documento.pdf.windp.json", junto al PDF.
+    Sidecar,
+    /// En el directorio de datos del usuario (para carpetas donde no se puede escribir nada).
+    DataDir,
+}
+
+impl AnnotationStorage {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "pdf" => Ok(Self::Pdf),
+            "sidecar" => Ok(Self::Sidecar),
+            "data" | "data-dir" => Ok(Self::DataDir),
+            _ => Err(format!("Almacén de anotaciones inválido (pdf|sidecar|data): {}", value)),
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Pdf => "pdf",
+            Self::Sidecar => "sidecar",
+            Self::DataDir => "data",
+        }
+    }
+
+    /// Archivo de anotaciones de un documento. None si se guardan en el PDF.
+    pub fn path_for(self, document: &str) -> Option<PathBuf> {
+        match self {
+            Self::Pdf => None,
+            Self::Sidecar => Some(PathBuf::from(format!("{}.windp.json", document))),
+            Self::DataDir => {
+                // El mismo nombre puede repetirse en carpetas distintas: lo distingue el hash de la ruta
+                let full = std::fs::canonicalize(document).unwrap_or_else(|_| PathBuf::from(document));
+                let stem = full.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+                let name = format!("{}-{:016x}.json", stem, fnv1a(full.to_string_lossy().as_bytes()));
+                Some(data_dir().join("annotations").join(name))
+            },
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct SidecarFile {
+    format: String,
+    version: u32,
+    #[serde(default)]
+    document: String,
+    pages: BTreeMap<u16, Vec<Annotation>>, // Base 1
+}
+
+/// El contenido del archivo de anotaciones (también lo usa la orden remota `export_annotations`).
+pub fn to_json(document: &str, store: &AnnotationStore) -> serde_json::Value {
+    let file = SidecarFile {
+        format: FORMAT.to_string(),
+        version: VERSION,
+        document: Path::new(document).file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default(),
+        pages: store.pages().map(|(page, list)| (page + 1, list.to_vec())).collect(),
+    };
+    serde_json::to_value(file).unwrap_or_default()
+}
+
+/// Escribe las anotaciones en `path`. Como al guardar el PDF, primero en un temporal
+/// que luego se renombra encima: un corte a medias no deja el archivo roto.
+pub fn save(path: &Path, document: &str, store: &AnnotationStore) -> Result<()> {
+    if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+        std::fs::create_dir_all(dir).with_context(|| format!("No se pudo crear {:?}", dir))?;
+    }
+    let temp = path.with_extension("json.tmp");
+    let json = serde_json::to_vec_pretty(&to_json(document, store))?;
+    let mut file = std::fs::File::create(&temp).with_context(|| format!("No se pudo escribir {:?}", temp))?;
+    file.write_all(&json)?;
+    file.sync_all()?;
+    drop(file);
+    std::fs::rename(&temp, path).with_context(|| format!("No se pudo escribir {:?}", path))
+}
+
+/// Lee un archivo de anotaciones: (página en base 0, anotación) en orden de pintado.
+/// Si el archivo no existe, no hay anotaciones (no es un error).
+pub fn load(path: &Path) -> Result<Vec<(u16, Annotation)>> {
+    let content = match std::fs::read(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e).with_context(|| format!("No se pudo leer {:?}", path)),
+    };
+    let file: SidecarFile = serde_json::from_slice(&content).with_context(|| format!("{:?} no es un archivo de anotaciones válido", path))?;
+    if file.format != FORMAT || file.version > VERSION {
+        bail!("{:?}: formato {} v{} no soportado", path, file.format, file.version);
+    }
+
+    Ok(file.pages.into_iter()
+        .filter(|(page, _)| *page >= 1)
+        .flat_map(|(page, list)| list.into_iter().map(move |a| (page - 1, a)))
+        .collect())
+}
+
+/// Directorio de datos del usuario para WindP.
+/// Windows: %APPDATA%\WindP; macOS: ~/Library/Application Support/WindP; resto: $XDG_DATA_HOME/windp.
+pub fn data_dir() -> PathBuf {
+    let env = |name: &str| std::env::var_os(name).filter(|v| !v.is_empty()).map(PathBuf::from);
+    if cfg!(windows) {
+        if let Some(appdata) = env("APPDATA") {
+            return appdata.join("WindP");
+        }
+    } else if cfg!(target_os = "macos") {
+        if let Some(home) = env("HOME") {
+            return home.join("Library/Application Support/WindP");
+        }
+    } else if let Some(data) = env("XDG_DATA_HOME").or_else(|| env("HOME").map(|home| home.join(".local/share"))) {
+        return data.join("windp");
+    }
+    std::env::temp_dir().join("windp")
+}
+
+// FNV-1a de 64 bits: estable entre versiones de Rust (DefaultHasher no lo garantiza)
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, &b| (hash ^ b as u64).wrapping_mul(0x100000001b3))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pdf::test_path;
+    use crate::ui::overlay::{AnnotationKind, InkSample, PagePoint, Shape};
+
+    fn p(x: f32, y: f32) -> PagePoint {
+        PagePoint { x, y }
+    }
+
+    // Una anotación de cada clase (y de cada figura), repartidas en dos páginas
+    fn every_kind() -> Vec<(u16, Annotation)> {
+        let shape = |shape| AnnotationKind::Shape { shape, from: p(72.0, 600.0), to: p(200.5, 650.25), width: 2.0 };
+        let kinds = [
+            (0, AnnotationKind::Ink { samples: vec![InkSample { pos: p(72.0, 700.5), pressure: 0.25 }, InkSample { pos: p(80.0, 710.0), pressure: 1.0 }], width: 1.5 }),
+            (0, shape(Shape::Rectangle)),
+            (0, shape(Shape::Ellipse)),
+            (0, shape(Shape::Line)),
+            (0, shape(Shape::Arrow)),
+            (2, AnnotationKind::TextHighlight { rects: vec![[72.0, 500.0, 300.0, 512.0], [72.0, 486.0, 150.0, 498.0]], text: "texto \"resaltado\"\nen dos líneas".into() }),
+            (2, AnnotationKind::Note { at: p(40.0, 780.0), text: "Revisar ✓".into() }),
+            (2, AnnotationKind::FreeText { at: p(100.0, 300.0), text: "Aprobado".into(), size: 14.4 }),
+        ];
+        kinds.into_iter().enumerate().map(|(i, (page, kind))| {
+            let author = (i % 2 == 0).then(|| "Ana".to_string());
+            let modified = (i % 3 == 0).then(|| "D:20240105120000+01'00'".to_string());
+            let name = Some(format!("nombre-{}", i));
+            (page, Annotation { id: 0, kind, color: [20, 40 + i as u8, 60, 255 - i as u8], author, modified, name })
+        }).collect()
+    }
+
+    #[test]
+    fn every_kind_round_trips() {
+        let mut store = AnnotationStore::new();
+        for (page, annotation) in every_kind() {
+            store.insert(page, annotation);
+        }
+        let path = test_path("sidecar.windp.json");
+        save(&path, "/docs/informe.pdf", &store).unwrap();
+        let loaded = load(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+
+        // Mismo orden de pintado, mismos datos; los ids no se guardan
+        assert_eq!(loaded, every_kind());
+        let json = to_json("/docs/informe.pdf", &store);
+        assert_eq!(json["document"], "informe.pdf");
+        assert_eq!(json["pages"].as_object().unwrap().keys().collect::<Vec<_>>(), ["1", "3"]);
+    }
+
+    #[test]
+    fn documented_example_loads() {
+        let doc = include_str!("sidecar.rs");
+        let example: String = doc.lines()
+            .skip_while(|line| !line.starts_with("//! ```json"))
+            .skip(1)
+            .take_while(|line| !line.starts_with("//! ```"))
+            .map(|line| line.trim_start_matches("//!"))
+            .collect();
+        let path = test_path("example.windp.json");
+        std::fs::write(&path, example).unwrap();
+        let loaded = load(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(loaded.iter().map(|(page, _)| *page).collect::<Vec<_>>(), [0, 0, 2, 2, 2]);
+        assert_eq!(loaded[3].1.author.as_deref(), Some("Ana"));
+    }
+
+    #[test]
+    fn missing_files_are_empty_and_foreign_ones_are_rejected() {
+        assert!(load(&test_path("no-existe.windp.json")).unwrap().is_empty());
+        let cases = [
+            r#"{"format": "otra-cosa", "version": 1, "pages": {}}"#,
+            r#"{"format": "windp-annotations", "version": 99, "pages": {}}"#,
+            r#"{"format": "windp-annotations", "version": 1, "pages": {"1": [{"type": "desconocida"}]}}"#,
+            "no es json",
+        ];
+        let path = test_path("foreign.windp.json");
+        for content in cases {
+            std::fs::write(&path, content).unwrap();
+            assert!(load(&path).is_err(), "{}", content);
+        }
+        // La página 0 no existe (son base 1): se ignora
+        std::fs::write(&path, r#"{"format": "windp-annotations", "version": 1, "pages": {"0": [{"type": "note", "at": {"x": 1, "y": 2}, "text": "", "color": [0, 0, 0, 255]}]}}"#).unwrap();
+        assert!(load(&path).unwrap().is_empty());
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -1,13 +1,24 @@
 use winit::{
-    event::{ElementState, KeyEvent, WindowEvent, MouseScrollDelta, MouseButton},
-    keyboard::{KeyCode, PhysicalKey},
+    event::{ElementState, Ime, KeyEvent, WindowEvent, MouseScrollDelta, MouseButton, Touch, TouchPhase},
+    keyboard::{Key, KeyCode, ModifiersState, NamedKey, PhysicalKey},
     window::Window,
 };
 use wgpu::util::DeviceExt;
-use crate::gpu::{GpuContext, texture::Texture};
-use crate::pdf::{PdfSystem, render::render_page_to_memory};
-use crate::ui::{UiState, Tool}; 
+use crate::gpu::{GpuContext, strokes::StrokeLayer, texture::Texture};
+use crate::pdf::{same_file, PdfSystem, annotations::hide_annotations, images::{collect_images, is_image, Fit}, impose::Layout, organize::{split_target, PagePlan, PageSource}, redact::RedactionMark, render::{render_page_to_memory, PageBitmap}, search::{search_document, SearchHit}, text::{self as page_text, PageChar}};
+use crate::ui::{palette_slots, UiState, Tool}; 
+use crate::ui::carousel::{self, Carousel, CarouselPage};
+use crate::ui::hud::{self, Hud, HANDLE_SIZE};
+use crate::ui::summary::{self, SummaryEntry};
+use crate::ui::overlay::{Annotation, AnnotationKind, AnnotationStore, InkSample, PagePoint, PixelRect, RasterTarget, Shape, NOTE_ICON_SIZE, hit_test, inside_polygon, local_author, pdf_date_now, multiply_layer, rasterize_annotation, rasterize_annotation_in, rasterize_layers};
+use crate::cli::{parse_image_page, parse_page_ranges, parse_paper_size, InitialView, ZoomMode};
+use crate::sidecar::{self, AnnotationStorage};
+use crate::xfdf;
+use anyhow::{anyhow, bail, Context};
 use pdfium_render::prelude::*;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use std::path::{Path, PathBuf};
 
 #[repr(C)]
 #[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
@@ -22,17 +33,122 @@ struct CameraUniform {
     scale: [f32; 2],
     translation: [f32; 2],
     ui_flags: [f32; 2], 
+    screen: [f32; 2],
+    tool: [f32; 4], // Casilla de herramienta, casilla de color, paleta visible, grosor en px
+    tool_color: [f32; 4],
+    palette: [f32; 4], // Casillas de herramientas, de colores y en total (el shader no conoce PALETTE)
 }
 
 const VERTICES: &[Vertex] = &[
     Vertex { position: [-1.0, 1.0, 0.0], tex_coords: [0.0, 0.0] },
     Vertex { position: [-1.0, -1.0, 0.0], tex_coords: [0.0, 1.0] },
-    Vertex { position: 1.0, -1.0, 0.0], tex_coords: [1.0, 1.0] },
-    Vertex { position: 1.0, 1.0, 0.0], tex_coords: [1.0, 0.0] },
+    Vertex { position: [1.0, -1.0, 0.0], tex_coords: [1.0, 1.0] },
+    Vertex { position: [1.0, 1.0, 0.0], tex_coords: [1.0, 0.0] },
 ];
 
 const INDICES: &[u16] = &[0, 1, 2, 2, 3, 0];
 
+// Escala de rasterizado de las páginas (1.0 = 72 DPI)
+const RENDER_SCALE: f32 = 1.5;
+// Píxeles por punto PDF al 100% de zoom (pantalla a 96 DPI)
+const PIXELS_PER_POINT: f32 = 96.0 / 72.0;
+// Distancia máxima (en puntos PDF) a la que un click "engancha" el texto al resaltar
+const TEXT_SNAP_DISTANCE: f32 = 12.0;
+// Lado máximo del overlay raster: por encima, el zoom ya no gana nitidez (y la memoria se dispara)
+const MAX_OVERLAY_SIDE: f32 = 4096.0;
+// Desplazamiento (en puntos PDF) de lo pegado sobre la misma página que el original, para que se vea
+const PASTE_OFFSET: f32 = 12.0;
+// Lado mínimo (en puntos PDF) al encoger una selección con las asas
+const MIN_SELECTION_SIDE: f32 = 4.0;
+// Cuánto se ve en el HUD un aviso (un error al guardar, al leer las anotaciones...)
+const NOTICE_DURATION: Duration = Duration::from_secs(6);
+// Resolución de las anotaciones que se pintan como imagen al exportarlas aplanadas (a PDF o PNG)
+const FLATTEN_DPI: f32 = 200.0;
+
+/// Texto que se está escribiendo (nota o texto libre) en la página actual.
+struct TextEdit {
+    id: u64,
+    before: Option<Annotation>, // Cómo estaba antes de editar; None si se acaba de crear
+}
+
+/// Qué hace el campo de ruta al pulsar Enter.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum PromptAction {
+    SaveAs,
+    ExportXfdf,
+    ImportXfdf,
+    ExportMarkdown,
+    ExportFlattened,
+    RedactSearch,
+    ApplyRedactions,
+    ImportPages,
+    SaveOrganized,
+    MergeWith,
+    SaveMerged,
+    Split,
+    Impose,
+    CombineImages,
+    SaveImages,
+}
+
+impl PromptAction {
+    fn title(self) -> &'static str {
+        match self {
+            PromptAction::SaveAs => "Guardar como - Enter para guardar, Esc para cancelar",
+            PromptAction::ExportXfdf => "Exportar anotaciones a XFDF - Enter para exportar, Esc para cancelar",
+            PromptAction::ImportXfdf => "Importar anotaciones de XFDF - Enter para importar, Esc para cancelar",
+            PromptAction::ExportMarkdown => "Exportar resumen a Markdown - Enter para exportar, Esc para cancelar",
+            PromptAction::ExportFlattened => "Exportar aplanado (.pdf, o .png#1-3 para imágenes) - Enter para exportar, Esc para cancelar",
+            PromptAction::RedactSearch => "Marcar para redactar todas las apariciones de - Enter para marcar, Esc para cancelar",
+            PromptAction::ApplyRedactions => "Guardar la copia redactada - Enter para aplicar, Esc para cancelar",
+            PromptAction::ImportPages => "Importar páginas de otro PDF (.pdf, o .pdf#1-3) - Enter para importar, Esc para cancelar",
+            PromptAction::SaveOrganized => "Guardar las páginas organizadas como - Enter para guardar, Esc para cancelar",
+            PromptAction::MergeWith => "Unir detrás del documento los PDF (separados por ;) - Enter para seguir, Esc para cancelar",
+            PromptAction::SaveMerged => "Guardar el documento unido como - Enter para guardar, Esc para cancelar",
+            PromptAction::Split => "Dividir en un PDF por rango (nombre.pdf#1-3,4-) - Enter para dividir, Esc para cancelar",
+            PromptAction::Impose => "Exportar para imprimir (.pdf#2up, #4up o #booklet; papel opcional: #booklet:a4) - Enter para exportar, Esc para cancelar",
+            PromptAction::CombineImages => "Combinar en un PDF las imágenes o carpetas (separadas por ;) - Enter para seguir, Esc para cancelar",
+            PromptAction::SaveImages => "Guardar las imágenes como (.pdf#papel:ajuste; papel auto, a4...; ajuste fit o fill) - Enter para guardar, Esc para cancelar",
+        }
+    }
+}
+
+/// Arrastre en curso con la herramienta de selección.
+enum SelectionDrag {
+    /// Mover (`handle` None) o redimensionar desde un asa: las anotaciones como estaban al pulsar,
+    /// la caja que las envolvía y dónde se pulsó.
+    Transform { before: Vec<Annotation>, bounds: [f32; 4], handle: Option<[i8; 2]>, start: PagePoint },
+    /// Marco rectangular para elegir varias.
+    Marquee { from: PagePoint, to: PagePoint },
+    /// Lazo a mano alzada (con Alt) para elegir varias.
+    Lasso(Vec<PagePoint>),
+}
+
+/// Anotaciones copiadas con Ctrl+C (o Ctrl+X) para pegarlas en esta u otra página.
+#[derive(Default)]
+struct Clipboard {
+    annotations: Vec<Annotation>,
+    occupied: Option<u16>, // Página donde siguen las originales: al pegar ahí, la copia se desplaza
+}
+
+/// Panel lateral con el resumen de las anotaciones (Ctrl+L).
+struct SummaryPanel {
+    entries: Vec<SummaryEntry>,
+    revision: u64, // Revisión del modelo con la que se hizo la lista
+    scroll: i32,
+    max_scroll: i32,
+    hits: Vec<(i32, i32, usize)>, // Zona vertical de cada entrada visible (píxeles de pantalla)
+}
+
+/// Foto del estado visible, para quien observa la app desde fuera (control remoto).
+#[derive(Clone, Debug, PartialEq, serde::Serialize)]
+pub struct ViewSnapshot {
+    pub file: Option<String>,
+    pub page: u16, // Base 1, como la ve el usuario
+    pub total_pages: u16,
+    pub zoom: f32,
+}
+
 pub struct State<'a> {
     gpu: GpuContext,
     render_pipeline: wgpu::RenderPipeline,
@@ -40,6 +156,7 @@ pub struct State<'a> {
     index_buffer: wgpu::Buffer,
     
     // BindGroups
+    texture_bg_layout: wgpu::BindGroupLayout,
     diffuse_bind_group: wgpu::BindGroup,
     camera_bind_group: wgpu::BindGroup,
     
@@ -47,9 +164,25 @@ pub struct State<'a> {
     diffuse_texture: Texture,
     overlay_texture: Texture,
     overlay_buffer: Vec<u8>, // Copia en CPU para pintar rápido
+    overlay_width: u32,
+    overlay_height: u32,
+    overlay_scale: f32, // Píxeles por punto PDF del overlay (sigue al zoom, no al bitmap)
+    overlay_dirty: PixelRect, // Zona del buffer cambiada desde el último frame: se sube en update()
+    strokes: StrokeLayer, // Trazos, figuras y resaltados: geometría en GPU, no píxeles del overlay
     page_width: u32,
     page_height: u32,
     
+    // HUD: popups y avisos en espacio de pantalla, encima de todo
+    hud: Hud,
+    hud_texture: Texture,
+    hud_bg_layout: wgpu::BindGroupLayout,
+    hud_bind_group: wgpu::BindGroup,
+    hud_pipeline: wgpu::RenderPipeline,
+    hud_visible: bool,
+    hud_dirty: bool,
+    hud_view: [f32; 4], // Cámara con la que se pintó (los popups siguen a su nota)
+    notice: Option<(String, Instant)>, // Aviso para el usuario y desde cuándo se ve
+
     // Estado Cámara
     camera_buffer: wgpu::Buffer,
     camera_uniform: CameraUniform,
@@ -58,28 +191,66 @@ pub struct State<'a> {
     
     // Lógica App
     ui: UiState,
+    pdf_system: &'a PdfSystem,
     document: Option<PdfDocument<'a>>,
+    document_path: Option<String>,
+    image_source: Option<String>, // Imagen abierta: document_path es entonces su conversión a PDF (en el temporal)
+    document_password: Option<&'a str>,
+    annotations: AnnotationStore,
+    annotation_storage: AnnotationStorage,
+    sidecar_path: Option<PathBuf>, // Archivo de anotaciones aparte (None: van dentro del PDF)
+    saved_revision: u64, // Revisión del modelo que ya está en el archivo aparte
+    active_stroke: Option<u64>, // Trazo, figura o resaltado que se está dibujando ahora mismo
+    page_chars: Option<Vec<PageChar>>, // Capa de texto de la página actual (se lee al primer uso)
+    text_anchor: Option<usize>, // Carácter donde empezó el resaltado de texto en curso
+    editing: Option<TextEdit>,
+    hovered_note: Option<u64>, // Nota bajo el cursor: su texto se ve en un popup de solo lectura
+    selection: Vec<u64>, // Anotaciones de la página actual elegidas con la herramienta de selección
+    selection_drag: Option<SelectionDrag>,
+    clipboard: Clipboard,
+    redactions: Vec<RedactionMark>, // Zonas marcadas para redactar: nada se borra hasta aplicarlas
+    redaction_drag: Option<(PagePoint, PagePoint)>,
+    summary: Option<SummaryPanel>,
+    summary_text: HashMap<u16, Vec<PageChar>>, // Capa de texto de las páginas citadas en el resumen (no cambia al anotar)
+    carousel: Option<Carousel>, // Organizador de páginas, mientras el carrusel está abierto
+    prompt: Option<(PromptAction, String)>, // Ruta que se está escribiendo ("Guardar como", XFDF...)
+    merge_inputs: Vec<String>, // PDF o imágenes elegidos para combinar, mientras se pide dónde guardar el resultado
     current_page: u16,
     total_pages: u16,
     
     // Input State
     mouse_pressed: bool,
     last_mouse_pos: [f64; 2], // Para calcular el delta del drag
+    pen_pressure: f32, // Presión del último evento táctil (1.0 con ratón)
+    modifiers: ModifiersState,
+    ime_active: bool, // Con IME el texto llega por Ime::Commit, no por KeyEvent.text
     
     num_indices: u32,
 }
 
 impl<'a> State<'a> {
-    pub async fn new(window: &Window, pdf_system: &'a PdfSystem, file_path: Option<String>) -> Self {
+    pub async fn new(window: &Window, pdf_system: &'a PdfSystem, file_path: Option<String>, password: Option<&'a str>, annotation_storage: AnnotationStorage) -> Self {
         let gpu = GpuContext::new(window).await;
         let ui = UiState::new(&gpu.device, &gpu.queue);
 
         // 1. Cargar PDF Inicial
-        let (document, page_bitmap, total) = if let Some(path) = file_path {
-            match pdf_system.open_file(&path) {
+        // Una imagen se abre como un PDF de una página convertido en el directorio temporal
+        let mut startup_error = None;
+        let (file_path, image_source) = match file_path {
+            Some(path) if is_image(Path::new(&path)) => match pdf_system.image_as_pdf(Path::new(&path)) {
+                Ok(converted) => (Some(converted), Some(path)),
+                Err(e) => {
+                    startup_error = Some(format!("No se pudo abrir la imagen {}: {:#}", path, e));
+                    (None, None)
+                },
+            },
+            file_path => (file_path, None),
+        };
+        let (document, page_bitmap, total) = if let Some(path) = &file_path {
+            match pdf_system.open_file_with_password(path, password) {
                 Ok(doc) => {
                     let total = doc.pages().len();
-                    let bitmap = render_page_to_memory(&doc, 0, 1.5).unwrap_or_else(|_| create_fallback());
+                    let bitmap = render_page_to_memory(&doc, 0, RENDER_SCALE).unwrap_or_else(|_| create_fallback());
                     (Some(doc), bitmap, total)
                 },
                 Err(_) => (None, create_fallback(), 0)
@@ -87,6 +258,7 @@ impl<'a> State<'a> {
         } else {
              (None, create_fallback(), 0)
         };
+        let document_path = document.as_ref().and(file_path);
 
         // 2. Crear Texturas
         let diffuse_texture = Texture::from_bytes(&gpu.device, &gpu.queue, &page_bitmap.data, page_bitmap.width, page_bitmap.height, Some("PDF")).unwrap();
@@ -96,12 +268,16 @@ impl<'a> State<'a> {
         let overlay_buffer = vec![0u8; overlay_size];
         let overlay_texture = Texture::from_bytes(&gpu.device, &gpu.queue, &overlay_buffer, page_bitmap.width, page_bitmap.height, Some("Overlay")).unwrap();
 
+        // Trazos vectoriales: su propia pasada, a resolución de ventana
+        let strokes = StrokeLayer::new(&gpu.device, gpu.size.width.max(1), gpu.size.height.max(1));
+
         // 3. Pipeline Config
         let texture_bg_layout = gpu.device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
             entries: &[
                 wgpu::BindGroupLayoutEntry { binding: 0, visibility: wgpu::ShaderStages::FRAGMENT, ty: wgpu::BindingType::Texture { multisampled: false, view_dimension: wgpu::TextureViewDimension::D2, sample_type: wgpu::TextureSampleType::Float { filterable: true } }, count: None },
                 wgpu::BindGroupLayoutEntry { binding: 1, visibility: wgpu::ShaderStages::FRAGMENT, ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering), count: None },
                 wgpu::BindGroupLayoutEntry { binding: 2, visibility: wgpu::ShaderStages::FRAGMENT, ty: wgpu::BindingType::Texture { multisampled: false, view_dimension: wgpu::TextureViewDimension::D2, sample_type: wgpu::TextureSampleType::Float { filterable: true } }, count: None },
+                wgpu::BindGroupLayoutEntry { binding: 3, visibility: wgpu::ShaderStages::FRAGMENT, ty: wgpu::BindingType::Texture { multisampled: false, view_dimension: wgpu::TextureViewDimension::D2, sample_type: wgpu::TextureSampleType::Float { filterable: true } }, count: None },
             ],
             label: Some("Texture BG Layout"),
         });
@@ -112,11 +288,16 @@ impl<'a> State<'a> {
                 wgpu::BindGroupEntry { binding: 0, resource: wgpu::BindingResource::TextureView(&diffuse_texture.view) },
                 wgpu::BindGroupEntry { binding: 1, resource: wgpu::BindingResource::Sampler(&diffuse_texture.sampler) },
                 wgpu::BindGroupEntry { binding: 2, resource: wgpu::BindingResource::TextureView(&overlay_texture.view) },
+                wgpu::BindGroupEntry { binding: 3, resource: wgpu::BindingResource::TextureView(&strokes.view) },
             ],
             label: Some("Diffuse BG"),
         });
 
-        let camera_uniform = CameraUniform { scale: [1.0, 1.0], translation: [0.0, 0.0], ui_flags: [0.0, 0.0] };
+        let camera_uniform = CameraUniform {
+            scale: [1.0, 1.0], translation: [0.0, 0.0], ui_flags: [0.0, 0.0],
+            screen: [1.0, 1.0], tool: [-1.0, -1.0, 0.0, 0.0], tool_color: [0.0; 4],
+            palette: palette_slots(),
+        };
         let camera_buffer = gpu.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some("Camera Buffer"),
             contents: bytemuck::cast_slice(&[camera_uniform]),
@@ -157,6 +338,35 @@ impl<'a> State<'a> {
             multiview: None,
         });
 
+        // 4. HUD: textura del tamaño de la ventana con su propio pipeline (triángulo a pantalla completa)
+        let hud = Hud::new(gpu.size.width.max(1), gpu.size.height.max(1));
+        let hud_texture = Texture::from_bytes(&gpu.device, &gpu.queue, &hud.data, hud.width, hud.height, Some("HUD")).unwrap();
+        let hud_bg_layout = gpu.device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry { binding: 0, visibility: wgpu::ShaderStages::FRAGMENT, ty: wgpu::BindingType::Texture { multisampled: false, view_dimension: wgpu::TextureViewDimension::D2, sample_type: wgpu::TextureSampleType::Float { filterable: true } }, count: None },
+                wgpu::BindGroupLayoutEntry { binding: 1, visibility: wgpu::ShaderStages::FRAGMENT, ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering), count: None },
+            ],
+            label: Some("HUD BG Layout"),
+        });
+        let hud_bind_group = create_hud_bind_group(&gpu.device, &hud_bg_layout, &hud_texture);
+
+        let hud_shader = gpu.device.create_shader_module(wgpu::include_wgsl!("../assets/shaders/hud.wgsl"));
+        let hud_pipeline_layout = gpu.device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("HUD Pipeline Layout"),
+            bind_group_layouts: &[&hud_bg_layout],
+            push_constant_ranges: &[],
+        });
+        let hud_pipeline = gpu.device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("HUD Pipeline"),
+            layout: Some(&hud_pipeline_layout),
+            vertex: wgpu::VertexState { module: &hud_shader, entry_point: "vs_hud", buffers: &[] },
+            fragment: Some(wgpu::FragmentState { module: &hud_shader, entry_point: "fs_hud", targets: &[Some(wgpu::ColorTargetState { format: gpu.config.format, blend: Some(wgpu::BlendState::ALPHA_BLENDING), write_mask: wgpu::ColorWrites::ALL })] }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
         let vertex_buffer = gpu.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some("Vertex Buffer"),
             contents: bytemuck::cast_slice(VERTICES),
@@ -169,23 +379,46 @@ impl<'a> State<'a> {
             usage: wgpu::BufferUsages::INDEX,
         });
 
-        Self {
+        let mut state = Self {
             gpu, render_pipeline, vertex_buffer, index_buffer,
-            diffuse_bind_group, camera_bind_group, camera_buffer, camera_uniform,
+            texture_bg_layout, diffuse_bind_group, camera_bind_group, camera_buffer, camera_uniform,
             diffuse_texture, overlay_texture, overlay_buffer,
+            overlay_width: page_bitmap.width, overlay_height: page_bitmap.height, overlay_scale: RENDER_SCALE,
+            overlay_dirty: PixelRect { x0: 0, y0: 0, x1: 0, y1: 0 }, strokes,
             page_width: page_bitmap.width, page_height: page_bitmap.height,
+            hud, hud_texture, hud_bg_layout, hud_bind_group, hud_pipeline,
+            hud_visible: false, hud_dirty: false, hud_view: [0.0; 4],
+            notice: startup_error.inspect(|message| log::error!("{}", message)).map(|message| (message, Instant::now())),
             zoom: 1.0, pan: [0.0, 0.0],
-            ui, document, current_page: 0, total_pages: total,
-            mouse_pressed: false, last_mouse_pos: [0.0, 0.0],
+            ui, pdf_system, document, document_path, image_source, document_password: password,
+            annotations: AnnotationStore::new(), annotation_storage, sidecar_path: None, saved_revision: 0, active_stroke: None, page_chars: None, text_anchor: None, editing: None, hovered_note: None, selection: Vec::new(), selection_drag: None, clipboard: Clipboard::default(), redactions: Vec::new(), redaction_drag: None, summary: None, summary_text: HashMap::new(), carousel: None, prompt: None, merge_inputs: Vec::new(),
+            current_page: 0, total_pages: total,
+            mouse_pressed: false, last_mouse_pos: [0.0, 0.0], pen_pressure: 1.0,
+            modifiers: ModifiersState::empty(), ime_active: false,
             num_indices: INDICES.len() as u32,
+        };
+        state.load_annotations();
+        if !state.annotations.is_empty() {
+            state.go_to_page(0); // Sin las anotaciones que ahora pinta el overlay
         }
+        state
     }
 
     // --- LÓGICA CORE ---
 
     fn load_page(&mut self, page_idx: u16) {
+        self.end_stroke(); // Un trazo a medias se queda en la página donde empezó
+        self.finish_text_edit();
+        if page_idx != self.current_page {
+            self.select(Vec::new());
+        }
         if let Some(doc) = &self.document {
-            if let Ok(bitmap) = render_page_to_memory(doc, page_idx, 1.5) {
+            if let Ok(bitmap) = render_page_to_memory(doc, page_idx, RENDER_SCALE) {
+                // 0. Si la página no mide lo mismo que la anterior, las texturas no sirven
+                if bitmap.width != self.page_width || bitmap.height != self.page_height {
+                    self.recreate_page_textures(bitmap.width, bitmap.height);
+                }
+
                 // 1. Actualizar Textura del PDF
                 self.gpu.queue.write_texture(
                     wgpu::ImageCopyTexture { texture: &self.diffuse_texture.texture, mip_level: 0, origin: wgpu::Origin3d::ZERO, aspect: wgpu::TextureAspect::All },
@@ -194,188 +427,2256 @@ impl<'a> State<'a> {
                     wgpu::Extent3d { width: bitmap.width, height: bitmap.height, depth_or_array_layers: 1 }
                 );
                 
-                // 2. Limpiar Overlay (Subrayados)
-                self.overlay_buffer.fill(0);
-                self.gpu.queue.write_texture(
-                    wgpu::ImageCopyTexture { texture: &self.overlay_texture.texture, mip_level: 0, origin: wgpu::Origin3d::ZERO, aspect: wgpu::TextureAspect::All },
-                    &self.overlay_buffer,
-                    wgpu::ImageDataLayout { offset: 0, bytes_per_row: Some(4 * bitmap.width), rows_per_image: Some(bitmap.height) },
-                    wgpu::Extent3d { width: bitmap.width, height: bitmap.height, depth_or_array_layers: 1 }
-                );
-
                 self.current_page = page_idx;
+                self.page_chars = None;
+                self.hud_dirty = true; // Las marcas de redacción son de cada página
                 self.page_width = bitmap.width;
                 self.page_height = bitmap.height;
-                println!("Página cargada: {}", page_idx + 1);
+
+                // 2. Overlay: las anotaciones de esta página, a la resolución del zoom actual
+                self.rebuild_overlay();
+                log::debug!("Página cargada: {}", page_idx + 1);
             }
         }
     }
 
-    fn paint_overlay(&mut self, ndc_x: f64, ndc_y: f64) {
-        // Transformar NDC (-1 a 1) a Espacio Textura (0 a Width)
-        // Invertimos la transformación de cámara: (ndc - translation) / scale
-        let aspect = self.gpu.size.width as f32 / self.gpu.size.height as f32;
-        
-        let x_cam = (ndc_x as f32 - self.pan[0]) / self.zoom;
-        let y_cam = (ndc_y as f32 - self.pan[1]) / (self.zoom * aspect); // Corregir por aspect ratio vertical si se aplica en shader? 
-        // Nota: En shader usamos scale.y = zoom * aspect. Revisar shader.wgsl vs update()
-        // En update: scale.y = zoom * aspect. Entonces Y_cam = (y - pan.y) / scale.y.
-        
-        // Coordenadas UV (0 a 1)
-        // El quad es de -1 a 1. UV 0,0 es TopLeft.
-        let u = (x_cam + 1.0) * 0.5;
-        let v = (1.0 - y_cam) * 0.5;
+    /// Salta a una página (base 0), limitada al rango del documento.
+    pub fn go_to_page(&mut self, page_idx: u16) {
+        if self.total_pages > 0 {
+            self.load_page(page_idx.min(self.total_pages - 1));
+        }
+    }
 
-        if u >= 0.0 && u <= 1.0 && v >= 0.0 && v <= 1.0 {
-            let tx = (u * self.page_width as f32) as i32;
-            let ty = (v * self.page_height as f32) as i32;
-            let radius = 5; // Radio del pincel
-            
-            let mut modified = false;
-
-            // Dibujar círculo simple en el buffer CPU
-            for dy in -radius..=radius {
-                for dx in -radius..=radius {
-                    if dx*dx + dy*dy <= radius*radius {
-                        let px = tx + dx;
-                        let py = ty + dy;
-                        if px >= 0 && px < self.page_width as i32 && py >= 0 && py < self.page_height as i32 {
-                            let idx = ((py as u32 * self.page_width + px as u32) * 4) as usize;
-                            // Amarillo fluorescente (RGBA)
-                            self.overlay_buffer[idx] = 255;   // R
-                            self.overlay_buffer[idx+1] = 255; // G
-                            self.overlay_buffer[idx+2] = 0;   // B
-                            self.overlay_buffer[idx+3] = 100; // Alpha (Semi-transparente)
-                            modified = true;
-                        }
-                    }
-                }
+    /// Fija el zoom (1.0 = página a lo ancho de la ventana), con los mismos límites que la rueda.
+    pub fn set_zoom(&mut self, zoom: f32) {
+        self.zoom = zoom.clamp(0.1, 10.0);
+    }
+
+    /// Busca un término en el documento y salta a la página de la primera coincidencia.
+    pub fn search(&mut self, term: &str) -> Result<Vec<SearchHit>, PdfiumError> {
+        let Some(doc) = &self.document else {
+            return Ok(Vec::new());
+        };
+        let hits = search_document(doc, term)?;
+        if let Some(first) = hits.first() {
+            if first.page != self.current_page {
+                self.load_page(first.page);
             }
+        }
+        Ok(hits)
+    }
 
-            if modified {
-                // Subir TODO el buffer a la GPU (Optimización futura: subir solo región sucia)
-                self.gpu.queue.write_texture(
-                    wgpu::ImageCopyTexture { texture: &self.overlay_texture.texture, mip_level: 0, origin: wgpu::Origin3d::ZERO, aspect: wgpu::TextureAspect::All },
-                    &self.overlay_buffer,
-                    wgpu::ImageDataLayout { offset: 0, bytes_per_row: Some(4 * self.page_width), rows_per_image: Some(self.page_height) },
-                    wgpu::Extent3d { width: self.page_width, height: self.page_height, depth_or_array_layers: 1 }
-                );
+    /// Aplica un modo de zoom de la línea de comandos a la cámara actual.
+    pub fn set_zoom_mode(&mut self, mode: ZoomMode) {
+        // Con zoom 1.0 el quad de la página ocupa todo el ancho de la ventana,
+        // y (ver update) su alto en píxeles es igual a ese ancho.
+        let win_w = self.gpu.size.width.max(1) as f32;
+        let win_h = self.gpu.size.height.max(1) as f32;
+        match mode {
+            ZoomMode::FitWidth => self.set_zoom(1.0),
+            ZoomMode::FitPage => self.set_zoom((win_h / win_w).min(1.0)),
+            ZoomMode::Percent(pct) => {
+                let page_width_pt = self.page_width as f32 / RENDER_SCALE;
+                self.set_zoom(page_width_pt * PIXELS_PER_POINT * pct / 100.0 / win_w);
+            },
+        }
+        self.pan = [0.0, 0.0];
+    }
+
+    /// Lleva el documento abierto a la vista inicial pedida (página, destino, zoom, búsqueda).
+    /// Si se piden página y destino a la vez, gana el destino, igual que en Acrobat.
+    pub fn apply_initial_view(&mut self, view: &InitialView) {
+        if let Some(page) = view.page {
+            self.go_to_page(page);
+        }
+        if let (Some(name), Some(path)) = (&view.named_dest, &self.document_path) {
+            match self.pdf_system.resolve_named_destination(path, self.document_password, name) {
+                Some(page) => self.go_to_page(page),
+                None => self.notify(format!("Destino con nombre no encontrado: {}", name)),
+            }
+        }
+        if let Some(mode) = view.zoom {
+            self.set_zoom_mode(mode);
+        }
+        if let Some(term) = &view.search {
+            match self.search(term) {
+                Ok(hits) => log::info!("Búsqueda '{}': {} coincidencias", term, hits.len()),
+                Err(e) => self.notify(format!("Error en la búsqueda: {:?}", e)),
             }
         }
     }
 
-    pub fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>) {
-        self.gpu.resize(new_size);
+    /// Modo presentación: sin barra ni carrusel, la página entera en pantalla.
+    pub fn set_presentation(&mut self, enabled: bool) {
+        self.ui.presentation = enabled;
+        if enabled {
+            self.ui.is_carousel_open = false;
+            self.set_zoom_mode(ZoomMode::FitPage);
+        }
     }
 
-    pub fn input(&mut self, event: &WindowEvent) -> bool {
-        match event {
-            WindowEvent::MouseInput { state, button: MouseButton::Left, .. } => {
-                let pressed = *state == ElementState::Pressed;
-                self.mouse_pressed = pressed;
-                
-                if pressed {
-                    // 1. Chequear UI
-                    if self.ui.hit_test(self.last_mouse_pos[0], self.last_mouse_pos[1], self.gpu.size.width as f64, self.gpu.size.height as f64) {
-                        return true; 
-                    }
-                }
-                true
+    // Ruta del documento tal como la abrió el usuario: la imagen, si es una imagen convertida
+    fn source_path(&self) -> Option<&String> {
+        self.image_source.as_ref().or(self.document_path.as_ref())
+    }
+
+    pub fn snapshot(&self) -> ViewSnapshot {
+        ViewSnapshot {
+            file: self.source_path().cloned(),
+            page: if self.total_pages > 0 { self.current_page + 1 } else { 0 },
+            total_pages: self.total_pages,
+            zoom: self.zoom,
+        }
+    }
+
+    /// Sustituye el documento actual por otro (p.ej. pedido por otra instancia).
+    /// Si el archivo no se puede abrir, se conserva el documento anterior.
+    /// Una imagen (PNG, JPEG o TIFF) se abre como un documento de una página.
+    pub fn open_document(&mut self, path: &str, page: Option<u16>, password: Option<&'a str>) -> anyhow::Result<()> {
+        let image = is_image(Path::new(path));
+        let pdf_path = if image { self.pdf_system.image_as_pdf(Path::new(path))? } else { path.to_string() };
+        let doc = self.pdf_system.open_file_with_password(&pdf_path, password).map_err(|e| anyhow!("{:?}", e))?;
+        self.finish_text_edit(); // Lo que se estaba escribiendo es del documento anterior
+        self.autosave();
+        self.total_pages = doc.pages().len();
+        self.document = Some(doc);
+        self.document_path = Some(pdf_path);
+        self.image_source = image.then(|| path.to_string());
+        self.document_password = password;
+        self.editing = None;
+        self.load_annotations();
+        self.zoom = 1.0;
+        self.pan = [0.0, 0.0];
+
+        self.go_to_page(page.unwrap_or(0));
+        log::info!("Documento abierto: {}", path);
+        Ok(())
+    }
+
+    /// Guarda las anotaciones dentro del propio PDF (Ctrl+S), o en su archivo aparte si
+    /// se eligió guardarlas así (ese se guarda solo tras cada cambio: aquí solo se fuerza).
+    pub fn save(&mut self) -> anyhow::Result<usize> {
+        let path = self.document_path.clone().ok_or_else(|| anyhow!("No hay ningún documento abierto"))?;
+        if self.annotation_storage == AnnotationStorage::Pdf {
+            if self.image_source.is_some() {
+                bail!("El documento es una imagen: guárdalo como PDF con Ctrl+Shift+S");
+            }
+            return self.save_as(&path);
+        }
+        let path = self.source_path().cloned().unwrap_or(path);
+
+        self.end_stroke();
+        self.finish_text_edit();
+        let Some(sidecar_path) = self.sidecar_path.clone() else {
+            bail!("El archivo de anotaciones no se pudo leer al abrir: no se sobrescribe");
+        };
+        sidecar::save(&sidecar_path, &path, &self.annotations)?;
+        self.saved_revision = self.annotations.revision();
+        let count = self.annotations.pages().map(|(_, list)| list.len()).sum();
+        log::info!("Anotaciones guardadas en {:?} ({})", sidecar_path, count);
+        Ok(count)
+    }
+
+    /// Escribe el documento con las anotaciones como objetos PDF en `path` y pasa a trabajar
+    /// sobre ese archivo. Se guarda primero en un temporal junto al destino y se renombra
+    /// encima: si algo falla a medias, el archivo anterior sigue intacto.
+    pub fn save_as(&mut self, path: &str) -> anyhow::Result<usize> {
+        self.end_stroke();
+        self.finish_text_edit();
+        let Some(source) = self.document_path.clone() else { bail!("No hay ningún documento abierto") };
+        // Una imagen se guarda como PDF nuevo: escribirlo encima de ella la perdería
+        if self.image_source.as_ref().is_some_and(|image| same_file(Path::new(path), Path::new(image))) {
+            bail!("El PDF no puede sustituir a la imagen original: elige otra ruta");
+        }
+
+        // Con las anotaciones aparte, el PDF original no se toca: "Guardar como" escribe una copia
+        // con ellas incrustadas (junto a las que ya traía) y seguimos trabajando sobre el original
+        if self.annotation_storage != AnnotationStorage::Pdf {
+            if same_file(Path::new(path), Path::new(&source)) {
+                bail!("Las anotaciones de este documento se guardan aparte: elige otra ruta para la copia");
+            }
+            let written = self.pdf_system.save_with_annotations(&source, self.document_password, Path::new(path), &self.annotations)?.written;
+            log::info!("Copia guardada: {} ({} anotaciones)", path, written);
+            return Ok(written);
+        }
+        let target = Path::new(path);
+        let file_name = target.file_name().ok_or_else(|| anyhow!("Ruta inválida: {}", path))?;
+        let temp = target.with_file_name(format!(".{}.windp-tmp", file_name.to_string_lossy()));
+        let written = match self.pdf_system.save_with_annotations(&source, self.document_password, &temp, &self.annotations) {
+            Ok(written) => written,
+            Err(e) => {
+                let _ = std::fs::remove_file(&temp);
+                return Err(e);
             },
-            WindowEvent::CursorMoved { position, .. } => {
-                // Normalizado -1 a 1
-                let x = (position.x / self.gpu.size.width as f64) * 2.0 - 1.0;
-                let y = -((position.y / self.gpu.size.height as f64) * 2.0 - 1.0);
-                
-                let dx = x - self.last_mouse_pos[0];
-                let dy = y - self.last_mouse_pos[1];
-                self.last_mouse_pos = [x, y];
+        };
 
-                if self.mouse_pressed {
-                    match self.ui.active_tool {
-                        Tool::Pan => {
-                            // Arrastrar documento
-                            self.pan[0] += dx as f32;
-                            self.pan[1] += dy as f32;
-                        },
-                        Tool::Highlighter => {
-                            // Pintar
-                            self.paint_overlay(x, y);
-                        },
-                        _ => {}
+        // PDFium mantiene abierto el original (y en Windows no se puede sustituir un archivo
+        // abierto): lo cerramos antes de renombrar y abrimos el resultado
+        self.document = None;
+        if let Err(e) = std::fs::rename(&temp, target) {
+            let _ = std::fs::remove_file(&temp);
+            self.reopen(&source)?;
+            return Err(e).with_context(|| format!("No se pudo escribir {}", path));
+        }
+        self.image_source = None; // Ya es un PDF de verdad
+        self.annotations.mark_saved(&written.placed);
+        self.reopen(path)?;
+        log::info!("Guardado: {} ({} anotaciones escritas)", path, written.written);
+        Ok(written.written)
+    }
+
+    // Vuelve a abrir el documento tras guardarlo, conservando página, cámara y el modelo con su
+    // historial: no se relee nada, solo se ocultan otra vez los /Annot que están en el modelo
+    fn reopen(&mut self, path: &str) -> anyhow::Result<()> {
+        let doc = self.pdf_system.open_file_with_password(path, self.document_password)
+            .map_err(|e| anyhow!("No se pudo abrir {}: {:?}", path, e))?;
+        let store = &self.annotations;
+        let hidden: Vec<(u16, usize)> = store.origin_pages().into_iter()
+            .flat_map(|page| store.origins_on(page).into_keys().map(move |index| (page, index)))
+            .collect();
+        hide_annotations(&doc, &hidden);
+        self.total_pages = doc.pages().len();
+        self.document = Some(doc);
+        self.document_path = Some(path.to_string());
+        self.go_to_page(self.current_page);
+        Ok(())
+    }
+
+    // Pasa al modelo las anotaciones que ya trae el PDF y las oculta en el render de PDFium:
+    // a partir de aquí las pinta el overlay y se pueden mover, recolorear o borrar.
+    // Con archivo aparte se leen de él, y las del PDF se quedan como están (las pinta PDFium).
+    // La página visible hay que volver a cargarla después.
+    fn load_annotations(&mut self) {
+        self.annotations = AnnotationStore::new();
+        self.selection.clear();
+        self.clipboard = Clipboard::default();
+        self.redactions.clear();
+        self.carousel = None; // Las páginas eran de otro documento: se rehace en el próximo update
+        self.saved_revision = 0;
+        self.sidecar_path = None;
+        if let Some(panel) = &mut self.summary {
+            panel.revision = u64::MAX; // La lista era de otro documento: se rehace en el próximo update
+            panel.scroll = 0;
+        }
+        self.summary_text.clear();
+        let (Some(doc), Some(path)) = (&self.document, &self.document_path) else { return };
+
+        // Las anotaciones aparte de una imagen van con la imagen, no con su conversión temporal
+        if let Some(sidecar_path) = self.annotation_storage.path_for(self.image_source.as_ref().unwrap_or(path)) {
+            match sidecar::load(&sidecar_path) {
+                Ok(loaded) => {
+                    for (page, annotation) in loaded {
+                        self.annotations.import(page, annotation);
                     }
-                }
-                true
-            },
-            WindowEvent::MouseWheel { delta, .. } => {
-                let scroll = match delta { MouseScrollDelta::LineDelta(_, y) => *y * 0.1, MouseScrollDelta::PixelDelta(pos) => pos.y as f32 * 0.001 };
-                self.zoom = (self.zoom + scroll).clamp(0.1, 10.0);
-                true
+                    self.sidecar_path = Some(sidecar_path);
+                },
+                // Sin sidecar_path no se guarda nada: mejor no machacar un archivo que no entendemos
+                Err(e) => self.notify(format!("No se pudieron leer las anotaciones (no se guardarán los cambios): {:#}", e)),
+            }
+            return;
+        }
+
+        let imported = match self.pdf_system.read_annotations(path, self.document_password) {
+            Ok(imported) => imported,
+            Err(e) => {
+                self.notify(format!("No se pudieron leer las anotaciones del PDF: {:#}", e));
+                return;
             },
-            WindowEvent::KeyboardInput { event: KeyEvent { state: ElementState::Pressed, physical_key: PhysicalKey::Code(keycode), .. }, .. } => {
-                match keycode {
-                    KeyCode::ArrowRight => {
-                        if self.current_page < self.total_pages - 1 {
-                            self.load_page(self.current_page + 1);
-                        }
-                        true
-                    },
-                    KeyCode::ArrowLeft => {
-                        if self.current_page > 0 {
-                            self.load_page(self.current_page - 1);
-                        }
-                        true
-                    },
-                    _ => false,
-                }
+        };
+        let hidden: Vec<(u16, usize)> = imported.iter()
+            .flat_map(|page| page.annotations.iter().map(|(index, _)| (page.page, *index)))
+            .collect();
+        hide_annotations(doc, &hidden);
+
+        let mut count = 0;
+        for page_annotations in imported {
+            for (index, annotation) in page_annotations.annotations {
+                self.annotations.import_at(page_annotations.page, annotation, index);
+                count += 1;
+            }
+        }
+        if count > 0 {
+            log::info!("Anotaciones leídas del PDF: {}", count);
+        }
+    }
+
+    // Con las anotaciones en un archivo aparte, cada cambio terminado se escribe enseguida
+    fn autosave(&mut self) {
+        if self.annotations.revision() == self.saved_revision {
+            return;
+        }
+        self.saved_revision = self.annotations.revision();
+        let (Some(sidecar_path), Some(document)) = (&self.sidecar_path, self.source_path()) else { return };
+        if let Err(e) = sidecar::save(sidecar_path, document, &self.annotations) {
+            self.notify(format!("No se pudieron guardar las anotaciones: {:#}", e));
+        }
+    }
+
+    /// Escribe las anotaciones del documento en `path` con el formato de los archivos aparte.
+    pub fn export_annotations(&self, path: &Path) -> anyhow::Result<usize> {
+        let Some(document) = self.source_path() else { bail!("No hay ningún documento abierto") };
+        sidecar::save(path, document, &self.annotations)?;
+        Ok(self.annotations.pages().map(|(_, list)| list.len()).sum())
+    }
+
+    /// Exporta las anotaciones a XFDF, para intercambiarlas con otros visores sin mandar el PDF.
+    pub fn export_xfdf(&mut self, path: &Path) -> anyhow::Result<usize> {
+        self.end_stroke();
+        self.finish_text_edit();
+        let Some(document) = self.source_path() else { bail!("No hay ningún documento abierto") };
+        let count = xfdf::export(path, document, &self.annotations)?;
+        log::info!("XFDF exportado: {:?} ({} anotaciones)", path, count);
+        Ok(count)
+    }
+
+    /// Añade las anotaciones de un XFDF al documento (se pueden deshacer). Las de páginas que
+    /// no existen y las que ya están idénticas (p.ej. al importar dos veces) se saltan.
+    pub fn import_xfdf(&mut self, path: &Path) -> anyhow::Result<usize> {
+        self.end_stroke();
+        self.finish_text_edit();
+        if self.document.is_none() {
+            bail!("No hay ningún documento abierto");
+        }
+
+        // Idénticas salvo el id (y el nombre, si el XFDF no lo trae, porque se genera al cargarla)
+        let mut accepted = Vec::new();
+        for (page, annotation) in xfdf::import(path)? {
+            let same = |a: &Annotation| Annotation { id: a.id, name: annotation.name.clone().or_else(|| a.name.clone()), ..annotation.clone() } == *a;
+            if page >= self.total_pages || self.annotations.page(page).iter().any(same) || accepted.contains(&(page, annotation.clone())) {
+                continue;
+            }
+            accepted.push((page, annotation));
+        }
+        // Todo en un solo paso del historial: deshacer quita la importación entera
+        let count = self.annotations.insert_pages(accepted).len();
+        self.rebuild_overlay();
+        self.hud_dirty = true;
+        log::info!("XFDF importado: {:?} ({} anotaciones)", path, count);
+        Ok(count)
+    }
+
+    // Lista del resumen, con el texto de cada resaltado leído de la capa de texto de su página.
+    // Cada página se extrae una sola vez por documento: anotar no cambia su texto
+    fn summary_entries(&mut self) -> Vec<SummaryEntry> {
+        let document = self.document.as_ref();
+        summary::build(&self.annotations, &mut self.summary_text, |page| {
+            document.and_then(|doc| page_text::page_chars(doc, page).ok()).unwrap_or_default()
+        })
+    }
+
+    fn toggle_summary(&mut self) {
+        self.summary = match self.summary {
+            Some(_) => None,
+            None => {
+                let entries = self.summary_entries();
+                Some(SummaryPanel { entries, revision: self.annotations.revision(), scroll: 0, max_scroll: 0, hits: Vec::new() })
             },
-            _ => false,
+        };
+        self.hud_dirty = true;
+    }
+
+    // Con el panel abierto, la lista sigue a los cambios (editar, deshacer, importar...)
+    fn refresh_summary(&mut self) {
+        let revision = self.annotations.revision();
+        if self.summary.as_ref().is_some_and(|panel| panel.revision != revision) {
+            let entries = self.summary_entries();
+            if let Some(panel) = &mut self.summary {
+                panel.entries = entries;
+                panel.revision = revision;
+            }
+            self.hud_dirty = true;
         }
     }
 
-    pub fn update(&mut self) {
-        // Mantener el aspect ratio correcto del PDF
+    fn cursor_on_summary(&self) -> bool {
+        if self.summary.is_none() {
+            return false;
+        }
+        let (x, y) = self.cursor_pixel();
+        let (left, top, w, h) = self.hud.summary_rect();
+        x >= left && x < left + w && y >= top && y < top + h
+    }
+
+    // Posición del cursor en píxeles de ventana (last_mouse_pos está en NDC)
+    fn cursor_pixel(&self) -> (i32, i32) {
+        let x = (self.last_mouse_pos[0] + 1.0) * 0.5 * self.gpu.size.width as f64;
+        let y = (1.0 - self.last_mouse_pos[1]) * 0.5 * self.gpu.size.height as f64;
+        (x as i32, y as i32)
+    }
+
+    // Click sobre el panel de resumen: una entrada lleva a su anotación. Fuera del panel, false.
+    fn click_summary(&mut self) -> bool {
+        if !self.cursor_on_summary() {
+            return false;
+        }
+        let (_, y) = self.cursor_pixel();
+        let target = self.summary.as_ref()
+            .and_then(|panel| panel.hits.iter().find(|(top, bottom, _)| y >= *top && y < *bottom).map(|&(_, _, i)| &panel.entries[i]))
+            .map(|entry| (entry.page, entry.id));
+        if let Some((page, id)) = target {
+            self.focus_annotation(page, id);
+        }
+        true
+    }
+
+    /// Lleva la vista a una anotación: su página, centrada en pantalla y seleccionada.
+    pub fn focus_annotation(&mut self, page: u16, id: u64) {
+        self.go_to_page(page);
+        let Some(annotation) = self.annotations.get(self.current_page, id) else { return };
+        let [left, bottom, right, top] = annotation.kind.bounds();
+        let (page_w, page_h) = self.page_size_pt();
         let aspect = self.gpu.size.width as f32 / self.gpu.size.height as f32;
-        self.camera_uniform.scale = [self.zoom, self.zoom * aspect]; 
-        self.camera_uniform.translation = self.pan;
-        self.camera_uniform.ui_flags[0] = if self.ui.is_carousel_open { 1.0 } else { 0.0 };
-        self.gpu.queue.write_buffer(&self.camera_buffer, 0, bytemuck::cast_slice(&[self.camera_uniform]));
+        // Inversa de page_to_screen con el centro de la anotación en NDC (0, 0)
+        let x_cam = (left + right) * 0.5 / page_w * 2.0 - 1.0;
+        let y_cam = (bottom + top) * 0.5 / page_h * 2.0 - 1.0;
+        self.pan = [-x_cam * self.zoom, -y_cam * self.zoom * aspect];
+
+        self.ui.active_tool = Tool::Select;
+        self.ui.last_annotation_tool = Tool::Select;
+        self.select(vec![id]);
     }
 
-    pub fn size(&self) -> winit::dpi::PhysicalSize<u32> { self.gpu.size }
+    /// Exporta el resumen de anotaciones a Markdown (citas de lo resaltado, con su página).
+    pub fn export_markdown(&mut self, path: &Path) -> anyhow::Result<usize> {
+        self.end_stroke();
+        self.finish_text_edit();
+        let Some(document) = self.source_path() else { bail!("No hay ningún documento abierto") };
+        let name = Path::new(document).file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+        let entries = self.summary_entries();
+        std::fs::write(path, summary::to_markdown(&name, &entries)).with_context(|| format!("No se pudo escribir {:?}", path))?;
+        log::info!("Resumen exportado: {:?} ({} anotaciones)", path, entries.len());
+        Ok(entries.len())
+    }
 
-    pub fn render(&mut self) -> Result<(), wgpu::SurfaceError> {
-        let output = self.gpu.surface.get_current_texture()?;
-        let view = output.texture.create_view(&wgpu::TextureViewDescriptor::default());
-        let mut encoder = self.gpu.device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("Render Encoder") });
+    /// Exporta el documento con las anotaciones pintadas para siempre, como se ven en pantalla:
+    /// una copia del PDF, o si `path` acaba en .png, una imagen por página (`pages` con rangos
+    /// como "1-3,5"; por defecto la actual) llamadas "<nombre>-<página>.png". Devuelve cuántos
+    /// archivos (PNG) o anotaciones (PDF) se escribieron.
+    pub fn export_flattened(&mut self, path: &Path, pages: Option<&str>) -> anyhow::Result<usize> {
+        self.end_stroke();
+        self.finish_text_edit();
+        let (Some(source), Some(document)) = (self.document_path.clone(), &self.document) else { bail!("No hay ningún documento abierto") };
+        let scale = FLATTEN_DPI / 72.0;
 
-        {
-            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                label: Some("Render Pass"),
-                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                    view: &view,
-                    resolve_target: None,
-                    ops: wgpu::Operations { load: wgpu::LoadOp::Clear(wgpu::Color { r: 0.05, g: 0.05, b: 0.08, a: 1.0 }), store: wgpu::StoreOp::Store },
-                })],
-                depth_stencil_attachment: None,
-                occlusion_query_set: None,
-                timestamp_writes: None,
-            });
+        if !path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("png")) {
+            if same_file(path, Path::new(&source)) {
+                bail!("La copia aplanada no puede sustituir al documento: elige otra ruta");
+            }
+            let count = self.pdf_system.save_flattened(&source, self.document_password, path, &self.annotations, scale)?;
+            log::info!("PDF aplanado: {:?} ({} anotaciones)", path, count);
+            return Ok(count);
+        }
 
-            render_pass.set_pipeline(&self.render_pipeline);
-            render_pass.set_bind_group(0, &self.diffuse_bind_group, &[]);
-            render_pass.set_bind_group(1, &self.camera_bind_group, &[]);
-            render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
-            render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
-            render_pass.draw_indexed(0..self.num_indices, 0, 0..1);
+        let pages: Vec<u16> = match pages {
+            Some(spec) => parse_page_ranges(spec, self.total_pages).map_err(|e| anyhow!(e))?.into_iter().flatten().collect(),
+            None => vec![self.current_page],
+        };
+        let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+        // El documento abierto ya tiene ocultas las anotaciones que pinta el overlay
+        for &page_idx in &pages {
+            let mut bitmap = render_page_to_memory(document, page_idx, scale).map_err(|e| anyhow!("Página {}: {:?}", page_idx + 1, e))?;
+            let page_h = bitmap.height as f32 / scale;
+            for layer in rasterize_layers(self.annotations.page(page_idx), bitmap.width, bitmap.height, scale, page_h) {
+                multiply_layer(&mut bitmap.data, &layer);
+            }
+            let target = path.with_file_name(format!("{}-{}.png", stem, page_idx + 1));
+            bitmap.to_rgba_image().save(&target).with_context(|| format!("No se pudo guardar {:?}", target))?;
+            log::info!("PNG aplanado: {:?}", target);
         }
+        Ok(pages.len())
+    }
 
-        self.gpu.queue.submit(std::iter::once(encoder.finish()));
-        output.present();
-        Ok(())
+    /// Entra o sale del modo de redacción. Las marcas se conservan hasta aplicarlas.
+    fn toggle_redaction(&mut self) {
+        self.end_stroke();
+        self.finish_text_edit();
+        self.ui.active_tool = if self.ui.active_tool == Tool::Redact { Tool::Pan } else { Tool::Redact };
+        self.hud_dirty = true;
+    }
+
+    // Click con la herramienta de redacción: sobre una marca la quita (así se revisan), fuera empieza otra
+    fn redact_pressed(&mut self, ndc_x: f64, ndc_y: f64) {
+        let Some(point) = self.screen_to_page(ndc_x, ndc_y) else { return };
+        let page = self.current_page;
+        let hit = self.redactions.iter().rposition(|&(p, [left, bottom, right, top])| {
+            p == page && (left..=right).contains(&point.x) && (bottom..=top).contains(&point.y)
+        });
+        match hit {
+            Some(index) => {
+                self.redactions.remove(index);
+                self.mouse_pressed = false;
+            },
+            None => self.redaction_drag = Some((point, point)),
+        }
+        self.hud_dirty = true;
+    }
+
+    fn redact_dragged(&mut self, ndc_x: f64, ndc_y: f64) {
+        let point = self.screen_to_page_unclamped(ndc_x, ndc_y);
+        if let Some((_, to)) = &mut self.redaction_drag {
+            *to = point;
+            self.hud_dirty = true;
+        }
+    }
+
+    fn end_redaction_drag(&mut self) {
+        let Some((from, to)) = self.redaction_drag.take() else { return };
+        let (page_w, page_h) = self.page_size_pt();
+        let rect = [from.x.min(to.x).max(0.0), from.y.min(to.y).max(0.0), from.x.max(to.x).min(page_w), from.y.max(to.y).min(page_h)];
+        // Un click suelto no marca nada
+        if rect[2] - rect[0] >= MIN_SELECTION_SIDE && rect[3] - rect[1] >= MIN_SELECTION_SIDE {
+            self.redactions.push((self.current_page, rect));
+        }
+        self.hud_dirty = true;
+    }
+
+    /// Marca para redactar todas las apariciones de `term` en el documento y salta a la primera.
+    /// Devuelve cuántas se marcaron.
+    pub fn mark_redactions(&mut self, term: &str) -> anyhow::Result<usize> {
+        let hits = self.search(term).map_err(|e| anyhow!("Error en la búsqueda: {:?}", e))?;
+        for hit in &hits {
+            self.redactions.extend(hit.rects.iter().map(|&rect| (hit.page, rect)));
+        }
+        self.hud_dirty = true;
+        log::info!("Marcadas para redactar: {} apariciones de {:?}", hits.len(), term);
+        Ok(hits.len())
+    }
+
+    /// Escribe en `path` una copia del documento sin nada de lo que hay bajo las marcas, con cajas
+    /// negras encima (ver `pdf::redact`). El original no se toca. Devuelve cuántos objetos se quitaron o taparon.
+    pub fn apply_redactions(&mut self, path: &Path) -> anyhow::Result<usize> {
+        let Some(source) = self.document_path.clone() else { bail!("No hay ningún documento abierto") };
+        if self.redactions.is_empty() {
+            bail!("No hay nada marcado para redactar");
+        }
+        if same_file(path, Path::new(&source)) {
+            bail!("La copia redactada no puede sustituir al documento: elige otra ruta");
+        }
+        let redacted = self.pdf_system.save_redacted(&source, self.document_password, path, &self.redactions)?;
+        log::info!("Copia redactada: {:?} ({} marcas, {} objetos quitados o tapados)", path, self.redactions.len(), redacted.changed);
+        if !redacted.whole_forms.is_empty() {
+            let mut pages: Vec<String> = redacted.whole_forms.iter().map(|page| (page + 1).to_string()).collect();
+            pages.dedup();
+            self.notify(format!("Se quitaron enteros {} formularios (XObject) que tocaban las marcas, con todo lo que había en ellos: páginas {}", redacted.whole_forms.len(), pages.join(", ")));
+        }
+        self.redactions.clear();
+        self.hud_dirty = true;
+        Ok(redacted.changed)
+    }
+
+    // El carrusel abierto es el organizador de páginas: se rehace al abrirlo (una casilla por
+    // página, en orden) y al cerrarlo se descarta lo que no se haya guardado
+    fn sync_carousel(&mut self) {
+        let open = self.ui.is_carousel_open && self.document.is_some();
+        if open == self.carousel.is_some() {
+            return;
+        }
+        if let (true, Some(doc)) = (open, &self.document) {
+            let sizes = (0..self.total_pages)
+                .map(|page| doc.pages().page_size(page).map_or((612.0, 792.0), |rect| (rect.width().value, rect.height().value)))
+                .collect();
+            let mut organizer = Carousel::new(sizes, self.current_page as usize);
+            organizer.reveal_selected(self.carousel_bottom());
+            self.carousel = Some(organizer);
+        } else if self.carousel.take().is_some_and(|organizer| organizer.changed) {
+            log::info!("Organizador cerrado: se descartan los cambios sin guardar");
+        }
+        self.hud_dirty = true;
+    }
+
+    // Límite inferior del carrusel en píxeles de ventana (encima de la barra)
+    fn carousel_bottom(&self) -> i32 {
+        self.gpu.size.height as i32 - self.ui.bottom_bar_height as i32
+    }
+
+    fn cursor_on_carousel(&self) -> bool {
+        if self.carousel.is_none() || self.ui.presentation {
+            return false;
+        }
+        let (x, y) = self.cursor_pixel();
+        (x as f32) < self.ui.side_panel_width && y < self.carousel_bottom()
+    }
+
+    // Click en el carrusel: elige la casilla (y muestra la página si es del documento) y empieza a
+    // arrastrarla. Fuera del carrusel, false.
+    fn click_carousel(&mut self) -> bool {
+        if !self.cursor_on_carousel() {
+            return false;
+        }
+        let (_, y) = self.cursor_pixel();
+        let Some(organizer) = &mut self.carousel else { return false };
+        let mut shown = None;
+        if let Some(index) = organizer.slot_at(y) {
+            organizer.selected = index;
+            organizer.drag = Some(index);
+            if let PageSource::Original(page) = organizer.pages[index].plan.source {
+                shown = Some(page);
+            }
+        }
+        if let Some(page) = shown.filter(|&page| page != self.current_page) {
+            self.go_to_page(page);
+        }
+        self.hud_dirty = true;
+        true
+    }
+
+    // Suelta la casilla arrastrada en el hueco bajo el cursor
+    fn end_carousel_drag(&mut self) {
+        let (_, y) = self.cursor_pixel();
+        let Some(organizer) = &mut self.carousel else { return };
+        if let Some(from) = organizer.drag.take() {
+            let gap = organizer.gap_at(y);
+            organizer.move_page(from, gap);
+            self.hud_dirty = true;
+        }
+    }
+
+    // Teclado del organizador: cambia la casilla elegida y, si es del documento, la muestra
+    fn select_carousel_page(&mut self, forward: bool) {
+        let bottom = self.carousel_bottom();
+        let Some(organizer) = &mut self.carousel else { return };
+        if organizer.pages.is_empty() {
+            return;
+        }
+        organizer.selected = if forward { (organizer.selected + 1).min(organizer.pages.len() - 1) } else { organizer.selected.saturating_sub(1) };
+        organizer.reveal_selected(bottom);
+        if let PageSource::Original(page) = organizer.pages[organizer.selected].plan.source {
+            self.go_to_page(page);
+        }
+        self.hud_dirty = true;
+    }
+
+    fn edit_carousel(&mut self, edit: impl FnOnce(&mut Carousel)) {
+        let bottom = self.carousel_bottom();
+        if let Some(organizer) = &mut self.carousel {
+            edit(organizer);
+            organizer.reveal_selected(bottom);
+            self.hud_dirty = true;
+        }
+    }
+
+    // Pinta las miniaturas que faltan de las casillas visibles (las del documento se hacen al verlas)
+    fn fill_thumbnails(&mut self) {
+        let Some(organizer) = &self.carousel else { return };
+        let missing: Vec<_> = organizer.visible(self.carousel_bottom())
+            .filter_map(|index| {
+                let page = &organizer.pages[index];
+                match (&page.thumbnail, &page.plan.source) {
+                    (None, PageSource::Original(page_idx)) => Some((index, *page_idx, page.size)),
+                    _ => None,
+                }
+            })
+            .collect();
+        for (index, page_idx, size) in missing {
+            let thumbnail = self.render_thumbnail(page_idx, size).unwrap_or_else(|| carousel::blank_thumbnail(size));
+            if let Some(organizer) = &mut self.carousel {
+                organizer.pages[index].thumbnail = Some(thumbnail);
+            }
+        }
+    }
+
+    // Miniatura de una página del documento con sus anotaciones, como se guardará
+    fn render_thumbnail(&self, page_idx: u16, size: (f32, f32)) -> Option<PageBitmap> {
+        let scale = carousel::thumbnail_scale(size);
+        let mut bitmap = render_page_to_memory(self.document.as_ref()?, page_idx, scale).ok()?;
+        let page_h = bitmap.height as f32 / scale;
+        for layer in rasterize_layers(self.annotations.page(page_idx), bitmap.width, bitmap.height, scale, page_h) {
+            multiply_layer(&mut bitmap.data, &layer);
+        }
+        Some(bitmap)
+    }
+
+    /// Añade al organizador, detrás de la página elegida, páginas de otro PDF (`pages` con rangos
+    /// como "1-3,5"; por defecto todas). Devuelve cuántas se añadieron.
+    pub fn import_pages(&mut self, path: &Path, pages: Option<&str>) -> anyhow::Result<usize> {
+        if self.carousel.is_none() {
+            bail!("El organizador de páginas no está abierto");
+        }
+        let file = path.to_string_lossy().into_owned();
+        // Con la contraseña del documento abierto, como al combinar: a los que no la piden no les afecta
+        let other = self.pdf_system.open_file_with_password(&file, self.document_password).map_err(|e| anyhow!("No se pudo abrir {:?}: {:?}", path, e))?;
+        let total = other.pages().len();
+        let pages: Vec<u16> = match pages {
+            Some(spec) => parse_page_ranges(spec, total).map_err(|e| anyhow!(e))?.into_iter().flatten().collect(),
+            None => (0..total).collect(),
+        };
+
+        let mut imported = Vec::with_capacity(pages.len());
+        for page in pages {
+            let rect = other.pages().page_size(page).map_err(|e| anyhow!("Página {}: {:?}", page + 1, e))?;
+            let size = (rect.width().value, rect.height().value);
+            let thumbnail = render_page_to_memory(&other, page, carousel::thumbnail_scale(size)).unwrap_or_else(|_| carousel::blank_thumbnail(size));
+            imported.push(CarouselPage {
+                plan: PagePlan { source: PageSource::Imported { path: file.clone(), page }, rotation: 0 },
+                size,
+                thumbnail: Some(thumbnail),
+            });
+        }
+        let count = imported.len();
+        self.edit_carousel(|organizer| organizer.insert_after_selected(imported));
+        log::info!("Páginas importadas de {:?}: {}", path, count);
+        Ok(count)
+    }
+
+    /// Guarda en `path` el documento tal como está en el organizador (orden, giros, páginas quitadas,
+    /// en blanco o de otros PDF), con las anotaciones actuales. El original no se toca.
+    /// Devuelve cuántas páginas tiene.
+    pub fn save_organized(&mut self, path: &Path) -> anyhow::Result<usize> {
+        self.end_stroke();
+        self.finish_text_edit();
+        let Some(source) = self.document_path.clone() else { bail!("No hay ningún documento abierto") };
+        let Some(organizer) = &self.carousel else { bail!("El organizador de páginas no está abierto") };
+        if same_file(path, Path::new(&source)) {
+            bail!("El documento organizado no puede sustituir al original: elige otra ruta");
+        }
+        let plan = organizer.plan();
+        let count = self.with_annotated_copy(path, |copy| self.pdf_system.save_organized(copy, self.document_password, path, &plan))?;
+
+        if let Some(organizer) = &mut self.carousel {
+            organizer.changed = false;
+        }
+        log::info!("Páginas organizadas: {:?} ({} páginas)", path, count);
+        Ok(count)
+    }
+
+    // Escribe junto a `target` un temporal del documento con las anotaciones del modelo incrustadas,
+    // llama a `write` con su ruta y lo borra: lo que sale del documento lleva lo que se ve en pantalla
+    fn with_annotated_copy<T>(&self, target: &Path, write: impl FnOnce(&str) -> anyhow::Result<T>) -> anyhow::Result<T> {
+        let Some(source) = &self.document_path else { bail!("No hay ningún documento abierto") };
+        let file_name = target.file_name().ok_or_else(|| anyhow!("Ruta inválida: {:?}", target))?;
+        let temp = target.with_file_name(format!(".{}.windp-tmp", file_name.to_string_lossy()));
+        let result = self.pdf_system.save_with_annotations(source, self.document_password, &temp, &self.annotations)
+            .and_then(|_| write(&temp.to_string_lossy()));
+        let _ = std::fs::remove_file(&temp);
+        result
+    }
+
+    // Primer paso de "Unir" y de "Combinar imágenes": lo que se combina. Después (`next`) se pide dónde guardar
+    fn choose_merge_inputs(&mut self, list: &str, next: PromptAction) -> anyhow::Result<usize> {
+        let inputs: Vec<String> = list.split(';').map(str::trim).filter(|path| !path.is_empty()).map(String::from).collect();
+        if inputs.is_empty() {
+            bail!("No se eligió ningún archivo para combinar");
+        }
+        self.merge_inputs = inputs;
+        self.open_prompt(next);
+        Ok(self.merge_inputs.len())
+    }
+
+    /// Une en `path` el documento (con las anotaciones actuales) y detrás los PDF de `others`, enteros
+    /// y en ese orden. El original no se toca. Devuelve cuántas páginas tiene el resultado.
+    pub fn merge_documents(&mut self, others: &[String], path: &Path) -> anyhow::Result<usize> {
+        self.end_stroke();
+        self.finish_text_edit();
+        let Some(source) = self.document_path.clone() else { bail!("No hay ningún documento abierto") };
+        if std::iter::once(&source).chain(others).any(|input| same_file(Path::new(input), path)) {
+            bail!("El resultado no puede sustituir a uno de los documentos que se unen: elige otra ruta");
+        }
+        let pages = self.with_annotated_copy(path, |copy| {
+            let sources: Vec<&str> = std::iter::once(copy).chain(others.iter().map(String::as_str)).collect();
+            self.pdf_system.merge(&sources, self.document_password, path)
+        })?;
+        log::info!("Documentos unidos: {:?} ({} documentos, {} páginas)", path, others.len() + 1, pages);
+        Ok(pages)
+    }
+
+    /// Escribe en `path` un PDF con una página por imagen de `inputs` (archivos PNG, JPEG o TIFF, o
+    /// carpetas con ellos). `page` es el papel ("auto", por defecto, deja cada página del tamaño de su
+    /// imagen) y `fit` cómo entra la imagen ("fit" o "fill"). No necesita ningún documento abierto.
+    /// Devuelve cuántas páginas tiene.
+    pub fn combine_images(&mut self, inputs: &[String], path: &Path, page: Option<&str>, fit: Option<&str>) -> anyhow::Result<usize> {
+        let paper = parse_image_page(page.unwrap_or("auto")).map_err(|e| anyhow!(e))?;
+        let fit = Fit::parse(fit.unwrap_or("fit")).map_err(|e| anyhow!(e))?;
+        let images = collect_images(inputs)?;
+        let pages = self.pdf_system.images_to_pdf(&images, paper, fit, path)?;
+        log::info!("Imágenes combinadas: {:?} ({} páginas)", path, pages);
+        Ok(pages)
+    }
+
+    /// Exporta en `path` el documento (con las anotaciones actuales) con varias páginas por hoja para
+    /// imprimir: 2-up, 4-up o cuadernillo (ver `pdf::impose`). `sheet` es el papel ("a4", "210x297mm"...);
+    /// por defecto, el tamaño de la primera página. Devuelve cuántas caras de hoja tiene.
+    pub fn export_imposed(&mut self, path: &Path, layout: Layout, sheet: Option<&str>) -> anyhow::Result<usize> {
+        self.end_stroke();
+        self.finish_text_edit();
+        let Some(source) = self.document_path.clone() else { bail!("No hay ningún documento abierto") };
+        if same_file(path, Path::new(&source)) {
+            bail!("La copia para imprimir no puede sustituir al documento: elige otra ruta");
+        }
+        let sheet = sheet.map(parse_paper_size).transpose().map_err(|e| anyhow!(e))?;
+        let sheets = self.with_annotated_copy(path, |copy| self.pdf_system.save_imposed(copy, self.document_password, path, layout, sheet))?;
+        log::info!("Exportado para imprimir: {:?} ({}, {} caras)", path, layout.name(), sheets);
+        Ok(sheets)
+    }
+
+    /// Divide el documento (con las anotaciones actuales) en un PDF por rango de `ranges` ("1-3,4-";
+    /// por defecto uno por página), con el nombre de `base` y las páginas detrás ("base-1-3.pdf").
+    /// Devuelve cuántos archivos se escribieron.
+    pub fn split_document(&mut self, base: &Path, ranges: Option<&str>) -> anyhow::Result<usize> {
+        self.end_stroke();
+        self.finish_text_edit();
+        let Some(source) = self.document_path.clone() else { bail!("No hay ningún documento abierto") };
+        let ranges = match ranges {
+            Some(spec) => parse_page_ranges(spec, self.total_pages).map_err(|e| anyhow!(e))?,
+            None => (0..self.total_pages).map(|page| page..page + 1).collect(),
+        };
+        if ranges.iter().any(|range| same_file(&split_target(base, range), Path::new(&source))) {
+            bail!("Una de las partes sustituiría al documento: elige otro nombre");
+        }
+        let written = self.with_annotated_copy(base, |copy| self.pdf_system.split(copy, self.document_password, base, &ranges))?;
+        for path in &written {
+            log::info!("Parte escrita: {:?}", path);
+        }
+        Ok(written.len())
+    }
+
+    /// Las anotaciones del documento en el formato de los archivos aparte (ver sidecar.rs).
+    pub fn annotations_json(&self) -> Option<serde_json::Value> {
+        let document = self.source_path()?;
+        Some(sidecar::to_json(document, &self.annotations))
+    }
+
+    // Crea una textura nueva para la página y rehace el BindGroup que la referencia
+    fn recreate_page_textures(&mut self, width: u32, height: u32) {
+        let blank = vec![0u8; (width * height * 4) as usize];
+        self.diffuse_texture = Texture::from_bytes(&self.gpu.device, &self.gpu.queue, &blank, width, height, Some("PDF")).unwrap();
+        self.page_width = width;
+        self.page_height = height;
+        self.rebuild_bind_group();
+    }
+
+    fn rebuild_bind_group(&mut self) {
+        self.diffuse_bind_group = self.gpu.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &self.texture_bg_layout,
+            entries: &[
+                wgpu::BindGroupEntry { binding: 0, resource: wgpu::BindingResource::TextureView(&self.diffuse_texture.view) },
+                wgpu::BindGroupEntry { binding: 1, resource: wgpu::BindingResource::Sampler(&self.diffuse_texture.sampler) },
+                wgpu::BindGroupEntry { binding: 2, resource: wgpu::BindingResource::TextureView(&self.overlay_texture.view) },
+                wgpu::BindGroupEntry { binding: 3, resource: wgpu::BindingResource::TextureView(&self.strokes.view) },
+            ],
+            label: Some("Diffuse BG"),
+        });
+    }
+
+    // Tamaño de la página actual en puntos PDF
+    fn page_size_pt(&self) -> (f32, f32) {
+        (self.page_width as f32 / RENDER_SCALE, self.page_height as f32 / RENDER_SCALE)
+    }
+
+    // Escala del overlay que da un píxel de overlay por píxel de pantalla con el zoom actual.
+    // Nunca por debajo de la del bitmap del PDF ni por encima de MAX_OVERLAY_SIDE.
+    fn desired_overlay_scale(&self) -> f32 {
+        let (page_w, page_h) = self.page_size_pt();
+        let on_screen = self.gpu.size.width as f32 * self.zoom / page_w.max(1.0);
+        let max_scale = MAX_OVERLAY_SIDE / page_w.max(page_h).max(1.0);
+        on_screen.clamp(RENDER_SCALE.min(max_scale), max_scale)
+    }
+
+    /// Regenera las anotaciones de la página desde el modelo: las vectoriales y el overlay raster.
+    fn rebuild_overlay(&mut self) {
+        self.rebuild_strokes();
+        self.rasterize_overlay();
+    }
+
+    // Geometría de las anotaciones vectoriales terminadas (la que se está dibujando va aparte)
+    fn rebuild_strokes(&mut self) {
+        let active = self.active_stroke;
+        let annotations: Vec<&Annotation> = self.annotations.page(self.current_page).iter()
+            .filter(|a| a.kind.is_stroke() && Some(a.id) != active)
+            .collect();
+        self.strokes.set_page(&self.gpu.device, &self.gpu.queue, &annotations);
+    }
+
+    // Vuelve a teselar la anotación en curso: lo único que cambia mientras se dibuja
+    fn update_active_stroke(&mut self) {
+        let active = self.active_stroke.and_then(|id| self.annotations.get(self.current_page, id));
+        self.strokes.set_active(&self.gpu.device, &self.gpu.queue, active);
+    }
+
+    /// Pinta el overlay raster (notas y texto libre) a la resolución del zoom actual.
+    fn rasterize_overlay(&mut self) {
+        let scale = self.desired_overlay_scale();
+        let (page_w, page_h) = self.page_size_pt();
+        let width = ((page_w * scale) as u32).max(1);
+        let height = ((page_h * scale) as u32).max(1);
+
+        self.overlay_buffer.clear();
+        self.overlay_buffer.resize((width * height * 4) as usize, 0);
+
+        let mut target = RasterTarget { data: &mut self.overlay_buffer, width, height, scale, page_height: page_h };
+        for annotation in self.annotations.page(self.current_page).iter().filter(|a| !a.kind.is_stroke()) {
+            rasterize_annotation(&mut target, annotation);
+        }
+
+        if width != self.overlay_width || height != self.overlay_height {
+            self.overlay_texture = Texture::from_bytes(&self.gpu.device, &self.gpu.queue, &self.overlay_buffer, width, height, Some("Overlay")).unwrap();
+            self.overlay_width = width;
+            self.overlay_height = height;
+            self.overlay_dirty = PixelRect { x0: 0, y0: 0, x1: 0, y1: 0 }; // La textura nueva ya lleva los píxeles
+            self.rebuild_bind_group();
+        } else {
+            self.overlay_dirty = self.overlay_full_rect();
+        }
+        self.overlay_scale = scale;
+    }
+
+    fn overlay_full_rect(&self) -> PixelRect {
+        PixelRect { x0: 0, y0: 0, x1: self.overlay_width as i32, y1: self.overlay_height as i32 }
+    }
+
+    /// Repinta en el overlay solo la zona `bounds` ([left, bottom, right, top] en puntos): la borra y
+    /// vuelve a pintar lo que la toque. Para cambios pequeños, como escribir en un texto libre.
+    fn repaint_overlay_area(&mut self, bounds: [f32; 4]) {
+        let (_, page_h) = self.page_size_pt();
+        let scale = self.overlay_scale;
+        // Un par de píxeles de margen para el antialiasing de los bordes
+        let rect = PixelRect {
+            x0: (bounds[0] * scale).floor() as i32 - 2,
+            y0: ((page_h - bounds[3]) * scale).floor() as i32 - 2,
+            x1: (bounds[2] * scale).ceil() as i32 + 2,
+            y1: ((page_h - bounds[1]) * scale).ceil() as i32 + 2,
+        }.intersect(&self.overlay_full_rect());
+        if rect.is_empty() {
+            return;
+        }
+
+        let row = self.overlay_width as usize * 4;
+        for y in rect.y0 as usize..rect.y1 as usize {
+            self.overlay_buffer[y * row + rect.x0 as usize * 4..y * row + rect.x1 as usize * 4].fill(0);
+        }
+        let mut target = RasterTarget { data: &mut self.overlay_buffer, width: self.overlay_width, height: self.overlay_height, scale, page_height: page_h };
+        for annotation in self.annotations.page(self.current_page).iter().filter(|a| !a.kind.is_stroke()) {
+            rasterize_annotation_in(&mut target, annotation, rect);
+        }
+        self.overlay_dirty = self.overlay_dirty.union(&rect);
+    }
+
+    /// Sube a la textura solo el rectángulo del overlay que cambió. Una vez por frame (desde update),
+    /// no en cada evento del ratón o del teclado.
+    fn flush_overlay(&mut self) {
+        let rect = self.overlay_dirty;
+        if rect.is_empty() {
+            return;
+        }
+        self.overlay_dirty = PixelRect { x0: 0, y0: 0, x1: 0, y1: 0 };
+        // El buffer es la página entera: el offset apunta al primer píxel del rectángulo y
+        // bytes_per_row salta filas completas
+        let offset = (rect.y0 as u64 * self.overlay_width as u64 + rect.x0 as u64) * 4;
+        self.gpu.queue.write_texture(
+            wgpu::ImageCopyTexture {
+                texture: &self.overlay_texture.texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d { x: rect.x0 as u32, y: rect.y0 as u32, z: 0 },
+                aspect: wgpu::TextureAspect::All,
+            },
+            &self.overlay_buffer,
+            wgpu::ImageDataLayout { offset, bytes_per_row: Some(4 * self.overlay_width), rows_per_image: Some(self.overlay_height) },
+            wgpu::Extent3d { width: (rect.x1 - rect.x0) as u32, height: (rect.y1 - rect.y0) as u32, depth_or_array_layers: 1 }
+        );
+    }
+
+    /// Pantalla (NDC) -> punto de página PDF. None si el cursor está fuera de la página.
+    fn screen_to_page(&self, ndc_x: f64, ndc_y: f64) -> Option<PagePoint> {
+        let point = self.screen_to_page_unclamped(ndc_x, ndc_y);
+        let (page_w, page_h) = self.page_size_pt();
+        ((0.0..=page_w).contains(&point.x) && (0.0..=page_h).contains(&point.y)).then_some(point)
+    }
+
+    /// Como `screen_to_page`, aunque el cursor se salga de la página (arrastrar una selección hasta el borde).
+    fn screen_to_page_unclamped(&self, ndc_x: f64, ndc_y: f64) -> PagePoint {
+        // Invertimos la transformación de cámara: (ndc - translation) / scale
+        // (en update: scale.x = zoom, scale.y = zoom * aspect)
+        let aspect = self.gpu.size.width as f32 / self.gpu.size.height as f32;
+        let x_cam = (ndc_x as f32 - self.pan[0]) / self.zoom;
+        let y_cam = (ndc_y as f32 - self.pan[1]) / (self.zoom * aspect);
+
+        // Coordenadas UV (0 a 1). El quad es de -1 a 1. UV 0,0 es TopLeft.
+        let u = (x_cam + 1.0) * 0.5;
+        let v = (1.0 - y_cam) * 0.5;
+
+        let (page_w, page_h) = self.page_size_pt();
+        PagePoint { x: u * page_w, y: (1.0 - v) * page_h }
+    }
+
+    /// Empieza a usar la herramienta activa en un punto de pantalla (click o lápiz apoyado).
+    fn tool_pressed(&mut self, ndc_x: f64, ndc_y: f64) {
+        match self.ui.active_tool {
+            Tool::Pen | Tool::Highlighter => self.paint_overlay(ndc_x, ndc_y),
+            Tool::TextHighlight => self.begin_text_highlight(ndc_x, ndc_y),
+            Tool::Note => self.place_text(ndc_x, ndc_y, false),
+            Tool::FreeText => self.place_text(ndc_x, ndc_y, true),
+            Tool::Eraser => self.erase_at(ndc_x, ndc_y),
+            Tool::Rectangle => self.begin_shape(Shape::Rectangle, ndc_x, ndc_y),
+            Tool::Ellipse => self.begin_shape(Shape::Ellipse, ndc_x, ndc_y),
+            Tool::Line => self.begin_shape(Shape::Line, ndc_x, ndc_y),
+            Tool::Arrow => self.begin_shape(Shape::Arrow, ndc_x, ndc_y),
+            Tool::Select => self.select_at(ndc_x, ndc_y),
+            Tool::Redact => self.redact_pressed(ndc_x, ndc_y),
+            Tool::None | Tool::Pan => {},
+        }
+    }
+
+    /// Arrastre con el botón pulsado (o el lápiz apoyado).
+    fn tool_dragged(&mut self, ndc_x: f64, ndc_y: f64, dx: f64, dy: f64) {
+        match self.ui.active_tool {
+            Tool::Pan => {
+                // Arrastrar documento
+                self.pan[0] += dx as f32;
+                self.pan[1] += dy as f32;
+            },
+            Tool::Pen | Tool::Highlighter => self.paint_overlay(ndc_x, ndc_y),
+            Tool::TextHighlight => self.drag_text_highlight(ndc_x, ndc_y),
+            Tool::Eraser => self.erase_at(ndc_x, ndc_y),
+            Tool::Rectangle | Tool::Ellipse | Tool::Line | Tool::Arrow => self.drag_shape(ndc_x, ndc_y),
+            Tool::Select => self.drag_selection(ndc_x, ndc_y),
+            Tool::Redact => self.redact_dragged(ndc_x, ndc_y),
+            Tool::Note | Tool::FreeText | Tool::None => {},
+        }
+    }
+
+    fn paint_overlay(&mut self, ndc_x: f64, ndc_y: f64) {
+        let Some(point) = self.screen_to_page(ndc_x, ndc_y) else { return };
+        let page = self.current_page;
+        let settings = self.ui.settings();
+        let sample = InkSample { pos: point, pressure: self.pen_pressure };
+
+        // 1. Modelo: la primera muestra abre un trazo nuevo, las siguientes lo alargan
+        match self.active_stroke {
+            Some(id) => self.annotations.push_sample(page, id, sample),
+            None => {
+                let kind = AnnotationKind::Ink { samples: vec![sample], width: settings.width };
+                self.active_stroke = Some(self.annotations.add(page, kind, settings.color));
+            },
+        }
+
+        // 2. GPU: solo sube la geometría de este trazo, el resto de la página ya está allí
+        self.update_active_stroke();
+    }
+
+    /// Goma de trazos: borra entera cualquier anotación que pase bajo el cursor.
+    fn erase_at(&mut self, ndc_x: f64, ndc_y: f64) {
+        let Some(point) = self.screen_to_page(ndc_x, ndc_y) else { return };
+        let radius = self.ui.settings().width;
+        let hits: Vec<u64> = self.annotations.page(self.current_page).iter()
+            .filter(|a| hit_test(a, point, radius))
+            .map(|a| a.id)
+            .collect();
+
+        if hits.is_empty() {
+            return;
+        }
+        for id in hits {
+            self.annotations.remove(self.current_page, id);
+        }
+        self.rebuild_overlay();
+    }
+
+    fn begin_shape(&mut self, shape: Shape, ndc_x: f64, ndc_y: f64) {
+        let Some(point) = self.screen_to_page(ndc_x, ndc_y) else { return };
+        let settings = self.ui.settings();
+        let kind = AnnotationKind::Shape { shape, from: point, to: point, width: settings.width };
+        self.active_stroke = Some(self.annotations.add(self.current_page, kind, settings.color));
+    }
+
+    // La figura va de donde se pulsó a donde está el cursor: se rehace entera en cada movimiento
+    fn drag_shape(&mut self, ndc_x: f64, ndc_y: f64) {
+        let Some(point) = self.screen_to_page(ndc_x, ndc_y) else { return };
+        let Some(id) = self.active_stroke else { return };
+        let Some(annotation) = self.annotations.get_mut(self.current_page, id) else { return };
+        if let AnnotationKind::Shape { to, .. } = &mut annotation.kind {
+            *to = point;
+        }
+        self.update_active_stroke();
+    }
+
+    // Caracteres de la página actual; se leen de PDFium una sola vez por página
+    fn current_page_chars(&mut self) -> &[PageChar] {
+        if self.page_chars.is_none() {
+            let chars = self.document.as_ref()
+                .and_then(|doc| page_text::page_chars(doc, self.current_page).ok())
+                .unwrap_or_default();
+            self.page_chars = Some(chars);
+        }
+        self.page_chars.as_deref().unwrap_or(&[])
+    }
+
+    /// Resaltado de texto: empieza en el carácter bajo el cursor (o muy cerca de él).
+    fn begin_text_highlight(&mut self, ndc_x: f64, ndc_y: f64) {
+        let Some(point) = self.screen_to_page(ndc_x, ndc_y) else { return };
+        let Some(anchor) = page_text::char_near(self.current_page_chars(), point.x, point.y, TEXT_SNAP_DISTANCE) else {
+            log::debug!("Resaltar texto: no hay texto bajo el cursor");
+            return;
+        };
+        self.text_anchor = Some(anchor);
+
+        let kind = AnnotationKind::TextHighlight { rects: Vec::new(), text: String::new() };
+        self.active_stroke = Some(self.annotations.add(self.current_page, kind, self.ui.settings().color));
+        self.drag_text_highlight(ndc_x, ndc_y);
+    }
+
+    // Del carácter inicial al más cercano al cursor, ampliado a palabras completas
+    fn drag_text_highlight(&mut self, ndc_x: f64, ndc_y: f64) {
+        let Some(point) = self.screen_to_page(ndc_x, ndc_y) else { return };
+        let Some(anchor) = self.text_anchor else { return };
+        let chars = self.current_page_chars();
+        let Some(end) = page_text::char_near(chars, point.x, point.y, f32::INFINITY) else { return };
+        let range = page_text::word_range(chars, anchor, end);
+        let (new_rects, new_text) = (page_text::line_rects(chars, range.clone()), page_text::range_text(chars, range));
+
+        let Some(id) = self.active_stroke else { return };
+        let Some(annotation) = self.annotations.get_mut(self.current_page, id) else { return };
+        if let AnnotationKind::TextHighlight { rects, text } = &mut annotation.kind {
+            if *rects == new_rects {
+                return; // Mismas palabras: nada que repintar
+            }
+            *rects = new_rects;
+            *text = new_text;
+        }
+        self.update_active_stroke();
+    }
+
+    /// Cierra el trazo o la figura en curso: pasa a la geometría de la página y al historial.
+    /// También termina el arrastre de una anotación seleccionada.
+    fn end_stroke(&mut self) {
+        self.end_selection_drag();
+        self.end_redaction_drag();
+        let Some(id) = self.active_stroke.take() else { return };
+        self.text_anchor = None;
+        let page = self.current_page;
+        self.update_active_stroke();
+        let Some(annotation) = self.annotations.get(page, id) else { return };
+
+        // Un click sin arrastrar con una figura no dibuja nada
+        if let AnnotationKind::Shape { from, to, .. } = &annotation.kind {
+            if from == to {
+                self.annotations.discard(page, id);
+                return;
+            }
+        }
+        self.annotations.commit_added(page, id);
+        self.rebuild_strokes();
+    }
+
+    fn select(&mut self, ids: Vec<u64>) {
+        if self.selection != ids {
+            self.selection = ids;
+            self.hud_dirty = true;
+        }
+    }
+
+    // La selección solo se ve (y se puede borrar o copiar) con su herramienta activa
+    fn has_selection(&self) -> bool {
+        self.ui.active_tool == Tool::Select && !self.selection.is_empty()
+    }
+
+    fn selected_annotations(&self) -> impl Iterator<Item = &Annotation> + '_ {
+        let (annotations, page) = (&self.annotations, self.current_page);
+        self.selection.iter().filter_map(move |id| annotations.get(page, *id))
+    }
+
+    // Caja que envuelve la selección ([left, bottom, right, top] en puntos). None si no hay nada elegido.
+    fn selection_bounds(&self) -> Option<[f32; 4]> {
+        union_bounds(self.selected_annotations())
+    }
+
+    /// Caja de página -> marco en píxeles de pantalla (left, top, right, bottom).
+    fn screen_box(&self, [left, bottom, right, top]: [f32; 4]) -> (f32, f32, f32, f32) {
+        let (x0, y0) = self.page_to_screen(PagePoint { x: left, y: top });
+        let (x1, y1) = self.page_to_screen(PagePoint { x: right, y: bottom });
+        (x0, y0, x1, y1)
+    }
+
+    // Asa de la selección bajo el cursor: qué lados de la caja mueve
+    fn handle_at_cursor(&self) -> Option<[i8; 2]> {
+        let (left, top, right, bottom) = self.screen_box(self.selection_bounds()?);
+        let (x, y) = self.cursor_pixel();
+        // Algo más de margen que el tamaño del asa: con el lápiz cuesta acertar
+        hud::selection_handles(left, top, right, bottom).into_iter()
+            .find(|(hx, hy, _)| (x - hx).abs() <= HANDLE_SIZE && (y - hy).abs() <= HANDLE_SIZE)
+            .map(|(_, _, sides)| sides)
+    }
+
+    /// Herramienta de selección. Al pulsar:
+    /// - sobre un asa de la selección, empieza a redimensionarla;
+    /// - sobre una anotación, la elige (con Mayúsculas la añade o la quita) y empieza a moverla con el resto;
+    /// - sobre la página vacía, empieza un marco para elegir varias (con Alt, un lazo a mano alzada).
+    fn select_at(&mut self, ndc_x: f64, ndc_y: f64) {
+        let point = self.screen_to_page_unclamped(ndc_x, ndc_y);
+        let shift = self.modifiers.shift_key();
+        if let Some(handle) = self.handle_at_cursor().filter(|_| !shift) {
+            self.begin_transform(Some(handle), point);
+            return;
+        }
+
+        // Unos puntos de margen: los trazos finos son difíciles de acertar
+        let hit = self.annotations.page(self.current_page).iter().rev()
+            .find(|a| hit_test(a, point, 3.0))
+            .map(|a| a.id);
+        match hit {
+            Some(id) if shift => {
+                let mut ids = self.selection.clone();
+                match ids.iter().position(|selected| *selected == id) {
+                    Some(index) => { ids.remove(index); },
+                    None => ids.push(id),
+                }
+                self.select(ids);
+            },
+            Some(id) => {
+                if !self.selection.contains(&id) {
+                    self.select(vec![id]);
+                }
+                self.begin_transform(None, point);
+            },
+            None => {
+                if !shift {
+                    self.select(Vec::new());
+                }
+                self.selection_drag = Some(if self.modifiers.alt_key() {
+                    SelectionDrag::Lasso(vec![point])
+                } else {
+                    SelectionDrag::Marquee { from: point, to: point }
+                });
+            },
+        }
+    }
+
+    fn begin_transform(&mut self, handle: Option<[i8; 2]>, start: PagePoint) {
+        let Some(bounds) = self.selection_bounds() else { return };
+        let before = self.selected_annotations().cloned().collect();
+        self.selection_drag = Some(SelectionDrag::Transform { before, bounds, handle, start });
+    }
+
+    fn drag_selection(&mut self, ndc_x: f64, ndc_y: f64) {
+        let point = self.screen_to_page_unclamped(ndc_x, ndc_y);
+        let page = self.current_page;
+        match &mut self.selection_drag {
+            Some(SelectionDrag::Transform { before, bounds, handle, start }) => {
+                let (dx, dy) = (point.x - start.x, point.y - start.y);
+                let resized = handle.map(|sides| resize_box(*bounds, sides, dx, dy));
+                // Siempre a partir de cómo estaban al pulsar, para no acumular errores.
+                // El historial recibe el cambio entero al soltar (ver end_selection_drag)
+                // Las notas y textos se repintan solo donde estaban y donde quedan
+                let mut raster_area: Option<[f32; 4]> = None;
+                let mut strokes = false;
+                for original in before.iter() {
+                    let Some(annotation) = self.annotations.get_mut(page, original.id) else { continue };
+                    let old = annotation.kind.bounds();
+                    annotation.kind = original.kind.clone();
+                    match resized {
+                        Some(new_bounds) => annotation.kind.scale(*bounds, new_bounds),
+                        None => annotation.kind.translate(dx, dy),
+                    }
+                    if annotation.kind.is_stroke() {
+                        strokes = true;
+                    } else {
+                        let moved = merge_boxes(old, annotation.kind.bounds());
+                        raster_area = Some(raster_area.map_or(moved, |area| merge_boxes(area, moved)));
+                    }
+                }
+                if strokes {
+                    self.rebuild_strokes();
+                }
+                if let Some(area) = raster_area {
+                    self.repaint_overlay_area(area);
+                }
+            },
+            Some(SelectionDrag::Marquee { to, .. }) => *to = point,
+            Some(SelectionDrag::Lasso(points)) => points.push(point),
+            None => return,
+        }
+        self.hud_dirty = true;
+    }
+
+    // Al soltar: el movimiento pasa al historial, o el marco o el lazo eligen lo que queda dentro
+    fn end_selection_drag(&mut self) {
+        let polygon = match self.selection_drag.take() {
+            Some(SelectionDrag::Transform { before, .. }) => {
+                self.annotations.commit_replaced_all(self.current_page, before);
+                return;
+            },
+            Some(SelectionDrag::Marquee { from, to }) => vec![from, PagePoint { x: to.x, y: from.y }, to, PagePoint { x: from.x, y: to.y }],
+            Some(SelectionDrag::Lasso(points)) => points,
+            None => return,
+        };
+        self.hud_dirty = true; // Se borra el marco
+        if polygon.len() < 3 {
+            return;
+        }
+
+        let inside: Vec<u64> = self.annotations.page(self.current_page).iter()
+            .filter(|a| inside_polygon(a, &polygon))
+            .map(|a| a.id)
+            .collect();
+        let mut ids = if self.modifiers.shift_key() { self.selection.clone() } else { Vec::new() };
+        for id in inside {
+            if !ids.contains(&id) {
+                ids.push(id);
+            }
+        }
+        self.select(ids);
+    }
+
+    /// Borra las anotaciones seleccionadas (Supr o Retroceso) en un solo paso del historial.
+    fn delete_selection(&mut self) {
+        let ids = std::mem::take(&mut self.selection);
+        self.selection_drag = None;
+        if self.annotations.remove_all(self.current_page, &ids) > 0 {
+            self.rebuild_overlay();
+        }
+        self.hud_dirty = true;
+    }
+
+    /// Copia la selección (Ctrl+C); con `cut` además la borra (Ctrl+X).
+    fn copy_selection(&mut self, cut: bool) {
+        let annotations: Vec<Annotation> = self.selected_annotations().cloned().collect();
+        if annotations.is_empty() {
+            return;
+        }
+        log::debug!("Anotaciones copiadas: {}", annotations.len());
+        self.clipboard = Clipboard { annotations, occupied: (!cut).then_some(self.current_page) };
+        if cut {
+            self.delete_selection();
+        }
+    }
+
+    /// Pega lo copiado en la página actual (Ctrl+V) y lo deja seleccionado para colocarlo.
+    fn paste(&mut self) {
+        self.end_stroke();
+        self.finish_text_edit();
+        let Some(bounds) = union_bounds(self.clipboard.annotations.iter()) else { return };
+        let page = self.current_page;
+        let offset = if self.clipboard.occupied == Some(page) { PASTE_OFFSET } else { 0.0 };
+
+        // Dentro de la página aunque vengan de una más grande o el desplazamiento las saque
+        let (page_w, page_h) = self.page_size_pt();
+        let dx = offset.min(page_w - bounds[2]).max(-bounds[0]);
+        let dy = (-offset).max(-bounds[1]).min(page_h - bounds[3]);
+        let (author, now) = (local_author(), pdf_date_now());
+        let mut pasted = self.clipboard.annotations.clone();
+        for annotation in &mut pasted {
+            annotation.kind.translate(dx, dy);
+            annotation.author = author.clone();
+            annotation.modified = Some(now.clone());
+            annotation.name = None; // Es otra anotación: `insert_all` le da nombre propio
+        }
+
+        let ids = self.annotations.insert_all(page, pasted.clone());
+        // Pegar otra vez desplaza respecto a esta copia, en escalera
+        self.clipboard = Clipboard { annotations: pasted, occupied: Some(page) };
+        self.ui.active_tool = Tool::Select;
+        self.ui.last_annotation_tool = Tool::Select;
+        self.select(ids);
+        self.rebuild_overlay();
+    }
+
+    /// Elige todas las anotaciones de la página (Ctrl+A).
+    fn select_all(&mut self) {
+        self.ui.active_tool = Tool::Select;
+        self.ui.last_annotation_tool = Tool::Select;
+        self.select(self.annotations.page(self.current_page).iter().map(|a| a.id).collect());
+    }
+
+    // Click en la barra o la paleta. Con una anotación seleccionada, elegir color la recolorea
+    // (conservando su alpha: un resaltado sigue siendo translúcido)
+    fn click_ui(&mut self, x: f64, y: f64) -> bool {
+        let color = self.ui.settings().color;
+        if !self.ui.hit_test(x, y, self.gpu.size.width as f64, self.gpu.size.height as f64) {
+            return false;
+        }
+        let new_color = self.ui.settings().color;
+        if self.has_selection() && new_color != color {
+            self.annotations.update(self.current_page, &self.selection.clone(), |a| {
+                a.color = [new_color[0], new_color[1], new_color[2], a.color[3]];
+            });
+            self.rebuild_overlay();
+        }
+        true
+    }
+
+    /// Página -> píxel de pantalla (inversa de screen_to_page), para colocar popups.
+    fn page_to_screen(&self, point: PagePoint) -> (f32, f32) {
+        let (page_w, page_h) = self.page_size_pt();
+        let aspect = self.gpu.size.width as f32 / self.gpu.size.height as f32;
+        let x_cam = point.x / page_w * 2.0 - 1.0;
+        let y_cam = point.y / page_h * 2.0 - 1.0;
+        let ndc_x = x_cam * self.zoom + self.pan[0];
+        let ndc_y = y_cam * self.zoom * aspect + self.pan[1];
+        ((ndc_x + 1.0) * 0.5 * self.gpu.size.width as f32, (1.0 - ndc_y) * 0.5 * self.gpu.size.height as f32)
+    }
+
+    /// Nota o texto libre bajo el cursor (los de encima primero).
+    fn text_annotation_at(&self, ndc_x: f64, ndc_y: f64, free_text: bool) -> Option<u64> {
+        let point = self.screen_to_page(ndc_x, ndc_y)?;
+        self.annotations.page(self.current_page).iter().rev()
+            .filter(|a| matches!((&a.kind, free_text), (AnnotationKind::Note { .. }, false) | (AnnotationKind::FreeText { .. }, true)))
+            .find(|a| hit_test(a, point, 0.0))
+            .map(|a| a.id)
+    }
+
+    /// Con la herramienta de nota o de texto: editar la que haya bajo el cursor o crear una nueva.
+    fn place_text(&mut self, ndc_x: f64, ndc_y: f64, free_text: bool) {
+        if let Some(id) = self.text_annotation_at(ndc_x, ndc_y, free_text) {
+            self.begin_text_edit(id, false);
+            return;
+        }
+        let Some(point) = self.screen_to_page(ndc_x, ndc_y) else { return };
+        let settings = self.ui.settings();
+        let kind = if free_text {
+            AnnotationKind::FreeText { at: point, text: String::new(), size: settings.width }
+        } else {
+            // El icono queda centrado en el click
+            let at = PagePoint { x: point.x - NOTE_ICON_SIZE * 0.5, y: point.y + NOTE_ICON_SIZE * 0.5 };
+            AnnotationKind::Note { at, text: String::new() }
+        };
+        let id = self.annotations.add(self.current_page, kind, settings.color);
+        if let Some(annotation) = self.annotations.get(self.current_page, id) {
+            self.repaint_overlay_area(annotation.kind.bounds());
+        }
+        self.begin_text_edit(id, true);
+    }
+
+    fn begin_text_edit(&mut self, id: u64, is_new: bool) {
+        let before = if is_new { None } else { self.annotations.get(self.current_page, id).cloned() };
+        self.editing = Some(TextEdit { id, before });
+        self.hud_dirty = true;
+    }
+
+    /// Termina la edición en curso y la pasa al historial. Un texto que se queda vacío
+    /// se borra (si era nuevo, ni siquiera llega al historial). Devuelve si había algo abierto.
+    fn finish_text_edit(&mut self) -> bool {
+        let Some(edit) = self.editing.take() else { return false };
+        let page = self.current_page;
+        self.hud_dirty = true;
+        let Some(mut current) = self.annotations.get(page, edit.id).cloned() else { return true };
+        let empty = current.kind.text_mut().is_none_or(|text| text.trim().is_empty());
+
+        match (edit.before, empty) {
+            (None, true) => self.annotations.discard(page, edit.id),
+            (None, false) => self.annotations.commit_added(page, edit.id),
+            (Some(before), true) => {
+                self.annotations.revert_to(page, &before);
+                self.annotations.remove(page, edit.id);
+            },
+            (Some(before), false) => self.annotations.commit_replaced(page, before),
+        }
+        self.rebuild_overlay();
+        true
+    }
+
+    fn insert_text(&mut self, input: &str) {
+        let Some(edit) = &self.editing else { return };
+        let Some(annotation) = self.annotations.get_mut(self.current_page, edit.id) else { return };
+        let before = annotation.kind.bounds();
+        let Some(text) = annotation.kind.text_mut() else { return };
+        text.extend(input.chars().filter(|c| !c.is_control() || *c == '\n'));
+        let repaint = matches!(annotation.kind, AnnotationKind::FreeText { .. });
+        let after = annotation.kind.bounds();
+
+        self.hud_dirty = true;
+        if repaint {
+            // El texto crece o encoge: basta con la caja de antes unida a la de ahora
+            self.repaint_overlay_area(merge_boxes(before, after));
+        }
+    }
+
+    fn delete_last_char(&mut self) {
+        let Some(edit) = &self.editing else { return };
+        let Some(annotation) = self.annotations.get_mut(self.current_page, edit.id) else { return };
+        let before = annotation.kind.bounds();
+        let Some(text) = annotation.kind.text_mut() else { return };
+        text.pop();
+        let repaint = matches!(annotation.kind, AnnotationKind::FreeText { .. });
+        let after = annotation.kind.bounds();
+
+        self.hud_dirty = true;
+        if repaint {
+            // El texto crece o encoge: basta con la caja de antes unida a la de ahora
+            self.repaint_overlay_area(merge_boxes(before, after));
+        }
+    }
+
+    /// Teclado mientras se escribe: todo va al texto salvo Esc / Ctrl+Enter, que terminan.
+    fn edit_key(&mut self, event: &KeyEvent) -> bool {
+        if event.state != ElementState::Pressed {
+            return true;
+        }
+        let command = self.modifiers.control_key() || self.modifiers.super_key();
+        match &event.logical_key {
+            Key::Named(NamedKey::Escape) => { self.finish_text_edit(); },
+            Key::Named(NamedKey::Enter) if command => { self.finish_text_edit(); },
+            Key::Named(NamedKey::Enter) => self.insert_text("\n"),
+            Key::Named(NamedKey::Backspace) => self.delete_last_char(),
+            _ => {
+                if let (Some(text), false, false) = (&event.text, self.ime_active, command) {
+                    self.insert_text(text);
+                }
+            },
+        }
+        true
+    }
+
+    // Abre el campo de ruta con una propuesta: el propio documento, o su .xfdf al lado
+    fn open_prompt(&mut self, action: PromptAction) {
+        if action == PromptAction::CombineImages {
+            // No hace falta documento: se propone su carpeta, si hay uno abierto
+            let dir = self.source_path().and_then(|document| Path::new(document).parent()).filter(|dir| !dir.as_os_str().is_empty());
+            let path = dir.map_or_else(String::new, |dir| format!("{}{}", dir.to_string_lossy(), std::path::MAIN_SEPARATOR));
+            self.prompt = Some((action, path));
+            self.hud_dirty = true;
+            return;
+        }
+        if action == PromptAction::SaveImages {
+            // Junto a la primera imagen (o carpeta), con su nombre
+            let first = self.merge_inputs.first().map_or("imagenes", |input| input.trim_end_matches(['/', '\\']));
+            self.prompt = Some((action, format!("{}#auto:fit", Path::new(first).with_extension("pdf").to_string_lossy())));
+            self.hud_dirty = true;
+            return;
+        }
+        let Some(document) = self.source_path() else { return };
+        let path = match action {
+            // Una imagen no se puede sobrescribir como PDF: se propone el mismo nombre con .pdf
+            PromptAction::SaveAs if self.image_source.is_some() => Path::new(document).with_extension("pdf").to_string_lossy().into_owned(),
+            PromptAction::SaveAs => document.clone(),
+            PromptAction::ExportXfdf | PromptAction::ImportXfdf => Path::new(document).with_extension("xfdf").to_string_lossy().into_owned(),
+            PromptAction::ExportMarkdown => Path::new(document).with_extension("md").to_string_lossy().into_owned(),
+            PromptAction::RedactSearch => String::new(),
+            PromptAction::ApplyRedactions => {
+                let stem = Path::new(document).file_stem().unwrap_or_default().to_string_lossy();
+                Path::new(document).with_file_name(format!("{}-redacted.pdf", stem)).to_string_lossy().into_owned()
+            },
+            PromptAction::ExportFlattened => {
+                let stem = Path::new(document).file_stem().unwrap_or_default().to_string_lossy();
+                Path::new(document).with_file_name(format!("{}-flat.pdf", stem)).to_string_lossy().into_owned()
+            },
+            PromptAction::SaveOrganized => {
+                let stem = Path::new(document).file_stem().unwrap_or_default().to_string_lossy();
+                Path::new(document).with_file_name(format!("{}-organized.pdf", stem)).to_string_lossy().into_owned()
+            },
+            PromptAction::SaveMerged => {
+                let stem = Path::new(document).file_stem().unwrap_or_default().to_string_lossy();
+                Path::new(document).with_file_name(format!("{}-merged.pdf", stem)).to_string_lossy().into_owned()
+            },
+            // Se propone cortar por la página actual (sin rangos sale una por página)
+            PromptAction::Split if self.current_page + 1 < self.total_pages => {
+                format!("{}#1-{},{}-", document, self.current_page + 1, self.current_page + 2)
+            },
+            PromptAction::Split => document.clone(),
+            PromptAction::Impose => {
+                let stem = Path::new(document).file_stem().unwrap_or_default().to_string_lossy();
+                format!("{}#booklet", Path::new(document).with_file_name(format!("{}-booklet.pdf", stem)).to_string_lossy())
+            },
+            // La carpeta del documento, con la barra final: solo falta el nombre
+            PromptAction::ImportPages | PromptAction::MergeWith => match Path::new(document).parent().filter(|dir| !dir.as_os_str().is_empty()) {
+                Some(dir) => format!("{}{}", dir.to_string_lossy(), std::path::MAIN_SEPARATOR),
+                None => String::new(),
+            },
+            PromptAction::CombineImages | PromptAction::SaveImages => return,
+        };
+        self.prompt = Some((action, path));
+        self.hud_dirty = true;
+    }
+
+    /// Teclado del campo de ruta ("Guardar como", XFDF): Enter confirma, Esc cancela.
+    fn prompt_key(&mut self, event: &KeyEvent) -> bool {
+        if event.state != ElementState::Pressed {
+            return true;
+        }
+        let command = self.modifiers.control_key() || self.modifiers.super_key();
+        let Some((action, path)) = &mut self.prompt else { return false };
+        match &event.logical_key {
+            Key::Named(NamedKey::Escape) => self.prompt = None,
+            Key::Named(NamedKey::Enter) => {
+                let (action, path) = (*action, path.trim().to_string());
+                self.prompt = None;
+                let result = match action {
+                    PromptAction::SaveAs => self.save_as(&path),
+                    PromptAction::ExportXfdf => self.export_xfdf(Path::new(&path)),
+                    PromptAction::ImportXfdf => self.import_xfdf(Path::new(&path)),
+                    PromptAction::ExportMarkdown => self.export_markdown(Path::new(&path)),
+                    PromptAction::RedactSearch => self.mark_redactions(&path),
+                    PromptAction::ApplyRedactions => self.apply_redactions(Path::new(&path)),
+                    // "ruta.png#1-3,5": las páginas van detrás de la almohadilla, como en la línea de comandos
+                    PromptAction::ExportFlattened => {
+                        let (file, pages) = split_options(&path, is_page_ranges);
+                        self.export_flattened(Path::new(file), pages)
+                    },
+                    PromptAction::ImportPages => {
+                        let (file, pages) = split_options(&path, is_page_ranges);
+                        self.import_pages(Path::new(file), pages)
+                    },
+                    PromptAction::SaveOrganized => self.save_organized(Path::new(&path)),
+                    PromptAction::MergeWith => self.choose_merge_inputs(&path, PromptAction::SaveMerged),
+                    PromptAction::SaveMerged => {
+                        let others = std::mem::take(&mut self.merge_inputs);
+                        self.merge_documents(&others, Path::new(&path))
+                    },
+                    PromptAction::Split => {
+                        let (file, ranges) = split_options(&path, is_page_ranges);
+                        self.split_document(Path::new(file), ranges)
+                    },
+                    // "salida.pdf#booklet:a4": imposición y, si se quiere, papel, detrás de la almohadilla
+                    PromptAction::Impose => {
+                        let (file, spec) = split_options(&path, |spec| impose_options(spec).is_ok());
+                        impose_options(spec.unwrap_or("2up")).map_err(|e| anyhow!(e))
+                            .and_then(|(layout, sheet)| self.export_imposed(Path::new(file), layout, sheet))
+                    },
+                    PromptAction::CombineImages => self.choose_merge_inputs(&path, PromptAction::SaveImages),
+                    // "salida.pdf#a4:fill": papel y ajuste detrás de la almohadilla, como en Imponer
+                    PromptAction::SaveImages => {
+                        let inputs = std::mem::take(&mut self.merge_inputs);
+                        let (file, spec) = split_options(&path, |spec| image_options(spec).is_ok());
+                        let (page, fit) = spec.map_or((None, None), |spec| {
+                            spec.split_once(':').map_or((Some(spec), None), |(page, fit)| (Some(page), Some(fit)))
+                        });
+                        self.combine_images(&inputs, Path::new(file), page, fit)
+                    },
+                };
+                if let Err(e) = result {
+                    self.notify(format!("Error: {:#}", e));
+                }
+            },
+            Key::Named(NamedKey::Backspace) => { path.pop(); },
+            _ => {
+                if let (Some(text), false, false) = (&event.text, self.ime_active, command) {
+                    path.extend(text.chars().filter(|c| !c.is_control()));
+                }
+            },
+        }
+        self.hud_dirty = true;
+        true
+    }
+
+    /// Muestra un aviso arriba durante unos segundos (y lo deja en el log): los fallos que el usuario
+    /// debe ver, sin consola a la que mirar.
+    pub fn notify(&mut self, message: String) {
+        log::warn!("{}", message);
+        self.notice = Some((message, Instant::now()));
+        self.hud_dirty = true;
+    }
+
+    // Repinta el HUD si cambió lo que muestra o la cámara (los popups siguen a su nota)
+    fn refresh_hud(&mut self) {
+        if self.notice.as_ref().is_some_and(|(_, since)| since.elapsed() > NOTICE_DURATION) {
+            self.notice = None;
+            self.hud_dirty = true;
+        }
+        let view = [self.zoom, self.pan[0], self.pan[1], self.gpu.size.width as f32 * self.gpu.size.height as f32];
+        let selected = self.selection_bounds().filter(|_| self.ui.active_tool == Tool::Select);
+        let redacting = self.ui.active_tool == Tool::Redact;
+        let organizing = self.carousel.is_some() && !self.ui.presentation;
+        // Sin editar, el texto de una nota se lee al pasar por encima o al seleccionarla (también con el dedo)
+        let selected_note = match self.selection.as_slice() {
+            [id] => Some(*id),
+            _ => None,
+        };
+        let read_note = self.hovered_note.or(selected_note).filter(|_| self.editing.is_none())
+            .and_then(|id| self.annotations.get(self.current_page, id))
+            .and_then(|a| match &a.kind {
+                AnnotationKind::Note { at, text } => Some((*at, text.clone())),
+                _ => None,
+            });
+        let wanted = self.editing.is_some() || read_note.is_some() || self.prompt.is_some() || selected.is_some() || self.selection_drag.is_some() || self.summary.is_some() || redacting || organizing || self.notice.is_some();
+        if wanted == self.hud_visible && !self.hud_dirty && (!wanted || view == self.hud_view) {
+            return;
+        }
+
+        self.hud.clear();
+        let editing = self.editing.as_ref().and_then(|edit| self.annotations.get(self.current_page, edit.id));
+        match editing.map(|a| &a.kind) {
+            Some(AnnotationKind::Note { at, text }) => {
+                let anchor = self.page_to_screen(PagePoint { x: at.x + NOTE_ICON_SIZE, y: at.y });
+                self.hud.note_popup(anchor, text, true);
+            },
+            Some(AnnotationKind::FreeText { .. }) if self.notice.is_none() => self.hud.hint("Escribiendo texto - Esc para terminar"),
+            _ => {},
+        }
+        if let Some((at, text)) = &read_note {
+            let anchor = self.page_to_screen(PagePoint { x: at.x + NOTE_ICON_SIZE, y: at.y });
+            self.hud.note_popup(anchor, text, false);
+        }
+        if let Some(bounds) = selected {
+            // Con varias, un marco por anotación y las asas en el que las envuelve a todas
+            let mut frames: Vec<_> = self.selected_annotations().map(|a| self.screen_box(a.kind.bounds())).collect();
+            if frames.len() == 1 {
+                frames.clear();
+            }
+            let (left, top, right, bottom) = self.screen_box(bounds);
+            for (x0, y0, x1, y1) in frames {
+                self.hud.selection_box(x0, y0, x1, y1, false);
+            }
+            self.hud.selection_box(left, top, right, bottom, true);
+        }
+        match &self.selection_drag {
+            Some(SelectionDrag::Marquee { from, to }) => {
+                let ((x0, y0), (x1, y1)) = (self.page_to_screen(*from), self.page_to_screen(*to));
+                self.hud.selection_box(x0, y0, x1, y1, false);
+            },
+            Some(SelectionDrag::Lasso(points)) => {
+                let points: Vec<_> = points.iter().map(|p| self.page_to_screen(*p)).collect();
+                self.hud.lasso(&points);
+            },
+            _ => {},
+        }
+        if redacting {
+            let page = self.current_page;
+            let drag = self.redaction_drag.map(|(from, to)| [from.x.min(to.x), from.y.min(to.y), from.x.max(to.x), from.y.max(to.y)]);
+            let marks: Vec<_> = self.redactions.iter().filter(|(p, _)| *p == page).map(|(_, rect)| *rect).chain(drag).collect();
+            for rect in marks {
+                let (left, top, right, bottom) = self.screen_box(rect);
+                self.hud.redaction_box(left, top, right, bottom);
+            }
+            if self.prompt.is_none() && self.notice.is_none() {
+                self.hud.hint(&format!("Redacción: {} marcas - arrastra para marcar, click para quitar, Ctrl+F busca, Enter aplica, Esc sale", self.redactions.len()));
+            }
+        }
+        if let Some(panel) = &mut self.summary {
+            (panel.hits, panel.max_scroll) = self.hud.summary_panel(&panel.entries, panel.scroll);
+        }
+        if organizing {
+            self.fill_thumbnails();
+            let (bottom, (_, y)) = (self.carousel_bottom(), self.cursor_pixel());
+            if let Some(organizer) = &mut self.carousel {
+                let drop = organizer.drag.map(|from| (from, organizer.gap_at(y))).filter(|&(from, gap)| gap != from && gap != from + 1).map(|(_, gap)| gap);
+                organizer.max_scroll = self.hud.carousel(organizer, self.ui.side_panel_width as i32, bottom, drop);
+                organizer.scroll = organizer.scroll.min(organizer.max_scroll);
+                if self.prompt.is_none() && !redacting && self.notice.is_none() {
+                    self.hud.hint(&format!("Páginas: {} - arrastra para ordenar, R gira, Supr quita, B en blanco, Ctrl+I importa, Ctrl+S guarda", organizer.pages.len()));
+                }
+            }
+        }
+        if let Some((action, path)) = &self.prompt {
+            self.hud.prompt(action.title(), path);
+        } else if let Some((message, _)) = &self.notice {
+            self.hud.hint(message);
+        }
+
+        self.gpu.queue.write_texture(
+            wgpu::ImageCopyTexture { texture: &self.hud_texture.texture, mip_level: 0, origin: wgpu::Origin3d::ZERO, aspect: wgpu::TextureAspect::All },
+            &self.hud.data,
+            wgpu::ImageDataLayout { offset: 0, bytes_per_row: Some(4 * self.hud.width), rows_per_image: Some(self.hud.height) },
+            wgpu::Extent3d { width: self.hud.width, height: self.hud.height, depth_or_array_layers: 1 }
+        );
+        self.hud_visible = wanted;
+        self.hud_dirty = false;
+        self.hud_view = view;
+    }
+
+    /// Deshace (o rehace) el último cambio en las anotaciones y refresca lo que se ve.
+    /// Si el cambio era de otra página, saltamos a ella para que se note qué pasó.
+    pub fn undo(&mut self, redo: bool) {
+        self.end_stroke();
+        self.finish_text_edit();
+        let page = if redo { self.annotations.redo() } else { self.annotations.undo() };
+        self.hud_dirty = true; // El marco de la selección sigue a la anotación
+        match page {
+            Some(page) if page == self.current_page => self.rebuild_overlay(),
+            Some(page) => self.go_to_page(page),
+            None => log::debug!("Nada que {}", if redo { "rehacer" } else { "deshacer" }),
+        }
+    }
+
+    pub fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>) {
+        self.gpu.resize(new_size);
+
+        // El HUD mide lo mismo que la ventana
+        let (width, height) = (self.gpu.size.width.max(1), self.gpu.size.height.max(1));
+        if (width, height) != (self.hud.width, self.hud.height) {
+            self.strokes.resize(&self.gpu.device, width, height);
+            self.rebuild_bind_group();
+            self.hud.resize(width, height);
+            self.hud_texture = Texture::from_bytes(&self.gpu.device, &self.gpu.queue, &self.hud.data, width, height, Some("HUD")).unwrap();
+            self.hud_bind_group = create_hud_bind_group(&self.gpu.device, &self.hud_bg_layout, &self.hud_texture);
+            self.hud_dirty = true;
+        }
+    }
+
+    pub fn input(&mut self, event: &WindowEvent) -> bool {
+        // Mientras se escribe una nota o un texto, el teclado es para el texto
+        if self.editing.is_some() {
+            match event {
+                WindowEvent::KeyboardInput { event, .. } => return self.edit_key(event),
+                WindowEvent::Ime(Ime::Commit(text)) => {
+                    self.insert_text(text);
+                    return true;
+                },
+                _ => {},
+            }
+        }
+        if self.prompt.is_some() {
+            match event {
+                WindowEvent::KeyboardInput { event, .. } => return self.prompt_key(event),
+                WindowEvent::Ime(Ime::Commit(text)) => {
+                    if let Some((_, path)) = &mut self.prompt {
+                        path.push_str(text);
+                    }
+                    self.hud_dirty = true;
+                    return true;
+                },
+                _ => {},
+            }
+        }
+
+        match event {
+            WindowEvent::Ime(Ime::Enabled) => {
+                self.ime_active = true;
+                false
+            },
+            WindowEvent::Ime(Ime::Disabled) => {
+                self.ime_active = false;
+                false
+            },
+            WindowEvent::MouseInput { state, button: MouseButton::Left, .. } => {
+                let pressed = *state == ElementState::Pressed;
+                self.mouse_pressed = pressed;
+                self.pen_pressure = 1.0;
+                if !pressed {
+                    self.end_stroke();
+                    self.end_carousel_drag();
+                }
+                
+                if pressed {
+                    // 1. Chequear UI (un click en la barra, el carrusel o el panel de resumen no pinta ni arrastra)
+                    if self.click_carousel() || self.click_summary() || self.click_ui(self.last_mouse_pos[0], self.last_mouse_pos[1]) {
+                        self.mouse_pressed = false;
+                        return true; 
+                    }
+                    // 2. Un click fuera del texto que se está escribiendo solo lo cierra
+                    if self.finish_text_edit() {
+                        self.mouse_pressed = false;
+                        return true;
+                    }
+                    // 3. Las notas se abren con un click aunque no esté activa su herramienta
+                    if matches!(self.ui.active_tool, Tool::None | Tool::Pan) {
+                        if let Some(id) = self.text_annotation_at(self.last_mouse_pos[0], self.last_mouse_pos[1], false) {
+                            self.mouse_pressed = false;
+                            self.begin_text_edit(id, false);
+                            return true;
+                        }
+                    }
+                    self.tool_pressed(self.last_mouse_pos[0], self.last_mouse_pos[1]);
+                }
+                true
+            },
+            WindowEvent::CursorMoved { position, .. } => {
+                // Normalizado -1 a 1
+                let x = (position.x / self.gpu.size.width as f64) * 2.0 - 1.0;
+                let y = -((position.y / self.gpu.size.height as f64) * 2.0 - 1.0);
+                
+                let dx = x - self.last_mouse_pos[0];
+                let dy = y - self.last_mouse_pos[1];
+                self.last_mouse_pos = [x, y];
+
+                if self.mouse_pressed {
+                    self.tool_dragged(x, y, dx, dy);
+                }
+                if self.carousel.as_ref().is_some_and(|organizer| organizer.drag.is_some()) {
+                    self.hud_dirty = true; // La marca de dónde caería sigue al cursor
+                }
+                let hovered = if self.mouse_pressed { None } else { self.text_annotation_at(x, y, false) };
+                if hovered != self.hovered_note {
+                    self.hovered_note = hovered;
+                    self.hud_dirty = true;
+                }
+                true
+            },
+            WindowEvent::Touch(Touch { phase, location, force, .. }) => {
+                // Lápiz o dedo: se comporta como el botón izquierdo, pero con presión
+                let x = (location.x / self.gpu.size.width as f64) * 2.0 - 1.0;
+                let y = -((location.y / self.gpu.size.height as f64) * 2.0 - 1.0);
+                let (dx, dy) = (x - self.last_mouse_pos[0], y - self.last_mouse_pos[1]);
+                self.pen_pressure = force.map(|f| f.normalized() as f32).unwrap_or(1.0);
+                self.last_mouse_pos = [x, y];
+
+                match phase {
+                    TouchPhase::Started => {
+                        let on_ui = self.click_carousel() || self.click_summary() || self.click_ui(x, y);
+                        self.mouse_pressed = !on_ui;
+                        if !on_ui {
+                            self.tool_pressed(x, y);
+                        }
+                    },
+                    TouchPhase::Moved => {
+                        if self.mouse_pressed {
+                            self.tool_dragged(x, y, dx, dy);
+                        }
+                        if self.carousel.as_ref().is_some_and(|organizer| organizer.drag.is_some()) {
+                            self.hud_dirty = true;
+                        }
+                    },
+                    TouchPhase::Ended | TouchPhase::Cancelled => {
+                        self.mouse_pressed = false;
+                        self.end_stroke();
+                        self.end_carousel_drag();
+                    },
+                }
+                true
+            },
+            WindowEvent::MouseWheel { delta, .. } if self.cursor_on_carousel() => {
+                let lines = match delta { MouseScrollDelta::LineDelta(_, y) => *y * 3.0, MouseScrollDelta::PixelDelta(pos) => pos.y as f32 / 20.0 };
+                if let Some(organizer) = &mut self.carousel {
+                    organizer.scroll = (organizer.scroll - (lines * 20.0) as i32).clamp(0, organizer.max_scroll);
+                    self.hud_dirty = true;
+                }
+                true
+            },
+            WindowEvent::MouseWheel { delta, .. } if self.cursor_on_summary() => {
+                let lines = match delta { MouseScrollDelta::LineDelta(_, y) => *y * 3.0, MouseScrollDelta::PixelDelta(pos) => pos.y as f32 / 20.0 };
+                if let Some(panel) = &mut self.summary {
+                    panel.scroll = (panel.scroll - (lines * 20.0) as i32).clamp(0, panel.max_scroll);
+                    self.hud_dirty = true;
+                }
+                true
+            },
+            WindowEvent::MouseWheel { delta, .. } => {
+                let scroll = match delta { MouseScrollDelta::LineDelta(_, y) => *y * 0.1, MouseScrollDelta::PixelDelta(pos) => pos.y as f32 * 0.001 };
+                self.set_zoom(self.zoom + scroll);
+                true
+            },
+            WindowEvent::ModifiersChanged(modifiers) => {
+                self.modifiers = modifiers.state();
+                false
+            },
+            WindowEvent::KeyboardInput { event: KeyEvent { state: ElementState::Pressed, physical_key: PhysicalKey::Code(keycode), .. }, .. } => {
+                // Ctrl (Cmd en macOS) + Z deshace; con Mayúsculas, rehace
+                // Ctrl + S guarda en el mismo archivo; con Mayúsculas, "Guardar como"
+                // Ctrl + E exporta las anotaciones a XFDF; con Mayúsculas, exporta aplanado (PDF o PNG)
+                // Ctrl + I importa un XFDF
+                // Ctrl + L abre el resumen de anotaciones; Ctrl + M lo exporta a Markdown
+                // Ctrl + J une otros PDF detrás del documento; Ctrl + D lo divide por rangos de páginas
+                // Ctrl + P exporta para imprimir con varias páginas por hoja (2-up, 4-up o cuadernillo)
+                // Ctrl + A/C/X/V eligen todas, copian, cortan y pegan anotaciones
+                // Ctrl + R entra o sale del modo de redacción; dentro, Ctrl + F marca un texto y Enter aplica
+                // Con el carrusel abierto (organizador): R gira (con Mayúsculas, al revés), Supr quita,
+                // B añade una página en blanco, Ctrl + I importa páginas y Ctrl + S guarda como archivo nuevo
+                let command = self.modifiers.control_key() || self.modifiers.super_key();
+                let redacting = self.ui.active_tool == Tool::Redact;
+                let organizing = self.carousel.is_some() && !self.ui.presentation;
+                match keycode {
+                    KeyCode::KeyI if command && organizing => {
+                        self.open_prompt(PromptAction::ImportPages);
+                        true
+                    },
+                    KeyCode::KeyS if command && organizing => {
+                        self.open_prompt(PromptAction::SaveOrganized);
+                        true
+                    },
+                    KeyCode::KeyR if command && self.document_path.is_some() => {
+                        self.toggle_redaction();
+                        true
+                    },
+                    KeyCode::KeyF if command && redacting => {
+                        self.open_prompt(PromptAction::RedactSearch);
+                        true
+                    },
+                    KeyCode::Enter | KeyCode::NumpadEnter if redacting && !self.redactions.is_empty() => {
+                        self.open_prompt(PromptAction::ApplyRedactions);
+                        true
+                    },
+                    KeyCode::Escape if redacting => {
+                        self.toggle_redaction();
+                        true
+                    },
+                    KeyCode::KeyZ if command => {
+                        self.undo(self.modifiers.shift_key());
+                        true
+                    },
+                    KeyCode::KeyS if command && self.document_path.is_some() => {
+                        if self.modifiers.shift_key() {
+                            self.open_prompt(PromptAction::SaveAs);
+                        } else if self.image_source.is_some() && self.annotation_storage == AnnotationStorage::Pdf {
+                            self.open_prompt(PromptAction::SaveAs); // Una imagen se guarda como PDF nuevo
+                        } else if let Err(e) = self.save() {
+                            self.notify(format!("Error al guardar: {:#}", e));
+                        }
+                        true
+                    },
+                    KeyCode::KeyE if command && self.document_path.is_some() => {
+                        self.open_prompt(if self.modifiers.shift_key() { PromptAction::ExportFlattened } else { PromptAction::ExportXfdf });
+                        true
+                    },
+                    KeyCode::KeyI if command && self.document_path.is_some() => {
+                        self.open_prompt(PromptAction::ImportXfdf);
+                        true
+                    },
+                    KeyCode::KeyL if command => {
+                        self.toggle_summary();
+                        true
+                    },
+                    KeyCode::KeyJ if command && self.modifiers.shift_key() => {
+                        self.open_prompt(PromptAction::CombineImages);
+                        true
+                    },
+                    KeyCode::KeyJ if command && self.document_path.is_some() => {
+                        self.open_prompt(PromptAction::MergeWith);
+                        true
+                    },
+                    KeyCode::KeyP if command && self.document_path.is_some() => {
+                        self.open_prompt(PromptAction::Impose);
+                        true
+                    },
+                    KeyCode::KeyD if command && self.document_path.is_some() => {
+                        self.open_prompt(PromptAction::Split);
+                        true
+                    },
+                    KeyCode::KeyM if command && self.document_path.is_some() => {
+                        self.open_prompt(PromptAction::ExportMarkdown);
+                        true
+                    },
+                    KeyCode::KeyA if command && self.document.is_some() => {
+                        self.select_all();
+                        true
+                    },
+                    KeyCode::KeyC | KeyCode::KeyX if command && self.has_selection() => {
+                        self.copy_selection(*keycode == KeyCode::KeyX);
+                        true
+                    },
+                    KeyCode::KeyV if command && !self.clipboard.annotations.is_empty() => {
+                        self.paste();
+                        true
+                    },
+                    KeyCode::Delete | KeyCode::Backspace if self.has_selection() => {
+                        self.delete_selection();
+                        true
+                    },
+                    KeyCode::Escape if self.has_selection() => {
+                        self.select(Vec::new());
+                        true
+                    },
+                    KeyCode::KeyR if organizing && !command => {
+                        let clockwise = !self.modifiers.shift_key();
+                        self.edit_carousel(|organizer| organizer.rotate_selected(clockwise));
+                        true
+                    },
+                    KeyCode::Delete | KeyCode::Backspace if organizing => {
+                        self.edit_carousel(Carousel::delete_selected);
+                        true
+                    },
+                    KeyCode::KeyB if organizing && !command => {
+                        self.edit_carousel(Carousel::insert_blank);
+                        true
+                    },
+                    KeyCode::ArrowUp | KeyCode::ArrowDown if organizing => {
+                        self.select_carousel_page(*keycode == KeyCode::ArrowDown);
+                        true
+                    },
+                    KeyCode::Escape if organizing => {
+                        self.ui.is_carousel_open = false;
+                        self.sync_carousel();
+                        true
+                    },
+                    KeyCode::Escape if self.summary.is_some() => {
+                        self.toggle_summary();
+                        true
+                    },
+                    KeyCode::ArrowRight => {
+                        if self.current_page < self.total_pages - 1 {
+                            self.load_page(self.current_page + 1);
+                        }
+                        true
+                    },
+                    KeyCode::ArrowLeft => {
+                        if self.current_page > 0 {
+                            self.load_page(self.current_page - 1);
+                        }
+                        true
+                    },
+                    _ => false,
+                }
+            },
+            _ => false,
+        }
+    }
+
+    pub fn update(&mut self) {
+        // Mantener el aspect ratio correcto del PDF
+        let aspect = self.gpu.size.width as f32 / self.gpu.size.height as f32;
+        self.camera_uniform.scale = [self.zoom, self.zoom * aspect]; 
+        self.camera_uniform.translation = self.pan;
+
+        // Si el zoom cambió bastante, el overlay se vuelve a rasterizar a la nueva resolución
+        // (con margen para no regenerarlo en cada paso de la rueda). Los trazos no: son vectoriales
+        let ratio = self.desired_overlay_scale() / self.overlay_scale;
+        if !(0.8..=1.25).contains(&ratio) {
+            self.rasterize_overlay();
+        }
+        self.flush_overlay();
+        self.sync_carousel();
+        self.camera_uniform.ui_flags[0] = if self.ui.is_carousel_open { 1.0 } else { 0.0 };
+        self.camera_uniform.ui_flags[1] = if self.ui.presentation { 1.0 } else { 0.0 };
+        self.camera_uniform.screen = [self.gpu.size.width as f32, self.gpu.size.height as f32];
+
+        // Paleta: qué casillas resaltar y la vista previa del grosor (en px de pantalla)
+        let settings = self.ui.settings();
+        let (page_w, _) = self.page_size_pt();
+        let px_per_point = self.gpu.size.width as f32 * self.zoom / page_w.max(1.0);
+        self.camera_uniform.tool = [
+            self.ui.active_tool_slot().map_or(-1.0, |s| s as f32),
+            self.ui.active_color_slot().map_or(-1.0, |s| s as f32),
+            if self.ui.active_tool.is_annotation_tool() { 1.0 } else { 0.0 },
+            settings.width * px_per_point,
+        ];
+        self.camera_uniform.tool_color = settings.color.map(|c| c as f32 / 255.0);
+        self.gpu.queue.write_buffer(&self.camera_buffer, 0, bytemuck::cast_slice(&[self.camera_uniform]));
+        self.strokes.set_camera(&self.gpu.queue, self.camera_uniform.scale, self.pan, self.page_size_pt());
+        self.refresh_summary();
+        self.refresh_hud();
+        self.autosave();
+    }
+
+    pub fn size(&self) -> winit::dpi::PhysicalSize<u32> { self.gpu.size }
+
+    pub fn render(&mut self) -> Result<(), wgpu::SurfaceError> {
+        let output = self.gpu.surface.get_current_texture()?;
+        let view = output.texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let mut encoder = self.gpu.device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("Render Encoder") });
+
+        // Primero los trazos en su textura: la pasada principal los compone sobre la página
+        self.strokes.render(&mut encoder);
+
+        {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Render Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &view,
+                    resolve_target: None,
+                    ops: wgpu::Operations { load: wgpu::LoadOp::Clear(wgpu::Color { r: 0.05, g: 0.05, b: 0.08, a: 1.0 }), store: wgpu::StoreOp::Store },
+                })],
+                depth_stencil_attachment: None,
+                occlusion_query_set: None,
+                timestamp_writes: None,
+            });
+
+            render_pass.set_pipeline(&self.render_pipeline);
+            render_pass.set_bind_group(0, &self.diffuse_bind_group, &[]);
+            render_pass.set_bind_group(1, &self.camera_bind_group, &[]);
+            render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+            render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
+            render_pass.draw_indexed(0..self.num_indices, 0, 0..1);
+
+            if self.hud_visible {
+                render_pass.set_pipeline(&self.hud_pipeline);
+                render_pass.set_bind_group(0, &self.hud_bind_group, &[]);
+                render_pass.draw(0..3, 0..1);
+            }
+        }
+
+        self.gpu.queue.submit(std::iter::once(encoder.finish()));
+        output.present();
+        Ok(())
+    }
+}
+
+fn create_hud_bind_group(device: &wgpu::Device, layout: &wgpu::BindGroupLayout, texture: &Texture) -> wgpu::BindGroup {
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        layout,
+        entries: &[
+            wgpu::BindGroupEntry { binding: 0, resource: wgpu::BindingResource::TextureView(&texture.view) },
+            wgpu::BindGroupEntry { binding: 1, resource: wgpu::BindingResource::Sampler(&texture.sampler) },
+        ],
+        label: Some("HUD BG"),
+    })
+}
+
+fn create_fallback() -> crate::pdf::render::PageBitmap {
+    crate::pdf::render::PageBitmap { width: 1, height: 1, data: vec![0, 0, 0, 255] }
+}
+
+// Separa "ruta#opciones" en los campos de ruta. La almohadilla solo abre las opciones si lo que va
+// detrás se entiende (`options`) y la ruta entera no es un archivo; si no, es parte del nombre
+// ("informe #3.pdf"), como en la línea de comandos
+fn split_options(path: &str, options: impl Fn(&str) -> bool) -> (&str, Option<&str>) {
+    match path.rsplit_once('#') {
+        Some((file, spec)) if options(spec) && !Path::new(path).exists() => (file, Some(spec)),
+        _ => (path, None),
+    }
+}
+
+// Rangos bien escritos, aunque aún no se sepa cuántas páginas tiene el documento
+fn is_page_ranges(spec: &str) -> bool {
+    parse_page_ranges(spec, u16::MAX).is_ok()
+}
+
+// "booklet:a4": imposición y, si se da, papel
+fn impose_options(spec: &str) -> Result<(Layout, Option<&str>), String> {
+    let (layout, sheet) = spec.split_once(':').map_or((spec, None), |(layout, sheet)| (layout, Some(sheet)));
+    if let Some(sheet) = sheet {
+        parse_paper_size(sheet)?;
+    }
+    Ok((Layout::parse(layout)?, sheet))
+}
+
+// "a4:fill": papel de las páginas y, si se da, ajuste de las imágenes
+fn image_options(spec: &str) -> Result<(), String> {
+    let (page, fit) = spec.split_once(':').map_or((spec, None), |(page, fit)| (page, Some(fit)));
+    parse_image_page(page)?;
+    fit.map_or(Ok(()), |fit| Fit::parse(fit).map(|_| ()))
+}
+
+// Caja que envuelve varias anotaciones ([left, bottom, right, top]). None si no hay ninguna.
+fn union_bounds<'b>(annotations: impl Iterator<Item = &'b Annotation>) -> Option<[f32; 4]> {
+    annotations.map(|a| a.kind.bounds()).reduce(merge_boxes)
+}
+
+fn merge_boxes(a: [f32; 4], b: [f32; 4]) -> [f32; 4] {
+    [a[0].min(b[0]), a[1].min(b[1]), a[2].max(b[2]), a[3].max(b[3])]
+}
+
+// Caja con los lados que mueve un asa desplazados (dx, dy), sin que llegue a darse la vuelta
+fn resize_box([mut left, mut bottom, mut right, mut top]: [f32; 4], sides: [i8; 2], dx: f32, dy: f32) -> [f32; 4] {
+    match sides[0] {
+        -1 => left = (left + dx).min(right - MIN_SELECTION_SIDE),
+        1 => right = (right + dx).max(left + MIN_SELECTION_SIDE),
+        _ => {},
+    }
+    match sides[1] {
+        -1 => bottom = (bottom + dy).min(top - MIN_SELECTION_SIDE),
+        1 => top = (top + dy).max(bottom + MIN_SELECTION_SIDE),
+        _ => {},
+    }
+    [left, bottom, right, top]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resize_box_table() {
+        let rect = [10.0, 20.0, 50.0, 60.0];
+        let cases = [
+            ([0, 0], 5.0, 5.0, rect),
+            ([1, 0], 15.0, 99.0, [10.0, 20.0, 65.0, 60.0]),
+            ([-1, 0], -5.0, 0.0, [5.0, 20.0, 50.0, 60.0]),
+            ([0, 1], 0.0, 10.0, [10.0, 20.0, 50.0, 70.0]),
+            ([0, -1], 0.0, -10.0, [10.0, 10.0, 50.0, 60.0]),
+            ([1, 1], 5.0, 5.0, [10.0, 20.0, 55.0, 65.0]),
+            ([-1, -1], 2.0, 3.0, [12.0, 23.0, 50.0, 60.0]),
+            // Arrastrar un lado más allá del opuesto no da la vuelta a la caja: se queda en el mínimo
+            ([1, 0], -100.0, 0.0, [10.0, 20.0, 10.0 + MIN_SELECTION_SIDE, 60.0]),
+            ([-1, 0], 100.0, 0.0, [50.0 - MIN_SELECTION_SIDE, 20.0, 50.0, 60.0]),
+            ([0, 1], 0.0, -100.0, [10.0, 20.0, 50.0, 20.0 + MIN_SELECTION_SIDE]),
+            ([0, -1], 0.0, 100.0, [10.0, 60.0 - MIN_SELECTION_SIDE, 50.0, 60.0]),
+        ];
+        for (sides, dx, dy, expected) in cases {
+            assert_eq!(resize_box(rect, sides, dx, dy), expected, "{:?} {} {}", sides, dx, dy);
+        }
+    }
+
+    #[test]
+    fn hash_suffix_is_only_options_when_it_parses() {
+        assert_eq!(split_options("salida.png#1-3,5", is_page_ranges), ("salida.png", Some("1-3,5")));
+        assert_eq!(split_options("salida.png#2-", is_page_ranges), ("salida.png", Some("2-")));
+        assert_eq!(split_options("informe #3.pdf", is_page_ranges), ("informe #3.pdf", None));
+        assert_eq!(split_options("a#b/c.pdf#1", is_page_ranges), ("a#b/c.pdf", Some("1")));
+        assert_eq!(split_options("plano.pdf", is_page_ranges), ("plano.pdf", None));
+
+        let impose = |spec: &str| impose_options(spec).is_ok();
+        assert_eq!(split_options("libro.pdf#booklet:a4", impose), ("libro.pdf", Some("booklet:a4")));
+        assert_eq!(split_options("libro.pdf#booklet:folio", impose), ("libro.pdf#booklet:folio", None));
+        assert_eq!(split_options("tomo #2.pdf", impose), ("tomo #2.pdf", None));
+
+        let images = |spec: &str| image_options(spec).is_ok();
+        assert_eq!(split_options("fotos.pdf#a4:fill", images), ("fotos.pdf", Some("a4:fill")));
+        assert_eq!(split_options("fotos #1.pdf", images), ("fotos #1.pdf", None));
     }
-}
 
-fn create_fallback() -> crate::pdf::render::PageBitmap {
-    crate::pdf::render::PageBitmap { width: 1, height: 1, data: vec![0, 0, 0, 255] }
 }
@@ -13,13 +13,11 @@ impl IconGenerator {
                 let dist = (dx*dx + dy*dy).sqrt();
                 let idx = ((y * size + x) * 4) as usize;
                 
-                // Círculo de la lupa
-                if (dist - radius).abs() < 2.0 {
+                // Círculo de la lupa y su mango
+                let ring = (dist - radius).abs() < 2.0;
+                let handle = x as f32 > center + radius * 0.5 && y as f32 > center + radius * 0.5 && (x as f32 - y as f32).abs() < 3.0;
+                if ring || handle {
                     data[idx] = 255; data[idx+1] = 255; data[idx+2] = 255; data[idx+3] = 255;
-                } 
-                // Mango de la lupa
-                else if x as f32 > center + radius * 0.5 && y as f32 > center + radius * 0.5 && (x as f32 - y as f32).abs() < 3.0 {
-                     data[idx] = 255; data[idx+1] = 255; data[idx+2] = 255; data[idx+3] = 255;
                 }
             }
         }
@@ -46,10 +44,9 @@ impl IconGenerator {
             for x in 0..size {
                 let idx = ((y * size + x) * 4) as usize;
                 // Tres líneas horizontales
-                if (y > size/4 && y < size/4 + 4) || (y > size/2 && y < size/2 + 4) || (y > size*3/4 && y < size*3/4 + 4) {
-                     if x > size/4 && x < size*3/4 {
-                        data[idx] = 255; data[idx+1] = 255; data[idx+2] = 255; data[idx+3] = 255;
-                     }
+                let line = (y > size/4 && y < size/4 + 4) || (y > size/2 && y < size/2 + 4) || (y > size*3/4 && y < size*3/4 + 4);
+                if line && x > size/4 && x < size*3/4 {
+                    data[idx] = 255; data[idx+1] = 255; data[idx+2] = 255; data[idx+3] = 255;
                 }
             }
         }
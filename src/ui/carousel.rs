@@ -1 +1,154 @@
+use std::ops::Range;
 
+use crate::pdf::organize::{PagePlan, PageSource};
+use crate::pdf::render::PageBitmap;
+
+/// Lado máximo en píxeles de una miniatura: la página entra entera, con su proporción.
+pub const THUMBNAIL_SIDE: u32 = 150;
+/// Alto de cada casilla del carrusel: miniatura, etiqueta debajo y separación.
+pub const SLOT_HEIGHT: i32 = THUMBNAIL_SIDE as i32 + 36;
+/// Margen sobre la primera casilla.
+pub const TOP_MARGIN: i32 = 16;
+
+/// Una página del carrusel: de dónde sale, su tamaño sin girar (puntos) y su miniatura,
+/// que se pinta la primera vez que se ve.
+pub struct CarouselPage {
+    pub plan: PagePlan,
+    pub size: (f32, f32),
+    pub thumbnail: Option<PageBitmap>,
+}
+
+/// Carrusel lateral como organizador de páginas: el documento tal como quedará al guardarlo.
+/// Nada toca el PDF abierto hasta que se guarda como archivo nuevo.
+pub struct Carousel {
+    pub pages: Vec<CarouselPage>,
+    pub selected: usize,
+    pub scroll: i32,
+    pub max_scroll: i32,
+    pub drag: Option<usize>, // Casilla que se está arrastrando para cambiarla de sitio
+    pub changed: bool,
+}
+
+impl Carousel {
+    /// Una casilla por página del documento, en su orden y sin girar.
+    pub fn new(sizes: Vec<(f32, f32)>, selected: usize) -> Self {
+        let pages = sizes.into_iter().enumerate().map(|(page, size)| CarouselPage {
+            plan: PagePlan { source: PageSource::Original(page as u16), rotation: 0 },
+            size,
+            thumbnail: None,
+        }).collect::<Vec<_>>();
+        let selected = selected.min(pages.len().saturating_sub(1));
+        Self { pages, selected, scroll: 0, max_scroll: 0, drag: None, changed: false }
+    }
+
+    /// El documento nuevo, página a página.
+    pub fn plan(&self) -> Vec<PagePlan> {
+        self.pages.iter().map(|page| page.plan.clone()).collect()
+    }
+
+    /// Casilla bajo `y` (píxeles de ventana), si hay alguna.
+    pub fn slot_at(&self, y: i32) -> Option<usize> {
+        let offset = y - TOP_MARGIN + self.scroll;
+        let index = offset.div_euclid(SLOT_HEIGHT);
+        (offset >= 0 && (index as usize) < self.pages.len()).then_some(index as usize)
+    }
+
+    /// Hueco entre casillas más cercano a `y`: dónde caería lo que se arrastra (0 = antes de la primera).
+    pub fn gap_at(&self, y: i32) -> usize {
+        let offset = y - TOP_MARGIN + self.scroll + SLOT_HEIGHT / 2;
+        (offset.max(0) / SLOT_HEIGHT).min(self.pages.len() as i32) as usize
+    }
+
+    /// Casillas que caben (aunque sea en parte) en un panel de `height` píxeles.
+    pub fn visible(&self, height: i32) -> Range<usize> {
+        let first = ((self.scroll - TOP_MARGIN).max(0) / SLOT_HEIGHT) as usize;
+        let last = ((self.scroll + height - TOP_MARGIN) / SLOT_HEIGHT + 1).max(0) as usize;
+        first.min(self.pages.len())..last.min(self.pages.len())
+    }
+
+    /// Desplaza lo justo para que se vea entera la casilla elegida en un panel de `height` píxeles.
+    pub fn reveal_selected(&mut self, height: i32) {
+        let top = self.selected as i32 * SLOT_HEIGHT;
+        self.scroll = self.scroll.min(top).max(top + TOP_MARGIN + SLOT_HEIGHT - height).max(0);
+    }
+
+    /// Lleva la casilla `from` al hueco `gap` (ver `gap_at`).
+    pub fn move_page(&mut self, from: usize, gap: usize) {
+        if from >= self.pages.len() || gap == from || gap == from + 1 {
+            return;
+        }
+        let page = self.pages.remove(from);
+        let to = if gap > from { gap - 1 } else { gap };
+        self.pages.insert(to, page);
+        self.selected = to;
+        self.changed = true;
+    }
+
+    /// Gira la página elegida un cuarto de vuelta.
+    pub fn rotate_selected(&mut self, clockwise: bool) {
+        if let Some(page) = self.pages.get_mut(self.selected) {
+            page.plan.rotation = (page.plan.rotation + if clockwise { 1 } else { 3 }) % 4;
+            self.changed = true;
+        }
+    }
+
+    pub fn delete_selected(&mut self) {
+        if self.selected < self.pages.len() {
+            self.pages.remove(self.selected);
+            self.selected = self.selected.min(self.pages.len().saturating_sub(1));
+            self.changed = true;
+        }
+    }
+
+    /// Añade páginas detrás de la elegida (al principio si no hay ninguna) y elige la primera de ellas.
+    pub fn insert_after_selected(&mut self, pages: Vec<CarouselPage>) {
+        let at = if self.pages.is_empty() { 0 } else { self.selected + 1 };
+        let count = pages.len();
+        self.pages.splice(at..at, pages);
+        if count > 0 {
+            self.selected = at;
+            self.changed = true;
+        }
+    }
+
+    /// Página en blanco del tamaño de la elegida tal como se ve (con su giro), detrás de ella.
+    pub fn insert_blank(&mut self) {
+        let (mut width, mut height) = self.pages.get(self.selected).map_or((612.0, 792.0), |page| page.size);
+        if self.pages.get(self.selected).is_some_and(|page| page.plan.rotation % 2 == 1) {
+            (width, height) = (height, width);
+        }
+        let blank = CarouselPage {
+            plan: PagePlan { source: PageSource::Blank { width, height }, rotation: 0 },
+            size: (width, height),
+            thumbnail: Some(blank_thumbnail((width, height))),
+        };
+        self.insert_after_selected(vec![blank]);
+    }
+
+    /// Texto bajo una miniatura: posición, de dónde sale la página ("+" si es de otro PDF)
+    /// y los grados que se le giran (la fuente del HUD no tiene "°").
+    pub fn label(&self, index: usize) -> String {
+        let page = &self.pages[index];
+        let source = match &page.plan.source {
+            PageSource::Original(n) => format!("{}", n + 1),
+            PageSource::Imported { page, .. } => format!("+{}", page + 1),
+            PageSource::Blank { .. } => "Blanca".to_string(),
+        };
+        match page.plan.rotation {
+            0 => format!("{}. {}", index + 1, source),
+            turns => format!("{}. {} ({})", index + 1, source, turns as u32 * 90),
+        }
+    }
+}
+
+/// Píxeles por punto con los que una página de ese tamaño cabe en una miniatura.
+pub fn thumbnail_scale(size: (f32, f32)) -> f32 {
+    THUMBNAIL_SIDE as f32 / size.0.max(size.1).max(1.0)
+}
+
+/// Miniatura de una página en blanco (BGRA).
+pub fn blank_thumbnail(size: (f32, f32)) -> PageBitmap {
+    let scale = thumbnail_scale(size);
+    let (width, height) = (((size.0 * scale) as u32).max(1), ((size.1 * scale) as u32).max(1));
+    PageBitmap { width, height, data: vec![255; (width * height * 4) as usize] }
+}
@@ -0,0 +1,58 @@
+use super::overlay::Annotation;
+
+/// Cambio reversible sobre las anotaciones de una página (base 0).
+/// Cada variante guarda lo necesario para aplicarse y revertirse sin recalcular nada.
+#[derive(Clone, Debug)]
+pub enum Edit {
+    Add { page: u16, annotation: Annotation },
+    /// `index` es la posición que ocupaba (el orden de la página es el orden de pintado).
+    Remove { page: u16, index: usize, annotation: Annotation },
+    /// Mover, recolorear...: la anotación antes y después (mismo id).
+    Replace { page: u16, before: Annotation, after: Annotation },
+    /// Varios cambios que se deshacen de una vez (mover una selección, pegar...). Suelen ser de la
+    /// misma página; importar un XFDF puede tocar varias (`page` es la primera).
+    Group(Vec<Edit>),
+}
+
+impl Edit {
+    pub fn page(&self) -> u16 {
+        match self {
+            Edit::Add { page, .. } | Edit::Remove { page, .. } | Edit::Replace { page, .. } => *page,
+            Edit::Group(edits) => edits.first().map_or(0, Edit::page),
+        }
+    }
+}
+
+// Límite de pasos guardados: los trazos largos ocupan, y nadie deshace 500 cambios
+const MAX_HISTORY: usize = 500;
+
+/// Pilas de deshacer/rehacer de un documento. Hacer un cambio nuevo vacía la de rehacer.
+#[derive(Default)]
+pub struct History {
+    undo: Vec<Edit>,
+    redo: Vec<Edit>,
+}
+
+impl History {
+    pub fn record(&mut self, edit: Edit) {
+        self.redo.clear();
+        self.undo.push(edit);
+        if self.undo.len() > MAX_HISTORY {
+            self.undo.remove(0);
+        }
+    }
+
+    /// Saca el último cambio hecho y lo pasa a la pila de rehacer.
+    pub fn undo(&mut self) -> Option<&Edit> {
+        let edit = self.undo.pop()?;
+        self.redo.push(edit);
+        self.redo.last()
+    }
+
+    /// Saca el último cambio deshecho y lo devuelve a la pila de deshacer.
+    pub fn redo(&mut self) -> Option<&Edit> {
+        let edit = self.redo.pop()?;
+        self.undo.push(edit);
+        self.undo.last()
+    }
+}
@@ -0,0 +1,359 @@
+use super::carousel::{Carousel, SLOT_HEIGHT, THUMBNAIL_SIDE, TOP_MARGIN};
+use super::overlay::Color;
+use super::summary::SummaryEntry;
+use super::text;
+
+/// Capa de interfaz en espacio de pantalla (popups de notas, avisos), pintada en CPU
+/// y subida como textura BGRA del tamaño de la ventana. A diferencia de la barra
+/// inferior no se mueve con la página.
+pub struct Hud {
+    pub width: u32,
+    pub height: u32,
+    pub data: Vec<u8>,
+}
+
+// Escala de la fuente en el HUD: píxeles por unidad de fuente
+const TEXT_PX: u32 = 2;
+const PADDING: i32 = 12;
+pub const NOTE_COLUMNS: usize = 36;
+const PROMPT_COLUMNS: usize = 60;
+const SUMMARY_COLUMNS: usize = 34;
+const SUMMARY_TEXT_LINES: usize = 3; // Más líneas se cortan: el texto entero está en el Markdown
+// Espacio que dejan abajo la barra y la paleta
+const SUMMARY_BOTTOM_MARGIN: i32 = 140;
+
+const GLASS: Color = [26, 26, 38, 225];
+const GLASS_BORDER: Color = [255, 255, 255, 40];
+const TEXT_COLOR: Color = [240, 240, 245, 255];
+const MUTED_TEXT: Color = [160, 160, 175, 255];
+const SELECTION: Color = [40, 140, 255, 255];
+const SELECTION_DASH: i32 = 6;
+const REDACTION_FILL: Color = [0, 0, 0, 150];
+const REDACTION_BORDER: Color = [220, 40, 40, 255];
+/// Lado en píxeles de las asas de una selección.
+pub const HANDLE_SIZE: i32 = 9;
+
+impl Hud {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self { width, height, data: vec![0; (width * height * 4) as usize] }
+    }
+
+    pub fn resize(&mut self, width: u32, height: u32) {
+        self.width = width;
+        self.height = height;
+        self.data = vec![0; (width * height * 4) as usize];
+    }
+
+    pub fn clear(&mut self) {
+        self.data.fill(0);
+    }
+
+    // Mezcla un píxel ("source over"); fuera de la ventana no hace nada
+    fn blend(&mut self, x: i32, y: i32, color: Color, coverage: f32) {
+        if x < 0 || y < 0 || x >= self.width as i32 || y >= self.height as i32 {
+            return;
+        }
+        let idx = ((y as u32 * self.width + x as u32) * 4) as usize;
+        let sa = color[3] as f32 / 255.0 * coverage;
+        let da = self.data[idx + 3] as f32 / 255.0;
+        let out_a = sa + da * (1.0 - sa);
+        if out_a <= 0.0 {
+            return;
+        }
+        // El buffer es BGRA, el color RGBA
+        for (dst, src) in [(0, 2), (1, 1), (2, 0)] {
+            let value = (color[src] as f32 * sa + self.data[idx + dst] as f32 * da * (1.0 - sa)) / out_a;
+            self.data[idx + dst] = value.round() as u8;
+        }
+        self.data[idx + 3] = (out_a * 255.0).round() as u8;
+    }
+
+    /// Panel de cristal con esquinas redondeadas y un borde tenue.
+    pub fn panel(&mut self, x: i32, y: i32, w: i32, h: i32, radius: f32) {
+        for py in y..y + h {
+            for px in x..x + w {
+                // Distancia con signo a un rectángulo redondeado
+                let (cx, cy) = (px as f32 + 0.5 - (x as f32 + w as f32 * 0.5), py as f32 + 0.5 - (y as f32 + h as f32 * 0.5));
+                let (qx, qy) = (cx.abs() - (w as f32 * 0.5 - radius), cy.abs() - (h as f32 * 0.5 - radius));
+                let d = (qx.max(0.0).powi(2) + qy.max(0.0).powi(2)).sqrt() + qx.max(qy).min(0.0) - radius;
+
+                let fill = (0.5 - d).clamp(0.0, 1.0);
+                if fill > 0.0 {
+                    self.blend(px, py, GLASS, fill);
+                    self.blend(px, py, GLASS_BORDER, (1.0 - (d + 1.0).abs()).clamp(0.0, 1.0));
+                }
+            }
+        }
+    }
+
+    pub fn text(&mut self, x: i32, y: i32, lines: &[String], color: Color) {
+        for (n, line) in lines.iter().enumerate() {
+            let top = y + (n as u32 * text::LINE_HEIGHT * TEXT_PX) as i32;
+            let mut pixels = Vec::new();
+            text::for_each_pixel(line, |col, row| pixels.push((col, row)));
+            for (col, row) in pixels {
+                for dy in 0..TEXT_PX {
+                    for dx in 0..TEXT_PX {
+                        self.blend(x + (col * TEXT_PX + dx) as i32, top + (row * TEXT_PX + dy) as i32, color, 1.0);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Popup de una nota junto a su icono (`anchor` en píxeles de pantalla).
+    /// Mientras se edita, el texto lleva un cursor al final.
+    pub fn note_popup(&mut self, anchor: (f32, f32), body: &str, editing: bool) {
+        let shown = if editing { format!("{}_", body) } else { body.to_string() };
+        let mut lines = text::wrap(&shown, NOTE_COLUMNS);
+        if body.is_empty() && !editing {
+            lines = vec!["(nota vacía)".to_string()];
+        }
+        let header = vec![if editing { "Nota - Esc para terminar".to_string() } else { "Nota".to_string() }];
+
+        let (text_w, text_h) = text::measure(&lines);
+        let line_px = (text::LINE_HEIGHT * TEXT_PX) as i32;
+        let w = (text_w * TEXT_PX) as i32 + PADDING * 2;
+        let w = w.max((text::measure(&header).0 * TEXT_PX) as i32 + PADDING * 2);
+        let h = (text_h * TEXT_PX) as i32 + line_px + PADDING * 3;
+
+        // A la derecha del icono, sin salirse de la ventana
+        let x = (anchor.0 as i32 + 8).min(self.width as i32 - w - 4).max(4);
+        let y = (anchor.1 as i32).min(self.height as i32 - h - 4).max(4);
+
+        self.panel(x, y, w, h, 10.0);
+        self.text(x + PADDING, y + PADDING, &header, MUTED_TEXT);
+        self.text(x + PADDING, y + PADDING * 2 + line_px, &lines, TEXT_COLOR);
+    }
+
+    /// Aviso centrado arriba (p.ej. mientras se escribe un texto libre).
+    pub fn hint(&mut self, message: &str) {
+        let lines = vec![message.to_string()];
+        let (text_w, text_h) = text::measure(&lines);
+        let w = (text_w * TEXT_PX) as i32 + PADDING * 2;
+        let h = (text_h * TEXT_PX) as i32 + PADDING * 2;
+        let x = (self.width as i32 - w) / 2;
+        self.panel(x, 16, w, h, 10.0);
+        self.text(x + PADDING, 16 + PADDING, &lines, TEXT_COLOR);
+    }
+
+    /// Campo de texto centrado arriba (p.ej. la ruta de "Guardar como"), con el cursor al final.
+    pub fn prompt(&mut self, title: &str, value: &str) {
+        let header = vec![title.to_string()];
+        let lines = text::wrap(&format!("{}_", value), PROMPT_COLUMNS);
+        let line_px = (text::LINE_HEIGHT * TEXT_PX) as i32;
+        let text_w = text::measure(&header).0.max(PROMPT_COLUMNS as u32 * text::ADVANCE);
+        let w = (text_w * TEXT_PX) as i32 + PADDING * 2;
+        let h = (text::measure(&lines).1 * TEXT_PX) as i32 + line_px + PADDING * 3;
+        let x = (self.width as i32 - w) / 2;
+
+        self.panel(x, 16, w, h, 10.0);
+        self.text(x + PADDING, 16 + PADDING, &header, MUTED_TEXT);
+        self.text(x + PADDING, 16 + PADDING * 2 + line_px, &lines, TEXT_COLOR);
+    }
+
+    /// Marco discontinuo alrededor de una selección (píxeles de pantalla), con asas para redimensionarla.
+    pub fn selection_box(&mut self, left: f32, top: f32, right: f32, bottom: f32, handles: bool) {
+        let (x0, y0, x1, y1) = frame(left, top, right, bottom);
+        let dashed = |i: i32| (i / SELECTION_DASH) % 2 == 0;
+        for x in x0..=x1 {
+            if dashed(x - x0) {
+                self.blend(x, y0, SELECTION, 1.0);
+                self.blend(x, y1, SELECTION, 1.0);
+            }
+        }
+        for y in y0..=y1 {
+            if dashed(y - y0) {
+                self.blend(x0, y, SELECTION, 1.0);
+                self.blend(x1, y, SELECTION, 1.0);
+            }
+        }
+        if !handles {
+            return;
+        }
+        for (hx, hy, _) in selection_handles(left, top, right, bottom) {
+            let half = HANDLE_SIZE / 2;
+            for py in hy - half..=hy + half {
+                for px in hx - half..=hx + half {
+                    let border = (px - hx).abs() == half || (py - hy).abs() == half;
+                    self.blend(px, py, if border { SELECTION } else { [255, 255, 255, 255] }, 1.0);
+                }
+            }
+        }
+    }
+
+    /// Zona marcada para redactar (píxeles de pantalla): oscurecida pero legible, para revisarla antes de aplicar.
+    pub fn redaction_box(&mut self, left: f32, top: f32, right: f32, bottom: f32) {
+        let (x0, y0) = (left.min(right).round() as i32, top.min(bottom).round() as i32);
+        let (x1, y1) = (left.max(right).round() as i32, top.max(bottom).round() as i32);
+        for y in y0..=y1 {
+            for x in x0..=x1 {
+                let border = x - x0 < 2 || x1 - x < 2 || y - y0 < 2 || y1 - y < 2;
+                self.blend(x, y, if border { REDACTION_BORDER } else { REDACTION_FILL }, 1.0);
+            }
+        }
+    }
+
+    /// Lazo de selección a mano alzada (píxeles de pantalla), cerrado con un tramo recto.
+    pub fn lasso(&mut self, points: &[(f32, f32)]) {
+        let mut walked = 0.0;
+        for (i, &(ax, ay)) in points.iter().enumerate() {
+            let (bx, by) = points[(i + 1) % points.len()];
+            let length = (bx - ax).hypot(by - ay);
+            let steps = length.ceil().max(1.0) as i32;
+            for step in 0..steps {
+                let t = step as f32 / steps as f32;
+                if (walked + t * length) as i32 / SELECTION_DASH % 2 == 0 {
+                    self.blend((ax + (bx - ax) * t) as i32, (ay + (by - ay) * t) as i32, SELECTION, 1.0);
+                }
+            }
+            walked += length;
+        }
+    }
+
+    /// Zona del panel de resumen (x, y, ancho, alto), pegado a la derecha de la ventana.
+    pub fn summary_rect(&self) -> (i32, i32, i32, i32) {
+        let w = (SUMMARY_COLUMNS as u32 * text::ADVANCE * TEXT_PX) as i32 + PADDING * 2;
+        let h = (self.height as i32 - 16 - SUMMARY_BOTTOM_MARGIN).max(PADDING * 4);
+        (self.width as i32 - w - 16, 16, w, h)
+    }
+
+    /// Panel con todas las anotaciones, agrupadas por página, desplazado `scroll` píxeles.
+    /// Devuelve la zona vertical de cada entrada visible (para los clicks) y el máximo desplazamiento.
+    pub fn summary_panel(&mut self, entries: &[SummaryEntry], scroll: i32) -> (Vec<(i32, i32, usize)>, i32) {
+        let (x, y, w, h) = self.summary_rect();
+        let line_px = (text::LINE_HEIGHT * TEXT_PX) as i32;
+        self.panel(x, y, w, h, 10.0);
+        let header = vec![format!("Anotaciones ({}) - Ctrl+M: Markdown", entries.len())];
+        self.text(x + PADDING, y + PADDING, &header, MUTED_TEXT);
+
+        // Contenido debajo de la cabecera; solo se pinta lo que cabe entero
+        let (top, bottom) = (y + PADDING * 2 + line_px, y + h - PADDING);
+        let mut cursor = top - scroll;
+        let mut hits = Vec::new();
+        let mut current_page = None;
+        if entries.is_empty() {
+            self.text(x + PADDING, top, &["No hay anotaciones".to_string()], TEXT_COLOR);
+        }
+
+        for (index, entry) in entries.iter().enumerate() {
+            if current_page != Some(entry.page) {
+                current_page = Some(entry.page);
+                if cursor >= top && cursor + line_px <= bottom {
+                    self.text(x + PADDING, cursor, &[format!("Página {}", entry.page + 1)], MUTED_TEXT);
+                }
+                cursor += line_px + PADDING / 2;
+            }
+
+            let mut title = entry.label.to_string();
+            if let Some(date) = &entry.date {
+                title = format!("{} - {}", title, date);
+            }
+            let mut lines = vec![title];
+            if !entry.text.is_empty() {
+                let quoted = if entry.is_quote { format!("\"{}\"", entry.text) } else { entry.text.clone() };
+                let mut body = text::wrap(&quoted, SUMMARY_COLUMNS - 2);
+                if body.len() > SUMMARY_TEXT_LINES {
+                    body.truncate(SUMMARY_TEXT_LINES);
+                    body[SUMMARY_TEXT_LINES - 1].push_str("...");
+                }
+                lines.extend(body.into_iter().map(|line| format!("  {}", line)));
+            }
+
+            let block_h = lines.len() as i32 * line_px;
+            if cursor >= top && cursor + block_h <= bottom {
+                // Muestra del color a la izquierda del título
+                let swatch = (line_px - 8).max(4);
+                for py in cursor + 2..cursor + 2 + swatch {
+                    for px in x + PADDING..x + PADDING + swatch {
+                        self.blend(px, py, [entry.color[0], entry.color[1], entry.color[2], 255], 1.0);
+                    }
+                }
+                self.text(x + PADDING + swatch + 6, cursor, &lines[..1], TEXT_COLOR);
+                self.text(x + PADDING, cursor + line_px, &lines[1..], MUTED_TEXT);
+                hits.push((cursor, cursor + block_h, index));
+            }
+            cursor += block_h + PADDING;
+        }
+
+        let content_h = cursor + scroll - top;
+        (hits, (content_h - (bottom - top)).max(0))
+    }
+
+    /// Carrusel de páginas en el panel lateral (`width` píxeles de ancho, hasta `bottom`): cada casilla
+    /// con su miniatura girada como quedará y su etiqueta. `drop` es el hueco donde caería la casilla
+    /// que se arrastra. Devuelve el máximo desplazamiento.
+    pub fn carousel(&mut self, carousel: &Carousel, width: i32, bottom: i32, drop: Option<usize>) -> i32 {
+        let line_px = (text::LINE_HEIGHT * TEXT_PX) as i32;
+        for index in carousel.visible(bottom) {
+            let page = &carousel.pages[index];
+            let top = TOP_MARGIN + index as i32 * SLOT_HEIGHT - carousel.scroll;
+            let Some(thumbnail) = &page.thumbnail else { continue };
+
+            // Miniatura centrada en su cuadrado, girada con el giro que se le ha añadido
+            let turns = page.plan.rotation % 4;
+            let (w, h) = if turns % 2 == 1 { (thumbnail.height as i32, thumbnail.width as i32) } else { (thumbnail.width as i32, thumbnail.height as i32) };
+            let x0 = (width - w) / 2;
+            let y0 = top + (THUMBNAIL_SIDE as i32 - h) / 2;
+            for y in y0.max(0)..(y0 + h).min(bottom) {
+                for x in x0.max(0)..(x0 + w).min(self.width as i32) {
+                    let (u, v) = (x - x0, y - y0);
+                    let (sx, sy) = match turns {
+                        1 => (v, w - 1 - u),
+                        2 => (w - 1 - u, h - 1 - v),
+                        3 => (h - 1 - v, u),
+                        _ => (u, v),
+                    };
+                    let src = ((sy as u32 * thumbnail.width + sx as u32) * 4) as usize;
+                    let dst = ((y as u32 * self.width + x as u32) * 4) as usize;
+                    self.data[dst..dst + 3].copy_from_slice(&thumbnail.data[src..src + 3]);
+                    self.data[dst + 3] = 255;
+                }
+            }
+            if index == carousel.selected {
+                for y in y0 - 3..y0 + h + 3 {
+                    for x in x0 - 3..x0 + w + 3 {
+                        if (x < x0 || x >= x0 + w || y < y0 || y >= y0 + h) && y < bottom {
+                            self.blend(x, y, SELECTION, 1.0);
+                        }
+                    }
+                }
+            }
+            let label_y = top + THUMBNAIL_SIDE as i32 + 6;
+            if label_y + line_px <= bottom {
+                let color = if index == carousel.selected { TEXT_COLOR } else { MUTED_TEXT };
+                self.text(x0.max(PADDING / 2), label_y, &[carousel.label(index)], color);
+            }
+        }
+
+        if let Some(gap) = drop {
+            let y = TOP_MARGIN + gap as i32 * SLOT_HEIGHT - carousel.scroll - 4;
+            for dy in -1..=1 {
+                for x in PADDING / 2..width - PADDING / 2 {
+                    if y + dy < bottom {
+                        self.blend(x, y + dy, SELECTION, 1.0);
+                    }
+                }
+            }
+        }
+
+        let content_h = TOP_MARGIN + carousel.pages.len() as i32 * SLOT_HEIGHT;
+        (content_h - bottom).max(0)
+    }
+}
+
+// Marco de una selección en píxeles: unos de aire para no tapar el borde de la anotación
+fn frame(left: f32, top: f32, right: f32, bottom: f32) -> (i32, i32, i32, i32) {
+    (left.min(right) as i32 - 4, top.min(bottom) as i32 - 4, left.max(right) as i32 + 4, top.max(bottom) as i32 + 4)
+}
+
+/// Centro de cada asa del marco de una selección (píxeles de pantalla) y qué lados de la caja mueve,
+/// en x e y de página: -1 izquierda/abajo, 1 derecha/arriba, 0 ninguno.
+pub fn selection_handles(left: f32, top: f32, right: f32, bottom: f32) -> [(i32, i32, [i8; 2]); 8] {
+    let (x0, y0, x1, y1) = frame(left, top, right, bottom);
+    let (xm, ym) = ((x0 + x1) / 2, (y0 + y1) / 2);
+    [
+        (x0, y0, [-1, 1]), (xm, y0, [0, 1]), (x1, y0, [1, 1]), (x1, ym, [1, 0]),
+        (x1, y1, [1, -1]), (xm, y1, [0, -1]), (x0, y1, [-1, -1]), (x0, ym, [-1, 0]),
+    ]
+}
@@ -1,16 +1,126 @@
 pub mod assets;
-use wgpu::util::DeviceExt;
+pub mod carousel;
+pub mod history;
+pub mod hud;
+pub mod overlay;
+pub mod summary;
+pub mod text;
+use std::collections::HashMap;
 use crate::gpu::texture::Texture;
+use overlay::{Color, HIGHLIGHT_YELLOW, INK_BLACK, NOTE_YELLOW, SHAPE_RED};
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub enum Tool {
     None,
     Pan,
+    Select, // Mover, recolorear o borrar una anotación ya hecha
+    Pen,
     Highlighter,
+    TextHighlight, // Resaltado que se ajusta a las cajas de los caracteres
+    Note,
+    FreeText,
+    Eraser,
+    Rectangle,
+    Ellipse,
+    Line,
+    Arrow,
+    Redact, // Marcar zonas para redactar (Ctrl+R); no está en la paleta
 }
 
+impl Tool {
+    /// Herramientas que trabajan sobre las anotaciones (las que muestran la paleta).
+    pub fn is_annotation_tool(self) -> bool {
+        !matches!(self, Tool::None | Tool::Pan | Tool::Redact)
+    }
+}
+
+/// Ajustes de una herramienta. Cada una recuerda los suyos (el subrayador no hereda el negro del lápiz).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ToolSettings {
+    pub color: Color,
+    pub width: f32, // Puntos PDF (en la goma, el radio de borrado; en el texto libre, el alto de línea)
+}
+
+impl ToolSettings {
+    fn default_for(tool: Tool) -> Self {
+        match tool {
+            Tool::Highlighter => Self { color: HIGHLIGHT_YELLOW, width: 6.5 },
+            Tool::TextHighlight => Self { color: HIGHLIGHT_YELLOW, width: 1.0 },
+            Tool::Note => Self { color: NOTE_YELLOW, width: 1.0 },
+            Tool::FreeText => Self { color: INK_BLACK, width: 12.0 },
+            Tool::Eraser => Self { color: [255, 255, 255, 255], width: 6.0 },
+            Tool::Rectangle | Tool::Ellipse | Tool::Line | Tool::Arrow => Self { color: SHAPE_RED, width: 2.0 },
+            _ => Self { color: INK_BLACK, width: 1.5 },
+        }
+    }
+}
+
+/// Colores predefinidos de la paleta (RGB; el alpha lo pone cada herramienta).
+/// El shader los dibuja en el mismo orden.
+pub const COLOR_PRESETS: [[u8; 3]; 6] = [
+    [20, 20, 20],   // Negro
+    [220, 40, 40],  // Rojo
+    [40, 90, 220],  // Azul
+    [40, 170, 70],  // Verde
+    [255, 230, 0],  // Amarillo
+    [255, 140, 0],  // Naranja
+];
+
+const MIN_WIDTH: f32 = 0.5;
+const MAX_WIDTH: f32 = 48.0;
+
+/// Casillas de la paleta, de izquierda a derecha. El shader asume este mismo orden.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PaletteItem {
+    Tool(Tool),
+    Color(usize),
+    Hue,          // Tira de color libre: la posición horizontal del click elige el tono
+    Thinner,
+    WidthPreview, // Solo muestra el grosor y color actuales
+    Thicker,
+}
+
+pub const PALETTE: [PaletteItem; 21] = [
+    PaletteItem::Tool(Tool::Select),
+    PaletteItem::Tool(Tool::Pen),
+    PaletteItem::Tool(Tool::Highlighter),
+    PaletteItem::Tool(Tool::TextHighlight),
+    PaletteItem::Tool(Tool::Note),
+    PaletteItem::Tool(Tool::FreeText),
+    PaletteItem::Tool(Tool::Eraser),
+    PaletteItem::Tool(Tool::Rectangle),
+    PaletteItem::Tool(Tool::Ellipse),
+    PaletteItem::Tool(Tool::Line),
+    PaletteItem::Tool(Tool::Arrow),
+    PaletteItem::Color(0),
+    PaletteItem::Color(1),
+    PaletteItem::Color(2),
+    PaletteItem::Color(3),
+    PaletteItem::Color(4),
+    PaletteItem::Color(5),
+    PaletteItem::Hue,
+    PaletteItem::Thinner,
+    PaletteItem::WidthPreview,
+    PaletteItem::Thicker,
+];
+
+/// Casillas de herramientas, de colores predefinidos y en total, para la cámara del shader.
+pub fn palette_slots() -> [f32; 4] {
+    let tools = PALETTE.iter().filter(|item| matches!(item, PaletteItem::Tool(_))).count();
+    let colors = PALETTE.iter().filter(|item| matches!(item, PaletteItem::Color(_))).count();
+    [tools as f32, colors as f32, PALETTE.len() as f32, 0.0]
+}
+
+// Geometría de la paleta en píxeles (fila encima de la barra inferior, centrada)
+pub const PALETTE_HEIGHT: f32 = 52.0;
+pub const PALETTE_SLOT: f32 = 44.0;
+
 pub struct UiState {
     pub active_tool: Tool,
+    pub last_annotation_tool: Tool, // A la que vuelve el botón lápiz
+    pub tool_settings: HashMap<Tool, ToolSettings>,
     pub is_carousel_open: bool,
+    pub presentation: bool, // Sin barra ni carrusel
     
     // Texturas de Iconos
     pub icon_search: Texture,
@@ -36,7 +146,10 @@ impl UiState {
 
         Self {
             active_tool: Tool::Pan,
+            last_annotation_tool: Tool::Highlighter,
+            tool_settings: HashMap::new(),
             is_carousel_open: false,
+            presentation: false,
             icon_search,
             icon_pen,
             icon_menu,
@@ -48,6 +161,10 @@ impl UiState {
     // Detectar clicks en la UI
     // Retorna true si el click fue en la UI (para no mover el PDF)
     pub fn hit_test(&mut self, x: f64, y: f64, win_width: f64, win_height: f64) -> bool {
+        if self.presentation {
+            return false;
+        }
+
         // Coordenadas x,y vienen normalizadas de -1 a 1 (sistema WGPU)
         // Convertimos a píxeles pantalla para facilitar lógica UI
         let px = (x + 1.0) * 0.5 * win_width;
@@ -61,32 +178,105 @@ impl UiState {
             // Botón Menú (Carrusel)
             if px > center - 100.0 && px < center - 60.0 {
                 self.is_carousel_open = !self.is_carousel_open;
-                println!("UI: Toggle Carrusel");
+                log::debug!("UI: Toggle Carrusel");
                 return true;
             }
-            // Botón Lápiz
+            // Botón Lápiz: abre la paleta con la última herramienta usada, o la cierra
             if px > center - 20.0 && px < center + 20.0 {
-                self.active_tool = match self.active_tool {
-                    Tool::Highlighter => Tool::Pan,
-                    _ => Tool::Highlighter,
-                };
-                println!("UI: Herramienta Lápiz {:?}", match self.active_tool { Tool::Highlighter => "ON", _ => "OFF"});
+                self.active_tool = if self.active_tool.is_annotation_tool() { Tool::Pan } else { self.last_annotation_tool };
+                log::debug!("UI: Herramienta {:?}", self.active_tool);
                 return true;
             }
             // Botón Buscar (Dummy)
             if px > center + 60.0 && px < center + 100.0 {
-                println!("UI: Buscar (Ctrl+F simulado)");
+                log::debug!("UI: Buscar (Ctrl+F simulado)");
                 return true;
             }
             return true; // Click en la barra, aunque no sea botón
         }
 
-        // 2. Chequear Panel Lateral (si está abierto)
+        // 2. Paleta de herramientas (solo visible con una herramienta de anotación)
+        if self.active_tool.is_annotation_tool() {
+            let palette_top = win_height - (self.bottom_bar_height + PALETTE_HEIGHT) as f64;
+            let palette_left = win_width / 2.0 - (PALETTE.len() as f32 * PALETTE_SLOT) as f64 / 2.0;
+            if py > palette_top && py < win_height - self.bottom_bar_height as f64 {
+                let slot = ((px - palette_left) / PALETTE_SLOT as f64).floor();
+                if slot >= 0.0 && (slot as usize) < PALETTE.len() {
+                    let fraction = ((px - palette_left) / PALETTE_SLOT as f64).fract() as f32;
+                    self.click_palette(PALETTE[slot as usize], fraction);
+                }
+                return true;
+            }
+        }
+
+        // 3. Chequear Panel Lateral (si está abierto)
         if self.is_carousel_open && px < self.side_panel_width as f64 {
-            println!("UI: Click en Carrusel");
+            log::debug!("UI: Click en Carrusel");
             return true;
         }
 
         false
     }
+
+    /// Ajustes de la herramienta activa.
+    pub fn settings(&self) -> ToolSettings {
+        self.settings_for(self.active_tool)
+    }
+
+    pub fn settings_for(&self, tool: Tool) -> ToolSettings {
+        self.tool_settings.get(&tool).copied().unwrap_or_else(|| ToolSettings::default_for(tool))
+    }
+
+    fn settings_mut(&mut self) -> &mut ToolSettings {
+        let tool = self.active_tool;
+        self.tool_settings.entry(tool).or_insert_with(|| ToolSettings::default_for(tool))
+    }
+
+    /// Casilla de la paleta que corresponde a la herramienta activa.
+    pub fn active_tool_slot(&self) -> Option<usize> {
+        PALETTE.iter().position(|item| *item == PaletteItem::Tool(self.active_tool))
+    }
+
+    /// Casilla del color predefinido activo (None si es un color libre).
+    pub fn active_color_slot(&self) -> Option<usize> {
+        let color = self.settings().color;
+        PALETTE.iter().position(|item| matches!(item, PaletteItem::Color(i) if COLOR_PRESETS[*i] == [color[0], color[1], color[2]]))
+    }
+
+    // `fraction` es la posición horizontal del click dentro de la casilla (0..1)
+    fn click_palette(&mut self, item: PaletteItem, fraction: f32) {
+        match item {
+            PaletteItem::Tool(tool) => {
+                self.active_tool = tool;
+                self.last_annotation_tool = tool;
+                log::debug!("UI: Herramienta {:?}", tool);
+            },
+            PaletteItem::Color(i) => self.set_color(COLOR_PRESETS[i]),
+            PaletteItem::Hue => self.set_color(hue_to_rgb(fraction)),
+            PaletteItem::Thinner => self.settings_mut().width = (self.settings().width / 1.25).max(MIN_WIDTH),
+            PaletteItem::Thicker => self.settings_mut().width = (self.settings().width * 1.25).min(MAX_WIDTH),
+            PaletteItem::WidthPreview => {},
+        }
+    }
+
+    // El alpha es de la herramienta: el subrayador sigue siendo translúcido en cualquier color
+    fn set_color(&mut self, rgb: [u8; 3]) {
+        let settings = self.settings_mut();
+        settings.color = [rgb[0], rgb[1], rgb[2], settings.color[3]];
+    }
+}
+
+/// Tono (0..1) a RGB con saturación y brillo máximos.
+fn hue_to_rgb(hue: f32) -> [u8; 3] {
+    let h = hue.clamp(0.0, 1.0) * 6.0;
+    let x = 1.0 - (h % 2.0 - 1.0).abs();
+    let (r, g, b) = match h as u32 {
+        0 => (1.0, x, 0.0),
+        1 => (x, 1.0, 0.0),
+        2 => (0.0, 1.0, x),
+        3 => (0.0, x, 1.0),
+        4 => (x, 0.0, 1.0),
+        _ => (1.0, 0.0, x),
+    };
+    [(r * 255.0) as u8, (g * 255.0) as u8, (b * 255.0) as u8]
 }
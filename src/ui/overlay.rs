@@ -1 +1,1304 @@
+use std::collections::hash_map::RandomState;
+use std::collections::{BTreeMap, HashMap};
+use std::hash::{BuildHasher, Hasher};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
 
+use serde::{Deserialize, Serialize};
+
+use super::history::{Edit, History};
+use super::text;
+use crate::pdf::annotations::NAME_PREFIX;
+
+/// Punto en el espacio de la página PDF: puntos (1/72"), origen abajo-izquierda, Y hacia arriba.
+/// Es el mismo sistema que usa PDFium, así que las anotaciones no dependen del zoom ni del bitmap.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PagePoint {
+    pub x: f32,
+    pub y: f32,
+}
+
+/// Una muestra de un trazo: posición y presión del lápiz (0..1; el ratón siempre da 1.0).
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct InkSample {
+    pub pos: PagePoint,
+    pub pressure: f32,
+}
+
+/// Color RGBA (0-255). El alpha es la opacidad de la anotación entera, no por píxel.
+pub type Color = [u8; 4];
+
+pub const HIGHLIGHT_YELLOW: Color = [255, 255, 0, 100]; // Amarillo fluorescente semi-transparente
+pub const INK_BLACK: Color = [20, 20, 20, 255];
+pub const SHAPE_RED: Color = [220, 40, 40, 255];
+pub const NOTE_YELLOW: Color = [245, 190, 30, 255];
+
+/// Lado del icono de una nota en puntos PDF (no escala con el texto, como en Acrobat).
+pub const NOTE_ICON_SIZE: f32 = 18.0;
+
+// Con presión mínima el trazo conserva un 35% del grosor nominal
+const MIN_PRESSURE_WIDTH: f32 = 0.35;
+// Separación máxima (en píxeles) entre puntos de la curva suavizada
+const CURVE_STEP_PX: f32 = 1.5;
+// Puntos con los que se aproxima una elipse
+const ELLIPSE_SEGMENTS: usize = 72;
+// Alto de línea mínimo (en puntos) al encoger un texto libre con las asas
+const MIN_FREE_TEXT_SIZE: f32 = 4.0;
+// Por debajo de este coseno entre dos tramos la unión es una esquina y se redondea con un círculo
+const SMOOTH_JOIN_COS: f32 = 0.95;
+
+// Los nombres serde son el formato del archivo de anotaciones (ver sidecar.rs)
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AnnotationKind {
+    /// Trazo a mano alzada: las muestras del cursor en orden. `width` es el grosor a presión máxima.
+    Ink { samples: Vec<InkSample>, width: f32 },
+    /// Figura definida por el punto donde se empezó a arrastrar y donde se soltó.
+    Shape { shape: Shape, from: PagePoint, to: PagePoint, width: f32 },
+    /// Resaltado ajustado al texto: una caja por línea ([left, bottom, right, top]) y el texto cubierto.
+    TextHighlight { rects: Vec<[f32; 4]>, text: String },
+    /// Nota adhesiva: un icono con su esquina superior izquierda en `at`; el texto se ve en un popup.
+    Note { at: PagePoint, text: String },
+    /// Texto libre pintado sobre la página. `at` es la esquina superior izquierda y `size` el alto de línea en puntos.
+    FreeText { at: PagePoint, text: String, size: f32 },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Shape {
+    Rectangle,
+    Ellipse,
+    Line,
+    Arrow,
+}
+
+impl AnnotationKind {
+    /// Contorno en coordenadas de página: una o varias polilíneas (la flecha tiene punta aparte).
+    /// Es la geometría "real" de la anotación: sirve para la goma y para exportar.
+    pub fn outline(&self) -> Vec<Vec<PagePoint>> {
+        match self {
+            AnnotationKind::Ink { samples, .. } => vec![samples.iter().map(|s| s.pos).collect()],
+            AnnotationKind::Shape { shape, from, to, width } => shape_outline(*shape, *from, *to, *width),
+            _ => self.areas().iter().map(|&[l, b, r, t]| {
+                let p = |x: f32, y: f32| PagePoint { x, y };
+                vec![p(l, b), p(r, b), p(r, t), p(l, t), p(l, b)]
+            }).collect(),
+        }
+    }
+
+    /// Zonas rellenas ([left, bottom, right, top] en puntos) de las anotaciones que son áreas
+    /// y no trazos: resaltados de texto, iconos de nota y cajas de texto libre.
+    pub fn areas(&self) -> Vec<[f32; 4]> {
+        match self {
+            AnnotationKind::TextHighlight { rects, .. } => rects.clone(),
+            AnnotationKind::Note { at, .. } => vec![[at.x, at.y - NOTE_ICON_SIZE, at.x + NOTE_ICON_SIZE, at.y]],
+            AnnotationKind::FreeText { at, text, size } => {
+                let (w, h) = free_text_size(text, *size);
+                vec![[at.x, at.y - h, at.x + w, at.y]]
+            },
+            AnnotationKind::Ink { .. } | AnnotationKind::Shape { .. } => Vec::new(),
+        }
+    }
+
+    pub fn width(&self) -> f32 {
+        match self {
+            AnnotationKind::Ink { width, .. } | AnnotationKind::Shape { width, .. } => *width,
+            _ => 0.0,
+        }
+    }
+
+    /// Caja que envuelve la anotación ([left, bottom, right, top] en puntos), grosor incluido.
+    pub fn bounds(&self) -> [f32; 4] {
+        let half = self.width() * 0.5;
+        let points = self.outline().into_iter().flatten();
+        let [l, b, r, t] = points.fold([f32::MAX, f32::MAX, f32::MIN, f32::MIN], |acc, p| {
+            [acc[0].min(p.x), acc[1].min(p.y), acc[2].max(p.x), acc[3].max(p.y)]
+        });
+        [l - half, b - half, r + half, t + half]
+    }
+
+    /// Desplaza la anotación entera `dx`, `dy` puntos.
+    pub fn translate(&mut self, dx: f32, dy: f32) {
+        let shift = |p: &mut PagePoint| {
+            p.x += dx;
+            p.y += dy;
+        };
+        match self {
+            AnnotationKind::Ink { samples, .. } => samples.iter_mut().for_each(|s| shift(&mut s.pos)),
+            AnnotationKind::Shape { from, to, .. } => {
+                shift(from);
+                shift(to);
+            },
+            AnnotationKind::TextHighlight { rects, .. } => {
+                for rect in rects.iter_mut() {
+                    *rect = [rect[0] + dx, rect[1] + dy, rect[2] + dx, rect[3] + dy];
+                }
+            },
+            AnnotationKind::Note { at, .. } | AnnotationKind::FreeText { at, .. } => shift(at),
+        }
+    }
+
+    /// Lleva la anotación de la caja `old` a la caja `new` ([left, bottom, right, top]) conservando
+    /// la posición relativa de cada punto (asas de la herramienta de selección). Los grosores no
+    /// cambian; el texto libre cambia de tamaño con el alto y el icono de nota solo se desplaza.
+    pub fn scale(&mut self, old: [f32; 4], new: [f32; 4]) {
+        let sx = (new[2] - new[0]) / (old[2] - old[0]).max(f32::EPSILON);
+        let sy = (new[3] - new[1]) / (old[3] - old[1]).max(f32::EPSILON);
+        let map = |p: &mut PagePoint| {
+            p.x = new[0] + (p.x - old[0]) * sx;
+            p.y = new[1] + (p.y - old[1]) * sy;
+        };
+        match self {
+            AnnotationKind::Ink { samples, .. } => samples.iter_mut().for_each(|s| map(&mut s.pos)),
+            AnnotationKind::Shape { from, to, .. } => {
+                map(from);
+                map(to);
+            },
+            AnnotationKind::TextHighlight { rects, .. } => {
+                for rect in rects.iter_mut() {
+                    let (mut lb, mut rt) = (PagePoint { x: rect[0], y: rect[1] }, PagePoint { x: rect[2], y: rect[3] });
+                    map(&mut lb);
+                    map(&mut rt);
+                    *rect = [lb.x, lb.y, rt.x, rt.y];
+                }
+            },
+            AnnotationKind::Note { at, .. } => map(at),
+            AnnotationKind::FreeText { at, size, .. } => {
+                map(at);
+                *size = (*size * sy).max(MIN_FREE_TEXT_SIZE);
+            },
+        }
+    }
+
+    /// Trazos, figuras y resaltados: se dibujan como geometría en GPU (gpu/strokes.rs).
+    /// Las notas y el texto libre siguen en el overlay raster.
+    pub fn is_stroke(&self) -> bool {
+        matches!(self, AnnotationKind::Ink { .. } | AnnotationKind::Shape { .. } | AnnotationKind::TextHighlight { .. })
+    }
+
+    /// Texto editable de la anotación (notas y texto libre).
+    pub fn text_mut(&mut self) -> Option<&mut String> {
+        match self {
+            AnnotationKind::Note { text, .. } | AnnotationKind::FreeText { text, .. } => Some(text),
+            _ => None,
+        }
+    }
+}
+
+fn free_text_lines(text: &str) -> Vec<String> {
+    text.split('\n').map(str::to_string).collect()
+}
+
+/// Ancho y alto en puntos de un texto libre (como mínimo un carácter, para poder verlo y clicarlo).
+fn free_text_size(text: &str, size: f32) -> (f32, f32) {
+    let unit = size / text::LINE_HEIGHT as f32;
+    let (w, h) = text::measure(&free_text_lines(text));
+    (w.max(text::ADVANCE) as f32 * unit, h.max(text::LINE_HEIGHT) as f32 * unit)
+}
+
+fn shape_outline(shape: Shape, from: PagePoint, to: PagePoint, width: f32) -> Vec<Vec<PagePoint>> {
+    let p = |x: f32, y: f32| PagePoint { x, y };
+    match shape {
+        Shape::Rectangle => vec![vec![from, p(to.x, from.y), to, p(from.x, to.y), from]],
+        Shape::Ellipse => {
+            let (cx, cy) = ((from.x + to.x) * 0.5, (from.y + to.y) * 0.5);
+            let (rx, ry) = ((to.x - from.x).abs() * 0.5, (to.y - from.y).abs() * 0.5);
+            vec![(0..=ELLIPSE_SEGMENTS).map(|i| {
+                let angle = i as f32 / ELLIPSE_SEGMENTS as f32 * std::f32::consts::TAU;
+                p(cx + rx * angle.cos(), cy + ry * angle.sin())
+            }).collect()]
+        },
+        Shape::Line => vec![vec![from, to]],
+        Shape::Arrow => {
+            // Punta de ±25° proporcional al grosor, para que se vea también con trazos finos
+            let angle = (to.y - from.y).atan2(to.x - from.x);
+            let head = (width * 4.0).max(8.0);
+            let wing = |delta: f32| p(to.x - head * (angle + delta).cos(), to.y - head * (angle + delta).sin());
+            let spread = 25f32.to_radians();
+            vec![vec![from, to], vec![wing(spread), to, wing(-spread)]]
+        },
+    }
+}
+
+/// ¿Pasa la anotación a menos de `tolerance` puntos de `point`? (goma de trazos)
+pub fn hit_test(annotation: &Annotation, point: PagePoint, tolerance: f32) -> bool {
+    // Resaltados, notas y textos son áreas: basta con tocar dentro
+    let areas = annotation.kind.areas();
+    if !areas.is_empty() {
+        return areas.iter().any(|&[l, b, r, t]| {
+            point.x >= l - tolerance && point.x <= r + tolerance && point.y >= b - tolerance && point.y <= t + tolerance
+        });
+    }
+    let reach = tolerance + annotation.kind.width() * 0.5;
+    annotation.kind.outline().iter().any(|polyline| match polyline.as_slice() {
+        [] => false,
+        [single] => distance_to_segment(point, *single, *single) <= reach,
+        _ => polyline.windows(2).any(|pair| distance_to_segment(point, pair[0], pair[1]) <= reach),
+    })
+}
+
+/// ¿Queda la anotación entera dentro de `polygon` (el marco o el lazo de la herramienta de selección)?
+pub fn inside_polygon(annotation: &Annotation, polygon: &[PagePoint]) -> bool {
+    let outline = annotation.kind.outline();
+    let mut points = outline.iter().flatten().peekable();
+    // Con un lazo cóncavo no basta con los vértices: un segmento puede salir y volver a entrar
+    points.peek().is_some() && points.all(|p| point_in_polygon(*p, polygon))
+        && !outline.iter().flat_map(|line| line.windows(2)).any(|segment| {
+            (0..polygon.len()).any(|i| segments_cross(segment[0], segment[1], polygon[i], polygon[(i + 1) % polygon.len()]))
+        })
+}
+
+// ¿Se cortan los segmentos ab y cd en un punto interior de ambos? (tocarse en un extremo no cuenta)
+fn segments_cross(a: PagePoint, b: PagePoint, c: PagePoint, d: PagePoint) -> bool {
+    let side = |p: PagePoint, q: PagePoint, r: PagePoint| (q.x - p.x) * (r.y - p.y) - (q.y - p.y) * (r.x - p.x);
+    let (d1, d2) = (side(c, d, a), side(c, d, b));
+    let (d3, d4) = (side(a, b, c), side(a, b, d));
+    d1 * d2 < 0.0 && d3 * d4 < 0.0
+}
+
+// Regla par-impar: cuántas veces cruza el polígono una semirrecta horizontal desde el punto
+fn point_in_polygon(p: PagePoint, polygon: &[PagePoint]) -> bool {
+    let mut inside = false;
+    for (i, a) in polygon.iter().enumerate() {
+        let b = polygon[(i + 1) % polygon.len()];
+        if (a.y > p.y) != (b.y > p.y) && p.x < a.x + (p.y - a.y) / (b.y - a.y) * (b.x - a.x) {
+            inside = !inside;
+        }
+    }
+    inside
+}
+
+fn distance_to_segment(p: PagePoint, a: PagePoint, b: PagePoint) -> f32 {
+    let (abx, aby) = (b.x - a.x, b.y - a.y);
+    let len2 = abx * abx + aby * aby;
+    let t = if len2 > 0.0 { (((p.x - a.x) * abx + (p.y - a.y) * aby) / len2).clamp(0.0, 1.0) } else { 0.0 };
+    ((p.x - (a.x + abx * t)).powi(2) + (p.y - (a.y + aby * t)).powi(2)).sqrt()
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Annotation {
+    #[serde(skip)]
+    pub id: u64, // Solo vale dentro de una sesión: al cargar se asigna otro
+    #[serde(flatten)]
+    pub kind: AnnotationKind,
+    pub color: Color,
+    // Datos de las anotaciones leídas del PDF (/T y /M), para no perderlos al guardar
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub author: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub modified: Option<String>,
+    // Identificador entre programas (/NM del PDF, `name` del XFDF). Se genera una vez, al crearla
+    // o al leer una que no lo trae, y se guarda con ella
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+}
+
+/// Dónde está en el PDF abierto una anotación del modelo: su página, el índice de su /Annot en
+/// ella y cómo era al leerla. Un Ink de varios trazos da varias anotaciones con el mismo origen.
+/// Al guardar solo se tocan los /Annot cuyas anotaciones cambiaron, y en sitio.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Origin {
+    pub page: u16,
+    pub index: usize,
+    pub saved: Annotation,
+}
+
+/// Todas las anotaciones de un documento, agrupadas por página (base 0), y su historial.
+/// Sobreviven al cambio de página: el overlay raster se regenera desde aquí.
+#[derive(Default)]
+pub struct AnnotationStore {
+    pages: BTreeMap<u16, Vec<Annotation>>,
+    next_id: u64,
+    history: History,
+    revision: u64, // Sube con cada cambio que entra (o sale) del historial
+    // Por id, las que vienen del PDF (también las ya borradas del modelo: al guardar hay que quitarlas)
+    origins: HashMap<u64, Origin>,
+}
+
+impl AnnotationStore {
+    pub fn new() -> Self {
+        Self { pages: BTreeMap::new(), next_id: 1, history: History::default(), revision: 0, origins: HashMap::new() }
+    }
+
+    pub fn page(&self, page: u16) -> &[Annotation] {
+        self.pages.get(&page).map(Vec::as_slice).unwrap_or(&[])
+    }
+
+    /// Páginas con anotaciones, en orden.
+    pub fn pages(&self) -> impl Iterator<Item = (u16, &[Annotation])> {
+        self.pages.iter().filter(|(_, list)| !list.is_empty()).map(|(page, list)| (*page, list.as_slice()))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pages().next().is_none()
+    }
+
+    /// Contador de cambios terminados: si no se movió, no hay nada nuevo que guardar.
+    pub fn revision(&self) -> u64 {
+        self.revision
+    }
+
+    fn record(&mut self, edit: Edit) {
+        self.history.record(edit);
+        self.revision += 1;
+    }
+
+    // Varios cambios como un solo paso del historial (uno solo no hace falta agruparlo)
+    fn record_all(&mut self, mut edits: Vec<Edit>) {
+        match edits.len() {
+            0 => {},
+            1 => self.record(edits.remove(0)),
+            _ => self.record(Edit::Group(edits)),
+        }
+    }
+
+    /// Añade una anotación a la página y devuelve su id.
+    /// No entra en el historial hasta `commit_added` (un trazo se añade al empezar y crece luego).
+    pub fn add(&mut self, page: u16, kind: AnnotationKind, color: Color) -> u64 {
+        let id = self.next_id;
+        self.next_id += 1;
+        let name = Some(new_name(&kind));
+        let annotation = Annotation { id, kind, color, author: local_author(), modified: Some(pdf_date_now()), name };
+        self.pages.entry(page).or_default().push(annotation);
+        id
+    }
+
+    /// Añade una anotación ya hecha (p.ej. leída de un XFDF) con un id nuevo y la deja en el historial.
+    pub fn insert(&mut self, page: u16, mut annotation: Annotation) -> u64 {
+        let id = self.next_id;
+        self.next_id += 1;
+        annotation.id = id;
+        ensure_name(&mut annotation);
+        self.pages.entry(page).or_default().push(annotation.clone());
+        self.record(Edit::Add { page, annotation });
+        id
+    }
+
+    /// Como `insert`, con varias a la vez: pegar una selección se deshace de una vez.
+    pub fn insert_all(&mut self, page: u16, annotations: Vec<Annotation>) -> Vec<u64> {
+        self.insert_pages(annotations.into_iter().map(|annotation| (page, annotation)).collect())
+    }
+
+    /// Como `insert_all`, con anotaciones de varias páginas (importar un XFDF se deshace de una vez).
+    pub fn insert_pages(&mut self, annotations: Vec<(u16, Annotation)>) -> Vec<u64> {
+        let mut ids = Vec::new();
+        let mut edits = Vec::new();
+        for (page, mut annotation) in annotations {
+            annotation.id = self.next_id;
+            self.next_id += 1;
+            ensure_name(&mut annotation);
+            ids.push(annotation.id);
+            self.pages.entry(page).or_default().push(annotation.clone());
+            edits.push(Edit::Add { page, annotation });
+        }
+        self.record_all(edits);
+        ids
+    }
+
+    /// Añade una anotación que ya existía (leída del PDF) con un id nuevo, fuera del historial.
+    pub fn import(&mut self, page: u16, mut annotation: Annotation) -> u64 {
+        ensure_name(&mut annotation);
+        annotation.id = self.next_id;
+        self.next_id += 1;
+        self.pages.entry(page).or_default().push(annotation);
+        self.next_id - 1
+    }
+
+    /// Como `import`, para una anotación leída del /Annot número `index` de la página.
+    pub fn import_at(&mut self, page: u16, annotation: Annotation, index: usize) -> u64 {
+        let id = self.import(page, annotation);
+        if let Some(saved) = self.get(page, id).cloned() {
+            self.origins.insert(id, Origin { page, index, saved });
+        }
+        id
+    }
+
+    /// De qué /Annot del PDF salió la anotación `id`, si salió de alguno.
+    pub fn origin(&self, id: u64) -> Option<&Origin> {
+        self.origins.get(&id)
+    }
+
+    /// Índices de los /Annot de la página que están en el modelo (o lo estuvieron y se borraron),
+    /// con los ids de sus anotaciones. En orden de índice.
+    pub fn origins_on(&self, page: u16) -> BTreeMap<usize, Vec<u64>> {
+        let mut indices: BTreeMap<usize, Vec<u64>> = BTreeMap::new();
+        for (id, origin) in self.origins.iter().filter(|(_, origin)| origin.page == page) {
+            indices.entry(origin.index).or_default().push(*id);
+        }
+        indices.values_mut().for_each(|ids| ids.sort_unstable());
+        indices
+    }
+
+    /// Tras guardar en el PDF que se sigue editando: las anotaciones de `placed` (id, página,
+    /// índice de su /Annot) pasan a venir de ese archivo tal como están ahora; las que no
+    /// aparecen, de ninguno. El historial no se toca: deshacer después sigue funcionando.
+    pub fn mark_saved(&mut self, placed: &[(u64, u16, usize)]) {
+        self.origins.clear();
+        for &(id, page, index) in placed {
+            if let Some(saved) = self.get(page, id).cloned() {
+                self.origins.insert(id, Origin { page, index, saved });
+            }
+        }
+    }
+
+    /// Páginas con algún /Annot en el modelo.
+    pub fn origin_pages(&self) -> Vec<u16> {
+        let mut pages: Vec<u16> = self.origins.values().map(|origin| origin.page).collect();
+        pages.sort_unstable();
+        pages.dedup();
+        pages
+    }
+
+    pub fn get(&self, page: u16, id: u64) -> Option<&Annotation> {
+        self.pages.get(&page)?.iter().find(|a| a.id == id)
+    }
+
+    pub fn get_mut(&mut self, page: u16, id: u64) -> Option<&mut Annotation> {
+        self.pages.get_mut(&page)?.iter_mut().find(|a| a.id == id)
+    }
+
+    /// Registra en el historial una anotación ya terminada.
+    pub fn commit_added(&mut self, page: u16, id: u64) {
+        if let Some(annotation) = self.get(page, id).cloned() {
+            self.record(Edit::Add { page, annotation });
+        }
+    }
+
+    /// Registra en el historial un cambio hecho en sitio (p.ej. el texto de una nota tras editarlo).
+    pub fn commit_replaced(&mut self, page: u16, before: Annotation) {
+        self.commit_replaced_all(page, vec![before]);
+    }
+
+    /// Como `commit_replaced`, con varias anotaciones cambiadas a la vez (mover o redimensionar una selección).
+    pub fn commit_replaced_all(&mut self, page: u16, before: Vec<Annotation>) {
+        let mut edits = Vec::new();
+        for before in before {
+            let Some(annotation) = self.get_mut(page, before.id) else { continue };
+            if *annotation != before {
+                annotation.modified = Some(pdf_date_now());
+                edits.push(Edit::Replace { page, before, after: annotation.clone() });
+            }
+        }
+        self.record_all(edits);
+    }
+
+    /// Devuelve una anotación a un estado anterior sin pasar por el historial.
+    pub fn revert_to(&mut self, page: u16, before: &Annotation) {
+        replace_annotation(&mut self.pages, page, before);
+    }
+
+    /// Quita una anotación que aún no llegó al historial (p.ej. una figura sin arrastrar).
+    pub fn discard(&mut self, page: u16, id: u64) {
+        if let Some(list) = self.pages.get_mut(&page) {
+            list.retain(|a| a.id != id);
+        }
+    }
+
+    /// Borra una anotación (goma). Devuelve false si no existía.
+    pub fn remove(&mut self, page: u16, id: u64) -> bool {
+        self.remove_all(page, &[id]) > 0
+    }
+
+    /// Borra varias anotaciones en un solo paso del historial. Devuelve cuántas existían.
+    pub fn remove_all(&mut self, page: u16, ids: &[u64]) -> usize {
+        let Some(list) = self.pages.get_mut(&page) else { return 0 };
+        let mut edits = Vec::new();
+        for id in ids {
+            // El índice es el de ese momento: al deshacer, el grupo se revierte en orden inverso
+            let Some(index) = list.iter().position(|a| a.id == *id) else { continue };
+            let annotation = list.remove(index);
+            edits.push(Edit::Remove { page, index, annotation });
+        }
+        let removed = edits.len();
+        self.record_all(edits);
+        removed
+    }
+
+    /// Modifica anotaciones (recolorear...) dejando el cambio en el historial como un solo paso.
+    pub fn update(&mut self, page: u16, ids: &[u64], mut change: impl FnMut(&mut Annotation)) {
+        let mut edits = Vec::new();
+        for id in ids {
+            let Some(annotation) = self.get_mut(page, *id) else { continue };
+            let before = annotation.clone();
+            change(annotation);
+            annotation.id = *id; // El id no se toca: el historial lo usa para encontrarla
+            if *annotation != before {
+                annotation.modified = Some(pdf_date_now());
+                edits.push(Edit::Replace { page, before, after: annotation.clone() });
+            }
+        }
+        self.record_all(edits);
+    }
+
+    /// Deshace el último cambio. Devuelve la página afectada.
+    pub fn undo(&mut self) -> Option<u16> {
+        let edit = self.history.undo()?;
+        revert_edit(&mut self.pages, edit);
+        self.revision += 1;
+        Some(edit.page())
+    }
+
+    /// Rehace el último cambio deshecho. Devuelve la página afectada.
+    pub fn redo(&mut self) -> Option<u16> {
+        let edit = self.history.redo()?;
+        apply_edit(&mut self.pages, edit);
+        self.revision += 1;
+        Some(edit.page())
+    }
+
+    /// Alarga un trazo en curso con una nueva muestra.
+    pub fn push_sample(&mut self, page: u16, id: u64, sample: InkSample) {
+        if let Some(Annotation { kind: AnnotationKind::Ink { samples, .. }, .. }) = self.get_mut(page, id) {
+            samples.push(sample);
+        }
+    }
+}
+
+/// Nombre único nuevo (/NM) para una anotación: "windp-" y un UUID v4. Las líneas y flechas
+/// llevan además su figura ("windp-arrow-..."), para reconocerlas al releer el PDF.
+pub fn new_name(kind: &AnnotationKind) -> String {
+    let tag = match kind {
+        AnnotationKind::Shape { shape: Shape::Line, .. } => "line-",
+        AnnotationKind::Shape { shape: Shape::Arrow, .. } => "arrow-",
+        _ => "",
+    };
+    format!("{}{}{}", NAME_PREFIX, tag, uuid_v4())
+}
+
+// Las que llegan sin nombre (de un PDF o XFDF que no lo trae, o pegadas) reciben uno
+fn ensure_name(annotation: &mut Annotation) {
+    if annotation.name.is_none() {
+        annotation.name = Some(new_name(&annotation.kind));
+    }
+}
+
+// UUID v4 sin dependencias: 128 bits de RandomState (claves aleatorias del sistema) sobre un
+// contador y la hora, con los bits de versión y variante
+fn uuid_v4() -> String {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_nanos()).unwrap_or(0);
+    let random = || {
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_u64(COUNTER.fetch_add(1, Ordering::Relaxed));
+        hasher.write_u128(nanos);
+        hasher.finish()
+    };
+    let high = (random() & !0xF000) | 0x4000;
+    let low = (random() & !(0b11 << 62)) | (0b10 << 62);
+    format!("{:08x}-{:04x}-{:04x}-{:04x}-{:012x}", high >> 32, (high >> 16) & 0xFFFF, high & 0xFFFF, low >> 48, low & 0xFFFF_FFFF_FFFF)
+}
+
+/// Autor de las anotaciones nuevas: el usuario del sistema (como hace Acrobat por defecto).
+pub fn local_author() -> Option<String> {
+    std::env::var("USERNAME").or_else(|_| std::env::var("USER")).ok().filter(|name| !name.is_empty())
+}
+
+/// Fecha y hora actuales (UTC) en el formato de fecha de PDF y XFDF: "D:20240105120000Z".
+pub fn pdf_date_now() -> String {
+    let secs = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs() as i64).unwrap_or(0);
+    let (days, time) = (secs.div_euclid(86_400), secs.rem_euclid(86_400));
+    let (year, month, day) = civil_from_days(days);
+    format!("D:{:04}{:02}{:02}{:02}{:02}{:02}Z", year, month, day, time / 3600, time % 3600 / 60, time % 60)
+}
+
+/// Días desde 1970 -> fecha civil (algoritmo de H. Hinnant, calendario gregoriano proléptico).
+pub fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    (yoe + era * 400 + (month <= 2) as i64, month, day)
+}
+
+/// Fecha civil -> días desde 1970 (la inversa de `civil_from_days`).
+pub fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = year - (month <= 2) as i64;
+    let era = year.div_euclid(400);
+    let yoe = year - era * 400;
+    let doy = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+/// Segundos que la hora local va por delante de UTC en ese instante (con el horario de verano que toque).
+#[cfg(unix)]
+pub fn local_utc_offset(utc: i64) -> i64 {
+    use std::os::raw::{c_char, c_int, c_long};
+    #[repr(C)]
+    struct Tm { fields: [c_int; 9], gmtoff: c_long, zone: *const c_char }
+    extern "C" {
+        fn localtime_r(time: *const c_long, result: *mut Tm) -> *mut Tm;
+    }
+    let time = utc as c_long;
+    let mut tm = Tm { fields: [0; 9], gmtoff: 0, zone: std::ptr::null() };
+    // SAFETY: localtime_r solo escribe en `tm`, que es nuestro y tiene el tamaño de struct tm
+    let ok = unsafe { !localtime_r(&time, &mut tm).is_null() };
+    if ok { tm.gmtoff as i64 } else { 0 }
+}
+
+#[cfg(windows)]
+pub fn local_utc_offset(utc: i64) -> i64 {
+    use std::os::raw::c_int;
+    extern "C" {
+        fn _localtime64_s(result: *mut [c_int; 9], time: *const i64) -> c_int;
+        fn _mkgmtime64(tm: *mut [c_int; 9]) -> i64;
+    }
+    let mut tm = [0 as c_int; 9];
+    // SAFETY: el CRT solo lee y escribe el struct tm (nueve int) que le pasamos.
+    // La hora local leída como si fuera UTC, menos la UTC de verdad, es el desfase
+    unsafe {
+        if _localtime64_s(&mut tm, &utc) != 0 {
+            return 0;
+        }
+        _mkgmtime64(&mut tm) - utc
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+pub fn local_utc_offset(_utc: i64) -> i64 {
+    0
+}
+
+fn apply_edit(pages: &mut BTreeMap<u16, Vec<Annotation>>, edit: &Edit) {
+    match edit {
+        Edit::Add { page, annotation } => pages.entry(*page).or_default().push(annotation.clone()),
+        Edit::Remove { page, annotation, .. } => {
+            if let Some(list) = pages.get_mut(page) {
+                list.retain(|a| a.id != annotation.id);
+            }
+        },
+        Edit::Replace { page, after, .. } => replace_annotation(pages, *page, after),
+        Edit::Group(edits) => edits.iter().for_each(|edit| apply_edit(pages, edit)),
+    }
+}
+
+fn revert_edit(pages: &mut BTreeMap<u16, Vec<Annotation>>, edit: &Edit) {
+    match edit {
+        Edit::Add { page, annotation } => {
+            if let Some(list) = pages.get_mut(page) {
+                list.retain(|a| a.id != annotation.id);
+            }
+        },
+        Edit::Remove { page, index, annotation } => {
+            let list = pages.entry(*page).or_default();
+            list.insert((*index).min(list.len()), annotation.clone());
+        },
+        Edit::Replace { page, before, .. } => replace_annotation(pages, *page, before),
+        Edit::Group(edits) => edits.iter().rev().for_each(|edit| revert_edit(pages, edit)),
+    }
+}
+
+fn replace_annotation(pages: &mut BTreeMap<u16, Vec<Annotation>>, page: u16, annotation: &Annotation) {
+    if let Some(slot) = pages.get_mut(&page).and_then(|list| list.iter_mut().find(|a| a.id == annotation.id)) {
+        *slot = annotation.clone();
+    }
+}
+
+/// Destino de rasterizado: un buffer BGRA (el formato de la textura) del tamaño de la página a una escala dada.
+pub struct RasterTarget<'a> {
+    pub data: &'a mut [u8],
+    pub width: u32,
+    pub height: u32,
+    pub scale: f32,       // Píxeles por punto PDF
+    pub page_height: f32, // Alto de la página en puntos (para invertir el eje Y)
+}
+
+impl RasterTarget<'_> {
+    /// Punto de página -> píxel del buffer (origen arriba-izquierda).
+    pub fn to_pixel(&self, point: PagePoint) -> (f32, f32) {
+        (point.x * self.scale, (self.page_height - point.y) * self.scale)
+    }
+
+    fn full_rect(&self) -> PixelRect {
+        PixelRect { x0: 0, y0: 0, x1: self.width as i32, y1: self.height as i32 }
+    }
+}
+
+/// Rectángulo de píxeles semiabierto [x0, x1) x [y0, y1).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PixelRect {
+    pub x0: i32,
+    pub y0: i32,
+    pub x1: i32,
+    pub y1: i32,
+}
+
+impl PixelRect {
+    pub fn is_empty(&self) -> bool {
+        self.x0 >= self.x1 || self.y0 >= self.y1
+    }
+
+    pub fn union(&self, other: &PixelRect) -> PixelRect {
+        if self.is_empty() {
+            return *other;
+        }
+        if other.is_empty() {
+            return *self;
+        }
+        PixelRect { x0: self.x0.min(other.x0), y0: self.y0.min(other.y0), x1: self.x1.max(other.x1), y1: self.y1.max(other.y1) }
+    }
+
+    pub fn intersect(&self, other: &PixelRect) -> PixelRect {
+        PixelRect { x0: self.x0.max(other.x0), y0: self.y0.max(other.y0), x1: self.x1.min(other.x1), y1: self.y1.min(other.y1) }
+    }
+}
+
+/// Un punto de la curva ya en píxeles, con su radio.
+#[derive(Clone, Copy)]
+struct CurvePoint {
+    x: f32,
+    y: f32,
+    r: f32,
+}
+
+/// Máscara de cobertura (0-255) de un trazo. Los segmentos se combinan con max(),
+/// así el trazo es una única banda plana: pasar dos veces por el mismo sitio no oscurece.
+struct CoverageMask {
+    rect: PixelRect,
+    data: Vec<u8>,
+}
+
+impl CoverageMask {
+    fn new(rect: PixelRect) -> Self {
+        let len = if rect.is_empty() { 0 } else { ((rect.x1 - rect.x0) * (rect.y1 - rect.y0)) as usize };
+        Self { rect, data: vec![0; len] }
+    }
+
+    fn at(&self, x: i32, y: i32) -> u8 {
+        self.data[((y - self.rect.y0) * (self.rect.x1 - self.rect.x0) + (x - self.rect.x0)) as usize]
+    }
+
+    /// Cápsula con antialiasing de a hasta b (radio interpolado). Devuelve los píxeles tocados.
+    fn capsule(&mut self, a: CurvePoint, b: CurvePoint) -> PixelRect {
+        let r_max = a.r.max(b.r) + 1.0;
+        let bounds = PixelRect {
+            x0: (a.x.min(b.x) - r_max).floor() as i32,
+            y0: (a.y.min(b.y) - r_max).floor() as i32,
+            x1: (a.x.max(b.x) + r_max).ceil() as i32 + 1,
+            y1: (a.y.max(b.y) + r_max).ceil() as i32 + 1,
+        }.intersect(&self.rect);
+        if bounds.is_empty() {
+            return bounds;
+        }
+
+        let (abx, aby) = (b.x - a.x, b.y - a.y);
+        let len2 = abx * abx + aby * aby;
+        let stride = self.rect.x1 - self.rect.x0;
+
+        for py in bounds.y0..bounds.y1 {
+            for px in bounds.x0..bounds.x1 {
+                let (cx, cy) = (px as f32 + 0.5, py as f32 + 0.5);
+                let t = if len2 > 0.0 { (((cx - a.x) * abx + (cy - a.y) * aby) / len2).clamp(0.0, 1.0) } else { 0.0 };
+                let (dx, dy) = (cx - (a.x + abx * t), cy - (a.y + aby * t));
+                let r = a.r + (b.r - a.r) * t;
+                let coverage = (r - (dx * dx + dy * dy).sqrt() + 0.5).clamp(0.0, 1.0);
+
+                if coverage > 0.0 {
+                    let idx = ((py - self.rect.y0) * stride + (px - self.rect.x0)) as usize;
+                    self.data[idx] = self.data[idx].max((coverage * 255.0) as u8);
+                }
+            }
+        }
+        bounds
+    }
+
+    /// Rectángulo relleno (en píxeles, con bordes fraccionarios). Devuelve los píxeles tocados.
+    fn fill_rect(&mut self, x0: f32, y0: f32, x1: f32, y1: f32) -> PixelRect {
+        let bounds = PixelRect { x0: x0.floor() as i32, y0: y0.floor() as i32, x1: x1.ceil() as i32, y1: y1.ceil() as i32 }
+            .intersect(&self.rect);
+        let stride = self.rect.x1 - self.rect.x0;
+
+        for py in bounds.y0..bounds.y1 {
+            let cover_y = (y1.min(py as f32 + 1.0) - y0.max(py as f32)).clamp(0.0, 1.0);
+            for px in bounds.x0..bounds.x1 {
+                let cover_x = (x1.min(px as f32 + 1.0) - x0.max(px as f32)).clamp(0.0, 1.0);
+                let idx = ((py - self.rect.y0) * stride + (px - self.rect.x0)) as usize;
+                self.data[idx] = self.data[idx].max((cover_x * cover_y * 255.0) as u8);
+            }
+        }
+        bounds
+    }
+
+    fn polyline(&mut self, points: &[CurvePoint]) -> PixelRect {
+        let mut touched = PixelRect { x0: 0, y0: 0, x1: 0, y1: 0 };
+        match points {
+            [] => {},
+            [single] => touched = self.capsule(*single, *single),
+            _ => {
+                for pair in points.windows(2) {
+                    touched = touched.union(&self.capsule(pair[0], pair[1]));
+                }
+            },
+        }
+        touched
+    }
+
+    /// Compone el color con la cobertura sobre el destino (solo en `rect`).
+    fn composite(&self, target: &mut RasterTarget, color: Color, rect: PixelRect) {
+        let rect = rect.intersect(&self.rect).intersect(&target.full_rect());
+        // La textura del overlay es Bgra8Unorm, como el bitmap de PDFium: el color (RGBA) se escribe
+        // como BGRA, o el rojo saldría azul (ver la prueba overlay_pixels_are_bgra)
+        let color = [color[2], color[1], color[0], color[3]];
+        for py in rect.y0..rect.y1 {
+            for px in rect.x0..rect.x1 {
+                let idx = ((py as u32 * target.width + px as u32) * 4) as usize;
+                let dst: [u8; 4] = target.data[idx..idx + 4].try_into().unwrap();
+                let coverage = self.at(px, py);
+                let out = if coverage == 0 { dst } else { blend_over(dst, color, coverage) };
+                target.data[idx..idx + 4].copy_from_slice(&out);
+            }
+        }
+    }
+}
+
+// "Source over" con alpha no premultiplicado
+fn blend_over(dst: [u8; 4], color: Color, coverage: u8) -> [u8; 4] {
+    let sa = color[3] as f32 / 255.0 * coverage as f32 / 255.0;
+    let da = dst[3] as f32 / 255.0;
+    let out_a = sa + da * (1.0 - sa);
+    if out_a <= 0.0 {
+        return [0, 0, 0, 0];
+    }
+    let channel = |i: usize| ((color[i] as f32 * sa + dst[i] as f32 * da * (1.0 - sa)) / out_a).round() as u8;
+    [channel(0), channel(1), channel(2), (out_a * 255.0).round() as u8]
+}
+
+/// Radio en píxeles de una muestra según la presión.
+fn sample_radius(width: f32, pressure: f32, scale: f32) -> f32 {
+    let factor = MIN_PRESSURE_WIDTH + (1.0 - MIN_PRESSURE_WIDTH) * pressure.clamp(0.0, 1.0);
+    width * factor * 0.5 * scale
+}
+
+/// Tramo suavizado (Catmull-Rom) entre las muestras i e i+1, en píxeles.
+/// En los extremos del trazo se repite la muestra final como punto de control.
+fn curve_segment(target: &RasterTarget, samples: &[InkSample], width: f32, i: usize) -> Vec<CurvePoint> {
+    let last = samples.len() - 1;
+    let control = |j: usize| {
+        let s = samples[j.min(last)];
+        let (x, y) = target.to_pixel(s.pos);
+        CurvePoint { x, y, r: sample_radius(width, s.pressure, target.scale) }
+    };
+    let (p0, p1, p2, p3) = (control(i.saturating_sub(1)), control(i), control(i + 1), control(i + 2));
+
+    let dist = ((p2.x - p1.x).powi(2) + (p2.y - p1.y).powi(2)).sqrt();
+    let steps = ((dist / CURVE_STEP_PX).ceil() as usize).max(1);
+
+    (0..=steps).map(|k| {
+        let t = k as f32 / steps as f32;
+        let (t2, t3) = (t * t, t * t * t);
+        let catmull = |a: f32, b: f32, c: f32, d: f32| {
+            0.5 * (2.0 * b + (c - a) * t + (2.0 * a - 5.0 * b + 4.0 * c - d) * t2 + (3.0 * b - a - 3.0 * c + d) * t3)
+        };
+        CurvePoint {
+            x: catmull(p0.x, p1.x, p2.x, p3.x),
+            y: catmull(p0.y, p1.y, p2.y, p3.y),
+            r: p1.r + (p2.r - p1.r) * t, // La presión se interpola linealmente
+        }
+    }).collect()
+}
+
+/// Curva completa de un trazo en píxeles.
+fn stroke_curve(target: &RasterTarget, samples: &[InkSample], width: f32) -> Vec<CurvePoint> {
+    if samples.len() == 1 {
+        return curve_segment(target, samples, width, 0)[..1].to_vec();
+    }
+    (0..samples.len() - 1).flat_map(|i| curve_segment(target, samples, width, i)).collect()
+}
+
+/// Curvas de una anotación en píxeles (trazo suavizado o contorno de la figura).
+fn annotation_curves(target: &RasterTarget, annotation: &Annotation) -> Vec<Vec<CurvePoint>> {
+    match &annotation.kind {
+        AnnotationKind::Ink { samples, width } => {
+            if samples.is_empty() {
+                return Vec::new();
+            }
+            vec![stroke_curve(target, samples, *width)]
+        },
+        AnnotationKind::Shape { width, .. } => {
+            let r = width * 0.5 * target.scale;
+            annotation.kind.outline().into_iter().map(|polyline| polyline.into_iter().map(|point| {
+                let (x, y) = target.to_pixel(point);
+                CurvePoint { x, y, r }
+            }).collect()).collect()
+        },
+        AnnotationKind::TextHighlight { .. } | AnnotationKind::Note { .. } | AnnotationKind::FreeText { .. } => Vec::new(),
+    }
+}
+
+/// Rectángulos rellenos de una anotación en puntos de página ([left, bottom, right, top]).
+pub fn filled_rects(kind: &AnnotationKind) -> Vec<[f32; 4]> {
+    match kind {
+        AnnotationKind::TextHighlight { rects, .. } => rects.clone(),
+        AnnotationKind::Note { at, .. } => {
+            // Hoja con marco y tres renglones
+            let (s, line) = (NOTE_ICON_SIZE, NOTE_ICON_SIZE / 9.0);
+            let (l, t) = (at.x, at.y);
+            let mut rects = vec![
+                [l, t - s, l + s, t - s + line * 1.5], // Marco: abajo, arriba (más grueso), lados
+                [l, t - line * 2.5, l + s, t],
+                [l, t - s, l + line, t],
+                [l + s - line, t - s, l + s, t],
+            ];
+            for n in 0..3 {
+                let y = t - line * (4.0 + n as f32 * 1.6);
+                rects.push([l + line * 2.0, y - line * 0.6, l + s - line * 2.0, y]);
+            }
+            rects
+        },
+        AnnotationKind::FreeText { at, text, size } => {
+            // Cada píxel encendido de la fuente bitmap es un cuadrado de `unit` puntos
+            let unit = size / text::LINE_HEIGHT as f32;
+            let mut rects = Vec::new();
+            for (n, line) in free_text_lines(text).iter().enumerate() {
+                let top = at.y - (n as u32 * text::LINE_HEIGHT) as f32 * unit;
+                text::for_each_pixel(line, |col, row| {
+                    let (l, t) = (at.x + col as f32 * unit, top - row as f32 * unit);
+                    rects.push([l, t - unit, l + unit, t]);
+                });
+            }
+            rects
+        },
+        AnnotationKind::Ink { .. } | AnnotationKind::Shape { .. } => Vec::new(),
+    }
+}
+
+// Rectángulos rellenos en píxeles del buffer: (x0, y0, x1, y1) con y hacia abajo
+fn fill_pixel_rects(target: &RasterTarget, annotation: &Annotation) -> Vec<(f32, f32, f32, f32)> {
+    filled_rects(&annotation.kind).iter().map(|&[l, b, r, t]| {
+        let (x0, y0) = target.to_pixel(PagePoint { x: l, y: t });
+        let (x1, y1) = target.to_pixel(PagePoint { x: r, y: b });
+        (x0, y0, x1, y1)
+    }).collect()
+}
+
+/// Pinta la cobertura de una anotación en la máscara. Devuelve los píxeles tocados.
+fn draw_coverage(mask: &mut CoverageMask, target: &RasterTarget, annotation: &Annotation) -> PixelRect {
+    let mut touched = PixelRect { x0: 0, y0: 0, x1: 0, y1: 0 };
+    for curve in annotation_curves(target, annotation) {
+        touched = touched.union(&mask.polyline(&curve));
+    }
+    for (x0, y0, x1, y1) in fill_pixel_rects(target, annotation) {
+        touched = touched.union(&mask.fill_rect(x0, y0, x1, y1));
+    }
+    touched
+}
+
+/// Pinta una anotación completa en el buffer.
+pub fn rasterize_annotation(target: &mut RasterTarget, annotation: &Annotation) {
+    let full = target.full_rect();
+    rasterize_annotation_in(target, annotation, full);
+}
+
+/// Como `rasterize_annotation`, sin tocar nada fuera de `clip` (para repintar solo una zona del overlay).
+pub fn rasterize_annotation_in(target: &mut RasterTarget, annotation: &Annotation, clip: PixelRect) {
+    let mut mask = CoverageMask::new(mask_rect(target, annotation).intersect(&clip));
+    let touched = draw_coverage(&mut mask, target, annotation);
+    mask.composite(target, annotation.color, touched);
+}
+
+// Píxeles que puede tocar la anotación: la máscara de cobertura solo reserva eso, no la página entera
+fn mask_rect(target: &RasterTarget, annotation: &Annotation) -> PixelRect {
+    let mut bounds = annotation_curves(target, annotation).iter()
+        .fold(PixelRect { x0: 0, y0: 0, x1: 0, y1: 0 }, |acc, c| acc.union(&curve_bounds(c)));
+    for (x0, y0, x1, y1) in fill_pixel_rects(target, annotation) {
+        bounds = bounds.union(&PixelRect { x0: x0.floor() as i32, y0: y0.floor() as i32, x1: x1.ceil() as i32, y1: y1.ceil() as i32 });
+    }
+    bounds.intersect(&target.full_rect())
+}
+
+/// Las dos capas de una página tal como se ven en pantalla, en buffers BGRA de `width` x `height`:
+/// el overlay raster (notas y texto libre) y los trazos. Para exportar con las anotaciones aplanadas.
+pub fn rasterize_layers(annotations: &[Annotation], width: u32, height: u32, scale: f32, page_height: f32) -> [Vec<u8>; 2] {
+    [false, true].map(|strokes| {
+        let mut data = vec![0u8; (width * height * 4) as usize];
+        let mut target = RasterTarget { data: &mut data, width, height, scale, page_height };
+        for annotation in annotations.iter().filter(|a| a.kind.is_stroke() == strokes) {
+            rasterize_annotation(&mut target, annotation);
+        }
+        data
+    })
+}
+
+/// Compone una capa sobre la página como el shader: mix(página, capa * página, alpha de la capa).
+/// Ambos buffers BGRA del mismo tamaño.
+pub fn multiply_layer(page: &mut [u8], layer: &[u8]) {
+    for (dst, src) in page.chunks_exact_mut(4).zip(layer.chunks_exact(4)) {
+        if src[3] == 0 {
+            continue;
+        }
+        let alpha = src[3] as f32 / 255.0;
+        for i in 0..3 {
+            let factor = 1.0 - alpha + alpha * src[i] as f32 / 255.0;
+            dst[i] = (dst[i] as f32 * factor).round() as u8;
+        }
+    }
+}
+
+fn curve_bounds(curve: &[CurvePoint]) -> PixelRect {
+    let mut rect = PixelRect { x0: i32::MAX, y0: i32::MAX, x1: i32::MIN, y1: i32::MIN };
+    for p in curve {
+        let r = p.r + 1.0;
+        rect.x0 = rect.x0.min((p.x - r).floor() as i32);
+        rect.y0 = rect.y0.min((p.y - r).floor() as i32);
+        rect.x1 = rect.x1.max((p.x + r).ceil() as i32 + 1);
+        rect.y1 = rect.y1.max((p.y + r).ceil() as i32 + 1);
+    }
+    rect
+}
+
+/// Curvas de una anotación en puntos de página, con el radio del trazo (en puntos) en cada punto:
+/// las mismas que pinta el raster (tinta suavizada o contorno de la figura), para teselarlas en GPU.
+/// `density` es cuántos puntos de curva, como mucho, hay por punto de página.
+pub fn page_curves(annotation: &Annotation, density: f32) -> Vec<Vec<(PagePoint, f32)>> {
+    // Destino sin píxeles, solo para la conversión: con alto de página 0, y_px = -y * escala
+    let scale = density * CURVE_STEP_PX;
+    let target = RasterTarget { data: &mut [], width: 0, height: 0, scale, page_height: 0.0 };
+    annotation_curves(&target, annotation).into_iter().map(|curve| {
+        curve.into_iter().map(|p| (PagePoint { x: p.x / scale, y: -p.y / scale }, p.r / scale)).collect()
+    }).collect()
+}
+
+/// Polígonos convexos, todos en sentido antihorario, cuya unión es la anotación tal como se ve:
+/// las cajas de un resaltado, o por cada curva de `page_curves` la banda de grosor variable
+/// (un cuadrilátero por tramo) con círculos en las puntas y las esquinas. En puntos de página.
+/// Los tesela la GPU y, como trayecto relleno con la regla del número de vueltas, van al PDF aplanado.
+pub fn page_polygons(annotation: &Annotation, density: f32) -> Vec<Vec<PagePoint>> {
+    let mut polygons = Vec::new();
+    match &annotation.kind {
+        AnnotationKind::TextHighlight { rects, .. } => {
+            for &[l, b, r, t] in rects {
+                let p = |x: f32, y: f32| PagePoint { x, y };
+                polygons.push(vec![p(l, b), p(r, b), p(r, t), p(l, t)]);
+            }
+        },
+        _ => {
+            for curve in page_curves(annotation, density) {
+                band_polygons(&curve, &mut polygons);
+            }
+        },
+    }
+    polygons
+}
+
+// Círculo: las puntas redondas y las esquinas (como las cápsulas del raster)
+fn disc(center: PagePoint, r: f32) -> Vec<PagePoint> {
+    let segments = ((r * 8.0).ceil() as usize).clamp(16, 64);
+    (0..segments).map(|i| {
+        let angle = i as f32 / segments as f32 * std::f32::consts::TAU;
+        PagePoint { x: center.x + r * angle.cos(), y: center.y + r * angle.sin() }
+    }).collect()
+}
+
+// Banda de grosor variable a lo largo de la curva (radio en cada punto) con puntas redondas.
+// En las uniones suaves los tramos comparten los vértices (inglete); las esquinas se redondean
+fn band_polygons(curve: &[(PagePoint, f32)], out: &mut Vec<Vec<PagePoint>>) {
+    // Puntos repetidos (el lápiz quieto) no tienen dirección
+    let mut points: Vec<(PagePoint, f32)> = Vec::with_capacity(curve.len());
+    for &(p, r) in curve {
+        if points.last().is_none_or(|(q, _)| (p.x - q.x).hypot(p.y - q.y) > 1e-4) {
+            points.push((p, r));
+        }
+    }
+    let (Some(&(first, first_r)), Some(&(last, last_r))) = (points.first(), points.last()) else { return };
+    out.push(disc(first, first_r));
+    if points.len() == 1 {
+        return;
+    }
+    out.push(disc(last, last_r));
+
+    // Normal unitaria (a la izquierda) de cada tramo
+    let normals: Vec<(f32, f32)> = points.windows(2).map(|pair| {
+        let (dx, dy) = (pair[1].0.x - pair[0].0.x, pair[1].0.y - pair[0].0.y);
+        let len = dx.hypot(dy);
+        (-dy / len, dx / len)
+    }).collect();
+    let is_corner = |i: usize| {
+        let (a, b) = (normals[i - 1], normals[i]);
+        a.0 * b.0 + a.1 * b.1 < SMOOTH_JOIN_COS
+    };
+    // Dirección del desplazamiento en el punto i para el tramo k: la bisectriz en una unión suave
+    // (alargada para conservar el grosor) o la normal del propio tramo en una esquina o un extremo
+    let offset = |i: usize, k: usize| {
+        if i == 0 || i == normals.len() || is_corner(i) {
+            return normals[k];
+        }
+        let (a, b) = (normals[i - 1], normals[i]);
+        let (mx, my) = (a.0 + b.0, a.1 + b.1);
+        let len2 = (mx * mx + my * my).max(f32::EPSILON);
+        (mx * 2.0 / len2, my * 2.0 / len2)
+    };
+
+    for k in 0..normals.len() {
+        let ((a, ra), (b, rb)) = (points[k], points[k + 1]);
+        let (na, nb) = (offset(k, k), offset(k + 1, k));
+        let side = |p: PagePoint, n: (f32, f32), r: f32| (PagePoint { x: p.x + n.0 * r, y: p.y + n.1 * r }, PagePoint { x: p.x - n.0 * r, y: p.y - n.1 * r });
+        let ((a_left, a_right), (b_left, b_right)) = (side(a, na, ra), side(b, nb, rb));
+        // Izquierda, derecha, y de vuelta por el otro extremo: antihorario
+        out.push(vec![a_left, a_right, b_right, b_left]);
+    }
+    // Esquinas: los puntos interiores (ni el primero ni el último)
+    for (i, &(point, radius)) in points.iter().enumerate().take(normals.len()).skip(1) {
+        if is_corner(i) {
+            out.push(disc(point, radius));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn p(x: f32, y: f32) -> PagePoint {
+        PagePoint { x, y }
+    }
+
+    fn annotation(kind: AnnotationKind) -> Annotation {
+        Annotation { id: 1, kind, color: [0, 0, 0, 255], author: None, modified: None, name: None }
+    }
+
+    #[test]
+    fn inside_polygon_table() {
+        let square = [p(0.0, 0.0), p(100.0, 0.0), p(100.0, 100.0), p(0.0, 100.0)];
+        // Lazo en forma de U: el hueco central (x 40..60, y 40..100) queda fuera
+        let u = [p(0.0, 0.0), p(100.0, 0.0), p(100.0, 100.0), p(60.0, 100.0), p(60.0, 40.0), p(40.0, 40.0), p(40.0, 100.0), p(0.0, 100.0)];
+        let ink = |points: &[(f32, f32)]| AnnotationKind::Ink { samples: points.iter().map(|&(x, y)| InkSample { pos: p(x, y), pressure: 1.0 }).collect(), width: 2.0 };
+        let cases = [
+            ("trazo dentro", ink(&[(10.0, 10.0), (90.0, 90.0)]), &square[..], true),
+            ("trazo que sale", ink(&[(10.0, 10.0), (110.0, 50.0)]), &square[..], false),
+            ("trazo vacío", ink(&[]), &square[..], false),
+            ("trazo en el hueco de la U", ink(&[(50.0, 60.0)]), &u[..], false),
+            ("trazo en un brazo de la U", ink(&[(20.0, 20.0), (20.0, 90.0)]), &u[..], true),
+            ("segmento que cruza el hueco", ink(&[(20.0, 80.0), (80.0, 80.0)]), &u[..], false),
+            ("rectángulo dentro", AnnotationKind::Shape { shape: Shape::Rectangle, from: p(5.0, 5.0), to: p(30.0, 30.0), width: 1.0 }, &square[..], true),
+            ("elipse que asoma", AnnotationKind::Shape { shape: Shape::Ellipse, from: p(-5.0, 5.0), to: p(30.0, 30.0), width: 1.0 }, &square[..], false),
+            ("resaltado de dos líneas", AnnotationKind::TextHighlight { rects: vec![[10.0, 10.0, 90.0, 20.0], [10.0, 30.0, 50.0, 40.0]], text: String::new() }, &square[..], true),
+            ("una línea del resaltado fuera", AnnotationKind::TextHighlight { rects: vec![[10.0, 10.0, 90.0, 20.0], [10.0, 90.0, 50.0, 120.0]], text: String::new() }, &square[..], false),
+            ("icono de nota", AnnotationKind::Note { at: p(10.0, 90.0), text: "x".into() }, &square[..], true),
+            ("nota en el borde superior", AnnotationKind::Note { at: p(10.0, 101.0), text: "x".into() }, &square[..], false),
+            ("polígono degenerado", ink(&[(10.0, 10.0)]), &[p(0.0, 0.0), p(100.0, 100.0)][..], false),
+        ];
+        for (name, kind, polygon, expected) in cases {
+            assert_eq!(inside_polygon(&annotation(kind), polygon), expected, "{}", name);
+        }
+    }
+
+    #[test]
+    fn overlay_pixels_are_bgra() {
+        let mut data = vec![0u8; 40 * 40 * 4];
+        let mut target = RasterTarget { data: &mut data, width: 40, height: 40, scale: 1.0, page_height: 40.0 };
+        let line = AnnotationKind::Shape { shape: Shape::Line, from: p(5.0, 20.0), to: p(35.0, 20.0), width: 6.0 };
+        rasterize_annotation(&mut target, &Annotation { color: [255, 64, 0, 255], ..annotation(line) });
+        let idx = (20 * 40 + 20) * 4;
+        assert_eq!(&data[idx..idx + 4], &[0, 64, 255, 255]);
+    }
+
+    #[test]
+    fn coverage_mask_covers_only_the_annotation() {
+        let mut data = vec![0u8; 1000 * 1000 * 4];
+        let target = RasterTarget { data: &mut data, width: 1000, height: 1000, scale: 2.0, page_height: 500.0 };
+        let ink = AnnotationKind::Ink { samples: [(100.0, 100.0), (120.0, 110.0)].map(|(x, y)| InkSample { pos: p(x, y), pressure: 1.0 }).to_vec(), width: 4.0 };
+        let rect = mask_rect(&target, &annotation(ink));
+        // El trazo mide 20x10 puntos (40x20 píxeles) más el grosor y el antialiasing
+        assert!(rect.x0 >= 190 && rect.x1 <= 250 && rect.y0 >= 770 && rect.y1 <= 815, "{:?}", rect);
+        let note = AnnotationKind::Note { at: p(-100.0, 600.0), text: String::new() };
+        assert!(mask_rect(&target, &annotation(note)).is_empty());
+    }
+
+    #[test]
+    fn origins_group_strokes_and_outlive_removal() {
+        let mut store = AnnotationStore::new();
+        let stroke = |x: f32| AnnotationKind::Ink { samples: vec![InkSample { pos: p(x, 0.0), pressure: 1.0 }], width: 1.0 };
+        let first = store.import_at(2, annotation(stroke(1.0)), 3);
+        let second = store.import_at(2, annotation(stroke(2.0)), 3);
+        let note = store.import_at(2, annotation(AnnotationKind::Note { at: p(0.0, 0.0), text: "n".into() }), 0);
+        let fresh = store.insert(2, annotation(stroke(3.0)));
+
+        assert_eq!(store.origins_on(2), BTreeMap::from([(0, vec![note]), (3, vec![first, second])]));
+        assert!(store.origins_on(1).is_empty());
+        assert_eq!(store.origin_pages(), vec![2]);
+        assert!(store.origin(fresh).is_none());
+
+        // Borrada del modelo, su /Annot sigue apuntado: al guardar hay que quitarlo
+        assert!(store.remove(2, first));
+        assert_eq!(store.origin(first).map(|origin| origin.index), Some(3));
+        assert_eq!(store.origins_on(2)[&3], vec![first, second]);
+        assert_eq!(store.origin(second).map(|origin| &origin.saved.kind), Some(&stroke(2.0)));
+    }
+
+    #[test]
+    fn mark_saved_moves_origins_and_keeps_history() {
+        let mut store = AnnotationStore::new();
+        let note = |text: &str| annotation(AnnotationKind::Note { at: p(0.0, 0.0), text: text.into() });
+        let kept = store.import_at(0, note("a"), 0);
+        let deleted = store.import_at(0, note("b"), 1);
+        let added = store.insert(0, note("c"));
+        assert!(store.remove(0, deleted));
+        store.update(0, &[kept], |a| a.kind.translate(1.0, 0.0));
+
+        store.mark_saved(&[(kept, 0, 0), (added, 0, 1)]);
+        assert_eq!(store.origins_on(0), BTreeMap::from([(0, vec![kept]), (1, vec![added])]));
+        assert_eq!(store.origin(kept).map(|origin| &origin.saved), store.get(0, kept));
+        assert!(store.origin(deleted).is_none());
+
+        // Deshacer sigue funcionando: el movimiento vuelve atrás y ya no coincide con lo guardado
+        assert_eq!(store.undo(), Some(0));
+        assert_ne!(store.origin(kept).map(|origin| &origin.saved), store.get(0, kept));
+        assert_eq!(store.undo(), Some(0));
+        assert!(store.get(0, deleted).is_some() && store.origin(deleted).is_none());
+    }
+
+    #[test]
+    fn pdf_date_now_is_a_utc_pdf_date() {
+        let before = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i64;
+        let date = pdf_date_now();
+        let after = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i64;
+        assert!(date.len() == 17 && date.starts_with("D:") && date.ends_with('Z'), "{}", date);
+        let field = |range: std::ops::Range<usize>| date[range].parse::<i64>().unwrap();
+        let secs = days_from_civil(field(2..6), field(6..8), field(8..10)) * 86_400 + field(10..12) * 3600 + field(12..14) * 60 + field(14..16);
+        assert!((before..=after).contains(&secs), "{} fuera de {}..={}", date, before, after);
+    }
+
+    #[test]
+    fn insert_pages_is_one_undo_step() {
+        let mut store = AnnotationStore::new();
+        let note = |text: &str| Annotation { name: None, ..annotation(AnnotationKind::Note { at: p(0.0, 0.0), text: text.into() }) };
+        let ids = store.insert_pages(vec![(0, note("a")), (3, note("b")), (3, note("c"))]);
+        assert_eq!(ids.len(), 3);
+        assert_eq!((store.page(0).len(), store.page(3).len()), (1, 2));
+        assert!(store.page(3).iter().all(|a| a.name.as_deref().is_some_and(|name| name.starts_with("windp-"))));
+        assert_eq!(store.undo(), Some(0));
+        assert!(store.is_empty());
+        assert!(store.undo().is_none());
+    }
+
+    #[test]
+    fn page_polygons_are_convex_and_counterclockwise() {
+        let zigzag = (0..8).map(|i| InkSample { pos: p(i as f32 * 10.0, if i % 2 == 0 { 0.0 } else { 10.0 }), pressure: 0.3 + i as f32 * 0.1 }).collect();
+        let kinds = [
+            AnnotationKind::Ink { samples: zigzag, width: 4.0 },
+            AnnotationKind::Ink { samples: vec![InkSample { pos: p(5.0, 5.0), pressure: 1.0 }], width: 3.0 },
+            AnnotationKind::Shape { shape: Shape::Arrow, from: p(0.0, 0.0), to: p(60.0, 30.0), width: 2.0 },
+            AnnotationKind::Shape { shape: Shape::Ellipse, from: p(0.0, 0.0), to: p(60.0, 30.0), width: 2.0 },
+            AnnotationKind::TextHighlight { rects: vec![[0.0, 0.0, 50.0, 10.0], [0.0, 12.0, 30.0, 22.0]], text: String::new() },
+        ];
+        for kind in kinds {
+            let polygons = page_polygons(&annotation(kind.clone()), 2.0);
+            assert!(!polygons.is_empty(), "{:?}", kind);
+            for polygon in polygons {
+                // Todos los giros a la izquierda (o rectos): convexo y antihorario
+                for i in 0..polygon.len() {
+                    let (a, b, c) = (polygon[i], polygon[(i + 1) % polygon.len()], polygon[(i + 2) % polygon.len()]);
+                    let cross = (b.x - a.x) * (c.y - b.y) - (b.y - a.y) * (c.x - b.x);
+                    assert!(cross >= -1e-3, "{:?}: {:?}", kind, polygon);
+                }
+            }
+        }
+        let note = AnnotationKind::Note { at: p(0.0, 0.0), text: String::new() };
+        assert!(page_polygons(&annotation(note), 2.0).is_empty());
+    }
+
+}
@@ -0,0 +1,249 @@
+use super::overlay::{civil_from_days, days_from_civil, hit_test, local_utc_offset, Annotation, AnnotationKind, AnnotationStore, Color, PagePoint, Shape};
+use super::COLOR_PRESETS;
+use crate::pdf::text::PageChar;
+use std::collections::HashMap;
+
+/// Una anotación tal como aparece en el panel de resumen y en el Markdown.
+#[derive(Clone, Debug)]
+pub struct SummaryEntry {
+    pub page: u16, // Base 0
+    pub id: u64,
+    pub label: &'static str,
+    pub color: Color,
+    pub text: String, // Texto resaltado (de la capa de texto) o contenido de la nota
+    pub is_quote: bool, // El texto es de la página (se cita) y no escrito por el usuario
+    pub author: Option<String>,
+    pub date: Option<String>, // Ya legible: "2024-01-05 12:00"
+}
+
+/// Recorre todas las anotaciones del documento por página y en orden de pintado.
+/// `text_layer` da los caracteres de una página: el texto de cada resaltado se lee de ahí
+/// (el guardado al resaltar puede faltar en los que vienen de un PDF o un XFDF). Solo se llama para
+/// las páginas que aún no están en `text_cache`, y solo si tienen algo que citar.
+pub fn build(store: &AnnotationStore, text_cache: &mut HashMap<u16, Vec<PageChar>>, mut text_layer: impl FnMut(u16) -> Vec<PageChar>) -> Vec<SummaryEntry> {
+    let mut entries = Vec::new();
+    for (page, annotations) in store.pages() {
+        for annotation in annotations {
+            let (label, text, is_quote) = match &annotation.kind {
+                AnnotationKind::TextHighlight { text, .. } => {
+                    let under = text_under(annotation, cached_text(text_cache, page, &mut text_layer));
+                    ("Resaltado", if under.is_empty() { text.clone() } else { under }, true)
+                },
+                // Un trazo translúcido es un subrayador a mano alzada: también cita el texto que cubre
+                AnnotationKind::Ink { .. } if annotation.color[3] < 255 => {
+                    ("Subrayado", text_under(annotation, cached_text(text_cache, page, &mut text_layer)), true)
+                },
+                AnnotationKind::Ink { .. } => ("Trazo", String::new(), false),
+                AnnotationKind::Shape { shape, .. } => (shape_label(*shape), String::new(), false),
+                AnnotationKind::Note { text, .. } => ("Nota", text.clone(), false),
+                AnnotationKind::FreeText { text, .. } => ("Texto", text.clone(), false),
+            };
+            entries.push(SummaryEntry {
+                page,
+                id: annotation.id,
+                label,
+                color: annotation.color,
+                text,
+                is_quote,
+                author: annotation.author.clone(),
+                date: annotation.modified.as_deref().and_then(display_date),
+            });
+        }
+    }
+    entries
+}
+
+fn cached_text<'a>(cache: &'a mut HashMap<u16, Vec<PageChar>>, page: u16, text_layer: &mut impl FnMut(u16) -> Vec<PageChar>) -> &'a [PageChar] {
+    cache.entry(page).or_insert_with(|| text_layer(page))
+}
+
+fn shape_label(shape: Shape) -> &'static str {
+    match shape {
+        Shape::Rectangle => "Rectángulo",
+        Shape::Ellipse => "Elipse",
+        Shape::Line => "Línea",
+        Shape::Arrow => "Flecha",
+    }
+}
+
+// Caracteres cuyo centro cae bajo la anotación, en orden de lectura
+fn text_under(annotation: &Annotation, chars: &[PageChar]) -> String {
+    let mut text = String::new();
+    let mut inside = false;
+    for c in chars {
+        match c.bounds {
+            Some([l, b, r, t]) => {
+                inside = hit_test(annotation, PagePoint { x: (l + r) * 0.5, y: (b + t) * 0.5 }, 0.0);
+                if inside {
+                    text.push(c.ch);
+                }
+            },
+            // Espacios y saltos (sin caja) solo cuentan entre caracteres resaltados
+            None if inside => text.push(' '),
+            None => {},
+        }
+    }
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// "D:20240105120000Z" (fecha PDF) -> "2024-01-05 13:00", en la hora local de quien lee. Las fechas
+/// sin zona horaria se muestran tal cual (no se sabe de dónde son); sin hora, solo el día.
+pub fn display_date(date: &str) -> Option<String> {
+    display_date_in(date, local_utc_offset)
+}
+
+fn display_date_in(date: &str, local_offset: impl Fn(i64) -> i64) -> Option<String> {
+    let body = date.trim().trim_start_matches("D:");
+    let digits: String = body.chars().take_while(char::is_ascii_digit).collect();
+    let part = |range: std::ops::Range<usize>| digits.get(range);
+    let (year, month, day) = (part(0..4)?, part(4..6).unwrap_or("01"), part(6..8).unwrap_or("01"));
+    let (Some(hour), Some(minute)) = (part(8..10), part(10..12)) else {
+        return Some(format!("{}-{}-{}", year, month, day));
+    };
+    let raw = format!("{}-{}-{} {}:{}", year, month, day, hour, minute);
+
+    // Zona: "Z", "+01'00'" o "-0530"; con ella se pasa a UTC y de ahí a la hora local
+    let number = |text: &str| text.parse::<i64>().ok();
+    let zone = &body[digits.len()..];
+    let source_offset = match zone.chars().next() {
+        Some('Z' | 'z') => Some(0),
+        Some(sign @ ('+' | '-')) => {
+            let fields: String = zone[1..].chars().filter(char::is_ascii_digit).collect();
+            let hours = fields.get(0..2).and_then(number);
+            let minutes = fields.get(2..4).and_then(number).unwrap_or(0);
+            hours.map(|hours| (hours * 3600 + minutes * 60) * if sign == '-' { -1 } else { 1 })
+        },
+        _ => None,
+    };
+    let (Some(source_offset), Some(y), Some(mo), Some(d), Some(h), Some(mi)) =
+        (source_offset, number(year), number(month), number(day), number(hour), number(minute)) else {
+        return Some(raw);
+    };
+    if !(1..=12).contains(&mo) || !(1..=31).contains(&d) || h > 23 || mi > 59 {
+        return Some(raw);
+    }
+
+    let utc = days_from_civil(y, mo, d) * 86_400 + h * 3600 + mi * 60 - source_offset;
+    let local = utc + local_offset(utc);
+    let (y, mo, d) = civil_from_days(local.div_euclid(86_400));
+    let time = local.rem_euclid(86_400);
+    Some(format!("{:04}-{:02}-{:02} {:02}:{:02}", y, mo, d, time / 3600, time % 3600 / 60))
+}
+
+/// Nombre del color de la paleta más parecido (el resaltado amarillo no es exactamente el de la paleta).
+pub fn color_name(color: Color) -> &'static str {
+    const NAMES: [&str; 6] = ["negro", "rojo", "azul", "verde", "amarillo", "naranja"];
+    let distance = |preset: &[u8; 3]| (0..3).map(|i| (preset[i] as i32 - color[i] as i32).pow(2)).sum::<i32>();
+    let nearest = COLOR_PRESETS.iter().enumerate().min_by_key(|(_, preset)| distance(preset)).map_or(0, |(i, _)| i);
+    NAMES[nearest]
+}
+
+/// Resumen en Markdown para notas de lectura: un apartado por página, los resaltados como citas.
+pub fn to_markdown(document_name: &str, entries: &[SummaryEntry]) -> String {
+    let mut md = format!("# Anotaciones - {}\n\n", document_name);
+    let mut current_page = None;
+
+    for entry in entries {
+        if current_page != Some(entry.page) {
+            current_page = Some(entry.page);
+            md.push_str(&format!("## Página {}\n\n", entry.page + 1));
+        }
+
+        let mut meta = vec![format!("{} {}", entry.label, color_name(entry.color)), format!("p. {}", entry.page + 1)];
+        meta.extend(entry.author.clone());
+        meta.extend(entry.date.clone());
+        let meta = meta.join(" · ");
+
+        if entry.is_quote && !entry.text.is_empty() {
+            md.push_str(&format!("> {}\n>\n> — *{}*\n\n", entry.text.replace('\n', "\n> "), meta));
+        } else if entry.text.is_empty() {
+            md.push_str(&format!("- *{}*\n\n", meta));
+        } else {
+            // Sangría para que las líneas de una nota larga sigan dentro del punto de la lista
+            md.push_str(&format!("- {}\n  *{}*\n\n", entry.text.replace('\n', "\n  "), meta));
+        }
+    }
+    md
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn display_date_table() {
+        // Lector en Madrid en invierno (UTC+1)
+        let madrid = |_: i64| 3600;
+        let cases = [
+            ("D:20240105120000Z", Some("2024-01-05 13:00")),
+            ("D:20240105120000+01'00'", Some("2024-01-05 12:00")),
+            ("D:20240105120000-0530", Some("2024-01-05 18:30")),
+            ("D:20241231233000Z", Some("2025-01-01 00:30")),
+            ("D:20240301003000+02'00'", Some("2024-02-29 23:30")),
+            ("D:20240105120000", Some("2024-01-05 12:00")), // Sin zona: tal cual
+            ("D:20240105120000+", Some("2024-01-05 12:00")),
+            (" 20240105 ", Some("2024-01-05")),
+            ("D:2024", Some("2024-01-01")),
+            ("D:20241345990000Z", Some("2024-13-45 99:00")), // Imposible: tal cual
+            ("ayer", None),
+            ("", None),
+        ];
+        for (date, expected) in cases {
+            assert_eq!(display_date_in(date, madrid).as_deref(), expected, "{}", date);
+        }
+    }
+
+    #[test]
+    fn civil_days_round_trip() {
+        for days in [-719_468, -1, 0, 59, 365, 11_016, 19_782, 2_932_896] {
+            let (year, month, day) = civil_from_days(days);
+            assert_eq!(days_from_civil(year, month, day), days);
+        }
+        assert_eq!(civil_from_days(19_782), (2024, 2, 29));
+    }
+
+    fn entry(page: u16, label: &'static str, text: &str, is_quote: bool) -> SummaryEntry {
+        SummaryEntry { page, id: 0, label, color: [255, 215, 0, 96], text: text.to_string(), is_quote, author: Some("ana".to_string()), date: Some("2024-01-05 13:00".to_string()) }
+    }
+
+    #[test]
+    fn markdown_groups_by_page_and_quotes_highlights() {
+        let entries = [
+            entry(0, "Resaltado", "una cita\nen dos líneas", true),
+            entry(0, "Subrayado", "", true),
+            entry(2, "Nota", "primera\nsegunda", false),
+            entry(2, "Trazo", "", false),
+        ];
+        let expected = "# Anotaciones - libro.pdf\n\n\
+            ## Página 1\n\n\
+            > una cita\n> en dos líneas\n>\n> — *Resaltado amarillo · p. 1 · ana · 2024-01-05 13:00*\n\n\
+            - *Subrayado amarillo · p. 1 · ana · 2024-01-05 13:00*\n\n\
+            ## Página 3\n\n\
+            - primera\n  segunda\n  *Nota amarillo · p. 3 · ana · 2024-01-05 13:00*\n\n\
+            - *Trazo amarillo · p. 3 · ana · 2024-01-05 13:00*\n\n";
+        assert_eq!(to_markdown("libro.pdf", &entries), expected);
+        assert_eq!(to_markdown("vacío.pdf", &[]), "# Anotaciones - vacío.pdf\n\n");
+    }
+
+    #[test]
+    fn page_text_is_read_once_and_only_where_quoted() {
+        let mut store = AnnotationStore::new();
+        let highlight = AnnotationKind::TextHighlight { rects: vec![[0.0, 0.0, 20.0, 10.0]], text: "guardado".to_string() };
+        store.add(0, highlight.clone(), [255, 215, 0, 96]);
+        store.add(0, highlight, [255, 215, 0, 96]);
+        store.add(1, AnnotationKind::Note { at: PagePoint { x: 5.0, y: 5.0 }, text: "hola".to_string() }, [255, 0, 0, 255]);
+
+        let mut cache = HashMap::new();
+        let mut reads = Vec::new();
+        let chars = vec![
+            PageChar { ch: 'o', bounds: Some([2.0, 2.0, 6.0, 8.0]) },
+            PageChar { ch: 'k', bounds: Some([6.0, 2.0, 10.0, 8.0]) },
+            PageChar { ch: 'x', bounds: Some([40.0, 2.0, 44.0, 8.0]) },
+        ];
+        for _ in 0..2 {
+            let entries = build(&store, &mut cache, |page| { reads.push(page); chars.clone() });
+            assert_eq!(entries.iter().map(|e| e.text.as_str()).collect::<Vec<_>>(), ["ok", "ok", "hola"]);
+        }
+        assert_eq!(reads, [0]);
+    }
+}
@@ -0,0 +1,220 @@
+// Fuente bitmap 5x7 generada a mano: sin dependencias ni archivos de fuente,
+// igual que los iconos procedurales de assets.rs. Cubre ASCII imprimible y los
+// acentos del castellano (las letras acentuadas se componen: base + marca).
+
+/// Ancho del glifo en unidades de fuente.
+pub const GLYPH_WIDTH: u32 = 5;
+/// Avance horizontal por carácter (glifo + separación).
+pub const ADVANCE: u32 = 6;
+/// Alto de línea: 2 filas para acentos de mayúsculas + 7 del glifo + 1 de interlineado.
+pub const LINE_HEIGHT: u32 = 10;
+// Filas de una celda (sin el interlineado)
+const CELL_ROWS: usize = 9;
+
+// Filas de cada glifo de ' ' (32) a '~' (126). Bit 4 = columna izquierda.
+const GLYPHS: [[u8; 7]; 95] = [
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // ' '
+    [0x04, 0x04, 0x04, 0x04, 0x04, 0x00, 0x04], // !
+    [0x0A, 0x0A, 0x0A, 0x00, 0x00, 0x00, 0x00], // "
+    [0x0A, 0x0A, 0x1F, 0x0A, 0x1F, 0x0A, 0x0A], // #
+    [0x04, 0x0F, 0x14, 0x0E, 0x05, 0x1E, 0x04], // $
+    [0x18, 0x19, 0x02, 0x04, 0x08, 0x13, 0x03], // %
+    [0x0C, 0x12, 0x14, 0x08, 0x15, 0x12, 0x0D], // &
+    [0x0C, 0x04, 0x08, 0x00, 0x00, 0x00, 0x00], // '
+    [0x02, 0x04, 0x08, 0x08, 0x08, 0x04, 0x02], // (
+    [0x08, 0x04, 0x02, 0x02, 0x02, 0x04, 0x08], // )
+    [0x00, 0x04, 0x15, 0x0E, 0x15, 0x04, 0x00], // *
+    [0x00, 0x04, 0x04, 0x1F, 0x04, 0x04, 0x00], // +
+    [0x00, 0x00, 0x00, 0x00, 0x0C, 0x04, 0x08], // ,
+    [0x00, 0x00, 0x00, 0x1F, 0x00, 0x00, 0x00], // -
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x0C, 0x0C], // .
+    [0x00, 0x01, 0x02, 0x04, 0x08, 0x10, 0x00], // /
+    [0x0E, 0x11, 0x13, 0x15, 0x19, 0x11, 0x0E], // 0
+    [0x04, 0x0C, 0x04, 0x04, 0x04, 0x04, 0x0E], // 1
+    [0x0E, 0x11, 0x01, 0x02, 0x04, 0x08, 0x1F], // 2
+    [0x1F, 0x02, 0x04, 0x02, 0x01, 0x11, 0x0E], // 3
+    [0x02, 0x06, 0x0A, 0x12, 0x1F, 0x02, 0x02], // 4
+    [0x1F, 0x10, 0x1E, 0x01, 0x01, 0x11, 0x0E], // 5
+    [0x06, 0x08, 0x10, 0x1E, 0x11, 0x11, 0x0E], // 6
+    [0x1F, 0x01, 0x02, 0x04, 0x08, 0x08, 0x08], // 7
+    [0x0E, 0x11, 0x11, 0x0E, 0x11, 0x11, 0x0E], // 8
+    [0x0E, 0x11, 0x11, 0x0F, 0x01, 0x02, 0x0C], // 9
+    [0x00, 0x0C, 0x0C, 0x00, 0x0C, 0x0C, 0x00], // :
+    [0x00, 0x0C, 0x0C, 0x00, 0x0C, 0x04, 0x08], // ;
+    [0x02, 0x04, 0x08, 0x10, 0x08, 0x04, 0x02], // <
+    [0x00, 0x00, 0x1F, 0x00, 0x1F, 0x00, 0x00], // =
+    [0x08, 0x04, 0x02, 0x01, 0x02, 0x04, 0x08], // >
+    [0x0E, 0x11, 0x01, 0x02, 0x04, 0x00, 0x04], // ?
+    [0x0E, 0x11, 0x01, 0x0D, 0x15, 0x15, 0x0E], // @
+    [0x0E, 0x11, 0x11, 0x11, 0x1F, 0x11, 0x11], // A
+    [0x1E, 0x11, 0x11, 0x1E, 0x11, 0x11, 0x1E], // B
+    [0x0E, 0x11, 0x10, 0x10, 0x10, 0x11, 0x0E], // C
+    [0x1C, 0x12, 0x11, 0x11, 0x11, 0x12, 0x1C], // D
+    [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x1F], // E
+    [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x10], // F
+    [0x0E, 0x11, 0x10, 0x17, 0x11, 0x11, 0x0F], // G
+    [0x11, 0x11, 0x11, 0x1F, 0x11, 0x11, 0x11], // H
+    [0x0E, 0x04, 0x04, 0x04, 0x04, 0x04, 0x0E], // I
+    [0x07, 0x02, 0x02, 0x02, 0x02, 0x12, 0x0C], // J
+    [0x11, 0x12, 0x14, 0x18, 0x14, 0x12, 0x11], // K
+    [0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x1F], // L
+    [0x11, 0x1B, 0x15, 0x15, 0x11, 0x11, 0x11], // M
+    [0x11, 0x11, 0x19, 0x15, 0x13, 0x11, 0x11], // N
+    [0x0E, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E], // O
+    [0x1E, 0x11, 0x11, 0x1E, 0x10, 0x10, 0x10], // P
+    [0x0E, 0x11, 0x11, 0x11, 0x15, 0x12, 0x0D], // Q
+    [0x1E, 0x11, 0x11, 0x1E, 0x14, 0x12, 0x11], // R
+    [0x0F, 0x10, 0x10, 0x0E, 0x01, 0x01, 0x1E], // S
+    [0x1F, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04], // T
+    [0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E], // U
+    [0x11, 0x11, 0x11, 0x11, 0x11, 0x0A, 0x04], // V
+    [0x11, 0x11, 0x11, 0x15, 0x15, 0x15, 0x0A], // W
+    [0x11, 0x11, 0x0A, 0x04, 0x0A, 0x11, 0x11], // X
+    [0x11, 0x11, 0x11, 0x0A, 0x04, 0x04, 0x04], // Y
+    [0x1F, 0x01, 0x02, 0x04, 0x08, 0x10, 0x1F], // Z
+    [0x0E, 0x08, 0x08, 0x08, 0x08, 0x08, 0x0E], // [
+    [0x00, 0x10, 0x08, 0x04, 0x02, 0x01, 0x00], // \
+    [0x0E, 0x02, 0x02, 0x02, 0x02, 0x02, 0x0E], // ]
+    [0x04, 0x0A, 0x11, 0x00, 0x00, 0x00, 0x00], // ^
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x1F], // _
+    [0x08, 0x04, 0x02, 0x00, 0x00, 0x00, 0x00], // `
+    [0x00, 0x00, 0x0E, 0x01, 0x0F, 0x11, 0x0F], // a
+    [0x10, 0x10, 0x16, 0x19, 0x11, 0x11, 0x1E], // b
+    [0x00, 0x00, 0x0E, 0x10, 0x10, 0x11, 0x0E], // c
+    [0x01, 0x01, 0x0D, 0x13, 0x11, 0x11, 0x0F], // d
+    [0x00, 0x00, 0x0E, 0x11, 0x1F, 0x10, 0x0E], // e
+    [0x06, 0x09, 0x08, 0x1C, 0x08, 0x08, 0x08], // f
+    [0x00, 0x0F, 0x11, 0x11, 0x0F, 0x01, 0x0E], // g
+    [0x10, 0x10, 0x16, 0x19, 0x11, 0x11, 0x11], // h
+    [0x04, 0x00, 0x0C, 0x04, 0x04, 0x04, 0x0E], // i
+    [0x02, 0x00, 0x06, 0x02, 0x02, 0x12, 0x0C], // j
+    [0x10, 0x10, 0x12, 0x14, 0x18, 0x14, 0x12], // k
+    [0x0C, 0x04, 0x04, 0x04, 0x04, 0x04, 0x0E], // l
+    [0x00, 0x00, 0x1A, 0x15, 0x15, 0x11, 0x11], // m
+    [0x00, 0x00, 0x16, 0x19, 0x11, 0x11, 0x11], // n
+    [0x00, 0x00, 0x0E, 0x11, 0x11, 0x11, 0x0E], // o
+    [0x00, 0x00, 0x1E, 0x11, 0x1E, 0x10, 0x10], // p
+    [0x00, 0x00, 0x0D, 0x13, 0x0F, 0x01, 0x01], // q
+    [0x00, 0x00, 0x16, 0x19, 0x10, 0x10, 0x10], // r
+    [0x00, 0x00, 0x0E, 0x10, 0x0E, 0x01, 0x1E], // s
+    [0x08, 0x08, 0x1C, 0x08, 0x08, 0x09, 0x06], // t
+    [0x00, 0x00, 0x11, 0x11, 0x11, 0x13, 0x0D], // u
+    [0x00, 0x00, 0x11, 0x11, 0x11, 0x0A, 0x04], // v
+    [0x00, 0x00, 0x11, 0x11, 0x15, 0x15, 0x0A], // w
+    [0x00, 0x00, 0x11, 0x0A, 0x04, 0x0A, 0x11], // x
+    [0x00, 0x00, 0x11, 0x11, 0x0F, 0x01, 0x0E], // y
+    [0x00, 0x00, 0x1F, 0x02, 0x04, 0x08, 0x1F], // z
+    [0x02, 0x04, 0x04, 0x08, 0x04, 0x04, 0x02], // {
+    [0x04, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04], // |
+    [0x08, 0x04, 0x04, 0x02, 0x04, 0x04, 0x08], // }
+    [0x00, 0x00, 0x08, 0x15, 0x02, 0x00, 0x00], // ~
+];
+
+#[derive(Clone, Copy)]
+enum Mark {
+    Acute,
+    Tilde,
+    Diaeresis,
+}
+
+fn mark_rows(mark: Mark) -> [u8; 2] {
+    match mark {
+        Mark::Acute => [0x02, 0x04],
+        Mark::Tilde => [0x0D, 0x16],
+        Mark::Diaeresis => [0x00, 0x0A],
+    }
+}
+
+fn ascii_rows(ch: char) -> [u8; 7] {
+    let code = ch as u32;
+    if (32..=126).contains(&code) {
+        GLYPHS[(code - 32) as usize]
+    } else {
+        GLYPHS[('?' as u32 - 32) as usize]
+    }
+}
+
+/// Filas de la celda de un carácter: 2 de acento + 7 de glifo.
+fn cell_rows(ch: char) -> [u8; CELL_ROWS] {
+    let (base, mark) = match ch {
+        'á' => ('a', Some(Mark::Acute)), 'é' => ('e', Some(Mark::Acute)), 'í' => ('ı', Some(Mark::Acute)),
+        'ó' => ('o', Some(Mark::Acute)), 'ú' => ('u', Some(Mark::Acute)), 'ñ' => ('n', Some(Mark::Tilde)),
+        'ü' => ('u', Some(Mark::Diaeresis)),
+        'Á' => ('A', Some(Mark::Acute)), 'É' => ('E', Some(Mark::Acute)), 'Í' => ('I', Some(Mark::Acute)),
+        'Ó' => ('O', Some(Mark::Acute)), 'Ú' => ('U', Some(Mark::Acute)), 'Ñ' => ('N', Some(Mark::Tilde)),
+        'Ü' => ('U', Some(Mark::Diaeresis)),
+        _ => (ch, None),
+    };
+
+    let mut rows = [0u8; CELL_ROWS];
+    match base {
+        // Signos de apertura: el de cierre girado 180°
+        '¿' | '¡' => {
+            let mut glyph = ascii_rows(if base == '¿' { '?' } else { '!' });
+            glyph.reverse();
+            for row in glyph.iter_mut() {
+                *row = row.reverse_bits() >> 3;
+            }
+            rows[2..].copy_from_slice(&glyph);
+        },
+        // "i" sin punto, para poder ponerle tilde
+        'ı' => rows[2..].copy_from_slice(&[0x00, 0x00, 0x0C, 0x04, 0x04, 0x04, 0x0E]),
+        _ => rows[2..].copy_from_slice(&ascii_rows(base)),
+    }
+
+    if let Some(mark) = mark {
+        // Minúsculas: el acento cabe en las dos primeras filas del glifo; mayúsculas: encima
+        let at = if base.is_lowercase() || base == 'ı' { 2 } else { 0 };
+        rows[at..at + 2].copy_from_slice(&mark_rows(mark));
+    }
+    rows
+}
+
+/// Recorre los píxeles encendidos de una línea, en unidades de fuente
+/// (columna, fila) desde la esquina superior izquierda de la línea.
+pub fn for_each_pixel(line: &str, mut pixel: impl FnMut(u32, u32)) {
+    for (i, ch) in line.chars().enumerate() {
+        let rows = cell_rows(ch);
+        for (row, bits) in rows.iter().enumerate() {
+            for col in 0..GLYPH_WIDTH {
+                if bits & (0x10 >> col) != 0 {
+                    pixel(i as u32 * ADVANCE + col, row as u32);
+                }
+            }
+        }
+    }
+}
+
+/// Parte un texto en líneas de como mucho `max_columns` caracteres, cortando por espacios
+/// cuando se puede. Los saltos de línea del texto se respetan.
+pub fn wrap(text: &str, max_columns: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    for paragraph in text.split('\n') {
+        let mut line = String::new();
+        for word in paragraph.split(' ') {
+            let fits = line.chars().count() + 1 + word.chars().count() <= max_columns;
+            if !line.is_empty() && !fits {
+                lines.push(std::mem::take(&mut line));
+            }
+            if !line.is_empty() {
+                line.push(' ');
+            }
+            // Palabras más largas que la línea: se cortan a la fuerza
+            let mut chars: Vec<char> = word.chars().collect();
+            while line.chars().count() + chars.len() > max_columns {
+                let room = max_columns - line.chars().count();
+                line.extend(chars.drain(..room));
+                lines.push(std::mem::take(&mut line));
+            }
+            line.extend(chars);
+        }
+        lines.push(line);
+    }
+    lines
+}
+
+/// Tamaño de un bloque de líneas en unidades de fuente (ancho, alto).
+pub fn measure(lines: &[String]) -> (u32, u32) {
+    let columns = lines.iter().map(|l| l.chars().count() as u32).max().unwrap_or(0);
+    (columns * ADVANCE, lines.len() as u32 * LINE_HEIGHT)
+}
@@ -0,0 +1,513 @@
+//! Importar y exportar anotaciones en XFDF (el XML de intercambio de Acrobat), sin el PDF.
+//!
+//! Se escribe un subconjunto que Acrobat, Foxit o PDF-XChange leen sin problemas:
+//! `highlight`, `ink`, `square`, `circle`, `line`, `text` (notas) y `freetext`, con autor
+//! (`title`) y fecha (`date`). Las páginas van en base 0 y las coordenadas en puntos PDF,
+//! como en el propio documento. Al importar, lo que no sabemos representar se ignora.
+
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+
+use crate::pdf::annotations::{default_appearance, parse_default_appearance};
+use crate::ui::overlay::{new_name, Annotation, AnnotationKind, AnnotationStore, Color, InkSample, PagePoint, Shape, HIGHLIGHT_YELLOW, INK_BLACK, NOTE_YELLOW};
+
+/// Escribe las anotaciones de `store` en un XFDF. Devuelve cuántas se exportaron.
+pub fn export(path: &Path, document: &str, store: &AnnotationStore) -> Result<usize> {
+    let (xml, count) = to_xfdf(document, store);
+    std::fs::write(path, xml).with_context(|| format!("No se pudo escribir {:?}", path))?;
+    Ok(count)
+}
+
+/// Lee un XFDF: (página en base 0, anotación) en el orden del archivo.
+pub fn import(path: &Path) -> Result<Vec<(u16, Annotation)>> {
+    let content = std::fs::read_to_string(path).with_context(|| format!("No se pudo leer {:?}", path))?;
+    let root = parse_xml(&content).with_context(|| format!("{:?} no es un XML válido", path))?;
+    if root.name != "xfdf" {
+        bail!("{:?} no es un archivo XFDF (raíz <{}>)", path, root.name);
+    }
+
+    let mut imported = Vec::new();
+    let mut skipped = 0;
+    for element in root.children.iter().filter(|e| e.name == "annots").flat_map(|e| &e.children) {
+        let annotations = read_annotation(element);
+        if annotations.is_empty() {
+            skipped += 1;
+        }
+        imported.extend(annotations);
+    }
+    if skipped > 0 {
+        log::warn!("XFDF: {} anotaciones de tipos no soportados ignoradas", skipped);
+    }
+    Ok(imported)
+}
+
+pub fn to_xfdf(document: &str, store: &AnnotationStore) -> (String, usize) {
+    let href = Path::new(document).file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<xfdf xmlns=\"http://ns.adobe.com/xfdf/\" xml:space=\"preserve\">\n");
+    xml.push_str(&format!("  <f href=\"{}\"/>\n  <annots>\n", escape(&href)));
+
+    let mut count = 0;
+    for (page, annotations) in store.pages() {
+        for annotation in annotations {
+            xml.push_str(&write_annotation(page, annotation));
+            count += 1;
+        }
+    }
+    xml.push_str("  </annots>\n</xfdf>\n");
+    (xml, count)
+}
+
+// --- EXPORTAR ---
+
+fn write_annotation(page: u16, annotation: &Annotation) -> String {
+    let kind = &annotation.kind;
+    let [r, g, b, a] = annotation.color;
+    // El nombre es el de la anotación (su /NM): el mismo en cada exportación
+    let name = annotation.name.clone().unwrap_or_else(|| new_name(kind));
+    let mut attrs = vec![("page", page.to_string()), ("name", name), ("flags", "print".to_string())];
+    if !matches!(kind, AnnotationKind::FreeText { .. }) {
+        // En el texto libre "color" sería el fondo de la caja: el del texto va en defaultappearance
+        attrs.push(("color", format!("#{:02X}{:02X}{:02X}", r, g, b)));
+    }
+    if a < 255 {
+        attrs.push(("opacity", number(a as f32 / 255.0)));
+    }
+    if let Some(author) = &annotation.author {
+        attrs.push(("title", author.clone()));
+    }
+    if let Some(modified) = &annotation.modified {
+        attrs.push(("date", modified.clone()));
+    }
+
+    let bounds = kind.bounds();
+    let (tag, body) = match kind {
+        AnnotationKind::Ink { samples, width } => {
+            attrs.push(("width", number(*width)));
+            let gesture: Vec<String> = samples.iter().map(|s| format!("{},{}", number(s.pos.x), number(s.pos.y))).collect();
+            ("ink", format!("<inklist><gesture>{}</gesture></inklist>", gesture.join(";")))
+        },
+        AnnotationKind::Shape { shape: shape @ (Shape::Line | Shape::Arrow), from, to, width } => {
+            attrs.push(("width", number(*width)));
+            attrs.push(("start", format!("{},{}", number(from.x), number(from.y))));
+            attrs.push(("end", format!("{},{}", number(to.x), number(to.y))));
+            attrs.push(("head", "None".to_string()));
+            attrs.push(("tail", if *shape == Shape::Arrow { "OpenArrow" } else { "None" }.to_string()));
+            ("line", String::new())
+        },
+        AnnotationKind::Shape { shape, width, .. } => {
+            attrs.push(("width", number(*width)));
+            (if *shape == Shape::Rectangle { "square" } else { "circle" }, String::new())
+        },
+        AnnotationKind::TextHighlight { rects, text } => {
+            // Cuatro esquinas por caja, en el orden de Acrobat: arriba-izq., arriba-der., abajo-izq., abajo-der.
+            let coords: Vec<String> = rects.iter()
+                .flat_map(|&[l, b, r, t]| [l, t, r, t, l, b, r, b])
+                .map(number)
+                .collect();
+            attrs.push(("coords", coords.join(",")));
+            ("highlight", contents(text))
+        },
+        AnnotationKind::Note { text, .. } => {
+            attrs.push(("icon", "Note".to_string()));
+            ("text", contents(text))
+        },
+        AnnotationKind::FreeText { text, size, .. } => {
+            attrs.push(("width", "0".to_string()));
+            let da = default_appearance(annotation.color, size / 1.2);
+            ("freetext", format!("{}<defaultappearance>{}</defaultappearance>", contents(text), escape(&da)))
+        },
+    };
+    attrs.insert(1, ("rect", bounds.map(number).join(",")));
+
+    let attrs: String = attrs.iter().map(|(key, value)| format!(" {}=\"{}\"", key, escape(value))).collect();
+    if body.is_empty() {
+        format!("    <{}{}/>\n", tag, attrs)
+    } else {
+        format!("    <{}{}>{}</{}>\n", tag, attrs, body, tag)
+    }
+}
+
+fn contents(text: &str) -> String {
+    if text.is_empty() { String::new() } else { format!("<contents>{}</contents>", escape(text)) }
+}
+
+// Hasta milésimas de punto, sin ceros de sobra
+fn number(value: f32) -> String {
+    let text = format!("{:.3}", value);
+    text.trim_end_matches('0').trim_end_matches('.').to_string()
+}
+
+fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\n' => out.push_str("&#xA;"),
+            '\r' => {},
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+// --- IMPORTAR ---
+
+fn read_annotation(element: &Element) -> Vec<(u16, Annotation)> {
+    let Some(page) = element.attr("page").and_then(|p| p.trim().parse::<u16>().ok()) else { return Vec::new() };
+    let numbers = |key: &str| element.attr(key).map(parse_numbers).unwrap_or_default();
+    let rect = numbers("rect");
+    let [left, bottom, right, top] = match rect.as_slice() {
+        &[x0, y0, x1, y1] => [x0.min(x1), y0.min(y1), x0.max(x1), y0.max(y1)],
+        _ => return Vec::new(),
+    };
+    let width = element.attr("width").and_then(|w| w.trim().parse::<f32>().ok()).unwrap_or(1.0);
+    let text = element.child("contents").map(|c| c.text.clone()).unwrap_or_default();
+
+    let kinds = match element.name.as_str() {
+        "highlight" => {
+            let rects: Vec<[f32; 4]> = numbers("coords").chunks_exact(8).map(|q| {
+                let (xs, ys) = ([q[0], q[2], q[4], q[6]], [q[1], q[3], q[5], q[7]]);
+                [xs.into_iter().fold(f32::MAX, f32::min), ys.into_iter().fold(f32::MAX, f32::min),
+                 xs.into_iter().fold(f32::MIN, f32::max), ys.into_iter().fold(f32::MIN, f32::max)]
+            }).collect();
+            let rects = if rects.is_empty() { vec![[left, bottom, right, top]] } else { rects };
+            vec![AnnotationKind::TextHighlight { rects, text }]
+        },
+        "ink" => {
+            // Un trazo por <gesture>, como al leer un Ink del PDF
+            element.child("inklist").map(|list| &list.children[..]).unwrap_or_default().iter()
+                .filter(|g| g.name == "gesture")
+                .map(|g| g.text.split(';').filter_map(|pair| match parse_numbers(pair).as_slice() {
+                    &[x, y] => Some(InkSample { pos: PagePoint { x, y }, pressure: 1.0 }),
+                    _ => None,
+                }).collect::<Vec<_>>())
+                .filter(|samples| !samples.is_empty())
+                .map(|samples| AnnotationKind::Ink { samples, width })
+                .collect()
+        },
+        "square" | "circle" => {
+            // Como en el PDF, el borde va por dentro de rect
+            let half = width * 0.5;
+            let shape = if element.name == "square" { Shape::Rectangle } else { Shape::Ellipse };
+            let from = PagePoint { x: left + half, y: bottom + half };
+            let to = PagePoint { x: right - half, y: top - half };
+            vec![AnnotationKind::Shape { shape, from, to, width }]
+        },
+        "line" => match (numbers("start").as_slice(), numbers("end").as_slice()) {
+            (&[x0, y0], &[x1, y1]) => {
+                let (start, end) = (PagePoint { x: x0, y: y0 }, PagePoint { x: x1, y: y1 });
+                let arrow = |key: &str| element.attr(key).is_some_and(|style| style.contains("Arrow"));
+                // Nuestra flecha tiene la punta en `to`: si en el XFDF va en el inicio, se da la vuelta
+                let kind = match (arrow("head"), arrow("tail")) {
+                    (_, true) => AnnotationKind::Shape { shape: Shape::Arrow, from: start, to: end, width },
+                    (true, false) => AnnotationKind::Shape { shape: Shape::Arrow, from: end, to: start, width },
+                    _ => AnnotationKind::Shape { shape: Shape::Line, from: start, to: end, width },
+                };
+                vec![kind]
+            },
+            _ => Vec::new(),
+        },
+        "text" => vec![AnnotationKind::Note { at: PagePoint { x: left, y: top }, text }],
+        "freetext" => {
+            let da = element.child("defaultappearance").map(|d| d.text.as_str()).or(element.attr("DA")).unwrap_or_default();
+            let (font_size, _) = parse_default_appearance(da);
+            vec![AnnotationKind::FreeText { at: PagePoint { x: left, y: top }, text, size: font_size * 1.2 }]
+        },
+        _ => Vec::new(),
+    };
+
+    let color = read_color(element, kinds.first());
+    let author = element.attr("title").map(str::to_string);
+    let modified = element.attr("date").map(str::to_string);
+    // Como en el PDF: con varios trazos, el nombre se lo queda el primero
+    let mut name = element.attr("name").map(str::to_string).filter(|name| !name.is_empty());
+    kinds.into_iter()
+        .map(|kind| (page, Annotation { id: 0, kind, color, author: author.clone(), modified: modified.clone(), name: name.take() }))
+        .collect()
+}
+
+fn read_color(element: &Element, kind: Option<&AnnotationKind>) -> Color {
+    let [r, g, b, _] = match kind {
+        Some(AnnotationKind::FreeText { .. }) => {
+            let da = element.child("defaultappearance").map(|d| d.text.as_str()).or(element.attr("DA")).unwrap_or_default();
+            parse_default_appearance(da).1
+        },
+        _ => element.attr("color").and_then(parse_hex_color).unwrap_or(match kind {
+            Some(AnnotationKind::TextHighlight { .. }) => HIGHLIGHT_YELLOW,
+            Some(AnnotationKind::Note { .. }) => NOTE_YELLOW,
+            _ => INK_BLACK,
+        }),
+    };
+    let opacity = element.attr("opacity").and_then(|o| o.trim().parse::<f32>().ok()).unwrap_or(1.0);
+    [r, g, b, (opacity.clamp(0.0, 1.0) * 255.0).round() as u8]
+}
+
+fn parse_hex_color(value: &str) -> Option<Color> {
+    let hex = value.trim().strip_prefix('#')?;
+    if hex.len() != 6 {
+        return None;
+    }
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+    Some([channel(0)?, channel(2)?, channel(4)?, 255])
+}
+
+fn parse_numbers(text: &str) -> Vec<f32> {
+    text.split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|t| !t.is_empty())
+        .filter_map(|t| t.parse::<f32>().ok())
+        .collect()
+}
+
+// --- XML MÍNIMO ---
+// Lo justo para XFDF: elementos, atributos, texto, CDATA y entidades. Los prefijos de
+// espacio de nombres se descartan (<xfdf:annots> es <annots>).
+
+#[derive(Debug, Default)]
+struct Element {
+    name: String,
+    attrs: Vec<(String, String)>,
+    children: Vec<Element>,
+    text: String,
+}
+
+impl Element {
+    fn attr(&self, key: &str) -> Option<&str> {
+        self.attrs.iter().find(|(k, _)| k == key).map(|(_, v)| v.as_str())
+    }
+
+    fn child(&self, name: &str) -> Option<&Element> {
+        self.children.iter().find(|c| c.name == name)
+    }
+}
+
+fn parse_xml(input: &str) -> Result<Element> {
+    let mut stack: Vec<Element> = vec![Element::default()]; // Raíz ficticia
+    let mut rest = input;
+
+    while !rest.is_empty() {
+        let Some(start) = rest.find('<') else {
+            stack.last_mut().unwrap().text.push_str(&unescape(rest));
+            break;
+        };
+        stack.last_mut().unwrap().text.push_str(&unescape(&rest[..start]));
+        rest = &rest[start..];
+
+        if let Some(after) = rest.strip_prefix("<![CDATA[") {
+            let end = after.find("]]>").context("CDATA sin cerrar")?;
+            stack.last_mut().unwrap().text.push_str(&after[..end]);
+            rest = &after[end + 3..];
+        } else if let Some(after) = rest.strip_prefix("<!--") {
+            rest = &after[after.find("-->").context("Comentario sin cerrar")? + 3..];
+        } else if rest.starts_with("<?") || rest.starts_with("<!") {
+            rest = &rest[rest.find('>').context("Declaración sin cerrar")? + 1..];
+        } else if let Some(after) = rest.strip_prefix("</") {
+            let end = after.find('>').context("Etiqueta sin cerrar")?;
+            let element = stack.pop().filter(|_| !stack.is_empty()).context("Cierre sin apertura")?;
+            if local_name(after[..end].trim()) != element.name {
+                bail!("Se esperaba </{}>", element.name);
+            }
+            stack.last_mut().context("Cierre sin apertura")?.children.push(element);
+            rest = &after[end + 1..];
+        } else {
+            let end = tag_end(rest).context("Etiqueta sin cerrar")?;
+            let tag = &rest[1..end];
+            let (tag, self_closing) = match tag.strip_suffix('/') {
+                Some(tag) => (tag, true),
+                None => (tag, false),
+            };
+            let element = parse_tag(tag)?;
+            if self_closing {
+                stack.last_mut().unwrap().children.push(element);
+            } else {
+                stack.push(element);
+            }
+            rest = &rest[end + 1..];
+        }
+    }
+
+    if stack.len() != 1 {
+        bail!("Elementos sin cerrar");
+    }
+    stack.pop().unwrap().children.into_iter().next().context("Documento vacío")
+}
+
+// Fin de una etiqueta: el primer '>' fuera de comillas
+fn tag_end(text: &str) -> Option<usize> {
+    let mut quote = None;
+    for (i, c) in text.char_indices() {
+        match (c, quote) {
+            ('"' | '\'', None) => quote = Some(c),
+            (c, Some(q)) if c == q => quote = None,
+            ('>', None) => return Some(i),
+            _ => {},
+        }
+    }
+    None
+}
+
+fn parse_tag(tag: &str) -> Result<Element> {
+    let tag = tag.trim();
+    let name_end = tag.find(char::is_whitespace).unwrap_or(tag.len());
+    let mut element = Element { name: local_name(&tag[..name_end]).to_string(), ..Default::default() };
+    let mut rest = tag[name_end..].trim_start();
+
+    while !rest.is_empty() {
+        let eq = rest.find('=').context("Atributo sin valor")?;
+        let key = rest[..eq].trim();
+        let value_part = rest[eq + 1..].trim_start();
+        let quote = value_part.chars().next().filter(|c| *c == '"' || *c == '\'').context("Atributo sin comillas")?;
+        let close = value_part[1..].find(quote).context("Atributo sin cerrar")?;
+        element.attrs.push((local_name(key).to_string(), unescape(&value_part[1..close + 1])));
+        rest = value_part[close + 2..].trim_start();
+    }
+    Ok(element)
+}
+
+fn local_name(name: &str) -> &str {
+    name.rsplit(':').next().unwrap_or(name)
+}
+
+fn unescape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(amp) = rest.find('&') {
+        out.push_str(&rest[..amp]);
+        rest = &rest[amp..];
+        let Some(semi) = rest.find(';') else { break };
+        let entity = &rest[1..semi];
+        let decoded = match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            _ => entity.strip_prefix("#x").or_else(|| entity.strip_prefix("#X")).map(|hex| u32::from_str_radix(hex, 16))
+                .or_else(|| entity.strip_prefix('#').map(|dec| dec.parse::<u32>()))
+                .and_then(|code| code.ok())
+                .and_then(char::from_u32),
+        };
+        match decoded {
+            Some(c) => {
+                out.push(c);
+                rest = &rest[semi + 1..];
+            },
+            // Entidad desconocida: se deja tal cual
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            },
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn p(x: f32, y: f32) -> PagePoint {
+        PagePoint { x, y }
+    }
+
+    fn store_with_every_kind() -> AnnotationStore {
+        let mut store = AnnotationStore::new();
+        let shape = |shape| AnnotationKind::Shape { shape, from: p(72.0, 600.0), to: p(200.5, 650.25), width: 2.0 };
+        let kinds = [
+            (0, AnnotationKind::Ink { samples: [(72.0, 700.5), (80.0, 710.0)].map(|(x, y)| InkSample { pos: p(x, y), pressure: 1.0 }).to_vec(), width: 1.5 }),
+            (0, shape(Shape::Rectangle)),
+            (0, shape(Shape::Ellipse)),
+            (0, shape(Shape::Line)),
+            (0, shape(Shape::Arrow)),
+            (2, AnnotationKind::TextHighlight { rects: vec![[72.0, 500.0, 300.0, 512.0], [72.0, 486.0, 150.0, 498.0]], text: "a < b & \"c\"\nd".into() }),
+            (2, AnnotationKind::Note { at: p(40.0, 780.0), text: "Revisar ✓".into() }),
+            (2, AnnotationKind::FreeText { at: p(100.0, 300.0), text: "Aprobado".into(), size: 12.0 }),
+        ];
+        for (i, (page, kind)) in kinds.into_iter().enumerate() {
+            let author = (i % 2 == 0).then(|| "Ana".to_string());
+            store.insert(page, Annotation { id: 0, kind, color: [20, 40, 60, 255 - i as u8 * 20], author, modified: Some("D:20240105120000Z".into()), name: None });
+        }
+        store
+    }
+
+    #[test]
+    fn every_kind_round_trips() {
+        let store = store_with_every_kind();
+        let (xml, count) = to_xfdf("/docs/informe.pdf", &store);
+        assert_eq!(count, 8);
+
+        let path = crate::pdf::test_path("round-trip.xfdf");
+        std::fs::write(&path, &xml).unwrap();
+        let imported = import(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        let mut again = AnnotationStore::new();
+        for (page, annotation) in imported.clone() {
+            again.insert(page, annotation);
+        }
+        // Releído y vuelto a escribir, sale lo mismo: nombres incluidos
+        assert_eq!(to_xfdf("/docs/informe.pdf", &again).0, xml);
+        let originals: Vec<&Annotation> = store.pages().flat_map(|(_, list)| list).collect();
+        for ((_, imported), original) in imported.iter().zip(originals) {
+            assert_eq!(imported.name, original.name);
+            assert_eq!((imported.color, &imported.author), (original.color, &original.author));
+            if !matches!(original.kind, AnnotationKind::FreeText { .. }) {
+                assert_eq!(imported.kind, original.kind);
+            }
+        }
+    }
+
+    #[test]
+    fn names_are_kept_and_unique() {
+        let store = store_with_every_kind();
+        let names: Vec<&str> = store.pages().flat_map(|(_, list)| list).filter_map(|a| a.name.as_deref()).collect();
+        assert_eq!(names.len(), 8);
+        assert!(names.iter().enumerate().all(|(i, name)| !names[..i].contains(name)), "{:?}", names);
+        assert!(names[4].starts_with("windp-arrow-") && names[0].starts_with("windp-") && names[0].len() == "windp-".len() + 36);
+
+        // Un nombre que viene de fuera se conserva; un ink de varios trazos lo deja en el primero
+        let root = parse_xml(r#"<xfdf><annots><ink page="0" rect="0,0,10,10" name="ajeno-1"><inklist><gesture>1,1;2,2</gesture><gesture>3,3;4,4</gesture></inklist></ink></annots></xfdf>"#).unwrap();
+        let strokes = read_annotation(&root.children[0].children[0]);
+        assert_eq!(strokes.iter().map(|(_, a)| a.name.as_deref()).collect::<Vec<_>>(), [Some("ajeno-1"), None]);
+    }
+
+    #[test]
+    fn xml_entities_cdata_and_namespaces() {
+        let root = parse_xml(concat!(
+            "<?xml version=\"1.0\"?>\n<!DOCTYPE xfdf>\n<!-- comentario <a> -->\n",
+            "<x:xfdf xmlns:x=\"http://ns.adobe.com/xfdf/\">",
+            "<x:a x:t=\"x &amp; &lt;&#65;&#x42;\" u='>\"'>&quot;&apos;&desconocida; &amp</x:a>",
+            "<b><![CDATA[<c>&amp;</c>]]></b><c/><d  k = \"1\"k2=\"2\" />",
+            "</x:xfdf>",
+        )).unwrap();
+        assert_eq!(root.name, "xfdf");
+        let names: Vec<&str> = root.children.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(names, ["a", "b", "c", "d"]);
+        let a = &root.children[0];
+        assert_eq!((a.attr("t"), a.attr("u")), (Some("x & <AB"), Some(">\"")));
+        assert_eq!(a.text, "\"'&desconocida; &amp");
+        assert_eq!(root.children[1].text, "<c>&amp;</c>");
+        assert_eq!((root.children[3].attr("k"), root.children[3].attr("k2")), (Some("1"), Some("2")));
+    }
+
+    #[test]
+    fn malformed_xml_is_rejected() {
+        let cases = ["", "solo texto", "<a>", "<a></b>", "</a>", "<a></a></a>", "<a", "<a b=c/>", "<a b='1/>", "<a b/>", "<!-- sin cerrar", "<a><![CDATA[ sin cerrar</a>"];
+        for input in cases {
+            assert!(parse_xml(input).is_err(), "{:?}", input);
+        }
+        assert!(parse_tag("a b=\"1\" c").is_err());
+        assert_eq!(parse_tag(" a:b  ").unwrap().name, "b");
+    }
+
+    #[test]
+    fn escape_and_unescape_round_trip() {
+        for text in ["", "plano", "a < b && c > \"d\"", "línea\nsiguiente", "&amp; ya escapado", "💡 & ✓"] {
+            assert_eq!(unescape(&escape(text)), text);
+        }
+        assert_eq!(unescape("&amp;lt; &#xZZ; &#1114112; & sin punto y coma"), "&lt; &#xZZ; &#1114112; & sin punto y coma");
+    }
+}